@@ -0,0 +1,203 @@
+use sgml::catalog::Catalog;
+use sgml::dtd::read_dtd;
+use sgml::flatten::{flatten_dtd, FlattenOptions};
+use sgml::printer::{print_dtd, KeywordCase, PrintOptions, ReferenceMode};
+use sgml::xml_dtd::{to_xml_dtd, XmlDtdOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::exit;
+
+const USAGE: &str = "Usage: dtd_fmt [options] [FILE...]
+
+Reformats DTDs into canonical form, reading stdin when no files are given.
+
+Options:
+    --width N       Wrap content models to N columns (default 80)
+    --lowercase     Write keywords in lower case
+    --expand        Replace parameter entity references with their text
+    --flatten       Replace INCLUDE marked sections with their content, drop IGNORE sections
+    --standalone    Read in external entities and expand all parameter references, producing one self-contained DTD
+    --keep-entities Keep general entity declarations in standalone output
+    --catalog FILE  A catalog of public identifiers for standalone output, may be repeated
+                    SGML_CATALOG_FILES is read after these, separated by colons
+    --xml           Convert to a standalone XML DTD, reporting constructs XML can't express
+    --lowercase-names
+                    Lowercase element and attribute names when converting to XML
+    --write         Rewrite files in place instead of printing them
+    --check         Exit with status 1 if any file is not already formatted";
+
+/// How to load the entities of a DTD when producing standalone output
+struct Standalone {
+    catalog: Catalog,
+    options: FlattenOptions,
+}
+
+fn format(
+    source: &str,
+    base: &Path,
+    options: &PrintOptions,
+    standalone: Option<&Standalone>,
+    xml: Option<&XmlDtdOptions>,
+) -> Result<String, String> {
+    let (rest, dtd) = read_dtd(source).map_err(|e| format!("{:?}", e))?;
+
+    // Refuse to format anything we didn't fully understand, it would be dropped from the output
+    if !rest.trim().is_empty() {
+        let line = source[..source.len() - rest.len()].lines().count() + 1;
+        return Err(format!("unable to parse declaration at line {}", line));
+    }
+
+    let dtd = match standalone {
+        Some(s) => flatten_dtd(&dtd, base, &s.catalog, &s.options).map_err(|e| e.to_string())?,
+        None => dtd,
+    };
+
+    match xml {
+        Some(xml) => {
+            let converted = to_xml_dtd(&dtd, xml);
+            for issue in &converted.issues {
+                eprintln!("warning: {}", issue);
+            }
+            Ok(print_dtd(&converted.dtd, options))
+        }
+        None => Ok(print_dtd(&dtd, options)),
+    }
+}
+
+fn main() {
+    let mut options = PrintOptions::default();
+    let mut write = false;
+    let mut check = false;
+    let mut standalone = false;
+    let mut flatten_options = FlattenOptions::default();
+    let mut catalog = Catalog::default();
+    let mut xml = false;
+    let mut xml_options = XmlDtdOptions::default();
+    let mut files = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => {
+                options.width = match args.next().and_then(|w| w.parse().ok()) {
+                    Some(width) => width,
+                    None => {
+                        eprintln!("--width expects a number");
+                        exit(2);
+                    }
+                }
+            }
+            "--lowercase" => options.keyword_case = KeywordCase::Lower,
+            "--expand" => options.parameter_references = ReferenceMode::Expand,
+            "--flatten" => options.flatten_marked_sections = true,
+            "--standalone" => standalone = true,
+            "--keep-entities" => flatten_options.keep_general_entities = true,
+            "--catalog" => {
+                let path = match args.next() {
+                    Some(path) => path,
+                    None => {
+                        eprintln!("--catalog expects a file");
+                        exit(2);
+                    }
+                };
+                if let Err(e) = catalog.load(Path::new(&path)) {
+                    eprintln!("unable to read {}: {}", path, e);
+                    exit(2);
+                }
+            }
+            "--xml" => xml = true,
+            "--lowercase-names" => xml_options.lowercase_names = true,
+            "--write" => write = true,
+            "--check" => check = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with("--") => {
+                eprintln!("Unknown option {}\n\n{}", arg, USAGE);
+                exit(2);
+            }
+            _ => files.push(arg),
+        }
+    }
+
+    // Missing catalogs in the environment are not worth stopping for
+    for (file, e) in catalog.load_env() {
+        eprintln!("warning: unable to read catalog {}: {}", file, e);
+    }
+
+    // XML has no marked sections or external parameter entities to resolve later, so conversion implies --standalone
+    let xml = if xml {
+        flatten_options.keep_general_entities = true;
+        options.keyword_case = KeywordCase::Upper;
+        Some(xml_options)
+    } else {
+        None
+    };
+    let standalone = if standalone || xml.is_some() {
+        Some(Standalone {
+            catalog,
+            options: flatten_options,
+        })
+    } else {
+        None
+    };
+
+    if files.is_empty() {
+        let mut s = String::new();
+        std::io::stdin().read_to_string(&mut s).unwrap();
+
+        match format(
+            &s,
+            Path::new("."),
+            &options,
+            standalone.as_ref(),
+            xml.as_ref(),
+        ) {
+            Ok(out) if check => exit(if out == s { 0 } else { 1 }),
+            Ok(out) => std::io::stdout().write_all(out.as_bytes()).unwrap(),
+            Err(e) => {
+                eprintln!("<stdin>: {}", e);
+                exit(2);
+            }
+        }
+        return;
+    }
+
+    let mut unformatted = false;
+    for file in &files {
+        let s = match std::fs::read_to_string(file) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                exit(2);
+            }
+        };
+
+        let base = Path::new(file).parent().unwrap_or_else(|| Path::new("."));
+        let out = match format(&s, base, &options, standalone.as_ref(), xml.as_ref()) {
+            Ok(out) => out,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                exit(2);
+            }
+        };
+
+        if check {
+            if out != s {
+                println!("{} is not formatted", file);
+                unformatted = true;
+            }
+        } else if write {
+            if out != s {
+                std::fs::write(file, out).unwrap();
+            }
+        } else {
+            print!("{}", out);
+        }
+    }
+
+    if unformatted {
+        exit(1);
+    }
+}
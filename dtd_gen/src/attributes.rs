@@ -0,0 +1,334 @@
+use crate::{Options, TypeNames};
+use proc_macro2::{Ident, TokenStream};
+use sgml::att_list::{AttributeDefinition, DeclaredValue, DefaultValue};
+
+/// Words that can't be used as field names, these get a trailing underscore
+const KEYWORDS: [&str; 51] = [
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
+    "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield", "union",
+];
+
+/// The words of a name, split at characters that can't appear in an identifier
+fn words(name: &str) -> impl Iterator<Item = &str> {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+}
+
+/// A snake case field name, such as `http_equiv` for `HTTP-EQUIV`
+pub(crate) fn field_name(name: &str) -> Ident {
+    let mut field = words(name)
+        .map(|w| w.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join("_");
+    if field.is_empty() || field.starts_with(|c: char| c.is_ascii_digit()) {
+        field.insert(0, '_');
+    }
+    if KEYWORDS.contains(&field.as_str()) {
+        field.push('_');
+    }
+    format_ident!("{}", field)
+}
+
+/// A camel case type or variant name, such as `HttpEquiv` for `HTTP-EQUIV`
+pub(crate) fn camel_name(name: &str) -> String {
+    let mut camel = words(name)
+        .map(|w| {
+            let mut w = w.to_ascii_lowercase();
+            w[..1].make_ascii_uppercase();
+            w
+        })
+        .collect::<String>();
+    if camel.is_empty() || camel.starts_with(|c: char| c.is_ascii_digit()) {
+        camel.insert(0, 'V');
+    }
+    if camel == "Self" {
+        camel.push('_');
+    }
+    camel
+}
+
+/// Give each name a number after its first use, so that fields and variants are unique
+pub(crate) fn unique(names: &mut Vec<String>, name: String) -> String {
+    let mut unique = name.clone();
+    let mut count = 1;
+    while names.contains(&unique) {
+        count += 1;
+        unique = format!("{}_{}", name, count);
+    }
+    names.push(unique.clone());
+    unique
+}
+
+/// A type name keeping the case of the declared name, such as `LIST_ITEM` for `LIST-ITEM`
+pub(crate) fn type_name(name: &str) -> String {
+    let mut ty = words(name).collect::<Vec<_>>().join("_");
+    if ty.is_empty() || ty.starts_with(|c: char| c.is_ascii_digit()) {
+        ty.insert(0, 'V');
+    }
+    if KEYWORDS.contains(&ty.as_str()) || ty == "Self" {
+        ty.push('_');
+    }
+    ty
+}
+
+/// Whether an attribute value is held in a generated enum, as its value is one of a group of tokens
+pub(crate) fn is_enum(definition: &AttributeDefinition) -> bool {
+    matches!(
+        definition.declared_value,
+        DeclaredValue::NameTokenGroup(_) | DeclaredValue::Notation(_)
+    )
+}
+
+/// The Rust type an attribute value is held in
+enum ValueType {
+    String,
+    Strings,
+    Number,
+    Numbers,
+    /// A generated enum with a variant for each token of a group
+    Enum(Ident, Vec<(Ident, String)>),
+}
+
+impl ValueType {
+    /// An enum is named by `types`, for the element's declared name
+    fn new(element: &str, definition: &AttributeDefinition, types: &TypeNames) -> Self {
+        match &definition.declared_value {
+            DeclaredValue::Number => ValueType::Number,
+            DeclaredValue::Numbers => ValueType::Numbers,
+            DeclaredValue::Entities
+            | DeclaredValue::IdRefs
+            | DeclaredValue::Names
+            | DeclaredValue::NmTokens
+            | DeclaredValue::NuTokens => ValueType::Strings,
+            DeclaredValue::NameTokenGroup(tokens) | DeclaredValue::Notation(tokens) => {
+                let mut names = Vec::new();
+                let variants = tokens
+                    .iter()
+                    .map(|t| {
+                        let variant = unique(&mut names, camel_name(t));
+                        (format_ident!("{}", variant), t.clone())
+                    })
+                    .collect();
+                ValueType::Enum(types.get_value(element, &definition.name), variants)
+            }
+            _ => ValueType::String,
+        }
+    }
+
+    fn tokens(&self) -> TokenStream {
+        match self {
+            ValueType::String => quote! { String },
+            ValueType::Strings => quote! { Vec<String> },
+            ValueType::Number => quote! { u32 },
+            ValueType::Numbers => quote! { Vec<u32> },
+            ValueType::Enum(name, _) => quote! { #name },
+        }
+    }
+
+    /// An expression for a value written in the declaration, if it is valid for this type
+    fn value(&self, value: &str) -> Option<TokenStream> {
+        match self {
+            ValueType::String => Some(quote! { #value.to_string() }),
+            ValueType::Strings => {
+                let values = value.split_whitespace();
+                Some(quote! { vec![#(#values.to_string()),*] })
+            }
+            ValueType::Number => {
+                let n = value.trim().parse::<u32>().ok()?;
+                Some(quote! { #n })
+            }
+            ValueType::Numbers => {
+                let values = value
+                    .split_whitespace()
+                    .map(|n| n.parse::<u32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()?;
+                Some(quote! { vec![#(#values),*] })
+            }
+            ValueType::Enum(name, variants) => {
+                let (variant, _) = variants
+                    .iter()
+                    .find(|(_, t)| t.eq_ignore_ascii_case(value.trim()))?;
+                Some(quote! { #name::#variant })
+            }
+        }
+    }
+}
+
+/// The attributes struct of an element, with enums for any token groups, and the name of the struct
+/// Their names are taken from `types`
+/// Returns None if the element has no attributes
+///
+/// #IMPLIED, #CURRENT and #CONREF attributes are optional, all others always have a value,
+/// which is the declared default value in the `Default` of the struct. The struct reads itself from
+/// the attributes of a start tag and lists those that need to be written
+pub(crate) fn generate_attributes(
+    element: &str,
+    definitions: &[&AttributeDefinition],
+    types: &TypeNames,
+    options: &Options,
+) -> Option<(Ident, TokenStream)> {
+    if definitions.is_empty() {
+        return None;
+    }
+    let name = types.get_attributes(element);
+    let mut field_names = Vec::new();
+    let derive_serde = options.derive_serde();
+    let serde_default = options.serde(quote! { default });
+
+    let mut fields = Vec::new();
+    let mut defaults = Vec::new();
+    let mut enums = Vec::new();
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    for definition in definitions {
+        let field = format_ident!(
+            "{}",
+            unique(&mut field_names, field_name(&definition.name).to_string())
+        );
+        let attribute = &definition.name;
+        let value_type = ValueType::new(element, definition, types);
+        let ty = value_type.tokens();
+        let parsed = quote! { ::sgml::typed::parse_attribute(#element, attribute)? };
+        let rename = options.serde(quote! { rename = #attribute });
+
+        let declared = match &definition.default_value {
+            DefaultValue::Value(v) | DefaultValue::Fixed(v) => value_type.value(v),
+            _ => None,
+        };
+        match definition.default_value {
+            DefaultValue::Implied | DefaultValue::Current | DefaultValue::ConRef => {
+                let skip = options.serde(quote! { skip_serializing_if = "Option::is_none" });
+                fields.push(quote! { #rename #skip pub #field: Option<#ty> });
+                defaults.push(quote! { #field: None });
+                reads.push(quote! {
+                    if attribute.name.eq_ignore_ascii_case(#attribute) {
+                        value.#field = Some(#parsed);
+                    }
+                });
+                writes.push(quote! {
+                    if let Some(v) = &self.#field {
+                        specified.push((#attribute, ::sgml::typed::AttributeValue::write(v)));
+                    }
+                });
+            }
+            _ => {
+                let default = declared
+                    .clone()
+                    .unwrap_or_else(|| quote! { Default::default() });
+                fields.push(quote! { #rename pub #field: #ty });
+                defaults.push(quote! { #field: #default });
+                reads.push(quote! {
+                    if attribute.name.eq_ignore_ascii_case(#attribute) {
+                        value.#field = #parsed;
+                    }
+                });
+                // A value the parser would supply itself is left out of SGML
+                let condition = match (&definition.default_value, &declared) {
+                    (DefaultValue::Value(v), Some(_)) | (DefaultValue::Fixed(v), Some(_))
+                        if matches!(value_type, ValueType::String) =>
+                    {
+                        quote! { all || self.#field != #v }
+                    }
+                    (_, Some(declared)) => quote! { all || self.#field != #declared },
+                    (_, None) => quote! { true },
+                };
+                writes.push(quote! {
+                    if #condition {
+                        let v = ::sgml::typed::AttributeValue::write(&self.#field);
+                        specified.push((#attribute, v));
+                    }
+                });
+            }
+        }
+
+        if let ValueType::Enum(enum_name, variants) = &value_type {
+            let idents = variants.iter().map(|(v, _)| v).collect::<Vec<_>>();
+            let tokens = variants.iter().map(|(_, t)| t).collect::<Vec<_>>();
+            let renames = tokens.iter().map(|t| options.serde(quote! { rename = #t }));
+            // The declared default if there is one, otherwise the first token
+            let default = declared.unwrap_or_else(|| {
+                let first = &variants[0].0;
+                quote! { #enum_name::#first }
+            });
+            enums.push(quote! {
+                #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+                #derive_serde
+                pub enum #enum_name {
+                    #(#renames #idents,)*
+                }
+
+                impl Default for #enum_name {
+                    fn default() -> Self {
+                        #default
+                    }
+                }
+
+                impl ::sgml::typed::AttributeValue for #enum_name {
+                    fn parse(value: &str) -> Option<Self> {
+                        #(
+                        if value.eq_ignore_ascii_case(#tokens) {
+                            return Some(#enum_name::#idents);
+                        }
+                        )*
+                        None
+                    }
+
+                    fn write(&self) -> String {
+                        match self {
+                            #(#enum_name::#idents => #tokens,)*
+                        }
+                        .to_string()
+                    }
+                }
+            });
+        }
+    }
+
+    Some((
+        name.clone(),
+        quote! {
+            #[derive(Debug, Clone, PartialEq)]
+            #derive_serde
+            #serde_default
+            pub struct #name {
+                #(#fields,)*
+            }
+
+            impl Default for #name {
+                fn default() -> Self {
+                    Self {
+                        #(#defaults,)*
+                    }
+                }
+            }
+
+            impl #name {
+                /// The attributes of a start tag, with those not specified left at their defaults
+                pub fn read(
+                    attributes: &[::sgml::instance::Attribute],
+                ) -> Result<Self, ::sgml::typed::BindError> {
+                    let mut value = Self::default();
+                    for attribute in attributes {
+                        #(#reads)*
+                    }
+                    Ok(value)
+                }
+
+                /// The attributes to write in a start tag, `all` to include those with their
+                /// declared default value
+                #[allow(unused_variables)]
+                pub fn specified(&self, all: bool) -> Vec<(&'static str, String)> {
+                    let mut specified = Vec::new();
+                    #(#writes)*
+                    specified
+                }
+            }
+
+            #(#enums)*
+        },
+    ))
+}
@@ -0,0 +1,620 @@
+use crate::attributes::{field_name, unique};
+use crate::{Options, TypeNames};
+use proc_macro2::{Ident, TokenStream};
+use sgml::content_model::{
+    Connector, ContentModel, ContentToken, DeclaredContent, ModelGroup, Occurrence,
+};
+
+/// The fields of an element struct following its content model, with the types of its groups
+pub(crate) struct Content {
+    pub fields: Vec<TokenStream>,
+    pub definitions: Vec<TokenStream>,
+    /// Statements reading the content from `events`, ending with `Ok(Self { ... })` where the
+    /// fields follow `init`
+    pub read: TokenStream,
+    /// Statements writing the fields of `self` to `writer`
+    pub write: TokenStream,
+}
+
+/// Generate fields that give the structure of an element's content: a field for each token of a
+/// sequence or `&` group, `Option` for `?`, `Vec` for `*` and `+`, and an enum for each `|` group
+///
+/// Returns None when the model is too complex to follow, such as mixed content, content with
+/// inclusions or content naming undeclared elements, which is left to a `Vec` of an enum instead.
+/// Elements that `boxed` names are held in a `Box`. The types of groups are named after `element`,
+/// the type of the element
+pub(crate) fn generate_content(
+    element: &Ident,
+    model: &ContentModel,
+    declared: &dyn Fn(&str) -> Option<String>,
+    types: &TypeNames,
+    boxed: &dyn Fn(&str) -> bool,
+    init: &TokenStream,
+    options: &Options,
+) -> Option<Content> {
+    let group = match &model.content {
+        DeclaredContent::Group(g) => g,
+        _ => return None,
+    };
+    if model.is_mixed() || !model.inclusions.is_empty() {
+        return None;
+    }
+
+    let mut generator = Generator {
+        element,
+        declared,
+        types,
+        boxed,
+        options,
+        groups: 0,
+        definitions: Vec::new(),
+        repeated: false,
+    };
+    let (fields, read, write) = match simplify(&ContentToken::Group(group.clone())) {
+        ContentToken::Group(g)
+            if g.occurrence == Occurrence::Once && g.connector != Connector::Or =>
+        {
+            let fields = generator.fields(&g)?;
+            (
+                fields.iter().map(|f| f.definition(options)).collect(),
+                read_fields(g.connector, &fields, init),
+                write_fields(g.connector, &fields, true),
+            )
+        }
+        ContentToken::Group(g) => {
+            let token = generator.group_token(&g, format_ident!("{}Content", element))?;
+            let field = Field {
+                ident: format_ident!("content"),
+                key: None,
+                token,
+            };
+            (
+                vec![field.definition(options)],
+                read_fields(Connector::Sequence, std::slice::from_ref(&field), init),
+                write_fields(Connector::Sequence, &[field], false),
+            )
+        }
+        token => {
+            let token = generator.token(&token)?;
+            let field = Field {
+                ident: field_name(&token.name),
+                key: Some(token.name.clone()),
+                token,
+            };
+            (
+                vec![field.definition(options)],
+                read_fields(Connector::Sequence, std::slice::from_ref(&field), init),
+                write_fields(Connector::Sequence, &[field], true),
+            )
+        }
+    };
+
+    Some(Content {
+        fields,
+        definitions: generator.definitions,
+        read,
+        write,
+    })
+}
+
+/// The elements the fields of `generate_content` hold without a `Vec` around them, which need a
+/// `Box` if they hold the element again. Empty when the content isn't followed
+pub(crate) fn held_by_value(
+    model: &ContentModel,
+    declared: &dyn Fn(&str) -> Option<String>,
+) -> Vec<String> {
+    fn held(
+        token: &ContentToken,
+        repeated: bool,
+        declared: &dyn Fn(&str) -> Option<String>,
+        names: &mut Vec<String>,
+    ) -> Option<()> {
+        match simplify(token) {
+            ContentToken::PcData => None,
+            ContentToken::Element(name, occurrence) => {
+                let name = declared(&name)?;
+                if !repeated && !occurrence.is_repeatable() && !names.contains(&name) {
+                    names.push(name);
+                }
+                Some(())
+            }
+            ContentToken::Group(g) => {
+                let repeated = repeated || g.occurrence.is_repeatable();
+                g.tokens
+                    .iter()
+                    .try_for_each(|t| held(t, repeated, declared, names))
+            }
+        }
+    }
+
+    let mut names = Vec::new();
+    match &model.content {
+        DeclaredContent::Group(g) if !model.is_mixed() && model.inclusions.is_empty() => {
+            match held(&ContentToken::Group(g.clone()), false, declared, &mut names) {
+                Some(()) => names,
+                None => Vec::new(),
+            }
+        }
+        _ => names,
+    }
+}
+
+/// The elements that can begin content following a model, those inclusions add left out
+pub(crate) fn first_elements(
+    model: &ContentModel,
+    declared: &dyn Fn(&str) -> Option<String>,
+) -> Vec<String> {
+    match &model.content {
+        DeclaredContent::Group(g) if model.is_mixed() => g
+            .get_element_names()
+            .iter()
+            .filter_map(|n| declared(n))
+            .collect(),
+        DeclaredContent::Group(g) => first(&ContentToken::Group(g.clone()), declared),
+        _ => Vec::new(),
+    }
+}
+
+/// Whether a token can match no content at all
+fn nullable(token: &ContentToken) -> bool {
+    match token {
+        ContentToken::PcData => true,
+        ContentToken::Element(_, occurrence) => occurrence.is_optional(),
+        ContentToken::Group(g) => {
+            g.occurrence.is_optional()
+                || match g.connector {
+                    Connector::Or => g.tokens.iter().any(nullable),
+                    _ => g.tokens.iter().all(nullable),
+                }
+        }
+    }
+}
+
+/// The declared names of the elements that can begin a token
+fn first(token: &ContentToken, declared: &dyn Fn(&str) -> Option<String>) -> Vec<String> {
+    let mut names = Vec::new();
+    match token {
+        ContentToken::PcData => {}
+        ContentToken::Element(name, _) => names.extend(declared(name)),
+        ContentToken::Group(g) => {
+            for t in &g.tokens {
+                for name in first(t, declared) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+                if g.connector == Connector::Sequence && !nullable(t) {
+                    break;
+                }
+            }
+        }
+    }
+    names
+}
+
+/// A group of one token stands for that token, with the occurrences of both combined
+fn simplify(token: &ContentToken) -> ContentToken {
+    match token {
+        ContentToken::Group(g) if g.tokens.len() == 1 => {
+            let combine = |inner: Occurrence| {
+                Occurrence::from_flags(
+                    inner.is_optional() || g.occurrence.is_optional(),
+                    inner.is_repeatable() || g.occurrence.is_repeatable(),
+                )
+            };
+            match simplify(&g.tokens[0]) {
+                ContentToken::Element(name, occurrence) => {
+                    ContentToken::Element(name, combine(occurrence))
+                }
+                ContentToken::Group(inner) => ContentToken::Group(ModelGroup {
+                    occurrence: combine(inner.occurrence),
+                    ..inner
+                }),
+                ContentToken::PcData => ContentToken::PcData,
+            }
+        }
+        token => token.clone(),
+    }
+}
+
+/// The type for a token with the given occurrence
+fn wrap(ty: TokenStream, occurrence: Occurrence) -> TokenStream {
+    match occurrence {
+        Occurrence::Once => ty,
+        Occurrence::Optional => quote! { Option<#ty> },
+        Occurrence::OneOrMore | Occurrence::ZeroOrMore => quote! { Vec<#ty> },
+    }
+}
+
+/// A token given a type, an element or a group with a type of its own
+#[derive(Clone)]
+struct Token {
+    /// The name fields and variants for it are given
+    name: String,
+    /// The element or group type, without its occurrence
+    ty: Ident,
+    group: bool,
+    /// Held in a `Box`, as the element holds the type the token is in
+    boxed: bool,
+    occurrence: Occurrence,
+    /// The elements that can begin it
+    first: Vec<String>,
+    nullable: bool,
+}
+
+impl Token {
+    fn field_type(&self) -> TokenStream {
+        let ty = &self.ty;
+        if self.boxed {
+            wrap(quote! { Box<#ty> }, self.occurrence)
+        } else {
+            wrap(quote! { #ty }, self.occurrence)
+        }
+    }
+
+    /// An expression reading one of the token from `events` in the content of `parent`
+    fn read_one(&self) -> TokenStream {
+        let ty = &self.ty;
+        if self.group {
+            quote! { <#ty as ::sgml::typed::ContentGroup>::read(parent, events)? }
+        } else if self.boxed {
+            quote! { Box::new(events.read::<#ty>(parent)?) }
+        } else {
+            quote! { events.read::<#ty>(parent)? }
+        }
+    }
+
+    /// Whether the next element can begin the token
+    fn at(&self) -> TokenStream {
+        let first = &self.first;
+        quote! { events.at(parent, &[#(#first),*])? }
+    }
+
+    /// An expression reading the token with its occurrence
+    fn read(&self) -> TokenStream {
+        let one = self.read_one();
+        let at = self.at();
+        match self.occurrence {
+            Occurrence::Once => one,
+            Occurrence::Optional => quote! {
+                if #at { Some(#one) } else { None }
+            },
+            Occurrence::ZeroOrMore => quote! {{
+                let mut values = Vec::new();
+                while #at {
+                    values.push(#one);
+                }
+                values
+            }},
+            Occurrence::OneOrMore => quote! {{
+                let mut values = vec![#one];
+                while #at {
+                    values.push(#one);
+                }
+                values
+            }},
+        }
+    }
+
+    /// A statement writing one of the token, which `value` refers to
+    fn write_one(&self, value: TokenStream, required: bool) -> TokenStream {
+        if self.group {
+            quote! { ::sgml::typed::ContentGroup::write(#value, writer); }
+        } else if self.boxed {
+            quote! { ::sgml::typed::TypedElement::write(&**#value, writer, #required); }
+        } else {
+            quote! { ::sgml::typed::TypedElement::write(#value, writer, #required); }
+        }
+    }
+
+    /// A statement writing the token with its occurrence, `required` when its start tag may be
+    /// omitted if it occurs once
+    fn write(&self, value: TokenStream, required: bool) -> TokenStream {
+        match self.occurrence {
+            Occurrence::Once => self.write_one(value, required),
+            Occurrence::Optional => {
+                let one = self.write_one(quote! { value }, false);
+                quote! { if let Some(value) = #value { #one } }
+            }
+            Occurrence::OneOrMore | Occurrence::ZeroOrMore => {
+                let one = self.write_one(quote! { value }, false);
+                quote! { for value in #value { #one } }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Field {
+    ident: Ident,
+    /// The name the field is serialized with, the name of its element or group
+    key: Option<String>,
+    token: Token,
+}
+
+impl Field {
+    fn definition(&self, options: &Options) -> TokenStream {
+        let ident = &self.ident;
+        let ty = self.token.field_type();
+        let rename = match &self.key {
+            Some(key) => options.serde(quote! { rename = #key }),
+            None => quote! {},
+        };
+        quote! { #rename pub #ident: #ty }
+    }
+}
+
+/// Statements reading the fields of a sequence or `&` group into `Self`, after the fields `init`
+fn read_fields(connector: Connector, fields: &[Field], init: &TokenStream) -> TokenStream {
+    let idents = fields.iter().map(|f| &f.ident).collect::<Vec<_>>();
+    if connector != Connector::And {
+        let values = fields.iter().map(|f| f.token.read());
+        return quote! {
+            Ok(Self {
+                #init
+                #(#idents: #values,)*
+            })
+        };
+    }
+
+    // The tokens of an `&` group come in any order, each is read where it can begin
+    let declarations = fields.iter().map(|f| {
+        let ident = &f.ident;
+        if f.token.occurrence.is_repeatable() {
+            quote! { let mut #ident = Vec::new(); }
+        } else {
+            quote! { let mut #ident = None; }
+        }
+    });
+    let branches = fields.iter().map(|f| {
+        let ident = &f.ident;
+        let at = f.token.at();
+        let one = f.token.read_one();
+        if f.token.occurrence.is_repeatable() {
+            quote! { if #at { #ident.push(#one); } }
+        } else {
+            quote! { if #ident.is_none() && #at { #ident = Some(#one); } }
+        }
+    });
+    let checks = fields.iter().map(|f| {
+        let ident = &f.ident;
+        let first = &f.token.first;
+        let missing = quote! { return Err(events.missing(parent, &[#(#first),*])) };
+        match f.token.occurrence {
+            Occurrence::Once => quote! {
+                let #ident = match #ident {
+                    Some(value) => value,
+                    None => #missing,
+                };
+            },
+            Occurrence::OneOrMore => quote! {
+                if #ident.is_empty() {
+                    #missing
+                }
+            },
+            _ => quote! {},
+        }
+    });
+    quote! {
+        #(#declarations)*
+        loop {
+            #(#branches else)* {
+                break;
+            }
+        }
+        #(#checks)*
+        Ok(Self {
+            #init
+            #(#idents,)*
+        })
+    }
+}
+
+/// Statements writing the fields of `self`, `required` for the fields of the sequence that is the
+/// content of an element
+fn write_fields(connector: Connector, fields: &[Field], required: bool) -> TokenStream {
+    let required = required && connector == Connector::Sequence;
+    let writes = fields.iter().map(|f| {
+        let ident = &f.ident;
+        f.token.write(quote! { &self.#ident }, required)
+    });
+    quote! { #(#writes)* }
+}
+
+struct Generator<'a> {
+    element: &'a Ident,
+    /// The name an element is declared with, if it is declared
+    declared: &'a dyn Fn(&str) -> Option<String>,
+    types: &'a TypeNames,
+    boxed: &'a dyn Fn(&str) -> bool,
+    options: &'a Options,
+    /// Groups given a type so far, used to number them
+    groups: usize,
+    definitions: Vec<TokenStream>,
+    /// Within a group that repeats, so its elements are held in a `Vec`
+    repeated: bool,
+}
+
+impl<'a> Generator<'a> {
+    /// The type of a token, None if it is character data or an undeclared element
+    fn token(&mut self, token: &ContentToken) -> Option<Token> {
+        match simplify(token) {
+            ContentToken::PcData => None,
+            ContentToken::Element(name, occurrence) => {
+                let name = (self.declared)(&name)?;
+                let by_value = !self.repeated && !occurrence.is_repeatable();
+                Some(Token {
+                    ty: self.types.get(&name),
+                    group: false,
+                    boxed: by_value && (self.boxed)(&name),
+                    occurrence,
+                    first: vec![name.clone()],
+                    nullable: occurrence.is_optional(),
+                    name,
+                })
+            }
+            ContentToken::Group(g) => {
+                self.groups += 1;
+                let name = format!("Group{}", self.groups);
+                let mut token = self.group_token(&g, format_ident!("{}{}", self.element, name))?;
+                token.name = name;
+                Some(token)
+            }
+        }
+    }
+
+    /// The fields for the tokens of a sequence or `&` group
+    fn fields(&mut self, group: &ModelGroup) -> Option<Vec<Field>> {
+        let mut names = Vec::new();
+        let mut idents = Vec::new();
+        group
+            .tokens
+            .iter()
+            .map(|t| {
+                let token = self.token(t)?;
+                let key = unique(&mut names, token.name.clone());
+                let ident = unique(&mut idents, field_name(&key).to_string());
+                Some(Field {
+                    ident: format_ident!("{}", ident),
+                    key: Some(key),
+                    token,
+                })
+            })
+            .collect()
+    }
+
+    /// Define a struct for a sequence or `&` group, or an enum for an `|` group, named `name`.
+    /// The elements of a group that repeats aren't held by value
+    fn group_token(&mut self, group: &ModelGroup, name: Ident) -> Option<Token> {
+        let repeated = self.repeated;
+        self.repeated = repeated || group.occurrence.is_repeatable();
+        let token = self.group_type(group, name);
+        self.repeated = repeated;
+        token
+    }
+
+    fn group_type(&mut self, group: &ModelGroup, name: Ident) -> Option<Token> {
+        let token = ContentToken::Group(group.clone());
+        let first = first(&token, self.declared);
+        let nullable = nullable(&token);
+        let definition = match group.connector {
+            Connector::Sequence | Connector::And => {
+                let fields = self.fields(group)?;
+                let definitions = fields.iter().map(|f| f.definition(self.options));
+                let derive_serde = self.options.derive_serde();
+                let serde_default = self.options.serde(quote! { default });
+                let read = read_fields(group.connector, &fields, &quote! {});
+                let write = write_fields(group.connector, &fields, false);
+                quote! {
+                    #[derive(Debug, Clone, Default)]
+                    #derive_serde
+                    #serde_default
+                    pub struct #name {
+                        #(#definitions,)*
+                    }
+
+                    impl ::sgml::typed::ContentGroup for #name {
+                        const FIRST: &'static [&'static str] = &[#(#first),*];
+
+                        fn read(
+                            parent: &str,
+                            events: &mut ::sgml::typed::Events,
+                        ) -> Result<Self, ::sgml::typed::BindError> {
+                            #read
+                        }
+
+                        fn write(&self, writer: &mut ::sgml::typed::Writer) {
+                            #write
+                        }
+                    }
+                }
+            }
+            Connector::Or => {
+                let mut names = Vec::new();
+                let mut idents = Vec::new();
+                let variants = group
+                    .tokens
+                    .iter()
+                    .map(|t| {
+                        let token = self.token(t)?;
+                        let key = unique(&mut names, token.name.clone());
+                        // Elements are named by their type, groups by their number
+                        let ident = if token.group {
+                            token.name.clone()
+                        } else {
+                            token.ty.to_string()
+                        };
+                        let variant = unique(&mut idents, ident);
+                        Some((format_ident!("{}", variant), key, token))
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                let first_variant = &variants.first()?.0;
+                let definitions = variants.iter().map(|(v, key, t)| {
+                    let ty = t.field_type();
+                    let rename = self.options.serde(quote! { rename = #key });
+                    quote! { #rename #v(#ty) }
+                });
+                let variants = variants.iter().map(|(v, _, t)| (v, t)).collect::<Vec<_>>();
+                let reads = variants.iter().map(|(v, t)| {
+                    let at = t.at();
+                    let read = t.read();
+                    quote! { if #at { return Ok(#name::#v(#read)); } }
+                });
+                // A variant that can be empty is taken when no other fits
+                let otherwise = match variants.iter().find(|(_, t)| t.nullable) {
+                    Some((v, t)) => {
+                        let read = t.read();
+                        quote! { Ok(#name::#v(#read)) }
+                    }
+                    None => quote! { Err(events.missing(parent, Self::FIRST)) },
+                };
+                let derive_serde = self.options.derive_serde();
+                let writes = variants.iter().map(|(v, t)| {
+                    let write = t.write(quote! { value }, false);
+                    quote! { #name::#v(value) => { #write } }
+                });
+                quote! {
+                    #[derive(Debug, Clone)]
+                    #derive_serde
+                    pub enum #name {
+                        #(#definitions,)*
+                    }
+
+                    impl Default for #name {
+                        fn default() -> Self {
+                            #name::#first_variant(Default::default())
+                        }
+                    }
+
+                    impl ::sgml::typed::ContentGroup for #name {
+                        const FIRST: &'static [&'static str] = &[#(#first),*];
+
+                        fn read(
+                            parent: &str,
+                            events: &mut ::sgml::typed::Events,
+                        ) -> Result<Self, ::sgml::typed::BindError> {
+                            #(#reads)*
+                            #otherwise
+                        }
+
+                        fn write(&self, writer: &mut ::sgml::typed::Writer) {
+                            match self {
+                                #(#writes)*
+                            }
+                        }
+                    }
+                }
+            }
+        };
+        self.definitions.push(definition);
+        Some(Token {
+            name: String::new(),
+            ty: name,
+            group: true,
+            boxed: false,
+            occurrence: group.occurrence,
+            first,
+            nullable,
+        })
+    }
+}
@@ -1,106 +1,788 @@
-#![crate_type = "proc-macro"]
-extern crate proc_macro;
-use proc_macro::TokenStream;
+//! Generates Rust types for the elements of a DTD, which read and write documents with the `sgml`
+//! crate. `generate` gives the code as a file, such as for a build script:
+//!
+//! ```no_run
+//! let (dtd, files) =
+//!     dtd_gen::load_dtd("dtd/html.dtd".as_ref(), Some("dtd/catalog".as_ref())).unwrap();
+//! for file in files {
+//!     println!("cargo:rerun-if-changed={}", file.display());
+//! }
+//! let options = dtd_gen::Options {
+//!     root: Some("HTML".to_string()),
+//!     ..Default::default()
+//! };
+//! let out = std::path::Path::new(&std::env::var_os("OUT_DIR").unwrap()).join("html.rs");
+//! std::fs::write(out, dtd_gen::generate(&dtd, &options).unwrap()).unwrap();
+//! ```
+//!
+//! which is then included with `include!(concat!(env!("OUT_DIR"), "/html.rs"));`. The `dtd!` macro
+//! of `dtd_gen_macro` generates the same code when the crate using it is compiled
+//!
+//! Each element gets a type named after it, with the characters that can't be in an identifier
+//! replaced by `_`, such as `LIST_ITEM` for `LIST-ITEM`. Names that would still clash, with each
+//! other or with a keyword, get a `_2` or `_` after them. The attributes of an element are a struct
+//! such as `LIST_ITEMAttributes`, with an enum such as `LIST_ITEMShape` for each token group, which
+//! are numbered the same way when an element already has the name
+//!
+//! # JSON
+//!
+//! With the `serde` feature (or `Options::serde`) the types derive `Serialize` and `Deserialize`,
+//! giving documents this mapping:
+//!
+//! - An element is an object, or `null` when it is `EMPTY` without attributes
+//! - Its attributes are the `attributes` object, keyed by the declared attribute names. `#IMPLIED`
+//!   attributes that aren't set are left out, and the values are as in the JSON encoding of
+//!   `sgml::json_schema`
+//! - Its content is keyed by the declared names of the child elements, with `_2`, `_3`, ... for
+//!   the later fields of the same element and `Group1`, `Group2`, ... for nested groups. A model
+//!   that is a single `|` or repeated group is `content`, and a `|` group is externally tagged by
+//!   the field of the choice, as `{"A": {...}}`
+//! - Mixed and `ANY` content is `children`, a list of children each externally tagged as
+//!   `{"P": {...}}` or `{"#PCDATA": "text"}`. `RCDATA` and `CDATA` text is `text`
+//! - Optional children are `null` when missing and repeated children are lists
+//! - Fields that are missing when reading take their default value
+use proc_macro2::{Ident, TokenStream};
+use quote::ToTokens;
+use sgml::catalog::Catalog;
+use sgml::content_model::DeclaredContent;
 use sgml::dtd::{read_dtd, DocumentTypeDefinition, DocumentTypeDefinitionElement};
-use sgml::element::{ContentModelTokenValue, Element};
-use sgml::entity::Entity;
+use sgml::flatten::{flatten_dtd_with_files, FlattenError, FlattenOptions};
+use sgml::printer::{print_dtd, PrintOptions};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::File;
 use std::io::Read;
+use std::path::{Path, PathBuf};
 
 #[macro_use]
 extern crate quote;
 
-fn generate_element(
+mod attributes;
+mod content;
+
+use attributes::{camel_name, generate_attributes, is_enum, type_name, unique};
+use content::{first_elements, generate_content, held_by_value};
+
+/// The type names of the elements, their attributes structs and the enums of their token groups,
+/// made from their declared names and kept apart from each other and from the `Root` enum
+pub(crate) struct TypeNames {
+    elements: HashMap<String, String>,
+    attributes: HashMap<String, String>,
+    /// Keyed by element and attribute name
+    values: HashMap<(String, String), String>,
+}
+
+impl TypeNames {
+    fn new(e: &DocumentTypeDefinition) -> Self {
+        let mut used = vec!["Root".to_string()];
+        let mut elements = HashMap::new();
+        for r in e.get_elements() {
+            elements
+                .entry(r.get_name())
+                .or_insert_with_key(|name| unique(&mut used, type_name(name)));
+        }
+
+        // Elements keep the names they were declared with, the other types give way to them
+        let mut attributes = HashMap::new();
+        let mut values = HashMap::new();
+        for r in e.get_elements() {
+            let name = r.get_name();
+            let definitions = r.get_attributes();
+            if definitions.is_empty() || attributes.contains_key(&name) {
+                continue;
+            }
+            let ty = &elements[&name];
+            for definition in definitions.into_iter().filter(|d| is_enum(d)) {
+                values
+                    .entry((name.clone(), definition.name.clone()))
+                    .or_insert_with(|| {
+                        unique(&mut used, format!("{}{}", ty, camel_name(&definition.name)))
+                    });
+            }
+            let attributes_name = unique(&mut used, format!("{}Attributes", ty));
+            attributes.insert(name, attributes_name);
+        }
+        Self {
+            elements,
+            attributes,
+            values,
+        }
+    }
+
+    /// The type name of an element, by its declared name
+    pub(crate) fn get(&self, name: &str) -> Ident {
+        match self.elements.get(name) {
+            Some(ty) => format_ident!("{}", ty),
+            None => format_ident!("{}", type_name(name)),
+        }
+    }
+
+    /// The name of the attributes struct of an element
+    pub(crate) fn get_attributes(&self, element: &str) -> Ident {
+        match self.attributes.get(element) {
+            Some(ty) => format_ident!("{}", ty),
+            None => format_ident!("{}Attributes", self.get(element)),
+        }
+    }
+
+    /// The name of the enum for the token group of an attribute
+    pub(crate) fn get_value(&self, element: &str, attribute: &str) -> Ident {
+        match self
+            .values
+            .get(&(element.to_string(), attribute.to_string()))
+        {
+            Some(ty) => format_ident!("{}", ty),
+            None => format_ident!("{}{}", self.get(element), camel_name(attribute)),
+        }
+    }
+}
+
+/// The elements that may appear in the content of an element, including inclusions
+/// Names that aren't declared are left out
+fn content_elements<'a>(
+    r: &DocumentTypeDefinitionElement<'a>,
+    e: &'a DocumentTypeDefinition,
+) -> Vec<DocumentTypeDefinitionElement<'a>> {
+    let model = match r.get_content() {
+        Some(model) => model,
+        None => return Vec::new(),
+    };
+    if model.content == DeclaredContent::Any {
+        return e.get_elements().collect();
+    }
+    let mut names = model.get_element_names();
+    for name in &model.inclusions {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    names
+        .iter()
+        .filter(|n| !model.exclusions.contains(n))
+        .filter_map(|n| e.get_element_by_name(n))
+        .collect()
+}
+
+/// The `TypedElement` impl of an element, with the methods reading and writing it as a document
+fn typed_element(
     r: &DocumentTypeDefinitionElement,
-    entities: &[Entity],
     e: &DocumentTypeDefinition,
-    mut already_generated: &mut Vec<String>,
-) -> proc_macro2::TokenStream {
+    types: &TypeNames,
+    read: TokenStream,
+    write: TokenStream,
+    has_attributes: bool,
+) -> TokenStream {
     let r_name = r.get_name();
+    let r_name_ident = types.get(&r_name);
+    let element = r.get_element();
+    let model = r.get_content();
+    let content = model.map(|m| &m.content);
 
-    let rc = r.get_children();
-    let r_children = rc
+    let declared = |name: &str| e.get_element_by_name(name).map(|c| c.get_name());
+    let empty = content == Some(&DeclaredContent::Empty);
+    let text = match content {
+        Some(DeclaredContent::Group(_)) => model.is_some_and(|m| m.is_mixed()),
+        Some(DeclaredContent::Empty) => false,
+        _ => true,
+    };
+    let cdata = content == Some(&DeclaredContent::CData);
+    let first = match model {
+        Some(m) if m.content == DeclaredContent::Any => {
+            e.get_elements().map(|c| c.get_name()).collect()
+        }
+        Some(m) => first_elements(m, &declared),
+        None => Vec::new(),
+    };
+    let contains = content_elements(r, e)
         .iter()
-        .filter(|c| !already_generated.contains(&c.get_name()))
+        .map(|c| c.get_name())
         .collect::<Vec<_>>();
-    println!("children = {:?}", r_children);
-    let children_name = format!("{}Children", r_name);
+    let inclusions: Vec<String> = model
+        .map(|m| m.inclusions.iter().filter_map(|n| declared(n)).collect())
+        .unwrap_or_default();
+    let start_optional = element.start_optional;
+    let end_optional = element.end_optional;
 
-    let childre_props = r_children
-        .iter()
-        .map(|f| {
-            let child_name = f.get_name();
-            let child_name_ident = format_ident!("{}", child_name);
+    let attributes = if has_attributes {
+        quote! { attributes }
+    } else {
+        quote! { _attributes }
+    };
+    let specified = if has_attributes {
+        quote! { let attributes = self.attributes.specified(writer.is_xml()); }
+    } else {
+        quote! { let attributes = Vec::new(); }
+    };
+
+    quote! {
+        impl ::sgml::typed::TypedElement for #r_name_ident {
+            const INFO: ::sgml::typed::ElementInfo = ::sgml::typed::ElementInfo {
+                name: #r_name,
+                start_optional: #start_optional,
+                end_optional: #end_optional,
+                empty: #empty,
+                text: #text,
+                cdata: #cdata,
+                first: &[#(#first),*],
+                contains: &[#(#contains),*],
+                inclusions: &[#(#inclusions),*],
+            };
+
+            #[allow(unused_variables)]
+            fn read(
+                #attributes: &[::sgml::instance::Attribute],
+                events: &mut ::sgml::typed::Events,
+            ) -> Result<Self, ::sgml::typed::BindError> {
+                let parent = <Self as ::sgml::typed::TypedElement>::INFO.name;
+                #read
+            }
+
+            #[allow(unused_variables)]
+            fn write(&self, writer: &mut ::sgml::typed::Writer, required: bool) {
+                let info = &<Self as ::sgml::typed::TypedElement>::INFO;
+                #specified
+                #write
+            }
+        }
+
+        impl #r_name_ident {
+            /// Read an SGML document, whose document element is this element when it has no
+            /// document type declaration
+            pub fn from_sgml(input: &str) -> Result<Self, ::sgml::typed::BindError> {
+                ::sgml::typed::from_sgml(input, document_type_definition())
+            }
+
+            /// Read the events of a parsed document, such as from an `SgmlReader`
+            pub fn from_events<'a>(
+                events: impl Iterator<Item = ::sgml::instance::Event<'a>> + 'a,
+            ) -> Result<Self, ::sgml::typed::BindError> {
+                ::sgml::typed::from_events(events)
+            }
+
+            /// Write as SGML, leaving out the tags the declaration allows to be omitted
+            pub fn to_sgml(&self) -> String {
+                ::sgml::typed::to_sgml(self)
+            }
+
+            /// Write as XML, with every tag
+            pub fn to_xml(&self) -> String {
+                ::sgml::typed::to_xml(self)
+            }
+        }
+    }
+}
+
+/// The types of one element, and the elements its struct holds without a `Vec` around them.
+/// The fields for elements that `boxed` names are boxed
+fn generate_element(
+    r: &DocumentTypeDefinitionElement,
+    e: &DocumentTypeDefinition,
+    types: &TypeNames,
+    boxed: &dyn Fn(&str) -> bool,
+    options: &Options,
+) -> TokenStream {
+    let r_name = r.get_name();
+    let r_name_ident = types.get(&r_name);
+
+    let attributes = generate_attributes(&r_name, &r.get_attributes(), types, options);
+    let derive_serde = options.derive_serde();
+    let serde_default = options.serde(quote! { default });
+    let has_attributes = attributes.is_some();
+    let attributes_field = attributes
+        .as_ref()
+        .map(|(name, _)| quote! { pub attributes: #name, });
+    let attributes_init = attributes
+        .as_ref()
+        .map(|(name, _)| quote! { attributes: #name::read(attributes)?, });
+    let attributes_struct = attributes.map(|(_, tokens)| tokens);
+
+    // Content without child elements holds only text, or nothing at all
+    match r.get_content().map(|c| &c.content) {
+        Some(DeclaredContent::Empty) => {
+            let r_struct = match &attributes_field {
+                Some(field) => quote! { #serde_default pub struct #r_name_ident { #field } },
+                None => quote! { pub struct #r_name_ident; },
+            };
+            let typed = typed_element(
+                r,
+                e,
+                types,
+                quote! { Ok(Self { #attributes_init }) },
+                quote! { writer.empty(info, &attributes); },
+                has_attributes,
+            );
+            let tokens = quote! {
+                #[derive(Debug, Clone, Default)]
+                #derive_serde
+                #r_struct
+
+                #attributes_struct
+
+                #typed
+            };
+            return tokens;
+        }
+        Some(DeclaredContent::CData) | Some(DeclaredContent::RCData) => {
+            let typed = typed_element(
+                r,
+                e,
+                types,
+                quote! { Ok(Self { #attributes_init text: events.read_text(parent)? }) },
+                quote! {
+                    writer.start(info, &attributes, required);
+                    writer.text(&self.text);
+                    writer.end();
+                },
+                has_attributes,
+            );
+            let tokens = quote! {
+                #[derive(Debug, Clone, Default)]
+                #derive_serde
+                #serde_default
+                pub struct #r_name_ident {
+                    #attributes_field
+                    pub text: String,
+                }
+
+                #attributes_struct
+
+                #typed
+            };
+            return tokens;
+        }
+        _ => {}
+    }
+
+    // Follow the content model where it can be, otherwise hold the children in order
+    let declared = |name: &str| e.get_element_by_name(name).map(|c| c.get_name());
+    let init = attributes_init.clone().unwrap_or_default();
+    if let Some(content) = r
+        .get_content()
+        .and_then(|m| generate_content(&r_name_ident, m, &declared, types, boxed, &init, options))
+    {
+        let fields = content.fields;
+        let definitions = content.definitions;
+        let read = content.read;
+        let write = content.write;
+        let typed = typed_element(
+            r,
+            e,
+            types,
+            read,
             quote! {
-                #child_name_ident(#child_name_ident)
+                writer.start(info, &attributes, required);
+                #write
+                writer.end();
+            },
+            has_attributes,
+        );
+        let tokens = quote! {
+            #[derive(Debug, Clone, Default)]
+            #derive_serde
+            #serde_default
+            pub struct #r_name_ident {
+                #attributes_field
+                #(#fields,)*
             }
-        })
-        .collect::<Vec<_>>();
 
-    let r_name_ident = format_ident!("{}", r_name);
-    let r_children_name_ident = format_ident!("{}Children", r_name);
+            #attributes_struct
 
-    //Must add everything new to the already genned list before recursing
-    for n in &r_children {
-        already_generated.push(n.get_name());
+            #typed
+
+            #(#definitions)*
+        };
+        return tokens;
     }
 
-    // Recurse for children
-    let childre_structs = r_children
+    let r_children_name_ident = format_ident!("{}Children", r_name_ident);
+
+    let child_names = content_elements(r, e)
+        .iter()
+        .map(|c| c.get_name())
+        .collect::<Vec<_>>();
+    let child_idents = child_names.iter().map(|n| types.get(n)).collect::<Vec<_>>();
+    let mut childre_props = child_names
         .iter()
-        .map(|c| generate_element(c, entities, e, &mut already_generated));
+        .zip(&child_idents)
+        .map(|(child_name, child_name_ident)| {
+            let rename = options.serde(quote! { rename = #child_name });
+            quote! {
+                #rename #child_name_ident(#child_name_ident)
+            }
+        })
+        .collect::<Vec<_>>();
+    // Character data is allowed between the elements of mixed content, in a variant named apart
+    // from those of the elements
+    let mixed = r.get_content().is_none_or(|c| c.is_mixed());
+    let mut variants = child_idents.iter().map(|i| i.to_string()).collect();
+    let text_variant = format_ident!("{}", unique(&mut variants, "Text".to_string()));
+    let read_text = if mixed {
+        let rename = options.serde(quote! { rename = "#PCDATA" });
+        childre_props.push(quote! { #rename #text_variant(String) });
+        quote! { children.push(#r_children_name_ident::#text_variant(text)) }
+    } else {
+        quote! { ::sgml::typed::check_text(parent, &text)? }
+    };
+    let write_text = if mixed {
+        quote! { #r_children_name_ident::#text_variant(text) => writer.text(text), }
+    } else {
+        quote! {}
+    };
+    let write_children = if mixed || !child_idents.is_empty() {
+        quote! {
+            for child in &self.children {
+                match child {
+                    #(#r_children_name_ident::#child_idents(child) => {
+                        ::sgml::typed::TypedElement::write(child, writer, false)
+                    })*
+                    #write_text
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
 
-    let x = quote! {
-        #[derive(Debug, Clone)]
+    let unexpected = quote! {
+        return Err(::sgml::typed::BindError::UnexpectedElement {
+            parent: parent.to_string(),
+            found: name,
+        })
+    };
+    let read_start = if child_names.is_empty() {
+        quote! { #unexpected }
+    } else {
+        quote! {
+            match name.as_str() {
+                #(#child_names => children.push(
+                    #r_children_name_ident::#child_idents(events.read(parent)?)
+                ),)*
+                _ => #unexpected,
+            }
+        }
+    };
+
+    let typed = typed_element(
+        r,
+        e,
+        types,
+        quote! {
+            #[allow(unused_mut)]
+            let mut children = Vec::new();
+            loop {
+                match events.next_content()? {
+                    ::sgml::typed::Content::Text(text) => #read_text,
+                    ::sgml::typed::Content::Start(name) => #read_start,
+                    ::sgml::typed::Content::End => break,
+                }
+            }
+            Ok(Self { #attributes_init children })
+        },
+        quote! {
+            writer.start(info, &attributes, required);
+            #write_children
+            writer.end();
+        },
+        has_attributes,
+    );
+
+    let tokens = quote! {
+        #[derive(Debug, Clone, Default)]
+        #derive_serde
+        #serde_default
         pub struct #r_name_ident {
+            #attributes_field
             pub children: Vec<#r_children_name_ident>
         }
 
+        #attributes_struct
+
+        #typed
+
         #[derive(Debug, Clone)]
+        #derive_serde
         pub enum #r_children_name_ident {
             #(
             #childre_props,
             )*
         }
-
-        #(#childre_structs)*
     };
-    x
+    tokens
+}
+
+/// The elements reachable from the roots through content models, in the order they are declared
+fn reachable_elements<'a>(
+    roots: &[DocumentTypeDefinitionElement<'a>],
+    e: &'a DocumentTypeDefinition,
+) -> Vec<DocumentTypeDefinitionElement<'a>> {
+    let mut reached = HashSet::new();
+    let mut pending = roots.to_vec();
+    while let Some(r) = pending.pop() {
+        if reached.insert(r.get_name()) {
+            pending.extend(content_elements(&r, e));
+        }
+    }
+    let mut names = HashSet::new();
+    e.get_elements()
+        .filter(|r| reached.contains(&r.get_name()) && names.insert(r.get_name()))
+        .collect()
+}
+
+/// Whether `to` can be reached from `from` following the elements each struct holds by value
+fn holds(by_value: &HashMap<String, Vec<String>>, from: &str, to: &str) -> bool {
+    let mut seen = HashSet::new();
+    let mut pending = vec![from];
+    while let Some(name) = pending.pop() {
+        if name == to {
+            return true;
+        }
+        if seen.insert(name) {
+            pending.extend(by_value.get(name).into_iter().flatten().map(String::as_str));
+        }
+    }
+    false
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The element to generate types from, otherwise every element no other element contains
+    pub root: Option<String>,
+    /// Derive `Serialize` and `Deserialize` with the mapping described above, on by default with
+    /// the `serde` feature. The generated code then needs the `serde` crate
+    pub serde: bool,
+}
+
+// `serde` follows the feature, so the impl is only derivable when it is off
+#[allow(clippy::derivable_impls)]
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            root: None,
+            serde: cfg!(feature = "serde"),
+        }
+    }
+}
+
+impl Options {
+    /// The derives for serde, if they are generated
+    pub(crate) fn derive_serde(&self) -> TokenStream {
+        if self.serde {
+            quote! { #[derive(::serde::Serialize, ::serde::Deserialize)] }
+        } else {
+            quote! {}
+        }
+    }
+
+    /// A serde attribute, if serde is derived
+    pub(crate) fn serde(&self, attribute: TokenStream) -> TokenStream {
+        if self.serde {
+            quote! { #[serde(#attribute)] }
+        } else {
+            quote! {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum GenerateError {
+    /// A file that couldn't be read
+    Read {
+        path: PathBuf,
+        reason: String,
+    },
+    /// A DTD that couldn't be parsed, with the line parsing stopped at
+    Parse {
+        path: PathBuf,
+        line: String,
+    },
+    Flatten(FlattenError),
+    /// The root element given in the options isn't declared
+    UndeclaredRoot(String),
+    /// The generated code isn't valid Rust, such as when a name can't be made into an identifier
+    Syntax(String),
 }
 
-#[proc_macro]
-pub fn dtd(_item: TokenStream) -> TokenStream {
-    let mut f = File::open("/mnt/Media/software/sgml/dtd_gen_example/dtd/html.dtd").unwrap();
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerateError::Read { path, reason } => {
+                write!(f, "unable to read {}: {}", path.display(), reason)
+            }
+            GenerateError::Parse { path, line } => {
+                write!(f, "unable to parse {} at '{}'", path.display(), line)
+            }
+            GenerateError::Flatten(e) => write!(f, "{}", e),
+            GenerateError::UndeclaredRoot(root) => write!(f, "no element {} is declared", root),
+            GenerateError::Syntax(reason) => {
+                write!(f, "the generated code isn't valid Rust: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GenerateError {}
+
+fn read_file(path: &Path) -> Result<String, GenerateError> {
     let mut s = String::new();
-    f.read_to_string(&mut s).unwrap();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut s))
+        .map_err(|e| GenerateError::Read {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+    Ok(s)
+}
 
-    let x = read_dtd(&s);
-    let (i, e) = x.unwrap();
+/// Read a DTD to generate types for, with every file that was read: the DTD, the catalog and the
+/// external entities. With a catalog, its external entities are resolved and its marked sections
+/// evaluated, keeping the general entities documents refer to
+pub fn load_dtd(
+    path: &Path,
+    catalog: Option<&Path>,
+) -> Result<(DocumentTypeDefinition, Vec<PathBuf>), GenerateError> {
+    let s = read_file(path)?;
+    let parse_error = |rest: &str| GenerateError::Parse {
+        path: path.to_path_buf(),
+        line: rest.lines().next().unwrap_or_default().to_string(),
+    };
+    let (i, dtd) = read_dtd(&s).map_err(|_| parse_error(&s))?;
+    if !i.trim().is_empty() {
+        return Err(parse_error(i));
+    }
 
-    let entities = &e.entities;
-    let roots = e.get_roots();
-    let root_names = roots.iter().map(|r| format_ident!("{}", r.get_name()));
+    match catalog {
+        Some(catalog_path) => {
+            let mut catalog = Catalog::default();
+            catalog
+                .load(catalog_path)
+                .map_err(|e| GenerateError::Read {
+                    path: catalog_path.to_path_buf(),
+                    reason: e.to_string(),
+                })?;
+            let base = path.parent().unwrap_or_else(|| Path::new(""));
+            let options = FlattenOptions {
+                keep_general_entities: true,
+                keep_comments: false,
+                ..FlattenOptions::default()
+            };
+            let (dtd, entities) = flatten_dtd_with_files(&dtd, base, &catalog, &options)
+                .map_err(GenerateError::Flatten)?;
+            let files = vec![path.to_path_buf(), catalog_path.to_path_buf()];
+            Ok((dtd, files.into_iter().chain(entities).collect()))
+        }
+        None => Ok((dtd, vec![path.to_path_buf()])),
+    }
+}
 
-    let mut already_generated = roots.iter().map(|r| r.get_name()).collect::<Vec<_>>();
+/// The types for the elements of a DTD as tokens, as the `dtd!` macro expands to
+pub fn generate_tokens(
+    e: &DocumentTypeDefinition,
+    options: &Options,
+) -> Result<TokenStream, GenerateError> {
+    generate_file(e, options).map(|file| file.into_token_stream())
+}
 
-    let roots = roots
+/// The generated items, each allowing the lints that names taken from the DTD and unused types
+/// would otherwise raise where they are included
+fn generate_file(
+    e: &DocumentTypeDefinition,
+    options: &Options,
+) -> Result<syn::File, GenerateError> {
+    let roots = match &options.root {
+        Some(root) => vec![e
+            .get_element_by_name(root)
+            .ok_or_else(|| GenerateError::UndeclaredRoot(root.clone()))?],
+        None => e.get_roots(),
+    };
+    let types = TypeNames::new(e);
+    let root_names = roots.iter().map(|r| types.get(&r.get_name()));
+    let root_renames = roots.iter().map(|r| {
+        let name = r.get_name();
+        options.serde(quote! { rename = #name })
+    });
+
+    // Every element is generated once, a field is boxed when its element holds the struct with
+    // the field again, as its type would otherwise have no size
+    let elements = reachable_elements(&roots, e);
+    let declared = |name: &str| e.get_element_by_name(name).map(|c| c.get_name());
+    let by_value = elements
+        .iter()
+        .map(|r| {
+            let by_value = r
+                .get_content()
+                .map(|m| held_by_value(m, &declared))
+                .unwrap_or_default();
+            (r.get_name(), by_value)
+        })
+        .collect::<HashMap<_, _>>();
+    let generated = elements
         .iter()
-        .map(|r| generate_element(r, &entities, &e, &mut already_generated))
+        .map(|r| {
+            let name = r.get_name();
+            let boxed = |child: &str| holds(&by_value, child, &name);
+            generate_element(r, e, &types, &boxed, options)
+        })
         .collect::<Vec<_>>();
 
+    let derive_serde = options.derive_serde();
     let root_struct = quote! {
         #[derive(Debug, Clone)]
+        #derive_serde
         pub enum Root {
             #(
-            #root_names(#root_names),
+            #root_renames #root_names(#root_names),
             )*
         }
     };
 
-    let a = quote! {
-        #(#roots)*
+    // Documents are parsed with the DTD as printed, which reads back as the same declarations
+    let text = print_dtd(e, &PrintOptions::default());
+    let tokens = quote! {
+        /// The DTD the types were generated from, parsed when it is first used
+        pub fn document_type_definition() -> &'static ::sgml::dtd::DocumentTypeDefinition {
+            static DTD: ::std::sync::OnceLock<::sgml::dtd::DocumentTypeDefinition> =
+                ::std::sync::OnceLock::new();
+            DTD.get_or_init(|| {
+                ::sgml::dtd::read_dtd(#text)
+                    .map(|(_, dtd)| dtd)
+                    .expect("the DTD was read when the types were generated")
+            })
+        }
+
+        #(#generated)*
         #root_struct
     };
 
-    a.into()
+    let mut file =
+        syn::parse2::<syn::File>(tokens).map_err(|e| GenerateError::Syntax(e.to_string()))?;
+    let allow = || -> syn::Attribute {
+        syn::parse_quote! {
+            #[allow(
+                dead_code,
+                non_camel_case_types,
+                clippy::derivable_impls,
+                clippy::upper_case_acronyms
+            )]
+        }
+    };
+    for item in &mut file.items {
+        let attrs = match item {
+            syn::Item::Struct(item) => &mut item.attrs,
+            syn::Item::Enum(item) => &mut item.attrs,
+            syn::Item::Impl(item) => &mut item.attrs,
+            syn::Item::Fn(item) => &mut item.attrs,
+            _ => continue,
+        };
+        attrs.insert(0, allow());
+    }
+    Ok(file)
+}
+
+/// The types for the elements of a DTD as formatted source, to be written to a file and included
+pub fn generate(e: &DocumentTypeDefinition, options: &Options) -> Result<String, GenerateError> {
+    let file = generate_file(e, options)?;
+    Ok(format!(
+        "// Generated by dtd_gen, changes are lost when it is generated again\n\n{}",
+        prettyplease::unparse(&file)
+    ))
 }
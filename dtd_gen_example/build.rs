@@ -0,0 +1,23 @@
+use dtd_gen::{generate, load_dtd, Options};
+use std::path::Path;
+
+/// Generate the types for a DTD in `dtd` as a file in `OUT_DIR`
+fn generate_file(name: &str, root: &str) {
+    let (dtd, files) = load_dtd(&Path::new("dtd").join(name).with_extension("dtd"), None).unwrap();
+    for file in files {
+        println!("cargo:rerun-if-changed={}", file.display());
+    }
+    let options = Options {
+        root: Some(root.to_string()),
+        ..Options::default()
+    };
+    let out = Path::new(&std::env::var_os("OUT_DIR").unwrap()).join(format!("{}.rs", name));
+    std::fs::write(out, generate(&dtd, &options).unwrap()).unwrap();
+}
+
+/// Generates the types for `dtd/lists.dtd` and `dtd/names.dtd` as files, where the HTML types
+/// come from `dtd!`
+fn main() {
+    generate_file("lists", "DOC");
+    generate_file("names", "DOC");
+}
@@ -1,10 +1,204 @@
-#[macro_use]
-extern crate dtd_gen;
-use dtd_gen::dtd;
+use dtd_gen_macro::dtd;
 
-dtd!();
+dtd!(
+    path = "dtd/html.dtd",
+    root = "HTML",
+    catalog = "dtd/catalog"
+);
 
 fn main() {
-    let html = LINK { children: vec![] };
+    let html = HTML::default();
     println!("{:?}", html);
 }
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    mod lists {
+        include!(concat!(env!("OUT_DIR"), "/lists.rs"));
+    }
+
+    mod names {
+        include!(concat!(env!("OUT_DIR"), "/names.rs"));
+    }
+
+    const DOCUMENT: &str = r#"<TITLE>Example &amp; test</TITLE>
+<H1>Heading</H1>
+<P>First paragraph
+<UL>
+<LI>One
+<LI>Two
+</UL>
+"#;
+
+    #[test]
+    pub fn test_round_trip() {
+        let html = HTML::from_sgml(DOCUMENT).unwrap();
+        assert_eq!(html.body.children.len(), 4);
+        assert!(matches!(&html.body.children[3], BODYChildren::UL(ul) if ul.li.len() == 2));
+
+        // Tags the parser infers are left out
+        let sgml = html.to_sgml();
+        assert_eq!(
+            sgml,
+            "<TITLE>Example &#38; test</TITLE><H1>Heading</H1>\n<P>First paragraph<UL><LI>One<LI>Two</UL>"
+        );
+        let again = HTML::from_sgml(&sgml).unwrap();
+        assert_eq!(format!("{:?}", again), format!("{:?}", html));
+
+        let xml = html.to_xml();
+        assert!(
+            xml.starts_with("<HTML VERSION=\"-//IETF//DTD HTML 2.0//EN\" SDAFORM=\"Book\"><HEAD>")
+        );
+        assert!(xml.ends_with("<LI SDAFORM=\"LItem\">Two</LI></UL></BODY></HTML>"));
+    }
+
+    /// Attribute values are as in the JSON encoding of `sgml::document::Document`
+    #[test]
+    pub fn test_json_encoding() {
+        let input = "<TITLE>T</TITLE><PRE WIDTH=40>x</PRE><P><IMG SRC=a.png ALT=a ISMAP ALIGN=top>";
+        let typed = serde_json::to_value(HTML::from_sgml(input).unwrap()).unwrap();
+        let document = sgml::document::parse_document(input, document_type_definition());
+        assert_eq!(document.errors, vec![]);
+        let document = serde_json::to_value(&document).unwrap();
+
+        let body = &document["content"][1]["content"];
+        let pairs = [
+            (&body[0], &typed["BODY"]["children"][0]["PRE"]),
+            (
+                &body[1]["content"][0],
+                &typed["BODY"]["children"][1]["P"]["children"][0]["IMG"],
+            ),
+        ];
+        for (element, typed) in pairs {
+            let attributes = element["attributes"].as_object().unwrap();
+            assert!(!attributes.is_empty(), "{:#}", element);
+            for (name, value) in attributes {
+                assert_eq!(&typed["attributes"][name], value, "{}", name);
+            }
+        }
+        assert_eq!(body[0]["attributes"]["WIDTH"], 40);
+    }
+
+    #[test]
+    pub fn test_shared_and_recursive_elements() {
+        use lists::*;
+
+        let doc = DOC::from_sgml(
+            "<DOC><NOTE>A <EM>note</EM> <NOTE>inside</NOTE></NOTE>\
+             <LIST><ITEM>One <EM>em</EM><LIST><ITEM>Two</LIST></LIST></DOC>",
+        )
+        .unwrap();
+        // EM is in both enums, wherever it was reached first
+        assert!(matches!(&doc.note.children[1], NOTEChildren::EM(_)));
+        assert!(matches!(&doc.list.item.children[1], ITEMChildren::EM(_)));
+        assert!(matches!(&doc.note.children[3], NOTEChildren::NOTE(_)));
+        let inner: &Option<Box<LIST>> = &doc.list.list;
+        assert!(inner.as_ref().is_some_and(|l| l.list.is_none()));
+
+        assert_eq!(
+            doc.to_sgml(),
+            "<DOC><NOTE>A <EM>note</EM> <NOTE>inside</NOTE></NOTE>\
+             <LIST><ITEM>One <EM>em</EM><LIST><ITEM>Two</LIST></LIST></DOC>"
+        );
+    }
+
+    #[test]
+    pub fn test_json() {
+        use lists::*;
+
+        let doc = DOC::from_sgml("<DOC><NOTE>A <EM>note</EM></NOTE><LIST><ITEM>One</LIST></DOC>")
+            .unwrap();
+        let json = serde_json::to_value(&doc).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "NOTE": {"children": [{"#PCDATA": "A "}, {"EM": {"children": [{"#PCDATA": "note"}]}}]},
+                "LIST": {"ITEM": {"children": [{"#PCDATA": "One"}]}, "LIST": null}
+            })
+        );
+        let again: DOC = serde_json::from_value(json).unwrap();
+        assert_eq!(format!("{:?}", again), format!("{:?}", doc));
+
+        // Attributes are keyed by their declared names, leaving out those not given
+        let html = HTML::from_sgml(DOCUMENT).unwrap();
+        let json = serde_json::to_value(&html).unwrap();
+        assert_eq!(json["attributes"]["VERSION"], "-//IETF//DTD HTML 2.0//EN");
+        assert_eq!(
+            json["BODY"]["children"][2]["P"]["children"][0]["#PCDATA"],
+            "First paragraph"
+        );
+        let again: HTML = serde_json::from_value(json).unwrap();
+        assert_eq!(again.to_sgml(), html.to_sgml());
+    }
+
+    #[test]
+    pub fn test_folded_names() {
+        use names::*;
+
+        // Names that fold to the same identifier are numbered
+        let doc = DOC::from_sgml(r#"<DOC X-Y="1" X.Y="2" KIND="A-" KIND-="B">"#).unwrap();
+        assert_eq!(doc.attributes.x_y.as_deref(), Some("1"));
+        assert_eq!(doc.attributes.x_y_2.as_deref(), Some("2"));
+        assert_eq!(doc.attributes.kind, DOCKind::A_2);
+        assert_eq!(doc.attributes.kind_2, DOCKind_2::B);
+        assert_eq!(
+            doc.to_sgml(),
+            r#"<DOC X-Y="1" X.Y="2" KIND="A-" KIND-="B"></DOC>"#
+        );
+
+        // An element named Text keeps its variant, character data takes another
+        let doc = DOC::from_sgml("<DOC>a<Text>b</Text></DOC>").unwrap();
+        assert!(matches!(&doc.children[0], DOCChildren::Text_2(t) if t == "a"));
+        assert!(matches!(&doc.children[1], DOCChildren::Text(_)));
+        assert_eq!(doc.to_sgml(), "<DOC>a<Text>b</Text></DOC>");
+
+        // Element types are named apart from each other, keeping the declared names for JSON
+        let doc = DOC::from_sgml(
+            "<DOC><LIST><LIST-ITEM>a<LIST-ITEM>b</LIST><LIST><type>c</LIST>\
+             <LIST.ITEM>d<Root></DOC>",
+        )
+        .unwrap();
+        let content = |n: usize| match &doc.children[n] {
+            DOCChildren::LIST(list) => Some(&list.content),
+            _ => None,
+        };
+        assert!(matches!(content(0), Some(LISTContent::LIST_ITEM(items)) if items.len() == 2));
+        assert!(matches!(content(1), Some(LISTContent::type_(_))));
+        assert!(matches!(&doc.children[2], DOCChildren::LIST_ITEM_2(_)));
+        assert!(matches!(&doc.children[3], DOCChildren::Root_2(_)));
+        let json = serde_json::to_value(&doc).unwrap();
+        assert_eq!(
+            json["children"][0]["LIST"]["content"]["LIST-ITEM"][1]["children"][0]["#PCDATA"],
+            "b"
+        );
+        assert_eq!(
+            json["children"][1]["LIST"]["content"]["type"]["children"][0]["#PCDATA"],
+            "c"
+        );
+        assert!(json["children"][2]["LIST.ITEM"].is_object());
+        let again: DOC = serde_json::from_value(json).unwrap();
+        assert_eq!(again.to_sgml(), doc.to_sgml());
+
+        // The types of attributes give way to elements with the same names
+        let doc = DOC::from_sgml(
+            "<DOC><LIST SHAPE=SQUARE><type>a</LIST><LISTShape><LISTAttributes></DOC>",
+        )
+        .unwrap();
+        let list = match &doc.children[0] {
+            DOCChildren::LIST(list) => list,
+            _ => panic!("{:?}", doc),
+        };
+        let attributes: &LISTAttributes_2 = &list.attributes;
+        assert_eq!(attributes.shape, LISTShape_2::Square);
+        assert!(matches!(
+            &doc.children[1],
+            DOCChildren::LISTShape(LISTShape)
+        ));
+        assert!(matches!(
+            &doc.children[2],
+            DOCChildren::LISTAttributes(LISTAttributes)
+        ));
+    }
+}
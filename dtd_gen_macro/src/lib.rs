@@ -0,0 +1,151 @@
+#![crate_type = "proc-macro"]
+extern crate proc_macro;
+use dtd_gen::{generate_tokens, load_dtd, Options};
+use proc_macro::TokenStream;
+use proc_macro2::{Literal, Span, TokenTree};
+use std::path::{Path, PathBuf};
+
+#[macro_use]
+extern crate quote;
+
+/// The arguments of `dtd!`, as `name = "value"` pairs separated by commas
+struct Arguments {
+    /// The DTD to generate types for
+    path: PathBuf,
+    /// The element to generate types from, otherwise every element no other element contains
+    root: Option<String>,
+    /// A catalog used to resolve the external entities of the DTD, which is flattened when one is given
+    catalog: Option<PathBuf>,
+}
+
+/// An error to report at the given place in the arguments
+struct ArgumentError(Span, String);
+
+impl ArgumentError {
+    fn to_compile_error(&self) -> proc_macro2::TokenStream {
+        let message = &self.1;
+        quote_spanned! { self.0=> compile_error!(#message); }
+    }
+}
+
+/// The value of a string literal, such as `"dtd/html.dtd"` or `r"dtd\html.dtd"`
+fn string_value(literal: Literal) -> Option<String> {
+    syn::parse2::<syn::LitStr>(TokenTree::Literal(literal).into())
+        .ok()
+        .map(|s| s.value())
+}
+
+fn parse_arguments(input: proc_macro2::TokenStream) -> Result<Arguments, ArgumentError> {
+    let mut path = None;
+    let mut root = None;
+    let mut catalog = None;
+
+    let mut tokens = input.into_iter();
+    while let Some(token) = tokens.next() {
+        let name = match token {
+            TokenTree::Ident(name) => name,
+            t => {
+                return Err(ArgumentError(
+                    t.span(),
+                    "expected an argument name".to_string(),
+                ))
+            }
+        };
+        match tokens.next() {
+            Some(TokenTree::Punct(p)) if p.as_char() == '=' => {}
+            _ => {
+                return Err(ArgumentError(
+                    name.span(),
+                    format!("expected `{} = \"...\"`", name),
+                ))
+            }
+        }
+        let value = match tokens.next() {
+            Some(TokenTree::Literal(l)) => {
+                let span = l.span();
+                match string_value(l) {
+                    Some(value) => value,
+                    None => return Err(ArgumentError(span, "expected a string".to_string())),
+                }
+            }
+            _ => return Err(ArgumentError(name.span(), "expected a string".to_string())),
+        };
+        match name.to_string().as_str() {
+            "path" => path = Some(PathBuf::from(value)),
+            "root" => root = Some(value),
+            "catalog" => catalog = Some(PathBuf::from(value)),
+            n => {
+                return Err(ArgumentError(
+                    name.span(),
+                    format!("unknown argument `{}`, expected path, root or catalog", n),
+                ))
+            }
+        }
+        match tokens.next() {
+            None => break,
+            Some(TokenTree::Punct(p)) if p.as_char() == ',' => {}
+            Some(t) => return Err(ArgumentError(t.span(), "expected `,`".to_string())),
+        }
+    }
+
+    let path = path.ok_or_else(|| {
+        ArgumentError(
+            Span::call_site(),
+            "missing argument, expected `dtd!(path = \"...\")`".to_string(),
+        )
+    })?;
+    Ok(Arguments {
+        path,
+        root,
+        catalog,
+    })
+}
+
+/// Resolve a path given to the macro against the crate that invokes it
+fn resolve(path: &Path) -> PathBuf {
+    match std::env::var_os("CARGO_MANIFEST_DIR") {
+        Some(dir) => Path::new(&dir).join(path),
+        None => path.to_path_buf(),
+    }
+}
+
+fn generate(arguments: &Arguments) -> Result<proc_macro2::TokenStream, String> {
+    let path = resolve(&arguments.path);
+    let catalog = arguments.catalog.as_ref().map(|c| resolve(c));
+    let (dtd, files) = load_dtd(&path, catalog.as_deref()).map_err(|e| e.to_string())?;
+    let options = Options {
+        root: arguments.root.clone(),
+        ..Options::default()
+    };
+    let tokens = generate_tokens(&dtd, &options).map_err(|e| e.to_string())?;
+
+    // Rebuild when the DTD, the catalog or an entity they refer to changes
+    let dependencies = files.iter().map(|p| p.display().to_string());
+    Ok(quote! {
+        #(const _: &[u8] = include_bytes!(#dependencies);)*
+        #tokens
+    })
+}
+
+/// Generate types for the elements of a DTD
+///
+/// `dtd!(path = "dtd/html.dtd", root = "HTML", catalog = "catalog")`, where paths are relative to
+/// the directory of the invoking crate's Cargo.toml. Only `path` is required
+///
+/// Each element type gets `from_sgml`, `from_events`, `to_sgml` and `to_xml`, which use the `sgml`
+/// crate, so the invoking crate depends on it as well. `dtd_gen::generate` writes the same code
+/// out as a file, such as from a build script
+#[proc_macro]
+pub fn dtd(item: TokenStream) -> TokenStream {
+    let arguments = match parse_arguments(item.into()) {
+        Ok(arguments) => arguments,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    match generate(&arguments) {
+        Ok(tokens) => tokens.into(),
+        Err(message) => ArgumentError(Span::call_site(), message)
+            .to_compile_error()
+            .into(),
+    }
+}
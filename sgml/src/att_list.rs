@@ -1,8 +1,19 @@
+use crate::borrowed;
+use crate::content_model::{parse_keyword, parse_name, take_separators};
 use crate::dtd::{take_until_whitespace, take_whitespace, MARKUP_DECLARATION_OPEN};
-use crate::template_strings::{parse_string, TemplateString};
+use crate::element::{
+    parse_borrowed_name_group_string, parse_element_name_group, ElementName, GROUP_CLOSE,
+    GROUP_OPEN,
+};
+use crate::entity::Entity;
+use crate::template_strings::{parse_borrowed_string, TemplateString};
+use nom::branch::alt;
 use nom::bytes::complete::{tag, tag_no_case, take_until};
-use nom::multi::many1;
+use nom::combinator::map;
+use nom::multi::{many0, many1, separated_list1};
+use nom::sequence::{delimited, terminated};
 use nom::IResult;
+use std::fmt;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct ATTListElement {
@@ -11,38 +22,274 @@ pub struct ATTListElement {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ATTList {
-    pub name: String,
+    pub name: TemplateString,
     //TODO: can't parse without custom string with expand on demand references or will be vulnerable to billion laughs
     // pub elements: Vec<ATTListElement>
     pub value: TemplateString,
 }
 
 pub fn parse_att_list(i: &str) -> IResult<&str, ATTList> {
+    let (i, att_list) = parse_borrowed_att_list(i)?;
+    Ok((i, att_list.into_owned()))
+}
+
+pub fn parse_borrowed_att_list(i: &str) -> IResult<&str, borrowed::ATTList<'_>> {
     let (i, _) = tag(MARKUP_DECLARATION_OPEN)(i)?;
     let (i, _) = tag_no_case("ATTLIST")(i)?;
 
     let (i, _) = take_whitespace(i)?;
-    let (i, name) = take_until_whitespace(i)?;
+    let (i, name) = match parse_borrowed_name_group_string(i) {
+        Ok((i, name_group)) => (i, name_group),
+        Err(_) => {
+            let (i, name) = take_until_whitespace(i)?;
+            (i, name.into())
+        }
+    };
     let (i, _) = take_whitespace(i)?;
     //Todo; loop
 
     // let (i, elements) = many1(parse_att_list_element)(i)?;
 
-    let (i, value) = parse_string(i, '>', true)?;
+    let (i, value) = parse_borrowed_string(i, '>', true)?;
 
     // let (i, _) = tag(">")(i)?;
 
     Ok((
         i,
-        ATTList {
-            name: name.to_string(),
+        borrowed::ATTList {
+            name,
             value: value,
             // elements
         },
     ))
 }
 
+impl ATTList {
+    /// The parsed attribute definitions, if the references can be resolved and the list is well formed
+    pub fn get_definitions(&self, entities: &[Entity]) -> Option<Vec<AttributeDefinition>> {
+        let expanded = self.value.try_expand(entities).ok()?;
+        match parse_attribute_definitions(&expanded) {
+            Ok(("", definitions)) => Some(definitions),
+            _ => None,
+        }
+    }
+
+    /// The element(s) this list applies to
+    pub fn get_name(&self, entities: &[Entity]) -> ElementName {
+        let expanded_name = self.name.expand(entities);
+
+        if let Ok((_, elements)) = parse_element_name_group(expanded_name.as_str()) {
+            ElementName::Group(elements)
+        } else {
+            ElementName::Single(expanded_name.trim().to_string())
+        }
+    }
+}
+
+/// See ISO(B.4.3.3)
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeclaredValue {
+    CData,
+    Entity,
+    Entities,
+    Id,
+    IdRef,
+    IdRefs,
+    Name,
+    Names,
+    NmToken,
+    NmTokens,
+    Number,
+    Numbers,
+    NuToken,
+    NuTokens,
+    Notation(Vec<String>),
+    NameTokenGroup(Vec<String>),
+}
+
+const DECLARED_VALUES: [(&str, DeclaredValue); 14] = [
+    ("CDATA", DeclaredValue::CData),
+    ("ENTITY", DeclaredValue::Entity),
+    ("ENTITIES", DeclaredValue::Entities),
+    ("ID", DeclaredValue::Id),
+    ("IDREF", DeclaredValue::IdRef),
+    ("IDREFS", DeclaredValue::IdRefs),
+    ("NAME", DeclaredValue::Name),
+    ("NAMES", DeclaredValue::Names),
+    ("NMTOKEN", DeclaredValue::NmToken),
+    ("NMTOKENS", DeclaredValue::NmTokens),
+    ("NUMBER", DeclaredValue::Number),
+    ("NUMBERS", DeclaredValue::Numbers),
+    ("NUTOKEN", DeclaredValue::NuToken),
+    ("NUTOKENS", DeclaredValue::NuTokens),
+];
+
+impl DeclaredValue {
+    /// The keyword for this declared value, None for groups
+    pub fn keyword(&self) -> Option<&'static str> {
+        DECLARED_VALUES
+            .iter()
+            .find(|(_, v)| v == self)
+            .map(|(k, _)| *k)
+    }
+}
+
+impl fmt::Display for DeclaredValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeclaredValue::Notation(g) => write!(f, "NOTATION ({})", g.join(" | ")),
+            DeclaredValue::NameTokenGroup(g) => write!(f, "({})", g.join(" | ")),
+            v => f.write_str(v.keyword().unwrap()),
+        }
+    }
+}
+
+/// See ISO(B.4.3.4)
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DefaultValue {
+    /// A value that must always be used, as written in the declaration
+    Fixed(String),
+    /// A default value, as written in the declaration without quotes
+    Value(String),
+    Required,
+    Current,
+    ConRef,
+    Implied,
+}
+
+impl fmt::Display for DefaultValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let literal = |v: &str| {
+            if v.contains('"') {
+                format!("'{}'", v)
+            } else {
+                format!("\"{}\"", v)
+            }
+        };
+        match self {
+            DefaultValue::Fixed(v) => write!(f, "#FIXED {}", literal(v)),
+            DefaultValue::Value(v) => f.write_str(&literal(v)),
+            DefaultValue::Required => f.write_str("#REQUIRED"),
+            DefaultValue::Current => f.write_str("#CURRENT"),
+            DefaultValue::ConRef => f.write_str("#CONREF"),
+            DefaultValue::Implied => f.write_str("#IMPLIED"),
+        }
+    }
+}
+
+/// See ISO(B.4.3.1)
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AttributeDefinition {
+    pub name: String,
+    pub declared_value: DeclaredValue,
+    pub default_value: DefaultValue,
+}
+
+impl fmt::Display for AttributeDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.name, self.declared_value, self.default_value
+        )
+    }
+}
+
+/// A group of names or name tokens, See ISO(B.4.3.3)
+fn parse_token_group(i: &str) -> IResult<&str, Vec<String>> {
+    let separator = |i| {
+        let (i, _) = take_separators(i)?;
+        let (i, c) = alt((tag("|"), tag(","), tag("&")))(i)?;
+        let (i, _) = take_separators(i)?;
+        Ok((i, c))
+    };
+    let (i, _) = tag(GROUP_OPEN)(i)?;
+    let (i, _) = take_separators(i)?;
+    let (i, tokens) = separated_list1(separator, parse_name)(i)?;
+    let (i, _) = take_separators(i)?;
+    let (i, _) = tag(GROUP_CLOSE)(i)?;
+    Ok((i, tokens.iter().map(|t| t.to_string()).collect()))
+}
+
+fn parse_declared_value(i: &str) -> IResult<&str, DeclaredValue> {
+    if let Ok((i, group)) = parse_token_group(i) {
+        return Ok((i, DeclaredValue::NameTokenGroup(group)));
+    }
+    let (i, keyword) = parse_name(i)?;
+    if keyword.eq_ignore_ascii_case("NOTATION") {
+        let (i, _) = take_separators(i)?;
+        let (i, group) = parse_token_group(i)?;
+        return Ok((i, DeclaredValue::Notation(group)));
+    }
+    match DECLARED_VALUES
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(keyword))
+    {
+        Some((_, value)) => Ok((i, value.clone())),
+        None => Err(nom::Err::Error(nom::error::make_error(
+            i,
+            nom::error::ErrorKind::Tag,
+        ))),
+    }
+}
+
+/// See ISO(B.7.1)
+fn parse_attribute_value_literal(i: &str) -> IResult<&str, &str> {
+    alt((
+        delimited(tag("\""), take_until("\""), tag("\"")),
+        delimited(tag("'"), take_until("'"), tag("'")),
+    ))(i)
+}
+
+fn parse_attribute_value(i: &str) -> IResult<&str, String> {
+    let (i, value) = alt((parse_attribute_value_literal, parse_name))(i)?;
+    Ok((i, value.to_string()))
+}
+
+fn parse_default_value(i: &str) -> IResult<&str, DefaultValue> {
+    if let Ok((i, _)) = parse_keyword("#FIXED")(i) {
+        let (i, _) = take_separators(i)?;
+        let (i, value) = parse_attribute_value(i)?;
+        return Ok((i, DefaultValue::Fixed(value)));
+    }
+    alt((
+        map(parse_keyword("#REQUIRED"), |_| DefaultValue::Required),
+        map(parse_keyword("#CURRENT"), |_| DefaultValue::Current),
+        map(parse_keyword("#CONREF"), |_| DefaultValue::ConRef),
+        map(parse_keyword("#IMPLIED"), |_| DefaultValue::Implied),
+        map(parse_attribute_value, DefaultValue::Value),
+    ))(i)
+}
+
+/// See ISO(B.4.3.1)
+pub fn parse_attribute_definition(i: &str) -> IResult<&str, AttributeDefinition> {
+    let (i, name) = parse_name(i)?;
+    let (i, _) = take_separators(i)?;
+    let (i, declared_value) = parse_declared_value(i)?;
+    let (i, _) = take_separators(i)?;
+    let (i, default_value) = parse_default_value(i)?;
+
+    Ok((
+        i,
+        AttributeDefinition {
+            name: name.to_string(),
+            declared_value,
+            default_value,
+        },
+    ))
+}
+
+/// Parse the expanded definition list of an ATTLIST declaration
+pub fn parse_attribute_definitions(i: &str) -> IResult<&str, Vec<AttributeDefinition>> {
+    let (i, _) = take_separators(i)?;
+    many0(terminated(parse_attribute_definition, take_separators))(i)
+}
+
 pub fn parse_att_list_element(i: &str) -> IResult<&str, ATTListElement> {
     let (i, att_name) = take_until_whitespace(i)?;
     let (i, _) = take_whitespace(i)?;
@@ -60,7 +307,9 @@ pub fn parse_att_list_element(i: &str) -> IResult<&str, ATTListElement> {
 
 #[cfg(test)]
 pub mod test {
-    use crate::att_list::{parse_att_list, ATTListElement};
+    use crate::att_list::{parse_att_list, ATTListElement, DeclaredValue, DefaultValue};
+    use crate::element::ElementName;
+    use crate::entity::parse_entity;
 
     #[test]
     pub fn test_att_list() {
@@ -72,10 +321,63 @@ pub mod test {
         println!("{:?}", x);
         let (i, e) = x.unwrap();
         assert_eq!(i, "");
-        assert_eq!(e.name, "BR");
+        assert_eq!(e.name, "BR".into());
         // assert_eq!(e.elements.first(), Some(&ATTListElement {
         //     name: "%SDAPREF;".to_string(),
         //     value: "\"&#RE;\"".to_string(),
         // }));
     }
+
+    #[test]
+    pub fn test_att_list_group() {
+        let x = parse_att_list(
+            "<!ATTLIST ( B | STRONG )
+        %SDAFORM; \"B\"
+        >",
+        );
+        println!("{:?}", x);
+        let (i, e) = x.unwrap();
+        assert_eq!(i, "");
+        assert!(matches!(
+            e.get_name(&[]),
+            ElementName::Group(g) if g == vec!["B".to_string(), "STRONG".to_string()]
+        ));
+    }
+
+    #[test]
+    pub fn test_attribute_definitions() {
+        let (_, sdaform) = parse_entity("<!ENTITY % SDAFORM  \"SDAFORM  CDATA  #FIXED\">").unwrap();
+        let (i, e) = parse_att_list(
+            "<!ATTLIST IMG
+        SRC CDATA  #REQUIRED
+        ALIGN (top|middle|bottom) #IMPLIED -- where to put it --
+        WIDTH NUMBER #implied
+        TYPE (TEXT | PASSWORD) TEXT
+        %SDAFORM; \"Fig\"
+        >",
+        )
+        .unwrap();
+        assert_eq!(i, "");
+
+        let definitions = e.get_definitions(&[sdaform]).unwrap();
+        assert_eq!(definitions.len(), 5);
+        assert_eq!(definitions[0].name, "SRC");
+        assert_eq!(definitions[0].declared_value, DeclaredValue::CData);
+        assert_eq!(definitions[0].default_value, DefaultValue::Required);
+        assert_eq!(
+            definitions[1].declared_value,
+            DeclaredValue::NameTokenGroup(vec![
+                "top".to_string(),
+                "middle".to_string(),
+                "bottom".to_string()
+            ])
+        );
+        assert_eq!(definitions[2].declared_value, DeclaredValue::Number);
+        assert_eq!(definitions[2].default_value, DefaultValue::Implied);
+        assert_eq!(
+            definitions[3].default_value,
+            DefaultValue::Value("TEXT".to_string())
+        );
+        assert_eq!(definitions[4].to_string(), "SDAFORM CDATA #FIXED \"Fig\"");
+    }
 }
@@ -0,0 +1,507 @@
+use crate::content_model::{Connector, ContentToken, ModelGroup, Occurrence};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+/// The token that stands for character data in a content model, See ISO(B.4.2.5)
+pub const PCDATA: &str = "#PCDATA";
+
+/// A content model as a regular expression over element names, matched one token at a time by
+/// taking derivatives. The expression left after matching some content is the state of the model,
+/// so it stays small and `&` groups need no expansion into every order.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Expression {
+    /// Matches only the end of the content
+    Empty,
+    /// Matches nothing, the content so far is not allowed
+    Fail,
+    /// Matches any content, for elements declared ANY
+    Any,
+    /// An element name, or `PCDATA`
+    Token(String),
+    Sequence(Vec<Expression>),
+    Or(Vec<Expression>),
+    /// All of the expressions, each once and whole, in any order. Once a member has started, the
+    /// state is the rest of that member followed by the `&` group of those not yet seen
+    And(Vec<Expression>),
+    Optional(Box<Expression>),
+    ZeroOrMore(Box<Expression>),
+    OneOrMore(Box<Expression>),
+}
+
+impl Expression {
+    /// The expression for a model group, with element names passed through `fold`
+    pub fn from_group(group: &ModelGroup, fold: &impl Fn(&str) -> String) -> Self {
+        let tokens = group
+            .tokens
+            .iter()
+            .map(|t| match t {
+                // Any number of characters, so data may also go on after an inclusion
+                ContentToken::PcData => {
+                    Expression::ZeroOrMore(Box::new(Expression::Token(PCDATA.to_string())))
+                }
+                ContentToken::Element(name, occurrence) => {
+                    Self::occurring(Expression::Token(fold(name)), *occurrence)
+                }
+                ContentToken::Group(g) => Self::from_group(g, fold),
+            })
+            .collect::<Vec<_>>();
+        let expression = match group.connector {
+            Connector::Sequence => Self::sequence(tokens),
+            Connector::Or => Self::or(tokens),
+            Connector::And => Self::and(tokens),
+        };
+        Self::occurring(expression, group.occurrence)
+    }
+
+    fn occurring(expression: Expression, occurrence: Occurrence) -> Self {
+        match occurrence {
+            Occurrence::Once => expression,
+            Occurrence::Optional => Expression::Optional(Box::new(expression)),
+            Occurrence::OneOrMore => Expression::OneOrMore(Box::new(expression)),
+            Occurrence::ZeroOrMore => Expression::ZeroOrMore(Box::new(expression)),
+        }
+    }
+
+    fn sequence(expressions: Vec<Expression>) -> Self {
+        let mut items = Vec::new();
+        for e in expressions {
+            match e {
+                Expression::Fail => return Expression::Fail,
+                Expression::Empty => {}
+                Expression::Sequence(inner) => items.extend(inner),
+                e => items.push(e),
+            }
+        }
+        match items.len() {
+            0 => Expression::Empty,
+            1 => items.pop().unwrap(),
+            _ => Expression::Sequence(items),
+        }
+    }
+
+    fn or(expressions: Vec<Expression>) -> Self {
+        let mut items = Vec::new();
+        for e in expressions {
+            let inner = match e {
+                Expression::Fail => continue,
+                Expression::Or(inner) => inner,
+                e => vec![e],
+            };
+            for e in inner {
+                if !items.contains(&e) {
+                    items.push(e);
+                }
+            }
+        }
+        items.sort();
+        match items.len() {
+            0 => Expression::Fail,
+            1 => items.pop().unwrap(),
+            _ => Expression::Or(items),
+        }
+    }
+
+    fn and(expressions: Vec<Expression>) -> Self {
+        let mut items = Vec::new();
+        for e in expressions {
+            match e {
+                Expression::Fail => return Expression::Fail,
+                Expression::Empty => {}
+                e => items.push(e),
+            }
+        }
+        match items.len() {
+            0 => Expression::Empty,
+            1 => items.pop().unwrap(),
+            _ => Expression::And(items),
+        }
+    }
+
+    /// Whether the content may end here
+    pub fn is_nullable(&self) -> bool {
+        match self {
+            Expression::Empty | Expression::Any => true,
+            Expression::Fail | Expression::Token(_) => false,
+            Expression::Sequence(items) | Expression::And(items) => {
+                items.iter().all(|e| e.is_nullable())
+            }
+            Expression::Or(items) => items.iter().any(|e| e.is_nullable()),
+            Expression::Optional(_) | Expression::ZeroOrMore(_) => true,
+            Expression::OneOrMore(e) => e.is_nullable(),
+        }
+    }
+
+    /// The state after `token`, which is `Fail` if the token isn't allowed here
+    pub fn derive(&self, token: &str) -> Expression {
+        match self {
+            Expression::Empty | Expression::Fail => Expression::Fail,
+            Expression::Any => Expression::Any,
+            Expression::Token(name) if name == token => Expression::Empty,
+            Expression::Token(_) => Expression::Fail,
+            Expression::Sequence(items) => {
+                let rest = Self::sequence(items[1..].to_vec());
+                let first = Self::sequence(vec![items[0].derive(token), rest.clone()]);
+                if items[0].is_nullable() {
+                    Self::or(vec![first, rest.derive(token)])
+                } else {
+                    first
+                }
+            }
+            Expression::Or(items) => Self::or(items.iter().map(|e| e.derive(token)).collect()),
+            // The member the token starts has to end before any of the others may start
+            Expression::And(items) => Self::or(
+                (0..items.len())
+                    .map(|n| {
+                        let mut rest = items.clone();
+                        let started = rest.remove(n).derive(token);
+                        Self::sequence(vec![started, Self::and(rest)])
+                    })
+                    .collect(),
+            ),
+            Expression::Optional(e) => e.derive(token),
+            Expression::ZeroOrMore(e) | Expression::OneOrMore(e) => {
+                Self::sequence(vec![e.derive(token), Expression::ZeroOrMore(e.clone())])
+            }
+        }
+    }
+
+    /// Whether `token` may come next
+    pub fn allows(&self, token: &str) -> bool {
+        self.derive(token) != Expression::Fail
+    }
+
+    /// The tokens that may come next, in model order. Empty for ANY, which allows every token
+    pub fn get_first(&self) -> Vec<String> {
+        let mut first = Vec::new();
+        self.collect_first(&mut first);
+        first
+    }
+
+    fn collect_first(&self, first: &mut Vec<String>) {
+        match self {
+            Expression::Empty | Expression::Fail | Expression::Any => {}
+            Expression::Token(name) => {
+                if !first.contains(name) {
+                    first.push(name.clone());
+                }
+            }
+            Expression::Sequence(items) => {
+                for e in items {
+                    e.collect_first(first);
+                    if !e.is_nullable() {
+                        break;
+                    }
+                }
+            }
+            Expression::Or(items) | Expression::And(items) => {
+                items.iter().for_each(|e| e.collect_first(first))
+            }
+            Expression::Optional(e) | Expression::ZeroOrMore(e) | Expression::OneOrMore(e) => {
+                e.collect_first(first)
+            }
+        }
+    }
+
+    /// Every token in the expression, in model order
+    fn collect_tokens(&self, tokens: &mut Vec<String>) {
+        match self {
+            Expression::Empty | Expression::Fail | Expression::Any => {}
+            Expression::Token(name) => {
+                if !tokens.contains(name) {
+                    tokens.push(name.clone());
+                }
+            }
+            Expression::Sequence(items) | Expression::Or(items) | Expression::And(items) => {
+                items.iter().for_each(|e| e.collect_tokens(tokens))
+            }
+            Expression::Optional(e) | Expression::ZeroOrMore(e) | Expression::OneOrMore(e) => {
+                e.collect_tokens(tokens)
+            }
+        }
+    }
+}
+
+/// A content model compiled to a deterministic automaton, whose states are the derivatives of its
+/// expression. There are only so many of them, as `or` keeps alternatives sorted and without
+/// duplicates
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Automaton {
+    states: Vec<State>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum State {
+    Compiled {
+        /// The state after each token allowed here, in model order
+        transitions: Vec<(String, usize)>,
+        accepting: bool,
+    },
+    /// Every token is allowed, as in ANY content
+    Any,
+}
+
+impl Automaton {
+    /// The state before any content
+    pub const START: usize = 0;
+
+    pub fn new(expression: &Expression) -> Self {
+        let mut alphabet = Vec::new();
+        expression.collect_tokens(&mut alphabet);
+
+        let mut expressions = vec![expression.clone()];
+        let mut index = HashMap::new();
+        index.insert(expression.clone(), Self::START);
+        let mut states = Vec::new();
+        while states.len() < expressions.len() {
+            let e = expressions[states.len()].clone();
+            if e == Expression::Any {
+                states.push(State::Any);
+                continue;
+            }
+            let mut transitions = Vec::new();
+            for token in &alphabet {
+                let next = e.derive(token);
+                if next == Expression::Fail {
+                    continue;
+                }
+                let n = *index.entry(next.clone()).or_insert_with(|| {
+                    expressions.push(next);
+                    expressions.len() - 1
+                });
+                transitions.push((token.clone(), n));
+            }
+            states.push(State::Compiled {
+                transitions,
+                accepting: e.is_nullable(),
+            });
+        }
+        Self { states }
+    }
+
+    /// The state after `token`, if it is allowed in `state`
+    pub fn next(&self, state: usize, token: &str) -> Option<usize> {
+        match &self.states[state] {
+            State::Compiled { transitions, .. } => transitions
+                .iter()
+                .find(|(t, _)| t == token)
+                .map(|(_, n)| *n),
+            State::Any => Some(state),
+        }
+    }
+
+    pub fn allows(&self, state: usize, token: &str) -> bool {
+        self.next(state, token).is_some()
+    }
+
+    /// Whether the content may end in `state`
+    pub fn is_accepting(&self, state: usize) -> bool {
+        match &self.states[state] {
+            State::Compiled { accepting, .. } => *accepting,
+            State::Any => true,
+        }
+    }
+
+    /// The tokens allowed in `state`, in model order. Empty for ANY, which allows every token
+    pub fn get_first(&self, state: usize) -> Vec<String> {
+        match &self.states[state] {
+            State::Compiled { transitions, .. } => {
+                transitions.iter().map(|(t, _)| t.clone()).collect()
+            }
+            State::Any => Vec::new(),
+        }
+    }
+
+    pub fn get_state_count(&self) -> usize {
+        self.states.len()
+    }
+}
+
+/// Where a content model is ambiguous: an element that could match more than one of its primitive
+/// content tokens without looking ahead, See ISO(11.2.4.3)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Ambiguity {
+    /// The shortest content that leads to the conflict, as element names and `PCDATA`
+    pub after: Vec<String>,
+    /// The element that matches more than one token
+    pub token: String,
+}
+
+impl fmt::Display for Ambiguity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} matches more than one token of the model", self.token)?;
+        if self.after.is_empty() {
+            f.write_str(" at the start")
+        } else {
+            write!(f, " after {}", self.after.join(", "))
+        }
+    }
+}
+
+/// Separates an element name from the number of its token in a marked expression
+const MARK: char = '\u{0}';
+
+/// The first ambiguity in a model group, with element names passed through `fold`. Each
+/// primitive content token is numbered, then the derivatives of the model are searched for a
+/// state where two tokens with the same name may come next. An `&` group is ambiguous exactly
+/// when the `,` groups of its orders would be, without expanding them
+pub fn find_ambiguity(group: &ModelGroup, fold: &impl Fn(&str) -> String) -> Option<Ambiguity> {
+    let count = Cell::new(0);
+    let marked = Expression::from_group(group, &|name| {
+        count.set(count.get() + 1);
+        format!("{}{}{}", fold(name), MARK, count.get())
+    });
+    let name = |token: &str| token.split(MARK).next().unwrap_or(token).to_string();
+
+    let mut seen = HashSet::new();
+    seen.insert(marked.clone());
+    let mut pending = VecDeque::from(vec![(marked, Vec::new())]);
+    while let Some((state, after)) = pending.pop_front() {
+        let first = state.get_first();
+        let mut names: Vec<String> = Vec::new();
+        for token in &first {
+            let n = name(token);
+            if n != PCDATA && names.contains(&n) {
+                return Some(Ambiguity { after, token: n });
+            }
+            names.push(n);
+        }
+        for token in first {
+            let next = state.derive(&token);
+            if seen.insert(next.clone()) {
+                let mut path = after.clone();
+                path.push(name(&token));
+                pending.push_back((next, path));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::content_model::parse_model_group;
+
+    fn expression(model: &str) -> Expression {
+        let (_, group) = parse_model_group(model).unwrap();
+        Expression::from_group(&group, &|name| name.to_uppercase())
+    }
+
+    fn matches(e: &Expression, tokens: &[&str]) -> bool {
+        tokens
+            .iter()
+            .fold(e.clone(), |e, t| e.derive(t))
+            .is_nullable()
+    }
+
+    #[test]
+    pub fn test_derivatives() {
+        let e = expression("(head, body)");
+        assert_eq!(e.get_first(), vec!["HEAD"]);
+        assert!(matches(&e, &["HEAD", "BODY"]));
+        assert!(!matches(&e, &["HEAD"]));
+        assert!(!e.allows("BODY"));
+
+        let e = expression("(a, b?, c*)+");
+        assert!(matches(&e, &["A", "A", "B", "C", "C", "A"]));
+        assert!(!matches(&e, &["A", "B", "B"]));
+        assert_eq!(e.derive("A").get_first(), vec!["B", "C", "A"]);
+
+        let e = expression("(#PCDATA | em)*");
+        assert!(e.allows(PCDATA));
+        assert!(matches(&e, &[PCDATA, "EM", PCDATA]));
+
+        let e = expression("(#PCDATA)");
+        assert!(e.is_nullable());
+        assert!(matches(&e, &[PCDATA, PCDATA]));
+        assert!(!e.allows("EM"));
+    }
+
+    #[test]
+    pub fn test_and_groups() {
+        let e = expression("(title & isindex? & base?)");
+        assert!(!e.is_nullable());
+        assert!(matches(&e, &["BASE", "TITLE"]));
+        assert!(matches(&e, &["TITLE", "ISINDEX", "BASE"]));
+        assert!(!matches(&e, &["TITLE", "TITLE"]));
+        assert!(!matches(&e, &["ISINDEX", "BASE"]));
+        // Only the title is left to find
+        assert_eq!(
+            e.derive("BASE").derive("ISINDEX").get_first(),
+            vec!["TITLE"]
+        );
+
+        // A member is not interleaved with the others
+        let e = expression("((a, b) & c)");
+        assert!(matches(&e, &["C", "A", "B"]));
+        assert!(!matches(&e, &["A", "C", "B"]));
+        let e = expression("(a* & b)");
+        assert!(matches(&e, &["A", "A", "B"]));
+        assert!(!matches(&e, &["A", "B", "A"]));
+    }
+
+    #[test]
+    pub fn test_ambiguity() {
+        let ambiguity = |model: &str| {
+            let (_, group) = parse_model_group(model).unwrap();
+            find_ambiguity(&group, &|name| name.to_uppercase())
+                .map(|a| (a.after.join(" "), a.token))
+        };
+        let at = |after: &str, token: &str| Some((after.to_string(), token.to_string()));
+
+        assert_eq!(ambiguity("((a, b) | (a, c))"), at("", "A"));
+        assert_eq!(ambiguity("(a, (b | c))"), None);
+        assert_eq!(ambiguity("(a?, a)"), at("", "A"));
+        assert_eq!(ambiguity("(a, b?, b)"), at("A", "B"));
+        assert_eq!(ambiguity("((a | b)*, a)"), at("", "A"));
+        assert_eq!(ambiguity("(a+, a?)"), at("A", "A"));
+        assert_eq!(ambiguity("(#PCDATA | a | b)*"), None);
+
+        // An optional member of an & group may also be the token after the group
+        assert_eq!(ambiguity("((a & b?), b)"), at("A", "B"));
+        // Only once the required members are all there
+        assert_eq!(ambiguity("((a & b), b)"), None);
+        assert_eq!(ambiguity("(a & a?)"), at("", "A"));
+        assert_eq!(ambiguity("(title & isindex? & base?)"), None);
+        assert_eq!(ambiguity("((a, b) & (a, c))"), at("", "A"));
+        assert_eq!(ambiguity("((a, b) & (c, d))"), None);
+        // After C, the second group has to end before the first can start
+        assert_eq!(ambiguity("((a, b) & (c, a))"), None);
+    }
+
+    #[test]
+    pub fn test_automaton() {
+        let a = Automaton::new(&expression("(a, b?, c*)+"));
+        let state = a.next(Automaton::START, "A").unwrap();
+        assert!(a.is_accepting(state));
+        assert_eq!(a.get_first(state), vec!["A", "B", "C"]);
+        let state = a.next(state, "C").unwrap();
+        assert_eq!(a.get_first(state), vec!["A", "C"]);
+        assert_eq!(a.next(state, "C"), Some(state));
+        assert!(!a.allows(state, "B"));
+
+        // A state for each subset of an & group seen so far
+        let a = Automaton::new(&expression("(title & isindex? & base?)"));
+        assert_eq!(a.get_state_count(), 8);
+        assert!(!a.is_accepting(Automaton::START));
+        let state = a.next(Automaton::START, "BASE").unwrap();
+        assert_eq!(a.get_first(state), vec!["TITLE", "ISINDEX"]);
+
+        let a = Automaton::new(&Expression::Any);
+        assert_eq!(a.next(Automaton::START, "P"), Some(Automaton::START));
+        assert!(a.is_accepting(Automaton::START));
+
+        // The states of real DTDs stay few
+        for name in ["html.dtd", "xhtml1-strict.dtd"] {
+            let dtd = crate::document::test::read_flat_dtd(name);
+            let grammar = crate::instance::Grammar::new(&[&dtd], None);
+            let most = dtd
+                .get_elements()
+                .filter_map(|e| grammar.get_element(&e.get_name()))
+                .map(|t| t.automaton.get_state_count())
+                .max();
+            assert!(most.is_some_and(|n| n < 32), "{} {:?}", name, most);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::att_list;
+use crate::dtd::{self, parse_borrowed_dtd_element};
+use crate::element;
+use crate::entity::{self, EntityType};
+use crate::marked_section;
+use crate::template_strings::{ChainElement, TemplateString};
+use nom::multi::many0;
+
+/// A `TemplateString` whose text and references are borrowed from the source it was parsed from
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TemplateStr<'src> {
+    pub chain: Vec<ChainStr<'src>>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ChainStr<'src> {
+    String(&'src str),
+    Reference(&'src str),
+}
+
+impl<'src> TemplateStr<'src> {
+    pub fn into_owned(self) -> TemplateString {
+        TemplateString {
+            chain: self
+                .chain
+                .into_iter()
+                .map(|c| match c {
+                    ChainStr::String(s) => ChainElement::String(s.to_string()),
+                    ChainStr::Reference(r) => ChainElement::Reference(r.to_string()),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<'src> From<&'src str> for TemplateStr<'src> {
+    fn from(s: &'src str) -> Self {
+        Self {
+            chain: vec![ChainStr::String(s)],
+        }
+    }
+}
+
+/// See `entity::Entity`
+#[derive(Debug, Clone)]
+pub struct Entity<'src> {
+    pub name: &'src str,
+    pub external: bool,
+    pub parameter: bool,
+    pub public: bool,
+    pub entity_type: EntityType,
+    pub content: TemplateStr<'src>,
+    /// The system identifier that may follow a public identifier
+    pub system_id: Option<&'src str>,
+    pub comment: Option<&'src str>,
+}
+
+impl<'src> Entity<'src> {
+    pub fn into_owned(self) -> entity::Entity {
+        entity::Entity {
+            name: self.name.to_string(),
+            external: self.external,
+            parameter: self.parameter,
+            public: self.public,
+            entity_type: self.entity_type,
+            content: self.content.into_owned(),
+            system_id: self.system_id.map(str::to_string),
+            comment: self.comment.map(str::to_string),
+        }
+    }
+}
+
+/// See `entity::ParameterReference`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ParameterReference<'src> {
+    pub name: &'src str,
+}
+
+impl<'src> ParameterReference<'src> {
+    pub fn into_owned(self) -> entity::ParameterReference {
+        entity::ParameterReference {
+            name: self.name.to_string(),
+        }
+    }
+}
+
+/// See `element::Element`
+#[derive(Debug, Clone)]
+pub struct Element<'src> {
+    pub name: TemplateStr<'src>,
+    /// False when the declaration has no omitted tag minimization parameters, as in XML DTDs
+    pub omitted_tag_minimization: bool,
+    pub start_optional: bool,
+    pub end_optional: bool,
+    pub content_model: TemplateStr<'src>,
+}
+
+impl<'src> Element<'src> {
+    pub fn into_owned(self) -> element::Element {
+        element::Element {
+            name: self.name.into_owned(),
+            omitted_tag_minimization: self.omitted_tag_minimization,
+            start_optional: self.start_optional,
+            end_optional: self.end_optional,
+            content_model: self.content_model.into_owned(),
+        }
+    }
+}
+
+/// See `att_list::ATTList`
+#[derive(Debug, Clone)]
+pub struct ATTList<'src> {
+    pub name: TemplateStr<'src>,
+    pub value: TemplateStr<'src>,
+}
+
+impl<'src> ATTList<'src> {
+    pub fn into_owned(self) -> att_list::ATTList {
+        att_list::ATTList {
+            name: self.name.into_owned(),
+            value: self.value.into_owned(),
+        }
+    }
+}
+
+/// See `marked_section::MarkedSection`
+#[derive(Debug, Clone, Copy)]
+pub struct MarkedSection<'src> {
+    pub status: &'src str,
+    pub content: &'src str,
+}
+
+impl<'src> MarkedSection<'src> {
+    /// Parse the content of this section as declarations
+    pub fn get_declarations(&self) -> Vec<DTDElement<'src>> {
+        many0(parse_borrowed_dtd_element)(self.content)
+            .map(|(_, elements)| elements)
+            .unwrap_or_default()
+    }
+
+    pub fn into_owned(self) -> marked_section::MarkedSection {
+        marked_section::MarkedSection {
+            status: self.status.to_string(),
+            content: self.content.to_string(),
+        }
+    }
+}
+
+/// See `dtd::DTDElement`
+#[derive(Debug, Clone)]
+pub enum DTDElement<'src> {
+    WhiteSpace(&'src str),
+    Comment(&'src str),
+    Entity(Entity<'src>),
+    MarkedSection(MarkedSection<'src>),
+    ParameterReference(ParameterReference<'src>),
+    Element(Element<'src>),
+    ATTList(ATTList<'src>),
+}
+
+impl<'src> DTDElement<'src> {
+    pub fn into_owned(self) -> dtd::DTDElement {
+        match self {
+            DTDElement::WhiteSpace(w) => dtd::DTDElement::WhiteSpace(w.to_string()),
+            DTDElement::Comment(c) => dtd::DTDElement::Comment(c.to_string()),
+            DTDElement::Entity(e) => dtd::DTDElement::Entity(e.into_owned()),
+            DTDElement::MarkedSection(ms) => dtd::DTDElement::MarkedSection(ms.into_owned()),
+            DTDElement::ParameterReference(pr) => {
+                dtd::DTDElement::ParameterReference(pr.into_owned())
+            }
+            DTDElement::Element(e) => dtd::DTDElement::Element(e.into_owned()),
+            DTDElement::ATTList(a) => dtd::DTDElement::ATTList(a.into_owned()),
+        }
+    }
+}
+
+/// A DTD that borrows its text from the source it was parsed from, for reading large DTDs
+/// without copying every name and literal. See `dtd::read_borrowed_dtd`
+#[derive(Debug, Clone)]
+pub struct DocumentTypeDefinition<'src> {
+    /// Every declaration in source order, including comments and marked sections
+    pub declarations: Vec<DTDElement<'src>>,
+}
+
+impl<'src> DocumentTypeDefinition<'src> {
+    pub fn get_entities(&self) -> impl Iterator<Item = &Entity<'src>> {
+        self.declarations.iter().filter_map(|d| match d {
+            DTDElement::Entity(e) => Some(e),
+            _ => None,
+        })
+    }
+
+    pub fn get_elements(&self) -> impl Iterator<Item = &Element<'src>> {
+        self.declarations.iter().filter_map(|d| match d {
+            DTDElement::Element(e) => Some(e),
+            _ => None,
+        })
+    }
+
+    /// The owned DTD, copying the text out of the source
+    pub fn into_owned(self) -> dtd::DocumentTypeDefinition {
+        self.declarations
+            .into_iter()
+            .map(DTDElement::into_owned)
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+impl<'src> From<DocumentTypeDefinition<'src>> for dtd::DocumentTypeDefinition {
+    fn from(dtd: DocumentTypeDefinition<'src>) -> Self {
+        dtd.into_owned()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::borrowed::{ChainStr, DTDElement};
+    use crate::dtd::{read_borrowed_dtd, read_dtd};
+    use crate::printer::{print_dtd, PrintOptions};
+
+    #[test]
+    pub fn test_borrowed_dtd() {
+        let source = std::fs::read_to_string("./dtd/html.dtd").unwrap();
+        let (rest, dtd) = read_borrowed_dtd(&source).unwrap();
+        assert_eq!(rest, "");
+
+        // The text is borrowed straight from the source
+        let html = dtd
+            .get_elements()
+            .find(|e| e.name.chain == vec![ChainStr::String("HTML")])
+            .unwrap();
+        let ChainStr::String(content) = html.content_model.chain[0] else {
+            panic!("expected text, found {:?}", html.content_model.chain[0]);
+        };
+        assert!(source.as_bytes().as_ptr_range().contains(&content.as_ptr()));
+        assert!(dtd.get_entities().any(|e| e.name == "HTML.Recommended"));
+        assert!(dtd
+            .declarations
+            .iter()
+            .any(|d| matches!(d, DTDElement::MarkedSection(_))));
+
+        // And converts to the same DTD as parsing into the owned form
+        let (_, owned) = read_dtd(&source).unwrap();
+        let options = PrintOptions::default();
+        assert_eq!(
+            print_dtd(&dtd.into_owned(), &options),
+            print_dtd(&owned, &options)
+        );
+    }
+}
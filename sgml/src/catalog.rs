@@ -0,0 +1,246 @@
+use crate::entity::Entity;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Maps the external identifiers of entities to files
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    pub public: HashMap<String, PathBuf>,
+    pub system: HashMap<String, PathBuf>,
+    /// The DTD for documents with no external identifier, by document type name
+    pub doctype: HashMap<String, PathBuf>,
+    /// The SGML declaration for documents that don't have their own
+    pub sgml_declaration: Option<PathBuf>,
+}
+
+/// The number of parameters each catalog entry takes, See OASIS TR 9401
+const CATALOG_ENTRIES: [(&str, usize); 13] = [
+    ("PUBLIC", 2),
+    ("SYSTEM", 2),
+    ("DOCTYPE", 2),
+    ("ENTITY", 2),
+    ("LINKTYPE", 2),
+    ("NOTATION", 2),
+    ("DTDDECL", 2),
+    ("DELEGATE", 2),
+    ("SGMLDECL", 1),
+    ("DOCUMENT", 1),
+    ("CATALOG", 1),
+    ("BASE", 1),
+    ("OVERRIDE", 1),
+];
+
+/// The entries of a catalog file as keywords and their parameters, with comments dropped
+fn parse_catalog_entries(text: &str) -> Vec<(String, Vec<String>)> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(comment) = rest.strip_prefix("--") {
+            rest = comment.find("--").map_or("", |n| &comment[n + 2..]);
+            continue;
+        }
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'');
+        let (token, len) = match quote {
+            Some(quote) => match rest[1..].find(quote) {
+                Some(n) => (&rest[1..n + 1], n + 2),
+                None => (&rest[1..], rest.len()),
+            },
+            None => {
+                let n = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                (&rest[..n], n)
+            }
+        };
+        tokens.push((token.to_string(), quote.is_some()));
+        rest = &rest[len..];
+    }
+
+    let mut entries = Vec::new();
+    let mut tokens = tokens.into_iter();
+    while let Some((keyword, quoted)) = tokens.next() {
+        let keyword = keyword.to_uppercase();
+        // Anything unrecognised is skipped a token at a time until an entry starts
+        let arity = match CATALOG_ENTRIES
+            .iter()
+            .find(|(k, _)| !quoted && *k == keyword)
+        {
+            Some((_, arity)) => *arity,
+            None => continue,
+        };
+        let parameters = tokens.by_ref().take(arity).map(|(t, _)| t).collect();
+        entries.push((keyword, parameters));
+    }
+    entries
+}
+
+/// Public identifiers are compared with runs of whitespace collapsed, See ISO(B.9.2)
+pub fn normalize_public_id(id: &str) -> String {
+    id.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl Catalog {
+    pub fn add_public(&mut self, id: &str, path: PathBuf) {
+        self.public.insert(normalize_public_id(id), path);
+    }
+
+    pub fn add_system(&mut self, id: &str, path: PathBuf) {
+        self.system.insert(id.to_string(), path);
+    }
+
+    /// Add the entries of a catalog file in the SGML Open format, where the first entry for an
+    /// identifier wins. Catalogs it refers to are read after it
+    pub fn load(&mut self, path: &Path) -> io::Result<()> {
+        let text = std::fs::read_to_string(path)?;
+        let mut base = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        let mut catalogs = Vec::new();
+        for (keyword, parameters) in parse_catalog_entries(&text) {
+            let file = |n: usize| base.join(&parameters[n]);
+            match (keyword.as_str(), parameters.len()) {
+                ("PUBLIC", 2) => {
+                    let path = file(1);
+                    self.public
+                        .entry(normalize_public_id(&parameters[0]))
+                        .or_insert(path);
+                }
+                ("SYSTEM", 2) => {
+                    let path = file(1);
+                    self.system.entry(parameters[0].clone()).or_insert(path);
+                }
+                ("DOCTYPE", 2) => {
+                    let path = file(1);
+                    self.doctype.entry(parameters[0].clone()).or_insert(path);
+                }
+                ("SGMLDECL", 1) if self.sgml_declaration.is_none() => {
+                    self.sgml_declaration = Some(file(0));
+                }
+                ("CATALOG", 1) => catalogs.push(file(0)),
+                ("BASE", 1) => base = file(0),
+                _ => {}
+            }
+        }
+        for catalog in catalogs {
+            self.load(&catalog)?;
+        }
+        Ok(())
+    }
+
+    /// Add the catalogs listed in `SGML_CATALOG_FILES`, separated by colons, after those already
+    /// loaded. Files that can't be read are returned rather than stopping the rest
+    pub fn load_env(&mut self) -> Vec<(String, io::Error)> {
+        let files = std::env::var("SGML_CATALOG_FILES").unwrap_or_default();
+        files
+            .split(':')
+            .filter(|f| !f.is_empty())
+            .filter_map(|file| {
+                self.load(Path::new(file))
+                    .err()
+                    .map(|e| (file.to_string(), e))
+            })
+            .collect()
+    }
+
+    /// Find the file holding an external entity, preferring the catalog entry for its public identifier
+    /// System identifiers that aren't in the catalog are taken relative to `base`
+    pub fn locate(&self, entity: &Entity, base: &Path) -> Option<PathBuf> {
+        self.locate_external_id(
+            entity.get_public_id().as_deref(),
+            entity.get_system_id().as_deref(),
+            base,
+        )
+    }
+
+    /// Find the file for a public and system identifier, as `locate` does for entities
+    pub fn locate_external_id(
+        &self,
+        public_id: Option<&str>,
+        system_id: Option<&str>,
+        base: &Path,
+    ) -> Option<PathBuf> {
+        let public = public_id.and_then(|id| self.public.get(&normalize_public_id(id)));
+        if let Some(path) = public {
+            return Some(path.clone());
+        }
+
+        let system_id = system_id?;
+        match self.system.get(system_id) {
+            Some(path) => Some(path.clone()),
+            None => Some(base.join(system_id)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::catalog::{parse_catalog_entries, Catalog};
+    use crate::entity::parse_entity;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    pub fn test_locate() {
+        let (_, lat1) = parse_entity(
+            "<!ENTITY % HTMLlat1 PUBLIC \"-//W3C//ENTITIES Latin 1 for XHTML//EN\" \"xhtml-lat1.ent\">",
+        )
+        .unwrap();
+        let (_, iso) = parse_entity(
+            "<!ENTITY % ISOlat1 PUBLIC \"ISO 8879-1986//ENTITIES  Added Latin 1//EN//HTML\">",
+        )
+        .unwrap();
+
+        let mut catalog = Catalog::default();
+        assert_eq!(
+            catalog.locate(&lat1, Path::new("dtd")),
+            Some(PathBuf::from("dtd/xhtml-lat1.ent"))
+        );
+        assert_eq!(catalog.locate(&iso, Path::new("dtd")), None);
+
+        catalog.add_public(
+            "ISO 8879-1986//ENTITIES Added Latin 1//EN//HTML",
+            PathBuf::from("ISOlat1.ent"),
+        );
+        assert_eq!(
+            catalog.locate(&iso, Path::new("dtd")),
+            Some(PathBuf::from("ISOlat1.ent"))
+        );
+    }
+
+    #[test]
+    pub fn test_catalog_entries() {
+        let entries = parse_catalog_entries(
+            "-- HTML -- PUBLIC \"-//IETF//DTD HTML 2.0//EN\" html.dtd\n\
+             OVERRIDE YES  doctype html 'html.dtd' BOGUS SGMLDECL \"sgml.dcl\"",
+        );
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "PUBLIC".to_string(),
+                    vec![
+                        "-//IETF//DTD HTML 2.0//EN".to_string(),
+                        "html.dtd".to_string()
+                    ]
+                ),
+                ("OVERRIDE".to_string(), vec!["YES".to_string()]),
+                (
+                    "DOCTYPE".to_string(),
+                    vec!["html".to_string(), "html.dtd".to_string()]
+                ),
+                ("SGMLDECL".to_string(), vec!["sgml.dcl".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_load() {
+        let mut catalog = Catalog::default();
+        catalog.load(Path::new("./dtd/catalog")).unwrap();
+        assert_eq!(
+            catalog.locate_external_id(Some("-//IETF//DTD  HTML 2.0//EN"), None, Path::new(".")),
+            Some(PathBuf::from("./dtd/html.dtd"))
+        );
+        assert!(catalog.load(Path::new("./dtd/missing")).is_err());
+    }
+}
@@ -4,19 +4,29 @@ use nom::IResult;
 type Comment = String;
 
 pub fn parse_inline_comment(i: &str) -> IResult<&str, Comment> {
+    let (i, content) = parse_borrowed_inline_comment(i)?;
+    Ok((i, content.to_string()))
+}
+
+pub fn parse_borrowed_inline_comment(i: &str) -> IResult<&str, &str> {
     let (i, _start) = tag("--")(i)?;
     let (i, content) = take_until("--")(i)?;
     let (i, _end) = tag("--")(i)?;
 
-    Ok((i, content.to_string()))
+    Ok((i, content))
 }
 
 pub fn parse_comment_block(i: &str) -> IResult<&str, Comment> {
+    let (i, content) = parse_borrowed_comment_block(i)?;
+    Ok((i, content.to_string()))
+}
+
+pub fn parse_borrowed_comment_block(i: &str) -> IResult<&str, &str> {
     let (i, _start) = tag("<!--")(i)?;
     let (i, content) = take_until("-->")(i)?;
     let (i, _end) = tag("-->")(i)?;
 
-    Ok((i, content.to_string()))
+    Ok((i, content))
 }
 
 #[test]
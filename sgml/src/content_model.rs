@@ -0,0 +1,441 @@
+use crate::comment::parse_inline_comment;
+use crate::dtd::is_whitespace;
+use crate::element::{
+    CONNECTOR_AND, CONNECTOR_OR, CONNECTOR_SEQUENCE, GROUP_CLOSE, GROUP_OPEN,
+    OPTIONAL_AND_REPEATABLE, OPTIONAL_OCCURRENCE_INDICATOR, REQUIRED_AND_REPEATABLE,
+};
+use nom::branch::alt;
+use nom::bytes::complete::{tag, tag_no_case, take_while1};
+use nom::combinator::{map, not, opt, peek};
+use nom::error::ErrorKind;
+use nom::multi::many0;
+use nom::sequence::terminated;
+use nom::IResult;
+use std::fmt;
+
+/// See ISO(B.4.2.7)
+pub const EXCLUSION_OPEN: &str = "-(";
+pub const INCLUSION_OPEN: &str = "+(";
+
+/// See ISO(B.4.2.8)
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Occurrence {
+    Once,
+    Optional,
+    OneOrMore,
+    ZeroOrMore,
+}
+
+impl Occurrence {
+    pub fn is_optional(&self) -> bool {
+        matches!(self, Occurrence::Optional | Occurrence::ZeroOrMore)
+    }
+
+    pub fn is_repeatable(&self) -> bool {
+        matches!(self, Occurrence::OneOrMore | Occurrence::ZeroOrMore)
+    }
+
+    pub fn from_flags(optional: bool, repeatable: bool) -> Self {
+        match (optional, repeatable) {
+            (false, false) => Occurrence::Once,
+            (true, false) => Occurrence::Optional,
+            (false, true) => Occurrence::OneOrMore,
+            (true, true) => Occurrence::ZeroOrMore,
+        }
+    }
+}
+
+impl fmt::Display for Occurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Occurrence::Once => "",
+            Occurrence::Optional => OPTIONAL_OCCURRENCE_INDICATOR,
+            Occurrence::OneOrMore => REQUIRED_AND_REPEATABLE,
+            Occurrence::ZeroOrMore => OPTIONAL_AND_REPEATABLE,
+        })
+    }
+}
+
+/// See ISO(B.4.2.5)
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Connector {
+    /// All tokens in order
+    Sequence,
+    /// One of the tokens
+    Or,
+    /// All tokens in any order
+    And,
+}
+
+impl Connector {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Connector::Sequence => CONNECTOR_SEQUENCE,
+            Connector::Or => CONNECTOR_OR,
+            Connector::And => CONNECTOR_AND,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ContentToken {
+    PcData,
+    Element(String, Occurrence),
+    Group(ModelGroup),
+}
+
+impl fmt::Display for ContentToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContentToken::PcData => f.write_str("#PCDATA"),
+            ContentToken::Element(name, occurrence) => write!(f, "{}{}", name, occurrence),
+            ContentToken::Group(g) => write!(f, "{}", g),
+        }
+    }
+}
+
+/// See ISO(B.4.2.5)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ModelGroup {
+    pub connector: Connector,
+    pub tokens: Vec<ContentToken>,
+    pub occurrence: Occurrence,
+}
+
+impl ModelGroup {
+    /// Whether character data may appear anywhere in this group, making it mixed content
+    pub fn contains_pcdata(&self) -> bool {
+        self.tokens.iter().any(|t| match t {
+            ContentToken::PcData => true,
+            ContentToken::Element(..) => false,
+            ContentToken::Group(g) => g.contains_pcdata(),
+        })
+    }
+
+    /// Every element named in this group or its subgroups, in order of first appearance
+    pub fn get_element_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        self.collect_element_names(&mut names);
+        names
+    }
+
+    fn collect_element_names(&self, names: &mut Vec<String>) {
+        for token in &self.tokens {
+            match token {
+                ContentToken::Element(name, _) => {
+                    if !names.contains(name) {
+                        names.push(name.clone());
+                    }
+                }
+                ContentToken::Group(g) => g.collect_element_names(names),
+                ContentToken::PcData => {}
+            }
+        }
+    }
+}
+
+impl fmt::Display for ModelGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = match self.connector {
+            Connector::Sequence => format!("{} ", CONNECTOR_SEQUENCE),
+            c => format!(" {} ", c.symbol()),
+        };
+        let tokens = self
+            .tokens
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>()
+            .join(&separator);
+        write!(
+            f,
+            "{}{}{}{}",
+            GROUP_OPEN, tokens, GROUP_CLOSE, self.occurrence
+        )
+    }
+}
+
+/// See ISO(B.4.2.6)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DeclaredContent {
+    CData,
+    RCData,
+    Empty,
+    Any,
+    Group(ModelGroup),
+}
+
+/// The parsed content of an element declaration, See ISO(B.4.2.6)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ContentModel {
+    pub content: DeclaredContent,
+    /// Elements allowed anywhere within this element, See ISO(B.4.2.7)
+    pub inclusions: Vec<String>,
+    /// Elements not allowed anywhere within this element
+    pub exclusions: Vec<String>,
+}
+
+impl ContentModel {
+    pub fn is_mixed(&self) -> bool {
+        match &self.content {
+            DeclaredContent::Group(g) => g.contains_pcdata(),
+            DeclaredContent::Any => true,
+            _ => false,
+        }
+    }
+
+    /// The elements this model names directly, excluding inclusions
+    pub fn get_element_names(&self) -> Vec<String> {
+        match &self.content {
+            DeclaredContent::Group(g) => g.get_element_names(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for ContentModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.content {
+            DeclaredContent::CData => f.write_str("CDATA")?,
+            DeclaredContent::RCData => f.write_str("RCDATA")?,
+            DeclaredContent::Empty => f.write_str("EMPTY")?,
+            DeclaredContent::Any => f.write_str("ANY")?,
+            DeclaredContent::Group(g) => write!(f, "{}", g)?,
+        }
+        if !self.exclusions.is_empty() {
+            write!(f, " -({})", self.exclusions.join(" | "))?;
+        }
+        if !self.inclusions.is_empty() {
+            write!(f, " +({})", self.inclusions.join(" | "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Skip parameter separators: whitespace and comments
+pub fn take_separators(i: &str) -> IResult<&str, ()> {
+    let (i, _) = many0(alt((
+        map(take_while1(|c: char| is_whitespace(c) || c == '\r'), |_| ()),
+        map(parse_inline_comment, |_| ()),
+    )))(i)?;
+    Ok((i, ()))
+}
+
+pub fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '.' || c == '-' || c == '_' || c == ':'
+}
+
+pub fn parse_name(i: &str) -> IResult<&str, &str> {
+    take_while1(is_name_char)(i)
+}
+
+/// A keyword that isn't the start of a longer name
+pub fn parse_keyword<'a>(
+    keyword: &'static str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    terminated(tag_no_case(keyword), not(peek(take_while1(is_name_char))))
+}
+
+fn parse_occurrence(i: &str) -> IResult<&str, Occurrence> {
+    // A plus sign followed by a group is an inclusion rather than an occurrence indicator
+    if i.starts_with(INCLUSION_OPEN) {
+        return Ok((i, Occurrence::Once));
+    }
+    let (i, indicator) = opt(alt((
+        tag(OPTIONAL_OCCURRENCE_INDICATOR),
+        tag(REQUIRED_AND_REPEATABLE),
+        tag(OPTIONAL_AND_REPEATABLE),
+    )))(i)?;
+    let occurrence = match indicator {
+        Some(OPTIONAL_OCCURRENCE_INDICATOR) => Occurrence::Optional,
+        Some(REQUIRED_AND_REPEATABLE) => Occurrence::OneOrMore,
+        Some(OPTIONAL_AND_REPEATABLE) => Occurrence::ZeroOrMore,
+        _ => Occurrence::Once,
+    };
+    Ok((i, occurrence))
+}
+
+fn parse_connector(i: &str) -> IResult<&str, Connector> {
+    alt((
+        map(tag(CONNECTOR_SEQUENCE), |_| Connector::Sequence),
+        map(tag(CONNECTOR_OR), |_| Connector::Or),
+        map(tag(CONNECTOR_AND), |_| Connector::And),
+    ))(i)
+}
+
+/// See ISO(B.4.2.5)
+pub fn parse_content_token(i: &str) -> IResult<&str, ContentToken> {
+    if let Ok((i, _)) = parse_keyword("#PCDATA")(i) {
+        return Ok((i, ContentToken::PcData));
+    }
+    if let Ok((i, group)) = parse_model_group(i) {
+        return Ok((i, ContentToken::Group(group)));
+    }
+    let (i, name) = parse_name(i)?;
+    let (i, occurrence) = parse_occurrence(i)?;
+    Ok((i, ContentToken::Element(name.to_string(), occurrence)))
+}
+
+/// See ISO(B.4.2.5)
+pub fn parse_model_group(i: &str) -> IResult<&str, ModelGroup> {
+    let (i, _) = tag(GROUP_OPEN)(i)?;
+    let (i, _) = take_separators(i)?;
+    let (mut i, first) = parse_content_token(i)?;
+
+    let mut tokens = vec![first];
+    let mut connector = None;
+    loop {
+        let (j, _) = take_separators(i)?;
+        let (j, c) = match parse_connector(j) {
+            Ok(r) => r,
+            Err(_) => {
+                i = j;
+                break;
+            }
+        };
+        // All connectors in a group must be the same
+        if connector.is_some() && connector != Some(c) {
+            return Err(nom::Err::Error(nom::error::make_error(i, ErrorKind::Tag)));
+        }
+        connector = Some(c);
+
+        let (j, _) = take_separators(j)?;
+        let (j, token) = parse_content_token(j)?;
+        tokens.push(token);
+        i = j;
+    }
+
+    let (i, _) = tag(GROUP_CLOSE)(i)?;
+    let (i, occurrence) = parse_occurrence(i)?;
+    Ok((
+        i,
+        ModelGroup {
+            connector: connector.unwrap_or(Connector::Sequence),
+            tokens,
+            occurrence,
+        },
+    ))
+}
+
+fn parse_exception(open: &'static str) -> impl FnMut(&str) -> IResult<&str, Vec<String>> {
+    move |i: &str| {
+        let (i, _) = tag(&open[..1])(i)?;
+        let (i, group) = parse_model_group(i)?;
+        Ok((i, group.get_element_names()))
+    }
+}
+
+/// Parse the expanded content of an element declaration, See ISO(B.4.2.6)
+pub fn parse_declared_content(i: &str) -> IResult<&str, ContentModel> {
+    let (i, _) = take_separators(i)?;
+
+    for (keyword, content) in [
+        ("CDATA", DeclaredContent::CData),
+        ("RCDATA", DeclaredContent::RCData),
+        ("EMPTY", DeclaredContent::Empty),
+    ] {
+        if let Ok((i, _)) = parse_keyword(keyword)(i) {
+            let (i, _) = take_separators(i)?;
+            return Ok((
+                i,
+                ContentModel {
+                    content,
+                    inclusions: Vec::new(),
+                    exclusions: Vec::new(),
+                },
+            ));
+        }
+    }
+
+    let (i, content) = alt((
+        map(parse_keyword("ANY"), |_| DeclaredContent::Any),
+        map(parse_model_group, DeclaredContent::Group),
+    ))(i)?;
+
+    // Exclusions come first, but accept either order
+    let (i, _) = take_separators(i)?;
+    let (i, exclusions) = opt(parse_exception(EXCLUSION_OPEN))(i)?;
+    let (i, _) = take_separators(i)?;
+    let (i, inclusions) = opt(parse_exception(INCLUSION_OPEN))(i)?;
+    let (i, _) = take_separators(i)?;
+    let (i, late_exclusions) = match exclusions {
+        Some(_) => (i, None),
+        None => opt(parse_exception(EXCLUSION_OPEN))(i)?,
+    };
+    let (i, _) = take_separators(i)?;
+
+    Ok((
+        i,
+        ContentModel {
+            content,
+            inclusions: inclusions.unwrap_or_default(),
+            exclusions: exclusions.or(late_exclusions).unwrap_or_default(),
+        },
+    ))
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::content_model::{
+        parse_declared_content, Connector, ContentToken, DeclaredContent, Occurrence,
+    };
+
+    #[test]
+    pub fn test_parse_groups() {
+        let (i, m) = parse_declared_content("(front?, (body | text)+, rear*)").unwrap();
+        assert_eq!(i, "");
+        let g = match &m.content {
+            DeclaredContent::Group(g) => g,
+            _ => panic!("Expected a group"),
+        };
+        assert_eq!(g.connector, Connector::Sequence);
+        assert_eq!(
+            g.tokens[0],
+            ContentToken::Element("front".to_string(), Occurrence::Optional)
+        );
+        assert!(
+            matches!(&g.tokens[1], ContentToken::Group(g) if g.connector == Connector::Or && g.occurrence == Occurrence::OneOrMore)
+        );
+        assert_eq!(g.get_element_names(), vec!["front", "body", "text", "rear"]);
+        assert!(!m.is_mixed());
+        assert_eq!(m.to_string(), "(front?, (body | text)+, rear*)");
+    }
+
+    #[test]
+    pub fn test_parse_exceptions() {
+        let (i, m) =
+            parse_declared_content("(#PCDATA|A|IMG)* -(A) +(INPUT|SELECT|TEXTAREA) -- comment --")
+                .unwrap();
+        assert_eq!(i, "");
+        assert!(m.is_mixed());
+        assert_eq!(m.exclusions, vec!["A"]);
+        assert_eq!(m.inclusions, vec!["INPUT", "SELECT", "TEXTAREA"]);
+        assert_eq!(
+            m.to_string(),
+            "(#PCDATA | A | IMG)* -(A) +(INPUT | SELECT | TEXTAREA)"
+        );
+
+        let (_, m) = parse_declared_content("( TITLE & ISINDEX? & BASE? ) +(META|LINK)").unwrap();
+        assert!(
+            matches!(&m.content, DeclaredContent::Group(g) if g.connector == Connector::And && g.occurrence == Occurrence::Once)
+        );
+        assert_eq!(m.inclusions, vec!["META", "LINK"]);
+    }
+
+    #[test]
+    pub fn test_parse_keywords() {
+        assert_eq!(
+            parse_declared_content("EMPTY").unwrap().1.content,
+            DeclaredContent::Empty
+        );
+        assert_eq!(
+            parse_declared_content("cdata").unwrap().1.content,
+            DeclaredContent::CData
+        );
+        assert_eq!(
+            parse_declared_content("ANY -(X)").unwrap().1.exclusions,
+            vec!["X"]
+        );
+        // Mixed connectors aren't allowed within one group
+        assert!(parse_declared_content("(a, b | c)").is_err());
+    }
+}
@@ -0,0 +1,1210 @@
+//! Reading documents into any type that implements `Deserialize`, without generating types for the
+//! DTD. The document is parsed against the DTD first, so omitted tags are inferred, entities
+//! expanded and attribute values normalized. An element is then read as a map of:
+//!
+//! - its attributes, with the default values of those the DTD gives one
+//! - its child elements, each name once, holding one element or a sequence of all of them
+//! - `$text`, the character data of the element and its descendants, when a struct has the field
+//! - `$value`, the content in order, when a struct has the field. Each child element is an enum
+//!   variant named after the element, and character data is the `$text` variant
+//!
+//! An element read as a string or number is its character data. As an enum it is the variant
+//! named after it, or the variant its character data names. Attribute values and character data
+//! read as a sequence are split into tokens, as for NAMES or NUMBERS attributes. Read into a type
+//! that takes any value, such as `serde_json::Value`, attribute values are as in the JSON encoding
+//! of `json_schema`.
+//!
+//! Names are matched to fields and variants as `Options::case` says. Fields for attributes and
+//! elements that may be left out need `Option` or `#[serde(default)]`
+use crate::att_list::{DeclaredValue, DefaultValue};
+use crate::document::{Document, ElementNode, Node};
+use crate::dtd::DocumentTypeDefinition;
+use crate::instance::{read_doctype, DocumentError, Grammar, Parser};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer, MapAccess,
+    SeqAccess, VariantAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use std::borrow::Cow;
+use std::fmt;
+
+/// How names of elements and attributes are given to the type being read
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NameCase {
+    /// A name is given as the field or variant it matches ignoring case and the separators `-`,
+    /// `.` and `_`, so `<LIST-ITEM>` fills `list_item` or the variant `ListItem`
+    Insensitive,
+    /// Names are lower case, with `-` and `.` replaced by `_`
+    Lower,
+    /// Names are as the DTD declares them
+    Declared,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub case: NameCase,
+    /// The document element of documents without a document type declaration, otherwise the one
+    /// element no other element contains
+    pub root: Option<String>,
+    /// Read documents with errors the parser recovered from, rather than failing with them
+    pub allow_errors: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            case: NameCase::Insensitive,
+            root: None,
+            allow_errors: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    /// The document isn't valid, with the errors the parser recovered from
+    Document(Vec<DocumentError>),
+    /// The document has no document element
+    NoElement,
+    /// The document doesn't fit the type, such as a missing field or a value that isn't a number
+    Custom(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Document(errors) => match errors.first() {
+                Some(first) if errors.len() > 1 => write!(
+                    f,
+                    "the document has {} errors, the first at {}",
+                    errors.len(),
+                    first
+                ),
+                Some(first) => write!(f, "the document has an error at {}", first),
+                None => f.write_str("the document has errors"),
+            },
+            Error::NoElement => f.write_str("the document has no element"),
+            Error::Custom(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Error::Custom(message.to_string())
+    }
+}
+
+/// Read a `T` from a document, with the default options
+pub fn from_str<T: DeserializeOwned>(
+    input: &str,
+    dtd: &DocumentTypeDefinition,
+) -> Result<T, Error> {
+    from_str_with_options(input, dtd, &Options::default())
+}
+
+pub fn from_str_with_options<T: DeserializeOwned>(
+    input: &str,
+    dtd: &DocumentTypeDefinition,
+    options: &Options,
+) -> Result<T, Error> {
+    let input = match &options.root {
+        Some(root)
+            if read_doctype(input).is_none() && !input.trim_start().starts_with("<!SGML") =>
+        {
+            Cow::Owned(format!("<!DOCTYPE {}>{}", root, input))
+        }
+        _ => Cow::Borrowed(input),
+    };
+    let parser = Parser::new(&input, dtd);
+    let grammar = parser.get_grammar().clone();
+    let document = Document::from_parser(parser);
+    if !options.allow_errors && !document.errors.is_empty() {
+        return Err(Error::Document(document.errors));
+    }
+    let root = document.get_root().ok_or(Error::NoElement)?;
+    let context = Context {
+        grammar: &grammar,
+        options,
+    };
+    T::deserialize(ElementDeserializer {
+        element: root,
+        context: &context,
+    })
+}
+
+/// What every part of a document is read with
+struct Context<'a> {
+    grammar: &'a Grammar,
+    options: &'a Options,
+}
+
+impl Context<'_> {
+    /// The name as it is given to the type, one of `fields` if it matches one
+    fn key(&self, name: &str, fields: &[&str]) -> String {
+        match self.options.case {
+            NameCase::Insensitive => fields
+                .iter()
+                .find(|f| same_name(f, name))
+                .map_or_else(|| name.to_string(), |f| f.to_string()),
+            NameCase::Lower => name.to_lowercase().replace(['-', '.'], "_"),
+            NameCase::Declared => name.to_string(),
+        }
+    }
+}
+
+fn same_name(field: &str, name: &str) -> bool {
+    let letters = |s: &'_ str| {
+        s.chars()
+            .filter(|c| !matches!(c, '-' | '.' | '_'))
+            .map(|c| c.to_ascii_uppercase())
+            .collect::<Vec<_>>()
+    };
+    letters(field) == letters(name)
+}
+
+/// The character data directly in some content, with entity references expanded
+fn text(nodes: &[Node]) -> Option<String> {
+    let mut text = None;
+    for node in nodes {
+        if let Node::Text(t) | Node::EntityReference { text: t, .. } = node {
+            text.get_or_insert_with(String::new).push_str(t);
+        }
+    }
+    text
+}
+
+/// The value of an attribute, or character data
+struct ValueDeserializer<'a> {
+    value: String,
+    /// How an attribute is declared, which gives its value in the JSON encoding when the type
+    /// being read doesn't say, See `json_schema`
+    declared: Option<DeclaredValue>,
+    context: &'a Context<'a>,
+}
+
+impl ValueDeserializer<'_> {
+    fn parse<T: std::str::FromStr>(&self, kind: &str) -> Result<T, Error> {
+        self.value
+            .trim()
+            .parse()
+            .map_err(|_| Error::Custom(format!("{:?} is not a valid {}", self.value, kind)))
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident $visit:ident $kind:literal,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.$visit(self.parse($kind)?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match &self.declared {
+            Some(DeclaredValue::Number) => match self.value.trim().parse() {
+                Ok(n) => visitor.visit_u64(n),
+                Err(_) => visitor.visit_string(self.value),
+            },
+            Some(
+                DeclaredValue::Numbers
+                | DeclaredValue::Entities
+                | DeclaredValue::IdRefs
+                | DeclaredValue::Names
+                | DeclaredValue::NmTokens
+                | DeclaredValue::NuTokens,
+            ) => self.deserialize_seq(visitor),
+            Some(DeclaredValue::NameTokenGroup(group) | DeclaredValue::Notation(group)) => {
+                match group
+                    .iter()
+                    .find(|t| t.eq_ignore_ascii_case(self.value.trim()))
+                {
+                    Some(token) => visitor.visit_str(token),
+                    None => visitor.visit_string(self.value),
+                }
+            }
+            _ => visitor.visit_string(self.value),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 visit_i8 "number",
+        deserialize_i16 visit_i16 "number",
+        deserialize_i32 visit_i32 "number",
+        deserialize_i64 visit_i64 "number",
+        deserialize_u8 visit_u8 "number",
+        deserialize_u16 visit_u16 "number",
+        deserialize_u32 visit_u32 "number",
+        deserialize_u64 visit_u64 "number",
+        deserialize_f32 visit_f32 "number",
+        deserialize_f64 visit_f64 "number",
+        deserialize_char visit_char "character",
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value.trim().to_lowercase().as_str() {
+            "true" | "yes" | "1" => visitor.visit_bool(true),
+            "false" | "no" | "0" => visitor.visit_bool(false),
+            _ => Err(Error::Custom(format!(
+                "{:?} is not a valid boolean",
+                self.value
+            ))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let context = self.context;
+        let declared = match self.declared {
+            Some(DeclaredValue::Numbers) => Some(DeclaredValue::Number),
+            _ => None,
+        };
+        visitor.visit_seq(Sequence(self.value.split_whitespace().map(|token| {
+            ValueDeserializer {
+                value: token.to_string(),
+                declared: declared.clone(),
+                context,
+            }
+        })))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let variant: String = self.context.key(self.value.trim(), variants);
+        visitor.visit_enum(variant.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf map struct identifier ignored_any
+    }
+}
+
+/// Deserializers read in turn
+struct Sequence<I>(I);
+
+impl<'de, I, D> SeqAccess<'de> for Sequence<I>
+where
+    I: Iterator<Item = D>,
+    D: Deserializer<'de, Error = Error>,
+{
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        self.0.next().map(|d| seed.deserialize(d)).transpose()
+    }
+}
+
+struct ElementDeserializer<'a> {
+    element: &'a ElementNode,
+    context: &'a Context<'a>,
+}
+
+impl<'a> ElementDeserializer<'a> {
+    fn text(&self) -> ValueDeserializer<'a> {
+        ValueDeserializer {
+            value: self.element.get_text(),
+            declared: None,
+            context: self.context,
+        }
+    }
+
+    /// The attributes, with the defaults of those that aren't specified
+    fn attributes(&self) -> Vec<(String, String, DeclaredValue)> {
+        let mut attributes: Vec<_> = self
+            .element
+            .attributes
+            .iter()
+            .map(|a| (a.name.clone(), a.value.clone(), a.declared_value.clone()))
+            .collect();
+        let grammar = self.context.grammar;
+        let definitions = grammar
+            .get_element(&self.element.name)
+            .map(|t| t.attributes.as_slice())
+            .unwrap_or_default();
+        for d in definitions {
+            if let DefaultValue::Value(v) | DefaultValue::Fixed(v) = &d.default_value {
+                if !attributes.iter().any(|(name, _, _)| name == &d.name) {
+                    attributes.push((
+                        d.name.clone(),
+                        grammar.normalize(&d.declared_value, v.clone()),
+                        d.declared_value.clone(),
+                    ));
+                }
+            }
+        }
+        attributes
+    }
+
+    /// The entries of the element as a map, for the given fields of a struct
+    fn entries(&self, fields: Option<&[&str]>) -> Vec<(String, Entry<'a>)> {
+        let context = self.context;
+        let key = |name: &str| context.key(name, fields.unwrap_or_default());
+        let mut entries: Vec<(String, Entry)> = self
+            .attributes()
+            .into_iter()
+            .map(|(name, value, declared)| (key(&name), Entry::Value(value, Some(declared))))
+            .collect();
+
+        let wants = |field: &str| fields.is_some_and(|f| f.contains(&field));
+        if wants("$text") {
+            entries.push((
+                "$text".to_string(),
+                Entry::Value(self.element.get_text(), None),
+            ));
+        } else if fields.is_none() {
+            if let Some(text) = text(&self.element.children).filter(|t| !t.trim().is_empty()) {
+                entries.push(("$text".to_string(), Entry::Value(text, None)));
+            }
+        }
+        if wants("$value") {
+            entries.push(("$value".to_string(), Entry::Content(&self.element.children)));
+        }
+
+        let mut elements: Vec<(&str, Vec<&ElementNode>)> = Vec::new();
+        for child in self.element.get_elements() {
+            match elements.iter_mut().find(|(name, _)| *name == child.name) {
+                Some((_, same)) => same.push(child),
+                None => elements.push((&child.name, vec![child])),
+            }
+        }
+        entries.extend(
+            elements
+                .into_iter()
+                .map(|(name, same)| (key(name), Entry::Elements(name, same))),
+        );
+        entries
+    }
+
+    fn map<'de, V: Visitor<'de>>(
+        self,
+        fields: Option<&[&str]>,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_map(Entries {
+            entries: self.entries(fields).into_iter(),
+            value: None,
+            context: self.context,
+        })
+    }
+}
+
+macro_rules! deserialize_text {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                self.text().$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for ElementDeserializer<'_> {
+    type Error = Error;
+
+    /// A map when the element has attributes or child elements, otherwise its character data
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.element.get_elements().next().is_some() || !self.attributes().is_empty() {
+            self.map(None, visitor)
+        } else {
+            self.text().deserialize_any(visitor)
+        }
+    }
+
+    deserialize_text! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_bytes
+        deserialize_byte_buf deserialize_identifier
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// The content in order, as with `$value`
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        ContentDeserializer {
+            nodes: &self.element.children,
+            context: self.context,
+        }
+        .deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.map(None, visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.map(Some(fields), visitor)
+    }
+
+    /// The variant named after the element, otherwise the one its character data names
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let variant = self.context.key(&self.element.name, variants);
+        if variants.contains(&variant.as_str()) || self.element.get_elements().next().is_some() {
+            visitor.visit_enum(ElementVariant {
+                variant,
+                element: self,
+            })
+        } else {
+            self.text().deserialize_enum(name, variants, visitor)
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+}
+
+/// An element as the variant of an enum named after it
+struct ElementVariant<'a> {
+    variant: String,
+    element: ElementDeserializer<'a>,
+}
+
+impl<'de, 'a> EnumAccess<'de> for ElementVariant<'a> {
+    type Error = Error;
+    type Variant = ElementDeserializer<'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Error> {
+        let variant =
+            seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.variant))?;
+        Ok((variant, self.element))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ElementDeserializer<'_> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.map(Some(fields), visitor)
+    }
+}
+
+/// The value of an entry of an element read as a map
+enum Entry<'a> {
+    /// An attribute value and how it is declared, or character data
+    Value(String, Option<DeclaredValue>),
+    /// The content in order
+    Content(&'a [Node]),
+    /// The child elements with a name, in order
+    Elements(&'a str, Vec<&'a ElementNode>),
+}
+
+struct Entries<'a> {
+    entries: std::vec::IntoIter<(String, Entry<'a>)>,
+    value: Option<Entry<'a>>,
+    context: &'a Context<'a>,
+}
+
+impl<'de> MapAccess<'de> for Entries<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let context = self.context;
+        match self.value.take() {
+            Some(Entry::Value(value, declared)) => seed.deserialize(ValueDeserializer {
+                value,
+                declared,
+                context,
+            }),
+            Some(Entry::Content(nodes)) => seed.deserialize(ContentDeserializer { nodes, context }),
+            Some(Entry::Elements(name, elements)) => seed.deserialize(ElementsDeserializer {
+                name,
+                elements,
+                context,
+            }),
+            None => Err(Error::Custom("a value is read before its key".to_string())),
+        }
+    }
+}
+
+/// The child elements with one name, a sequence or the one element
+struct ElementsDeserializer<'a> {
+    name: &'a str,
+    elements: Vec<&'a ElementNode>,
+    context: &'a Context<'a>,
+}
+
+impl<'a> ElementsDeserializer<'a> {
+    fn one(self) -> Result<ElementDeserializer<'a>, Error> {
+        match self.elements.as_slice() {
+            [element] => Ok(ElementDeserializer {
+                element,
+                context: self.context,
+            }),
+            _ => Err(Error::Custom(format!(
+                "{} elements {} where one is expected",
+                self.elements.len(),
+                self.name
+            ))),
+        }
+    }
+}
+
+macro_rules! deserialize_one {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                self.one()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for ElementsDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.elements.len() == 1 {
+            self.one()?.deserialize_any(visitor)
+        } else {
+            self.deserialize_seq(visitor)
+        }
+    }
+
+    deserialize_one! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_bytes
+        deserialize_byte_buf deserialize_unit deserialize_map deserialize_identifier
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.one()?.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let context = self.context;
+        visitor.visit_seq(Sequence(
+            self.elements
+                .into_iter()
+                .map(|element| ElementDeserializer { element, context }),
+        ))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.one()?.deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.one()?.deserialize_enum(name, variants, visitor)
+    }
+}
+
+/// The content of an element in order, as a sequence of child elements and character data
+struct ContentDeserializer<'a> {
+    nodes: &'a [Node],
+    context: &'a Context<'a>,
+}
+
+impl<'de> Deserializer<'de> for ContentDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        // Character data split by entity references is one item
+        let mut items = Vec::new();
+        for node in self.nodes {
+            match node {
+                Node::Element(element) => items.push(Item::Element(element)),
+                Node::Text(t) | Node::EntityReference { text: t, .. } => match items.last_mut() {
+                    Some(Item::Text(text)) => text.push_str(t),
+                    _ => items.push(Item::Text(t.clone())),
+                },
+                Node::ProcessingInstruction(_) | Node::Comment(_) => {}
+            }
+        }
+        let context = self.context;
+        visitor.visit_seq(Sequence(
+            items
+                .into_iter()
+                .map(|item| ItemDeserializer { item, context }),
+        ))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+enum Item<'a> {
+    Element(&'a ElementNode),
+    Text(String),
+}
+
+/// A child element or character data in the content of an element
+struct ItemDeserializer<'a> {
+    item: Item<'a>,
+    context: &'a Context<'a>,
+}
+
+macro_rules! deserialize_item {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                let context = self.context;
+                match self.item {
+                    Item::Element(element) => ElementDeserializer { element, context }.$method(visitor),
+                    Item::Text(value) => ValueDeserializer {
+                        value,
+                        declared: None,
+                        context,
+                    }
+                    .$method(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for ItemDeserializer<'_> {
+    type Error = Error;
+
+    deserialize_item! {
+        deserialize_any deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+        deserialize_i64 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_option deserialize_unit
+        deserialize_seq deserialize_map deserialize_identifier deserialize_ignored_any
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let context = self.context;
+        match self.item {
+            Item::Element(element) => {
+                ElementDeserializer { element, context }.deserialize_struct(name, fields, visitor)
+            }
+            Item::Text(value) => ValueDeserializer {
+                value,
+                declared: None,
+                context,
+            }
+            .deserialize_any(visitor),
+        }
+    }
+
+    /// Character data is the `$text` variant
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let context = self.context;
+        match self.item {
+            Item::Element(element) => {
+                ElementDeserializer { element, context }.deserialize_enum(name, variants, visitor)
+            }
+            Item::Text(value) => visitor.visit_enum(TextVariant { value, context }),
+        }
+    }
+}
+
+/// Character data as the `$text` variant of an enum
+struct TextVariant<'a> {
+    value: String,
+    context: &'a Context<'a>,
+}
+
+impl<'de, 'a> EnumAccess<'de> for TextVariant<'a> {
+    type Error = Error;
+    type Variant = ValueDeserializer<'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Error> {
+        let variant = seed.deserialize(IntoDeserializer::<Error>::into_deserializer("$text"))?;
+        Ok((
+            variant,
+            ValueDeserializer {
+                value: self.value,
+                declared: None,
+                context: self.context,
+            },
+        ))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ValueDeserializer<'_> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        Deserializer::deserialize_any(self, visitor)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::document::test::read_flat_dtd;
+    use crate::dtd::read_dtd;
+    use crate::instance::DocumentErrorKind;
+    use serde::Deserialize;
+
+    const MEMO_DTD: &str = r#"<!ENTITY co CDATA "&#169;">
+<!ELEMENT MEMO - - (TO+, FROM, BODY)>
+<!ELEMENT (TO|FROM) - O (#PCDATA)>
+<!ELEMENT BODY O O (P+)>
+<!ELEMENT P - O (#PCDATA|Q|FOOT-NOTE)*>
+<!ELEMENT Q - - (#PCDATA)>
+<!ELEMENT FOOT-NOTE - - (#PCDATA)>
+<!ATTLIST MEMO status (draft|final) draft
+               id ID #IMPLIED
+               pages NUMBERS #IMPLIED>
+"#;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Memo {
+        status: Status,
+        id: Option<String>,
+        #[serde(default)]
+        pages: Vec<u32>,
+        to: Vec<String>,
+        from: String,
+        body: Body,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Status {
+        Draft,
+        Final,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Body {
+        #[serde(rename = "P")]
+        paragraphs: Vec<Paragraph>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Paragraph {
+        #[serde(rename = "$text")]
+        text: String,
+        #[serde(rename = "$value")]
+        content: Vec<Inline>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Inline {
+        #[serde(rename = "$text")]
+        Text(String),
+        Q(String),
+        FootNote(String),
+    }
+
+    #[test]
+    pub fn test_from_str() {
+        let (_, dtd) = read_dtd(MEMO_DTD).unwrap();
+        let memo: Memo = from_str(
+            "<memo id=m1 pages='3 4'><to>You<to>Them<from>Me\n<p>A <q>quote</q> &co;<foot-note>1</foot-note>\n<p>Two</memo>",
+            &dtd,
+        )
+        .unwrap();
+        assert_eq!(
+            memo,
+            Memo {
+                status: Status::Draft,
+                id: Some("M1".to_string()),
+                pages: vec![3, 4],
+                to: vec!["You".to_string(), "Them".to_string()],
+                from: "Me".to_string(),
+                body: Body {
+                    paragraphs: vec![
+                        Paragraph {
+                            text: "A quote ©1".to_string(),
+                            content: vec![
+                                Inline::Text("A ".to_string()),
+                                Inline::Q("quote".to_string()),
+                                Inline::Text(" ©".to_string()),
+                                Inline::FootNote("1".to_string()),
+                            ],
+                        },
+                        Paragraph {
+                            text: "Two".to_string(),
+                            content: vec![Inline::Text("Two".to_string())],
+                        },
+                    ],
+                },
+            }
+        );
+    }
+
+    /// Read into a type that doesn't say, attributes are as in the JSON encoding of a document
+    #[test]
+    pub fn test_json_encoding() {
+        let (_, dtd) = read_dtd(MEMO_DTD).unwrap();
+        let input = "<MEMO id=m1 pages='3 4' final><TO>You<FROM>Me<P>One</MEMO>";
+        let value: serde_json::Value = from_str(input, &dtd).unwrap();
+        let document = serde_json::to_value(crate::document::parse_document(input, &dtd)).unwrap();
+        let attributes = document["attributes"].as_object().unwrap();
+        assert_eq!(attributes.len(), 3);
+        for (name, attribute) in attributes {
+            assert_eq!(&value[name], attribute, "{}", name);
+        }
+        assert_eq!(value["pages"], serde_json::json!([3, 4]));
+    }
+
+    #[test]
+    pub fn test_errors() {
+        let (_, dtd) = read_dtd(MEMO_DTD).unwrap();
+        let error = from_str::<Memo>("<MEMO><FROM>Me<P>One</MEMO>", &dtd).unwrap_err();
+        assert!(matches!(
+            &error,
+            Error::Document(errors) if errors[0].kind == DocumentErrorKind::ElementNotAllowed {
+                element: "FROM".to_string(),
+                context: "MEMO".to_string()
+            }
+        ));
+
+        // Read anyway, the parser has skipped what it couldn't fit
+        let options = Options {
+            allow_errors: true,
+            ..Options::default()
+        };
+        let error = from_str_with_options::<Memo>("<MEMO pages=x><TO>You</MEMO>", &dtd, &options)
+            .unwrap_err();
+        assert_eq!(
+            error,
+            Error::Custom("\"X\" is not a valid number".to_string())
+        );
+
+        let error = from_str::<Body>("<MEMO><TO>A<TO>B<FROM>C<P>D</MEMO>", &dtd).unwrap_err();
+        assert_eq!(error, Error::Custom("missing field `P`".to_string()));
+    }
+
+    #[test]
+    pub fn test_name_case() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Memo {
+            #[serde(rename = "TO")]
+            to: Vec<String>,
+            status: String,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Lower {
+            to: String,
+            from: String,
+        }
+
+        let (_, dtd) = read_dtd(MEMO_DTD).unwrap();
+        let input = "<MEMO status=final><TO>You<FROM>Me<P>One</MEMO>";
+        let options = Options {
+            case: NameCase::Declared,
+            ..Options::default()
+        };
+        assert_eq!(
+            from_str_with_options::<Memo>(input, &dtd, &options).unwrap(),
+            Memo {
+                to: vec!["You".to_string()],
+                status: "final".to_string()
+            }
+        );
+
+        let options = Options {
+            case: NameCase::Lower,
+            ..Options::default()
+        };
+        let lower: Lower = from_str_with_options(input, &dtd, &options).unwrap();
+        assert_eq!(lower.to, "You");
+    }
+
+    #[test]
+    pub fn test_html() {
+        #[derive(Debug, Deserialize)]
+        struct Html {
+            version: String,
+            head: Head,
+            body: Body,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Head {
+            title: String,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Body {
+            h1: String,
+            ul: List,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct List {
+            compact: Option<String>,
+            li: Vec<Item>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Item {
+            #[serde(rename = "$text")]
+            text: String,
+        }
+
+        let dtd = read_flat_dtd("html.dtd");
+        let options = Options {
+            root: Some("HTML".to_string()),
+            ..Options::default()
+        };
+        let html: Html = from_str_with_options(
+            "<title>Caf&eacute;</title><h1>Menu</h1><ul compact><li>Tea<li>Coffee</ul>",
+            &dtd,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(html.version, "-//IETF//DTD HTML 2.0//EN");
+        assert_eq!(html.head.title, "Café");
+        assert_eq!(html.body.h1, "Menu");
+        assert_eq!(html.body.ul.compact.as_deref(), Some("COMPACT"));
+        let items: Vec<_> = html.body.ul.li.iter().map(|i| i.text.as_str()).collect();
+        assert_eq!(items, vec!["Tea", "Coffee"]);
+    }
+}
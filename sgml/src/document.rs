@@ -0,0 +1,920 @@
+use crate::att_list::DeclaredValue;
+use crate::dtd::DocumentTypeDefinition;
+use crate::instance::{Attribute, Doctype, DocumentError, Event, Parser};
+
+/// See ISO(B.7)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Node {
+    Element(ElementNode),
+    Text(String),
+    /// A reference to an entity that is only character data, with its text as Unicode
+    EntityReference {
+        name: String,
+        text: String,
+    },
+    ProcessingInstruction(String),
+    Comment(String),
+}
+
+/// With the `serde` feature an element is an object in the JSON encoding described in
+/// `json_schema`, such as `{"name": "P", "attributes": {"ALIGN": "left"}, "content": ["One ",
+/// {"name": "EM", "content": ["two"]}]}`. Attribute values are typed by their declared values,
+/// and comments and processing instructions are left out on purpose. When read back, attribute
+/// values that are numbers are NUMBER or NUMBERS, other lists NMTOKENS and everything else CDATA
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ElementNode {
+    /// The name as declared
+    pub name: String,
+    /// The attributes specified in the start tag
+    pub attributes: Vec<Attribute>,
+    pub children: Vec<Node>,
+}
+
+impl ElementNode {
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|a| a.name.eq_ignore_ascii_case(name))
+            .map(|a| a.value.as_str())
+    }
+
+    /// The character data of this element and its descendants, with entity references expanded
+    pub fn get_text(&self) -> String {
+        let mut text = String::new();
+        for child in &self.children {
+            match child {
+                Node::Element(e) => text.push_str(&e.get_text()),
+                Node::Text(t) | Node::EntityReference { text: t, .. } => text.push_str(t),
+                _ => {}
+            }
+        }
+        text
+    }
+
+    /// The child elements, in order
+    pub fn get_elements(&self) -> impl Iterator<Item = &ElementNode> {
+        self.children.iter().filter_map(|c| match c {
+            Node::Element(e) => Some(e),
+            _ => None,
+        })
+    }
+}
+
+/// A parsed document, with the omitted tags it had filled in
+///
+/// With the `serde` feature a document is its document element, see `ElementNode`, so it can be
+/// checked against the JSON Schema of its DTD. The document type declaration, comments, processing
+/// instructions and errors are left out on purpose, as the encoding has no place for them, so
+/// reading a document gives one without them
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Document {
+    pub doctype: Option<Doctype>,
+    /// The document element, and the comments and processing instructions around it
+    pub children: Vec<Node>,
+    /// Errors the parser recovered from, so the document is only valid when there are none
+    pub errors: Vec<DocumentError>,
+}
+
+impl Document {
+    pub fn get_root(&self) -> Option<&ElementNode> {
+        self.children.iter().find_map(|c| match c {
+            Node::Element(e) => Some(e),
+            _ => None,
+        })
+    }
+}
+
+/// Parse a document against a flattened DTD, with its internal subset if it has one
+pub fn parse_document(input: &str, dtd: &DocumentTypeDefinition) -> Document {
+    Document::from_parser(Parser::new(input, dtd))
+}
+
+/// Check a document against a flattened DTD, giving every error with its position in the order
+/// they were found. The document is valid when there are none
+pub fn validate(input: &str, dtd: &DocumentTypeDefinition) -> Vec<DocumentError> {
+    let mut parser = Parser::new(input, dtd);
+    parser.by_ref().for_each(drop);
+    parser.get_errors().to_vec()
+}
+
+impl Document {
+    /// Build the tree of a document from the events of a parser
+    pub fn from_parser(mut parser: Parser) -> Document {
+        let mut children = Vec::new();
+        let mut open: Vec<ElementNode> = Vec::new();
+
+        for event in parser.by_ref() {
+            let node = match event {
+                Event::Start {
+                    name, attributes, ..
+                } => {
+                    open.push(ElementNode {
+                        name,
+                        attributes,
+                        children: Vec::new(),
+                    });
+                    continue;
+                }
+                Event::End { .. } => match open.pop() {
+                    Some(e) => Node::Element(e),
+                    None => continue,
+                },
+                Event::Text(text) => {
+                    let siblings = open.last_mut().map_or(&mut children, |e| &mut e.children);
+                    if let Some(Node::Text(previous)) = siblings.last_mut() {
+                        previous.push_str(&text);
+                        continue;
+                    }
+                    Node::Text(text.into_owned())
+                }
+                Event::EntityRef { name, text } => Node::EntityReference { name, text },
+                Event::ProcessingInstruction(pi) => Node::ProcessingInstruction(pi.into_owned()),
+                Event::Comment(c) => Node::Comment(c.into_owned()),
+                Event::MarkedSectionStart(_)
+                | Event::MarkedSectionEnd
+                | Event::EntityStart(_)
+                | Event::EntityEnd(_) => continue,
+            };
+            match open.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => children.push(node),
+            }
+        }
+
+        Document {
+            doctype: parser.get_doctype().cloned(),
+            children,
+            errors: parser.get_errors().to_vec(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod json {
+    use super::{Document, ElementNode, Node};
+    use crate::att_list::DeclaredValue;
+    use crate::instance::Attribute;
+    use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+    use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, Serializer};
+    use std::fmt;
+
+    /// The document element alone, failing without one
+    impl Serialize for Document {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self.get_root() {
+                Some(root) => root.serialize(serializer),
+                None => Err(ser::Error::custom("the document has no element")),
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Document {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            Ok(Document {
+                doctype: None,
+                children: vec![Node::Element(ElementNode::deserialize(deserializer)?)],
+                errors: Vec::new(),
+            })
+        }
+    }
+
+    impl Serialize for ElementNode {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(None)?;
+            map.serialize_entry("name", &self.name)?;
+            if !self.attributes.is_empty() {
+                map.serialize_entry("attributes", &Attributes(&self.attributes))?;
+            }
+            if self.children.iter().any(|c| Content(c).is_data()) {
+                map.serialize_entry("content", &Children(&self.children))?;
+            }
+            map.end()
+        }
+    }
+
+    struct Attributes<'a>(&'a [Attribute]);
+
+    impl Serialize for Attributes<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(self.0.len()))?;
+            for attribute in self.0 {
+                map.serialize_entry(&attribute.name, &AttributeValue(attribute))?;
+            }
+            map.end()
+        }
+    }
+
+    struct AttributeValue<'a>(&'a Attribute);
+
+    impl Serialize for AttributeValue<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let value = &self.0.value;
+            let tokens = || value.split_whitespace();
+            match &self.0.declared_value {
+                DeclaredValue::Number => match value.parse::<u64>() {
+                    Ok(n) => serializer.serialize_u64(n),
+                    Err(_) => serializer.serialize_str(value),
+                },
+                DeclaredValue::Numbers => {
+                    serializer.collect_seq(tokens().map(|n| match n.parse() {
+                        Ok(n) => Number::Number(n),
+                        Err(_) => Number::Other(n),
+                    }))
+                }
+                DeclaredValue::Entities
+                | DeclaredValue::IdRefs
+                | DeclaredValue::Names
+                | DeclaredValue::NmTokens
+                | DeclaredValue::NuTokens => serializer.collect_seq(tokens()),
+                // Tokens are the ones declared, rather than folded to upper case
+                DeclaredValue::NameTokenGroup(group) | DeclaredValue::Notation(group) => {
+                    let token = group.iter().find(|t| t.eq_ignore_ascii_case(value));
+                    serializer.serialize_str(token.unwrap_or(value))
+                }
+                _ => serializer.serialize_str(value),
+            }
+        }
+    }
+
+    /// A token of a NUMBERS value, which is left as it is when it isn't a number
+    enum Number<'a> {
+        Number(u64),
+        Other(&'a str),
+    }
+
+    impl Serialize for Number<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self {
+                Number::Number(n) => serializer.serialize_u64(*n),
+                Number::Other(s) => serializer.serialize_str(s),
+            }
+        }
+    }
+
+    struct Children<'a>(&'a [Node]);
+
+    impl Serialize for Children<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut seq = serializer.serialize_seq(None)?;
+            for child in self.0.iter().map(Content).filter(Content::is_data) {
+                seq.serialize_element(&child)?;
+            }
+            seq.end()
+        }
+    }
+
+    /// An item of the content array, an element or character data
+    struct Content<'a>(&'a Node);
+
+    impl Content<'_> {
+        fn is_data(&self) -> bool {
+            matches!(
+                self.0,
+                Node::Element(_) | Node::Text(_) | Node::EntityReference { .. }
+            )
+        }
+    }
+
+    impl Serialize for Content<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self.0 {
+                Node::Element(e) => e.serialize(serializer),
+                Node::Text(text) | Node::EntityReference { text, .. } => {
+                    serializer.serialize_str(text)
+                }
+                _ => serializer.serialize_unit(),
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for ElementNode {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_map(ElementVisitor)
+        }
+    }
+
+    struct ElementVisitor;
+
+    impl<'de> Visitor<'de> for ElementVisitor {
+        type Value = ElementNode;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an element object")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<ElementNode, A::Error> {
+            let mut name = None;
+            let mut attributes = Vec::new();
+            let mut children = Vec::new();
+            while let Some(key) = map.next_key::<String>()? {
+                match key.as_str() {
+                    "name" => name = Some(map.next_value()?),
+                    "attributes" => attributes = map.next_value::<ReadAttributes>()?.0,
+                    "content" => {
+                        children = map
+                            .next_value::<Vec<ReadContent>>()?
+                            .into_iter()
+                            .map(|c| c.0)
+                            .collect()
+                    }
+                    key => {
+                        return Err(de::Error::unknown_field(
+                            key,
+                            &["name", "attributes", "content"],
+                        ))
+                    }
+                }
+            }
+            Ok(ElementNode {
+                name: name.ok_or_else(|| de::Error::missing_field("name"))?,
+                attributes,
+                children,
+            })
+        }
+    }
+
+    struct ReadContent(Node);
+
+    impl<'de> Deserialize<'de> for ReadContent {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(ContentVisitor)
+        }
+    }
+
+    struct ContentVisitor;
+
+    impl<'de> Visitor<'de> for ContentVisitor {
+        type Value = ReadContent;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("character data or an element object")
+        }
+
+        fn visit_str<E: de::Error>(self, text: &str) -> Result<ReadContent, E> {
+            Ok(ReadContent(Node::Text(text.to_string())))
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<ReadContent, A::Error> {
+            ElementVisitor
+                .visit_map(map)
+                .map(|e| ReadContent(Node::Element(e)))
+        }
+    }
+
+    struct ReadAttributes(Vec<Attribute>);
+
+    impl<'de> Deserialize<'de> for ReadAttributes {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_map(AttributesVisitor)
+        }
+    }
+
+    struct AttributesVisitor;
+
+    impl<'de> Visitor<'de> for AttributesVisitor {
+        type Value = ReadAttributes;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an object of attribute values")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<ReadAttributes, A::Error> {
+            let mut attributes = Vec::new();
+            while let Some((name, ReadValue(value, declared_value))) = map.next_entry()? {
+                attributes.push(Attribute {
+                    name,
+                    value,
+                    declared_value,
+                });
+            }
+            Ok(ReadAttributes(attributes))
+        }
+    }
+
+    /// An attribute value, with the declared value its JSON type gives it
+    struct ReadValue(String, DeclaredValue);
+
+    impl<'de> Deserialize<'de> for ReadValue {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(ValueVisitor)
+        }
+    }
+
+    struct ValueVisitor;
+
+    impl<'de> Visitor<'de> for ValueVisitor {
+        type Value = ReadValue;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a string, a number or a list of them")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<ReadValue, E> {
+            Ok(ReadValue(value.to_string(), DeclaredValue::CData))
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<ReadValue, E> {
+            Ok(ReadValue(value.to_string(), DeclaredValue::Number))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ReadValue, A::Error> {
+            let mut tokens = Vec::new();
+            let mut numbers = true;
+            while let Some(ReadValue(token, declared_value)) = seq.next_element()? {
+                numbers &= declared_value == DeclaredValue::Number;
+                tokens.push(token);
+            }
+            let declared_value = if numbers && !tokens.is_empty() {
+                DeclaredValue::Numbers
+            } else {
+                DeclaredValue::NmTokens
+            };
+            Ok(ReadValue(tokens.join(" "), declared_value))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct XmlOptions {
+    /// Lowercase element names, attribute names and name token group values, as XHTML did for HTML
+    pub lowercase_names: bool,
+    /// Write character entities as their characters, otherwise as references that the
+    /// DTD of the XML has to declare
+    pub expand_entities: bool,
+    /// Start with an XML declaration
+    pub xml_declaration: bool,
+    /// The system identifier of a document type declaration to write before the document element
+    pub doctype: Option<String>,
+}
+
+impl Default for XmlOptions {
+    fn default() -> Self {
+        Self {
+            lowercase_names: false,
+            expand_entities: true,
+            xml_declaration: false,
+            doctype: None,
+        }
+    }
+}
+
+/// Write a document as well-formed XML
+pub fn to_xml(document: &Document, options: &XmlOptions) -> String {
+    let mut out = String::new();
+    if options.xml_declaration {
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    }
+    if let (Some(system_id), Some(root)) = (&options.doctype, document.get_root()) {
+        let quote = if system_id.contains('"') { '\'' } else { '"' };
+        out.push_str(&format!(
+            "<!DOCTYPE {} SYSTEM {}{}{}>\n",
+            xml_name(&root.name, options),
+            quote,
+            system_id,
+            quote
+        ));
+    }
+    for child in &document.children {
+        let len = out.len();
+        write_node(child, options, &mut out);
+        if out.len() > len {
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn write_node(node: &Node, options: &XmlOptions, out: &mut String) {
+    match node {
+        Node::Element(e) => write_element(e, options, out),
+        Node::Text(text) => out.push_str(&escape_text(text)),
+        Node::EntityReference { name, text } => {
+            if options.expand_entities {
+                out.push_str(&escape_text(text))
+            } else {
+                out.push_str(&format!("&{};", name))
+            }
+        }
+        Node::ProcessingInstruction(pi) => {
+            let pi = pi.strip_suffix('?').unwrap_or(pi);
+            // The target `xml` is reserved for the XML declaration
+            let target = pi.split_whitespace().next().unwrap_or("");
+            if !target.eq_ignore_ascii_case("xml") {
+                out.push_str(&format!("<?{}?>", pi.replace("?>", "? >")));
+            }
+        }
+        Node::Comment(c) => {
+            let mut c = c.replace("--", "- -");
+            if c.ends_with('-') {
+                c.push(' ');
+            }
+            out.push_str(&format!("<!--{}-->", c));
+        }
+    }
+}
+
+fn write_element(e: &ElementNode, options: &XmlOptions, out: &mut String) {
+    let name = xml_name(&e.name, options);
+    out.push('<');
+    out.push_str(&name);
+    for a in &e.attributes {
+        let value = match &a.declared_value {
+            DeclaredValue::NameTokenGroup(_) | DeclaredValue::Notation(_)
+                if options.lowercase_names =>
+            {
+                a.value.to_lowercase()
+            }
+            _ => a.value.clone(),
+        };
+        out.push_str(&format!(
+            " {}=\"{}\"",
+            xml_name(&a.name, options),
+            escape_attribute(&value)
+        ));
+    }
+    if e.children.is_empty() {
+        out.push_str("/>");
+        return;
+    }
+    out.push('>');
+    for child in &e.children {
+        write_node(child, options, out);
+    }
+    out.push_str(&format!("</{}>", name));
+}
+
+fn xml_name(name: &str, options: &XmlOptions) -> String {
+    if options.lowercase_names {
+        name.to_lowercase()
+    } else {
+        name.to_string()
+    }
+}
+
+fn escape_text(v: &str) -> String {
+    v.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Whitespace characters are written as references, so they survive attribute value normalization
+fn escape_attribute(v: &str) -> String {
+    escape_text(v)
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+        .replace('\t', "&#9;")
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::catalog::Catalog;
+    use crate::dtd::read_dtd;
+    use crate::flatten::{flatten_dtd, FlattenOptions};
+    use crate::instance::DocumentErrorKind;
+    use std::path::{Path, PathBuf};
+
+    const MEMO_DTD: &str = r#"<!ENTITY eacute SDATA "[eacute]">
+<!ENTITY co CDATA "&#169;">
+<!ENTITY dash SDATA "&#8212;">
+<!ENTITY sig "<SIG>The Management</SIG>">
+<!ELEMENT MEMO - - ((TO & FROM)?, BODY, SIG?)>
+<!ELEMENT (TO|FROM) - O (#PCDATA)>
+<!ELEMENT BODY O O (P+)>
+<!ELEMENT P - O (#PCDATA|Q)* +(FN)>
+<!ELEMENT Q - - (#PCDATA) -(FN)>
+<!ELEMENT FN - - (#PCDATA)>
+<!ELEMENT SIG - - RCDATA>
+<!ATTLIST MEMO status (draft|final) draft
+               id ID #IMPLIED>
+"#;
+
+    /// A DTD from ./dtd, flattened with its general entities
+    pub fn read_flat_dtd(name: &str) -> DocumentTypeDefinition {
+        let s = std::fs::read_to_string(Path::new("./dtd").join(name)).unwrap();
+        let (_, dtd) = read_dtd(&s).unwrap();
+        let mut catalog = Catalog::default();
+        catalog.add_public(
+            "ISO 8879-1986//ENTITIES Added Latin 1//EN//HTML",
+            PathBuf::from("./dtd/xhtml-lat1.ent"),
+        );
+        let options = FlattenOptions {
+            keep_general_entities: true,
+            ..FlattenOptions::default()
+        };
+        flatten_dtd(&dtd, Path::new("./dtd"), &catalog, &options).unwrap()
+    }
+
+    fn memo(input: &str) -> Document {
+        let (_, dtd) = read_dtd(MEMO_DTD).unwrap();
+        parse_document(input, &dtd)
+    }
+
+    #[test]
+    pub fn test_omitted_tags() {
+        let doc = memo(
+            "<!DOCTYPE memo>\n<memo final id=' m1\n'><from>Me<to>You\n<p>Caf&eacute;\n<p>A <q>quote</q>\n</memo>\n",
+        );
+        assert_eq!(doc.errors, vec![]);
+        assert_eq!(
+            to_xml(&doc, &XmlOptions::default()),
+            "<MEMO status=\"final\" id=\"M1\"><FROM>Me</FROM><TO>You</TO><BODY><P>Café</P>\
+             <P>A <Q>quote</Q></P></BODY></MEMO>\n"
+        );
+
+        let options = XmlOptions {
+            lowercase_names: true,
+            expand_entities: false,
+            xml_declaration: true,
+            doctype: Some("memo.dtd".to_string()),
+        };
+        assert!(to_xml(&doc, &options).starts_with(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE memo SYSTEM \"memo.dtd\">\n<memo status=\"final\" id=\"M1\"><from>Me</from><to>You</to><body><p>Caf&eacute;</p>"
+        ));
+    }
+
+    #[test]
+    pub fn test_exceptions_and_entities() {
+        let doc = memo(
+            "<!DOCTYPE MEMO [<!ENTITY me \"Me\">]><MEMO><P>Note<FN>1 &co;&dash;</FN> <Q>in <FN>x</FN></Q>\n&sig;</MEMO>",
+        );
+        let root = doc.get_root().unwrap();
+        let body = root.get_elements().next().unwrap();
+        let p = body.get_elements().next().unwrap();
+        assert_eq!(p.get_elements().next().unwrap().get_text(), "1 ©—");
+        // The footnote is excluded from quotations
+        assert_eq!(
+            doc.errors.iter().map(|e| &e.kind).collect::<Vec<_>>(),
+            vec![&DocumentErrorKind::ElementNotAllowed {
+                element: "FN".to_string(),
+                context: "Q".to_string()
+            }]
+        );
+        // The entity holds markup, which ends the paragraph and the body
+        let sig = root.get_elements().last().unwrap();
+        assert_eq!(sig.name, "SIG");
+        assert_eq!(sig.get_text(), "The Management");
+    }
+
+    #[test]
+    pub fn test_validate() {
+        let (_, dtd) = read_dtd(
+            r#"<!ELEMENT DOC - - (HEAD, SECTION+) +(NOTE)>
+<!ELEMENT HEAD - O (TITLE & DATE?)>
+<!ELEMENT (TITLE|DATE|NOTE) - - (#PCDATA)>
+<!ELEMENT SECTION - - (TITLE, P*) -(NOTE)>
+<!ELEMENT P - O (#PCDATA)>
+<!ATTLIST DOC version CDATA #FIXED "2"
+              id ID #REQUIRED
+              pages NUMBER #IMPLIED
+              refs IDREFS #IMPLIED>
+"#,
+        )
+        .unwrap();
+        let errors = validate(
+            "<DOC id=d1 version=3 pages=x refs='a 1b'>\n<HEAD><DATE>Today</DATE><TITLE>A</TITLE>\n\
+             <NOTE>Allowed</NOTE><SECTION><TITLE>One</TITLE><P>Text<NOTE>No</NOTE>\n\
+             </SECTION><SECTION><P color=red>Two</SECTION><FIG></FIG></DOC>",
+            &dtd,
+        );
+        let errors = errors
+            .iter()
+            .map(|e| (e.line, e.column, e.kind.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    1,
+                    42,
+                    DocumentErrorKind::FixedAttributeValue {
+                        attribute: "version".to_string(),
+                        value: "3".to_string(),
+                        fixed: "2".to_string()
+                    }
+                ),
+                (
+                    1,
+                    42,
+                    DocumentErrorKind::InvalidAttributeValue {
+                        attribute: "pages".to_string(),
+                        value: "X".to_string()
+                    }
+                ),
+                (
+                    1,
+                    42,
+                    DocumentErrorKind::InvalidAttributeValue {
+                        attribute: "refs".to_string(),
+                        value: "A 1B".to_string()
+                    }
+                ),
+                // Notes are allowed in the document, but not in its sections
+                (
+                    3,
+                    61,
+                    DocumentErrorKind::ElementNotAllowed {
+                        element: "NOTE".to_string(),
+                        context: "SECTION".to_string()
+                    }
+                ),
+                (
+                    4,
+                    33,
+                    DocumentErrorKind::ElementNotAllowed {
+                        element: "P".to_string(),
+                        context: "SECTION".to_string()
+                    }
+                ),
+                (
+                    4,
+                    33,
+                    DocumentErrorKind::UndeclaredAttribute {
+                        element: "P".to_string(),
+                        attribute: "color".to_string()
+                    }
+                ),
+                (
+                    4,
+                    46,
+                    DocumentErrorKind::IncompleteContent {
+                        element: "SECTION".to_string(),
+                        expected: vec!["TITLE".to_string()]
+                    }
+                ),
+                (
+                    4,
+                    51,
+                    DocumentErrorKind::UndeclaredElement("FIG".to_string())
+                ),
+            ]
+        );
+
+        // Broken markup is reported rather than stopping the check
+        let errors = validate("<DOC><HEAD><TITLE>A</TITLE></DOC", &dtd);
+        assert!(errors.iter().any(|e| e.kind
+            == DocumentErrorKind::MissingAttribute {
+                element: "DOC".to_string(),
+                attribute: "id".to_string()
+            }));
+        assert!(errors.iter().any(|e| matches!(
+            &e.kind,
+            DocumentErrorKind::IncompleteContent { element, expected }
+                if element == "DOC" && expected == &["SECTION".to_string()]
+        )));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_serde() {
+        let doc = memo("<MEMO final><P>One <Q>two</Q><!-- note --></MEMO>");
+        let json = serde_json::to_value(&doc).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"name": "MEMO", "attributes": {"status": "final"}, "content": [
+                {"name": "BODY", "content": [
+                    {"name": "P", "content": ["One ", {"name": "Q", "content": ["two"]}]}
+                ]}
+            ]})
+        );
+
+        // Reading it back gives the same elements, with the values as they are in JSON
+        let read = serde_json::from_value::<Document>(json.clone()).unwrap();
+        assert_eq!(read.get_root().unwrap().get_text(), "One two");
+        assert_eq!(serde_json::to_value(&read).unwrap(), json);
+
+        let numbers =
+            serde_json::json!({"name": "A", "attributes": {"n": 1, "ns": [1, 2], "t": ["a", "b"]}});
+        let a = serde_json::from_value::<ElementNode>(numbers.clone()).unwrap();
+        assert_eq!(a.attributes[1].value, "1 2");
+        assert_eq!(a.attributes[1].declared_value, DeclaredValue::Numbers);
+        assert_eq!(a.attributes[2].declared_value, DeclaredValue::NmTokens);
+        assert_eq!(serde_json::to_value(&a).unwrap(), numbers);
+    }
+
+    /// Documents are written in the encoding the JSON Schema of their DTD describes
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_serde_matches_json_schema() {
+        use crate::json_schema::test::validate;
+        use crate::json_schema::to_json_schema;
+        use crate::schema::test::html_schema_dtd;
+        use crate::schema::SchemaOptions;
+
+        let schema = |dtd: &DocumentTypeDefinition| {
+            let schema = to_json_schema(dtd, &SchemaOptions::default()).unwrap();
+            serde_json::from_str(&schema.to_string()).unwrap()
+        };
+        let check = |doc: &Document, dtd: &DocumentTypeDefinition| {
+            let json = serde_json::to_value(doc).unwrap();
+            let errors = validate(&schema(dtd), &json);
+            assert!(errors.is_empty(), "{:#}\n{:?}", json, errors);
+        };
+
+        let (_, dtd) = read_dtd(MEMO_DTD).unwrap();
+        let doc = parse_document(
+            "<MEMO id=m1 final><TO>You<FROM>Me<P>One <Q>two</Q>&co;<FN>x</FN><SIG>Us</SIG></MEMO>",
+            &dtd,
+        );
+        assert_eq!(doc.errors, vec![]);
+        check(&doc, &dtd);
+
+        // The schema tells the encoding apart from other JSON
+        let json = serde_json::to_value(&doc).unwrap();
+        let invalid = |change: &dyn Fn(&mut serde_json::Value)| {
+            let mut json = json.clone();
+            change(&mut json);
+            !validate(&schema(&dtd), &json).is_empty()
+        };
+        assert!(invalid(&|j| j["attributes"]["status"] = "sent".into()));
+        assert!(invalid(&|j| j["content"][0]["content"][0] = 1.into()));
+        assert!(invalid(&|j| drop(
+            j["content"].as_array_mut().unwrap().remove(2)
+        )));
+        assert!(invalid(&|j| j["name"] = "memo".into()));
+
+        let doc = parse_document(
+            "<title>Test</title>\n<h1>Hi</h1>\n<p>One\n<p>Two<br>\n<ul compact><li>A<li>B</ul>\n\
+             <pre width=40>x</pre><img src=a.png alt=a ismap>",
+            &read_flat_dtd("html.dtd"),
+        );
+        assert_eq!(doc.errors, vec![]);
+        check(&doc, &html_schema_dtd());
+    }
+
+    #[test]
+    pub fn test_marked_sections_and_errors() {
+        let doc = memo(
+            "<!-- a -- -- b --><MEMO><![ IGNORE [<P>gone]]><![CDATA[<P>]]><![ INCLUDE [<P>x]]><TO>y</MEMO></BOGUS>",
+        );
+        assert_eq!(
+            to_xml(&doc, &XmlOptions::default()),
+            "<!-- a -->\n<!-- b -->\n<MEMO>&lt;P&gt;<BODY><P>x</P></BODY><TO>y</TO></MEMO>\n"
+        );
+        let kinds = doc.errors.iter().map(|e| &e.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                &DocumentErrorKind::DataNotAllowed {
+                    context: "MEMO".to_string()
+                },
+                &DocumentErrorKind::ElementNotAllowed {
+                    element: "TO".to_string(),
+                    context: "MEMO".to_string()
+                },
+                &DocumentErrorKind::EndTagNotOpen("BOGUS".to_string()),
+            ]
+        );
+        assert_eq!(doc.errors[2].line, 1);
+    }
+
+    #[test]
+    pub fn test_html_document() {
+        let dtd = read_flat_dtd("html.dtd");
+        let doc = parse_document(
+            "<!DOCTYPE HTML PUBLIC \"-//IETF//DTD HTML 2.0//EN\">\n<title>Test</title>\n<h1>Hi</h1>\n<p>One\n<p>Two<br>\n<ul compact><li>A<li>B</ul>\n",
+            &dtd,
+        );
+        assert_eq!(doc.errors, vec![]);
+        assert_eq!(
+            doc.doctype.as_ref().unwrap().public_id.as_deref(),
+            Some("-//IETF//DTD HTML 2.0//EN")
+        );
+        let options = XmlOptions {
+            lowercase_names: true,
+            ..XmlOptions::default()
+        };
+        assert_eq!(
+            to_xml(&doc, &options),
+            "<html><head><title>Test</title></head><body><h1>Hi</h1>\n<p>One</p>\
+             <p>Two<br/></p><ul compact=\"compact\"><li>A</li><li>B</li></ul></body></html>\n"
+        );
+    }
+
+    #[test]
+    pub fn test_xhtml_document() {
+        let dtd = read_flat_dtd("xhtml1-strict.dtd");
+        let doc = parse_document(
+            "<?xml version=\"1.0\"?>\n<html xmlns=\"http://www.w3.org/1999/xhtml\"><head><title>T</title></head>\
+             <body><p class=\"a  b\">&nbsp;&amp;</p></body></html>",
+            &dtd,
+        );
+        assert_eq!(doc.errors, vec![]);
+        assert_eq!(
+            to_xml(&doc, &XmlOptions::default()),
+            "<html xmlns=\"http://www.w3.org/1999/xhtml\"><head><title>T</title></head>\
+             <body><p class=\"a  b\">\u{a0}&amp;</p></body></html>\n"
+        );
+    }
+
+    #[test]
+    pub fn test_sgml_declaration() {
+        let input = "<!SGML 'ISO 8879:1986' NAMECASE GENERAL NO ENTITY NO>\n<MEMO><P>x</MEMO>";
+        assert_eq!(memo(input).errors, vec![]);
+        let doc = memo(&input.replace("<P>", "<p>"));
+        assert_eq!(
+            doc.errors[0].kind,
+            DocumentErrorKind::UndeclaredElement("p".to_string())
+        );
+    }
+}
@@ -1,17 +1,29 @@
-use crate::att_list::{parse_att_list, ATTList, ATTListElement};
-use crate::comment::parse_comment_block;
+use crate::att_list::{
+    parse_attribute_definitions, parse_borrowed_att_list, ATTList, ATTListElement,
+    AttributeDefinition,
+};
+use crate::automaton::{find_ambiguity, Ambiguity};
+use crate::borrowed;
+use crate::comment::parse_borrowed_comment_block;
+use crate::content_model::{parse_declared_content, ContentModel, DeclaredContent};
 use crate::element::{
-    parse_content_model, parse_content_model_group, parse_element, ContentModelToken,
-    ContentModelTokenValue, Element, ElementName,
+    parse_borrowed_element, parse_content_model, parse_content_model_group,
+    parse_element_name_group, ContentModelToken, Element, ElementName,
+};
+use crate::entity::{
+    parse_borrowed_entity, parse_borrowed_parameter_reference, Entity, ParameterReference,
 };
-use crate::entity::{parse_entity, parse_parameter_reference, Entity, ParameterReference};
-use crate::marked_section::{parse_marked_section, MarkedSection};
+use crate::marked_section::{parse_borrowed_marked_section, MarkedSection};
+use crate::template_strings::TemplateString;
 use nom::branch::alt;
 use nom::bytes::complete::{take_while, take_while1};
 use nom::error::ErrorKind;
 use nom::multi::many0;
 use nom::sequence::tuple;
 use nom::IResult;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::OnceLock;
 
 /// See ISO(B.8.1)
 pub const MARKUP_DECLARATION_OPEN: &str = "<!";
@@ -27,155 +39,330 @@ pub struct DocumentTypeDefinitionNode {
     pub children: Vec<DocumentTypeDefinitionNode>,
 }
 
-#[derive(Debug, Clone)]
+/// An element of a DTD, with any name group in its declaration split into one element per name
+#[derive(Clone, Copy)]
 pub struct DocumentTypeDefinitionElement<'a> {
-    element: Element,
+    /// Position in the name index of the tree
+    index: usize,
     tree: &'a DocumentTypeDefinition,
 }
 
+impl<'a> fmt::Debug for DocumentTypeDefinitionElement<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DocumentTypeDefinitionElement")
+            .field("name", &self.name())
+            .field("element", self.get_element())
+            .finish()
+    }
+}
+
 impl<'a> DocumentTypeDefinitionElement<'a> {
-    pub fn get_content_model(&self) -> Vec<ContentModelToken> {
-        let (_, x) = parse_content_model(
-            self.element
-                .content_model
-                .expand(&self.tree.entities)
-                .as_str(),
-        )
-        .unwrap();
-        x
+    /// The declaration this element was declared by, which may declare other elements as well
+    pub fn get_element(&self) -> &'a Element {
+        &self.tree.elements[self.tree.element_names[self.index].1]
     }
 
-    ///TODO: not really needed, end user won't care if this was a (a|B) element or not (should this change be propegated to the parser as well?
-    pub fn decompose(&self) -> Vec<Self> {
-        self.element
-            .decompose(&self.tree.entities)
-            .into_iter()
-            .map(|e| DocumentTypeDefinitionElement {
-                element: e,
-                tree: self.tree,
+    pub fn name(&self) -> &'a str {
+        &self.tree.element_names[self.index].0
+    }
+
+    /// The content model of the declaration, expanded and parsed on first use, if its references
+    /// can be resolved and it can be parsed
+    pub fn get_content_model(&self) -> Option<&'a [ContentModelToken]> {
+        let declaration = self.tree.element_names[self.index].1;
+        self.tree.content_models[declaration]
+            .get_or_init(|| {
+                let expanded = self
+                    .get_element()
+                    .content_model
+                    .try_expand_in(self.tree)
+                    .ok()?;
+                parse_content_model(&expanded).ok().map(|(_, x)| x)
             })
-            .collect::<Vec<_>>()
+            .as_deref()
     }
 
-    pub fn get_name(&self) -> String {
-        self.element
-            .get_name(&self.tree.entities)
-            .as_single()
-            .expect("No children")
-    }
-
-    //TODO: return result (err if child is not found?)
-    pub fn get_children(&self) -> Vec<Self> {
-        let cm = self.get_content_model();
-        println!("Children of {}", self.get_name());
-
-        cm.iter()
-            .filter(|c| matches!(c.value, ContentModelTokenValue::Reference(_)))
-            .map(|c| {
-                match &c.value {
-                    ContentModelTokenValue::Reference(s) => s.clone(),
-                    _ => unimplemented!(),
+    /// The declared content of the declaration, if its references can be resolved and it is well formed
+    pub fn get_content(&self) -> Option<&'a ContentModel> {
+        let declaration = self.tree.element_names[self.index].1;
+        self.tree.declared_contents[declaration]
+            .get_or_init(|| {
+                let expanded = self
+                    .get_element()
+                    .content_model
+                    .try_expand_in(self.tree)
+                    .ok()?;
+                match parse_declared_content(&expanded) {
+                    Ok(("", model)) => Some(model),
+                    _ => None,
                 }
-                //TODO: this filter is a big hack: figure out why the parse can't parse HEAD correctly
-                //TODO: different bug: add support for tokenValue::pcdata in the parser
-            })
-            .filter(|c| c.len() > 0 && c != "#PCDATA")
-            .map(|name| {
-                self.tree
-                    .get_element_by_name(&name)
-                    .expect(&format!("Can't find element by name '{}'", name))
             })
-            .collect::<Vec<_>>()
+            .as_ref()
+    }
+
+    pub fn get_name(&self) -> String {
+        self.name().to_string()
+    }
+
+    /// See `DocumentTypeDefinition::get_attributes`
+    pub fn get_attributes(&self) -> Vec<&'a AttributeDefinition> {
+        self.tree.get_attributes(self.name())
+    }
+
+    /// The elements named in the content model, each once, or None if one of them isn't declared
+    pub fn get_children(&self) -> Option<Vec<Self>> {
+        self.get_content()
+            .map(|c| c.get_element_names())
+            .unwrap_or_default()
+            .iter()
+            .map(|name| self.tree.get_element_by_name(name))
+            .collect()
     }
 }
 
 /// See ISO(B.3.2)
+/// The lookups are indexed when the DTD is built from its declarations, which can't be changed
+/// afterwards
 #[derive(Debug, Clone)]
 pub struct DocumentTypeDefinition {
-    pub entities: Vec<Entity>,
-    pub elements: Vec<Element>,
+    entities: Vec<Entity>,
+    elements: Vec<Element>,
+    att_lists: Vec<ATTList>,
+    /// Every declaration in source order, including comments and marked sections
+    declarations: Vec<DTDElement>,
+    /// Positions in `entities` of the parameter entities, the first declaration of a name is binding
+    parameter_entities: HashMap<String, usize>,
+    /// Each name declared by each element declaration, with the position of the declaration in `elements`
+    element_names: Vec<(String, usize)>,
+    /// Positions in `element_names`, keyed by the folded name
+    element_index: HashMap<String, usize>,
+    /// The parsed content model of each element declaration, filled in on first use
+    content_models: Vec<OnceLock<Option<Vec<ContentModelToken>>>>,
+    /// The declared content of each element declaration, filled in on first use
+    declared_contents: Vec<OnceLock<Option<ContentModel>>>,
+    /// Positions in `att_lists` of the lists that apply to each element, keyed by the folded name
+    att_list_index: HashMap<String, Vec<usize>>,
+    /// The parsed definitions of each attribute list, filled in on first use
+    attribute_definitions: Vec<OnceLock<Vec<AttributeDefinition>>>,
 }
 
 impl From<Vec<DTDElement>> for DocumentTypeDefinition {
-    fn from(elements: Vec<DTDElement>) -> Self {
-        let entities = elements
-            .clone()
-            .into_iter()
-            .filter(|e| matches!(e, DTDElement::Entity(_)))
-            .map(|e| match e {
-                DTDElement::Entity(e) => e,
-                _ => unreachable!(),
+    fn from(declarations: Vec<DTDElement>) -> Self {
+        let entities = declarations
+            .iter()
+            .filter_map(|e| match e {
+                DTDElement::Entity(e) => Some(e.clone()),
+                _ => None,
             })
             .collect::<Vec<_>>();
 
-        let elements = elements
-            .into_iter()
-            .filter(|e| matches!(e, DTDElement::Element(_)))
-            .map(|e| match e {
-                DTDElement::Element(e) => e,
-                _ => unreachable!(),
+        let elements = declarations
+            .iter()
+            .filter_map(|e| match e {
+                DTDElement::Element(e) => Some(e.clone()),
+                _ => None,
             })
             .collect::<Vec<_>>();
 
-        Self { entities, elements }
+        let att_lists = declarations
+            .iter()
+            .filter_map(|e| match e {
+                DTDElement::ATTList(a) => Some(a.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut parameter_entities = HashMap::new();
+        for (position, e) in entities.iter().enumerate() {
+            if e.parameter {
+                parameter_entities.entry(e.name.clone()).or_insert(position);
+            }
+        }
+
+        let mut dtd = Self {
+            content_models: elements.iter().map(|_| OnceLock::new()).collect(),
+            declared_contents: elements.iter().map(|_| OnceLock::new()).collect(),
+            entities,
+            elements,
+            declarations,
+            parameter_entities,
+            element_names: Vec::new(),
+            element_index: HashMap::new(),
+            attribute_definitions: att_lists.iter().map(|_| OnceLock::new()).collect(),
+            att_lists,
+            att_list_index: HashMap::new(),
+        };
+
+        // Declarations with names that can't be resolved can't be looked up, so are left out
+        let mut element_names = Vec::new();
+        for (position, element) in dtd.elements.iter().enumerate() {
+            for name in dtd.declared_names(&element.name) {
+                element_names.push((name, position));
+            }
+        }
+        for (index, (name, _)) in element_names.iter().enumerate() {
+            dtd.element_index.entry(fold_name(name)).or_insert(index);
+        }
+        dtd.element_names = element_names;
+
+        let mut att_list_index = HashMap::new();
+        for (position, list) in dtd.att_lists.iter().enumerate() {
+            for name in dtd.declared_names(&list.name) {
+                att_list_index
+                    .entry(fold_name(&name))
+                    .or_insert_with(Vec::new)
+                    .push(position);
+            }
+        }
+        dtd.att_list_index = att_list_index;
+
+        dtd
     }
 }
 
+/// Serialized as the list of its declarations in source order, the lookups are indexed again when
+/// it is deserialized
+#[cfg(feature = "serde")]
+impl serde::Serialize for DocumentTypeDefinition {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.declarations.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DocumentTypeDefinition {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<DTDElement>::deserialize(deserializer).map(Self::from)
+    }
+}
+
+/// The name as element names are compared, upper case as with the default NAMECASE GENERAL YES
+fn fold_name(name: &str) -> String {
+    name.to_uppercase()
+}
+
 impl DocumentTypeDefinition {
-    pub fn get_element_by_name(&self, name: &str) -> Option<DocumentTypeDefinitionElement> {
-        self.get_elements()
-            .into_iter()
-            .find(|e| e.get_name() == name)
+    /// Every entity declaration in source order, outside marked sections
+    pub fn get_entities(&self) -> &[Entity] {
+        &self.entities
     }
 
-    pub fn get_elements(&self) -> Vec<DocumentTypeDefinitionElement> {
-        self.elements
-            .iter()
-            .cloned()
-            .flat_map(|e| {
-                DocumentTypeDefinitionElement {
-                    element: e,
-                    tree: self,
+    /// Every element declaration in source order, outside marked sections
+    pub fn get_element_declarations(&self) -> &[Element] {
+        &self.elements
+    }
+
+    /// Every attribute list declaration in source order, outside marked sections
+    pub fn get_att_lists(&self) -> &[ATTList] {
+        &self.att_lists
+    }
+
+    /// Every declaration in source order, including comments and marked sections
+    pub fn get_declarations(&self) -> &[DTDElement] {
+        &self.declarations
+    }
+
+    /// The element names given by the name or name group of a declaration, if its references can be resolved
+    fn declared_names(&self, name: &TemplateString) -> Vec<String> {
+        match name.try_expand_in(self) {
+            Ok(expanded) => match parse_element_name_group(&expanded) {
+                Ok((_, names)) => names,
+                Err(_) => vec![expanded.trim().to_string()],
+            },
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// The attributes defined for an element, ignoring case in its name
+    /// When several lists define the same attribute, the first definition is binding
+    pub fn get_attributes(&self, element: &str) -> Vec<&AttributeDefinition> {
+        let mut attributes: Vec<&AttributeDefinition> = Vec::new();
+        let positions = self.att_list_index.get(&fold_name(element));
+        for &position in positions.into_iter().flatten() {
+            let definitions = self.attribute_definitions[position].get_or_init(|| {
+                self.att_lists[position]
+                    .value
+                    .try_expand_in(self)
+                    .ok()
+                    .and_then(|expanded| match parse_attribute_definitions(&expanded) {
+                        Ok((rest, definitions)) if rest.trim().is_empty() => Some(definitions),
+                        _ => None,
+                    })
+                    .unwrap_or_default()
+            });
+            for definition in definitions {
+                if !attributes
+                    .iter()
+                    .any(|a| a.name.eq_ignore_ascii_case(&definition.name))
+                {
+                    attributes.push(definition);
                 }
-                .decompose()
-            })
-            .collect::<Vec<_>>()
+            }
+        }
+        attributes
     }
 
-    pub fn get_children(&self, element: &Element) -> Vec<ContentModelToken> {
-        let elements = &self.elements;
-        let s = element.content_model.expand(&self.entities);
-        let (_, cm) = parse_content_model(&s).expect("Failed to parse content model");
-        cm
+    /// The binding declaration of a parameter entity
+    pub fn get_parameter_entity(&self, name: &str) -> Option<&Entity> {
+        self.parameter_entities
+            .get(name)
+            .map(|&position| &self.entities[position])
     }
 
-    pub fn get_roots(&self) -> Vec<DocumentTypeDefinitionElement> {
-        let elements = self.get_elements();
+    /// Find an element by its name, ignoring case
+    pub fn get_element_by_name(&self, name: &str) -> Option<DocumentTypeDefinitionElement<'_>> {
+        self.element_index
+            .get(&fold_name(name))
+            .map(|&index| DocumentTypeDefinitionElement { index, tree: self })
+    }
 
-        let mut found = Vec::new();
+    /// Every element in declaration order, with name groups split into one element per name
+    pub fn get_elements(&self) -> impl Iterator<Item = DocumentTypeDefinitionElement<'_>> {
+        (0..self.element_names.len())
+            .map(move |index| DocumentTypeDefinitionElement { index, tree: self })
+    }
 
-        for z in &elements {
-            let cm = z.get_content_model();
+    /// The tokens of an element's content model, or None if it can't be parsed
+    pub fn get_children(&self, element: &Element) -> Option<Vec<ContentModelToken>> {
+        let s = element.content_model.expand_in(self);
+        parse_content_model(&s).ok().map(|(_, cm)| cm)
+    }
 
-            for c in &cm {
-                match c.value {
-                    ContentModelTokenValue::Reference(ref s) => {
-                        found.push(s.clone());
-                    }
-                    _ => {}
+    /// The elements that aren't referenced by the content model or inclusions of any element
+    pub fn get_roots(&self) -> Vec<DocumentTypeDefinitionElement<'_>> {
+        let mut found = HashSet::new();
+
+        for z in self.get_elements() {
+            if let Some(content) = z.get_content() {
+                for name in content
+                    .get_element_names()
+                    .iter()
+                    .chain(&content.inclusions)
+                {
+                    found.insert(fold_name(name));
                 }
             }
         }
 
-        elements
-            .into_iter()
-            .flat_map(|e| e.decompose())
-            .filter(|e| !found.contains(&e.get_name()))
+        self.get_elements()
+            .filter(|e| !found.contains(&fold_name(e.name())))
             .collect::<Vec<_>>()
     }
+
+    /// The elements whose content models are ambiguous, with the first conflict in each,
+    /// See ISO(11.2.4.3)
+    pub fn get_ambiguities(&self) -> Vec<(DocumentTypeDefinitionElement<'_>, Ambiguity)> {
+        self.get_elements()
+            .filter_map(|e| match &e.get_content()?.content {
+                DeclaredContent::Group(g) => Some((e, find_ambiguity(g, &fold_name)?)),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DTDElement {
     WhiteSpace(String),
     Comment(String),
@@ -202,25 +389,32 @@ pub fn take_until_whitespace(i: &str) -> IResult<&str, &str> {
 }
 
 pub fn parse_dtd_element(i: &str) -> IResult<&str, DTDElement> {
+    let (i, element) = parse_borrowed_dtd_element(i)?;
+    Ok((i, element.into_owned()))
+}
+
+pub fn parse_borrowed_dtd_element(i: &str) -> IResult<&str, borrowed::DTDElement<'_>> {
+    use borrowed::DTDElement;
+
     if let Ok((i, w)) = take_whitespace(i) {
-        return Ok((i, DTDElement::WhiteSpace(w.to_string())));
+        return Ok((i, DTDElement::WhiteSpace(w)));
     }
-    if let Ok((i, s)) = parse_comment_block(i) {
+    if let Ok((i, s)) = parse_borrowed_comment_block(i) {
         return Ok((i, DTDElement::Comment(s)));
     }
-    if let Ok((i, e)) = parse_entity(i) {
+    if let Ok((i, e)) = parse_borrowed_entity(i) {
         return Ok((i, DTDElement::Entity(e)));
     }
-    if let Ok((i, e)) = parse_element(i) {
+    if let Ok((i, e)) = parse_borrowed_element(i) {
         return Ok((i, DTDElement::Element(e)));
     }
-    if let Ok((i, ms)) = parse_marked_section(i) {
+    if let Ok((i, ms)) = parse_borrowed_marked_section(i) {
         return Ok((i, DTDElement::MarkedSection(ms)));
     }
-    if let Ok((i, pr)) = parse_parameter_reference(i) {
+    if let Ok((i, pr)) = parse_borrowed_parameter_reference(i) {
         return Ok((i, DTDElement::ParameterReference(pr)));
     }
-    if let Ok((i, at)) = parse_att_list(i) {
+    if let Ok((i, at)) = parse_borrowed_att_list(i) {
         return Ok((i, DTDElement::ATTList(at)));
     }
 
@@ -240,9 +434,18 @@ pub fn read_dtd(i: &str) -> IResult<&str, DocumentTypeDefinition> {
     Ok((i, elements.into()))
 }
 
+/// Like `read_dtd`, but borrows names and literals from the input rather than copying them
+pub fn read_borrowed_dtd(i: &str) -> IResult<&str, borrowed::DocumentTypeDefinition<'_>> {
+    let (i, declarations) = many0(parse_borrowed_dtd_element)(i)?;
+
+    Ok((i, borrowed::DocumentTypeDefinition { declarations }))
+}
+
 #[cfg(test)]
 pub mod test {
     use crate::dtd::read_dtd;
+    #[cfg(feature = "serde")]
+    use crate::dtd::DocumentTypeDefinition;
     use std::fs::File;
     use std::io::Read;
 
@@ -282,6 +485,150 @@ pub mod test {
         assert_eq!(i, "");
     }
 
+    #[test]
+    pub fn test_element_index() {
+        let (_, dtd) = read_dtd(
+            "<!ENTITY % heading \"H1|H2\">
+<!ENTITY % heading \"H3\">
+<!ELEMENT memo - O (body)>
+<!ELEMENT body - - (%heading;)*>
+<!ELEMENT (%heading;) - - (#PCDATA)>",
+        )
+        .unwrap();
+
+        // The first declaration of an entity is binding
+        assert_eq!(
+            dtd.get_parameter_entity("heading").unwrap().content,
+            "H1|H2".into()
+        );
+
+        // Name groups are split, and names are matched ignoring case
+        let names = dtd.get_elements().map(|e| e.get_name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["memo", "body", "H1", "H2"]);
+        let h2 = dtd.get_element_by_name("h2").unwrap();
+        assert_eq!(h2.get_name(), "H2");
+        assert!(dtd.get_element_by_name("H3").is_none());
+
+        // Both names share the content model of their declaration
+        let h1 = dtd.get_element_by_name("H1").unwrap();
+        assert!(std::ptr::eq(
+            h1.get_content_model().unwrap(),
+            h2.get_content_model().unwrap()
+        ));
+        assert!(h1.get_content().unwrap().is_mixed());
+        assert!(!dtd
+            .get_element_by_name("body")
+            .unwrap()
+            .get_content()
+            .unwrap()
+            .is_mixed());
+
+        let roots = dtd.get_roots();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].get_name(), "memo");
+        let children = dtd.get_element_by_name("BODY").unwrap().get_children();
+        assert_eq!(children.map(|c| c.len()), Some(2));
+    }
+
+    #[test]
+    pub fn test_roots() {
+        let (_, dtd) = read_dtd(
+            "<!ELEMENT list - - (list-item+) +(note)>
+<!ELEMENT list-item - O (#PCDATA)>
+<!ELEMENT note - - (#PCDATA)>
+<!ELEMENT bad - - (%missing;)>",
+        )
+        .unwrap();
+        let roots = dtd.get_roots();
+        let names = roots.iter().map(|e| e.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["list", "bad"]);
+        assert!(dtd
+            .get_element_by_name("bad")
+            .unwrap()
+            .get_content_model()
+            .is_none());
+        let children = dtd.get_element_by_name("list").unwrap().get_children();
+        assert_eq!(children.unwrap()[0].name(), "list-item");
+        let (_, dtd) = read_dtd("<!ELEMENT list - - (item+)>").unwrap();
+        let list = dtd.get_element_by_name("list").unwrap();
+        assert!(list.get_children().is_none());
+    }
+
+    #[test]
+    pub fn test_ambiguities() {
+        let (_, dtd) = read_dtd(
+            "<!ENTITY % inline \"em|strong\">
+<!ELEMENT doc - - (head, (p|list)*)>
+<!ELEMENT head - - ((title, subtitle?) | (title, date))>
+<!ELEMENT (list|menu) - - ((item & label?), label)>
+<!ELEMENT (title|subtitle|date|item|label|p) - - (#PCDATA|%inline;)*>",
+        )
+        .unwrap();
+        let ambiguities = dtd
+            .get_ambiguities()
+            .into_iter()
+            .map(|(e, a)| format!("{}: {}", e.get_name(), a))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ambiguities,
+            vec![
+                "head: TITLE matches more than one token of the model at the start",
+                "list: LABEL matches more than one token of the model after ITEM",
+                "menu: LABEL matches more than one token of the model after ITEM",
+            ]
+        );
+
+        for name in ["html.dtd", "xhtml1-strict.dtd"] {
+            let dtd = crate::document::test::read_flat_dtd(name);
+            let ambiguities = dtd.get_ambiguities();
+            assert!(ambiguities.is_empty(), "{} {:?}", name, ambiguities);
+        }
+    }
+
+    #[test]
+    pub fn test_attribute_index() {
+        let (_, dtd) = read_dtd(
+            "<!ENTITY % align \"ALIGN (left|right) left\">
+<!ELEMENT (H1|H2) - - (#PCDATA)>
+<!ATTLIST (H1|H2) %align; ID ID #IMPLIED>
+<!ATTLIST H1 ALIGN CDATA #REQUIRED CLASS CDATA #IMPLIED>",
+        )
+        .unwrap();
+
+        let h1 = dtd.get_element_by_name("h1").unwrap().get_attributes();
+        let names = h1.iter().map(|a| a.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["ALIGN", "ID", "CLASS"]);
+        assert_eq!(h1[0].declared_value.to_string(), "(left | right)");
+        assert_eq!(dtd.get_attributes("H2").len(), 2);
+        assert!(dtd.get_attributes("H3").is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn test_serde() {
+        use crate::printer::{print_dtd, PrintOptions};
+
+        let (_, dtd) = read_dtd(
+            "<!ENTITY % text \"#PCDATA|EM\">
+<!ELEMENT P - O (%text;)*>
+<!ATTLIST P ALIGN (left|right) left>",
+        )
+        .unwrap();
+        let json = serde_json::to_string(&dtd).unwrap();
+        assert!(json.starts_with(r#"[{"Entity":{"name":"text""#));
+
+        let read: DocumentTypeDefinition = serde_json::from_str(&json).unwrap();
+        let options = PrintOptions::default();
+        assert_eq!(print_dtd(&read, &options), print_dtd(&dtd, &options));
+        assert!(read
+            .get_element_by_name("p")
+            .unwrap()
+            .get_content()
+            .unwrap()
+            .is_mixed());
+        assert_eq!(read.get_attributes("P").len(), 1);
+    }
+
     #[test]
     pub fn test_read_html_dtd() {
         let mut f = File::open("./dtd/html.dtd").unwrap();
@@ -1,10 +1,14 @@
+use crate::borrowed::{self, TemplateStr};
+use crate::content_model::{parse_declared_content, ContentModel};
 use crate::dtd::{take_whitespace, take_whitespace_opt};
 use crate::entity::Entity;
-use crate::template_strings::{parse_string, ChainElement, TemplateString};
+use crate::template_strings::{parse_borrowed_string, ChainElement, TemplateString};
+use nom::branch::alt;
 use nom::bytes::complete::{tag, tag_no_case, take_until, take_while};
 use nom::combinator::opt;
 use nom::error::ErrorKind;
-use nom::multi::{separated_list, separated_nonempty_list};
+use nom::multi::separated_list1;
+use nom::sequence::delimited;
 use nom::IResult;
 
 #[derive(Debug, Clone)]
@@ -34,8 +38,11 @@ impl ElementName {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Element {
     pub name: TemplateString,
+    /// False when the declaration has no omitted tag minimization parameters, as in XML DTDs
+    pub omitted_tag_minimization: bool,
     pub start_optional: bool,
     pub end_optional: bool,
     pub content_model: TemplateString,
@@ -60,6 +67,15 @@ impl Element {
         }
     }
 
+    /// The parsed content model, if its references can be resolved and it is well formed
+    pub fn get_content(&self, entities: &[Entity]) -> Option<ContentModel> {
+        let expanded = self.content_model.try_expand(entities).ok()?;
+        match parse_declared_content(&expanded) {
+            Ok(("", model)) => Some(model),
+            _ => None,
+        }
+    }
+
     /// Converts groups elements into individual elements
     pub fn decompose(&self, entities: &[Entity]) -> Vec<Element> {
         match self.get_name(entities) {
@@ -68,6 +84,7 @@ impl Element {
                 .iter()
                 .map(|f| Element {
                     name: f.as_str().into(),
+                    omitted_tag_minimization: self.omitted_tag_minimization,
                     start_optional: self.start_optional,
                     end_optional: self.end_optional,
                     content_model: self.content_model.clone(),
@@ -78,17 +95,40 @@ impl Element {
 }
 
 pub fn parse_name_group_string(i: &str) -> IResult<&str, TemplateString> {
+    let (i, name) = parse_borrowed_name_group_string(i)?;
+    Ok((i, name.into_owned()))
+}
+
+pub fn parse_borrowed_name_group_string(i: &str) -> IResult<&str, TemplateStr<'_>> {
     let (i, _grpo) = tag(GROUP_OPEN)(i)?;
-    parse_string(i, ')', false)
+    parse_borrowed_string(i, ')', false)
+}
+
+fn take_tag_omission(i: &str) -> IResult<&str, bool> {
+    let (i, omission) = alt((tag("-"), tag_no_case("O")))(i)?;
+    let (i, _) = take_whitespace(i)?;
+    Ok((i, omission != "-"))
+}
+
+/// See ISO(B.4.2.5)
+pub fn parse_omitted_tag_minimization(i: &str) -> IResult<&str, (bool, bool)> {
+    let (i, start_optional) = take_tag_omission(i)?;
+    let (i, end_optional) = take_tag_omission(i)?;
+    Ok((i, (start_optional, end_optional)))
 }
 
 pub fn parse_element(i: &str) -> IResult<&str, Element> {
+    let (i, element) = parse_borrowed_element(i)?;
+    Ok((i, element.into_owned()))
+}
+
+pub fn parse_borrowed_element(i: &str) -> IResult<&str, borrowed::Element<'_>> {
     let (i, _start) = tag_no_case("<!element ")(i)?;
 
     // Try parsing names as a group first
     let mut i = i;
     let mut name;
-    if let Ok((j, name_group)) = parse_name_group_string(i) {
+    if let Ok((j, name_group)) = parse_borrowed_name_group_string(i) {
         name = name_group;
         i = j;
     } else {
@@ -98,20 +138,20 @@ pub fn parse_element(i: &str) -> IResult<&str, Element> {
     }
 
     let (i, _) = take_whitespace(i)?;
-    let (i, start_tag) = take_until(" ")(i)?;
-    let (i, _) = take_whitespace(i)?;
-    let (i, end_tag) = take_until(" ")(i)?;
-    let (i, _) = take_whitespace(i)?;
+    // Minimization parameters are left out entirely in XML DTDs
+    let (i, minimization) = opt(parse_omitted_tag_minimization)(i)?;
 
-    let (i, content) = parse_string(i, '>', true)?;
+    let (i, content) = parse_borrowed_string(i, '>', true)?;
     // let (i, _) = tag(">")(i)?;
 
+    let (start_optional, end_optional) = minimization.unwrap_or((false, false));
     Ok((
         i,
-        Element {
+        borrowed::Element {
             name,
-            start_optional: start_tag == "O",
-            end_optional: end_tag == "O",
+            omitted_tag_minimization: minimization.is_some(),
+            start_optional,
+            end_optional,
             content_model: content,
         },
     ))
@@ -171,6 +211,17 @@ pub fn test_spaced_group() {
     // assert_eq!(e.content_model.expand(&[]), "(text)*");
 }
 
+#[test]
+pub fn test_no_minimization() {
+    let x = parse_element("<!ELEMENT html (head, body)>");
+    println!("{:?}", x);
+    let (i, e) = x.unwrap();
+    assert_eq!(i, "");
+    assert_eq!(e.name, "html".into());
+    assert_eq!(e.omitted_tag_minimization, false);
+    assert_eq!(e.content_model.expand(&[]), "(head, body)");
+}
+
 #[derive(Debug, Clone)]
 pub enum ContentModelTokenValue {
     Empty,
@@ -219,7 +270,7 @@ pub fn parse_content_model_token(i: &str) -> IResult<&str, ContentModelToken> {
 }
 
 fn take_empty(i: &str) -> IResult<&str, &str> {
-    tag("EMPTY")(i)
+    tag_no_case("EMPTY")(i)
 }
 
 fn take_group_close(i: &str) -> IResult<&str, &str> {
@@ -228,11 +279,16 @@ fn take_group_close(i: &str) -> IResult<&str, &str> {
 
 pub fn parse_element_name_group(i: &str) -> IResult<&str, Vec<String>> {
     if !i.contains('|') {
-        return Err(nom::Err::Error((i, ErrorKind::Tag)));
+        return Err(nom::Err::Error(nom::error::make_error(i, ErrorKind::Tag)));
     }
-    let (i, _) = take_whitespace_opt(i)?;
-    let (i, x) = separated_nonempty_list(tag("|"), take_while(|c: char| c.is_alphanumeric()))(i)?;
-    let (i, _) = take_whitespace_opt(i)?;
+    let (i, x) = separated_list1(
+        tag("|"),
+        delimited(
+            take_whitespace_opt,
+            take_while(|c: char| c.is_alphanumeric() || c == '.' || c == '-'),
+            take_whitespace_opt,
+        ),
+    )(i)?;
     Ok((i, x.iter().map(|f| f.to_string()).collect()))
 
     // let (i, _grpo) = tag(GROUP_OPEN)(i)?;
@@ -245,7 +301,7 @@ pub fn parse_element_name_group(i: &str) -> IResult<&str, Vec<String>> {
 
         // If we are stuck in a loop and can't go further, error out
         if i == j {
-            return Err(nom::Err::Error((i, ErrorKind::Tag)));
+            return Err(nom::Err::Error(nom::error::make_error(i, ErrorKind::Tag)));
         }
 
         i = j;
@@ -280,7 +336,7 @@ pub fn parse_content_model_group(i: &str) -> IResult<&str, Vec<ContentModelToken
 
         // If we are stuck in a loop and can't go further, error out
         if i == j {
-            return Err(nom::Err::Error((i, ErrorKind::Tag)));
+            return Err(nom::Err::Error(nom::error::make_error(i, ErrorKind::Tag)));
         }
 
         i = j;
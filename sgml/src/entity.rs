@@ -1,57 +1,144 @@
-use crate::comment::parse_inline_comment;
+use crate::borrowed;
+use crate::comment::parse_borrowed_inline_comment;
 use crate::dtd::{take_until_whitespace, take_whitespace, take_whitespace_opt};
-use crate::template_strings::{parse_string, TemplateString};
+use crate::template_strings::{parse_borrowed_string, TemplateString};
+use nom::branch::alt;
 use nom::bytes::complete::{tag, tag_no_case, take_until, take_while1, take_while_m_n};
-use nom::combinator::opt;
+use nom::combinator::{map, opt};
 use nom::error::ErrorKind;
-use nom::sequence::tuple;
+use nom::sequence::{delimited, preceded, tuple};
 use nom::IResult;
 
+/// How the replacement text of an entity is interpreted, see ISO(B.6.2)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EntityType {
+    /// A plain parameter literal
+    Literal,
+    CData,
+    SData,
+    ProcessingInstruction,
+    StartTag,
+    EndTag,
+    MarkedSection,
+    MarkupDeclaration,
+}
+
+impl EntityType {
+    /// The declaration keyword for this type, if it has one
+    pub fn keyword(&self) -> Option<&'static str> {
+        match self {
+            EntityType::Literal => None,
+            EntityType::CData => Some("CDATA"),
+            EntityType::SData => Some("SDATA"),
+            EntityType::ProcessingInstruction => Some("PI"),
+            EntityType::StartTag => Some("STARTTAG"),
+            EntityType::EndTag => Some("ENDTAG"),
+            EntityType::MarkedSection => Some("MS"),
+            EntityType::MarkupDeclaration => Some("MD"),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Entity {
     pub name: String,
     pub external: bool,
     pub parameter: bool,
     pub public: bool,
+    pub entity_type: EntityType,
     pub content: TemplateString,
+    /// The system identifier that may follow a public identifier
+    pub system_id: Option<String>,
+    pub comment: Option<String>,
+}
+
+impl Entity {
+    pub fn get_public_id(&self) -> Option<String> {
+        if self.public {
+            Some(self.content.chain_text())
+        } else {
+            None
+        }
+    }
+
+    pub fn get_system_id(&self) -> Option<String> {
+        if self.external {
+            Some(self.content.chain_text())
+        } else {
+            self.system_id.clone()
+        }
+    }
+}
+
+fn parse_entity_type(i: &str) -> IResult<&str, EntityType> {
+    let (i, entity_type) = alt((
+        map(tag_no_case("CDATA"), |_| EntityType::CData),
+        map(tag_no_case("SDATA"), |_| EntityType::SData),
+        map(tag_no_case("PI"), |_| EntityType::ProcessingInstruction),
+        map(tag_no_case("STARTTAG"), |_| EntityType::StartTag),
+        map(tag_no_case("ENDTAG"), |_| EntityType::EndTag),
+        map(tag_no_case("MS"), |_| EntityType::MarkedSection),
+        map(tag_no_case("MD"), |_| EntityType::MarkupDeclaration),
+    ))(i)?;
+    let (i, _) = take_whitespace(i)?;
+    Ok((i, entity_type))
 }
 
 pub fn parse_entity(i: &str) -> IResult<&str, Entity> {
+    let (i, entity) = parse_borrowed_entity(i)?;
+    Ok((i, entity.into_owned()))
+}
+
+pub fn parse_borrowed_entity(i: &str) -> IResult<&str, borrowed::Entity<'_>> {
     let (i, _) = tag_no_case("<!ENTITY ")(i)?;
     let (i, param_marker) = opt(tuple((tag("%"), take_whitespace)))(i)?;
     let (i, name) = take_until_whitespace(i)?;
     let (i, _) = take_whitespace(i)?;
-    let (i, external_marker) = opt(tuple((tag("SYSTEM"), take_whitespace)))(i)?;
+    let (i, external_marker) = opt(tuple((tag_no_case("SYSTEM"), take_whitespace)))(i)?;
     //TODO: spec seems to state that system and public are mutually exclusive ISO(B6.2.3)
     // if this is a public entitiy, then the content is called the "public identifier" (TODO: seems that there can be two "fields"  one for public identifier and one for content
     //TODO: public identifiers can only contain "minimum data characters"
-    let (i, public_marker) = opt(tuple((tag("PUBLIC"), take_whitespace)))(i)?;
+    let (i, public_marker) = opt(tuple((tag_no_case("PUBLIC"), take_whitespace)))(i)?;
 
     //TODO: force parsing of the content to ignore anything that might be a delimiter, (used to prevent / when SHORTTAG is enabled from terminating in the following <!ENTITY sol "/"> -> <!ENTITY sol CDATA "/">)
-    let (i, _cdata) = opt(tuple((tag("CDATA"), take_whitespace)))(i)?;
+    let (i, entity_type) = opt(parse_entity_type)(i)?;
 
     let (i, _) = tag("\"")(i)?;
     //TODO: escaping and %asdf; substitution (note billion laughs)
     // let (i, content) = take_until("\"")(i)?;
 
-    let (i, content) = parse_string(i, '"', false)?;
+    let (i, content) = parse_borrowed_string(i, '"', false)?;
 
     // let (i, _content_term) = tag("\"")(i)?;
 
+    let (i, system_id) = if public_marker.is_some() {
+        opt(preceded(
+            take_whitespace,
+            delimited(tag("\""), take_until("\""), tag("\"")),
+        ))(i)?
+    } else {
+        (i, None)
+    };
+
     let (i, _) = take_whitespace_opt(i)?;
-    let (i, _inline_comment) = opt(parse_inline_comment)(i)?;
+    let (i, comment) = opt(parse_borrowed_inline_comment)(i)?;
     let (i, _) = take_whitespace_opt(i)?;
 
     let (i, _term) = tag(">")(i)?;
 
     Ok((
         i,
-        Entity {
-            name: name.to_string(),
+        borrowed::Entity {
+            name,
             external: external_marker.is_some(),
             parameter: param_marker.is_some(),
             public: public_marker.is_some(),
+            entity_type: entity_type.unwrap_or(EntityType::Literal),
             content: content,
+            system_id,
+            comment,
         },
     ))
 }
@@ -92,11 +179,41 @@ fn test_internal_parameter() {
     assert_eq!(e.parameter, true);
 }
 
+#[test]
+fn test_public_with_system_id() {
+    let x = parse_entity(
+        "<!ENTITY % HTMLlat1 PUBLIC
+   \"-//W3C//ENTITIES Latin 1 for XHTML//EN\"
+   \"xhtml-lat1.ent\">",
+    );
+    println!("{:?}", x);
+    let (i, e) = x.unwrap();
+    assert_eq!(i, "");
+    assert_eq!(
+        e.get_public_id(),
+        Some("-//W3C//ENTITIES Latin 1 for XHTML//EN".to_string())
+    );
+    assert_eq!(e.get_system_id(), Some("xhtml-lat1.ent".to_string()));
+}
+
+#[test]
+fn test_data_text_and_comment() {
+    let x = parse_entity("<!ENTITY amp CDATA \"&#38;\"     -- ampersand          -->");
+    println!("{:?}", x);
+    let (i, e) = x.unwrap();
+    assert_eq!(i, "");
+    assert_eq!(e.name, "amp");
+    assert_eq!(e.entity_type, EntityType::CData);
+    assert_eq!(e.content, "&#38;".into());
+    assert_eq!(e.comment, Some(" ampersand          ".to_string()));
+}
+
 const ENTITY_REFERENCE_OPEN: &str = "&";
 const PARAMETER_ENTITY_REFERENCE_OPEN: &str = "%";
 const REFERENCE_CLOSE: &str = ";";
 
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParameterReference {
     pub name: String,
 }
@@ -119,53 +236,41 @@ fn take_record_end(i: &str) -> IResult<&str, &str> {
     return if a.is_some() || b.is_some() || c.is_some() || d.is_some() {
         Ok((i, ""))
     } else {
-        Err(nom::Err::Error((i, ErrorKind::Tag)))
+        Err(nom::Err::Error(nom::error::make_error(i, ErrorKind::Tag)))
     };
 }
 
 pub fn parse_parameter_reference(i: &str) -> IResult<&str, ParameterReference> {
+    let (i, reference) = parse_borrowed_parameter_reference(i)?;
+    Ok((i, reference.into_owned()))
+}
+
+pub fn parse_borrowed_parameter_reference(
+    i: &str,
+) -> IResult<&str, borrowed::ParameterReference<'_>> {
     let (i, _) = tag(PARAMETER_ENTITY_REFERENCE_OPEN)(i)?;
-    let (i, name) = take_while1(|c: char| c.is_alphanumeric() || c == '.')(i)?;
+    let (i, name) =
+        take_while1(|c: char| c.is_alphanumeric() || c == '.' || c == '-' || c == '_')(i)?;
 
     let refc = take_reference_close(i);
 
     match refc {
-        Ok((i, _)) => Ok((
-            i,
-            ParameterReference {
-                name: name.to_string(),
-            },
-        )),
+        Ok((i, _)) => Ok((i, borrowed::ParameterReference { name })),
         // Special case(B.6.1) entity references don't need REFC if followed by either a space or a record end
         Err(e) => {
             // Check for space (note: dont consume just peek)
             if let Ok((_, _)) = take_space(i) {
-                return Ok((
-                    i,
-                    ParameterReference {
-                        name: name.to_string(),
-                    },
-                ));
+                return Ok((i, borrowed::ParameterReference { name }));
             }
 
             // Check for record end
             if let Ok((_, _)) = take_record_end(i) {
-                return Ok((
-                    i,
-                    ParameterReference {
-                        name: name.to_string(),
-                    },
-                ));
+                return Ok((i, borrowed::ParameterReference { name }));
             }
 
             // Check for end of data
             if i.len() == 0 {
-                return Ok((
-                    i,
-                    ParameterReference {
-                        name: name.to_string(),
-                    },
-                ));
+                return Ok((i, borrowed::ParameterReference { name }));
             }
 
             return Err(e);
@@ -187,6 +292,15 @@ pub fn entitiy_reference_only() {
     )
 }
 
+#[test]
+pub fn entitiy_reference_hyphenated() {
+    let x = parse_parameter_reference("%HTTP-Method) GET");
+    println!("{:?}", x);
+    let (i, e) = x.unwrap();
+    assert_eq!(i, ") GET");
+    assert_eq!(e.name, "HTTP-Method");
+}
+
 #[test]
 pub fn entitiy_reference_newline() {
     let x = parse_parameter_reference("%flow\n");
@@ -0,0 +1,151 @@
+use crate::att_list::{DeclaredValue, DefaultValue};
+use crate::entity::EntityType;
+use crate::instance::{Event, Grammar, Parser};
+
+/// The ESIS of a document in the output format of nsgmls, so the two can be compared line by line.
+/// Every declared attribute comes before its element, and the output ends with `C` when the
+/// document conforms
+pub fn to_esis(parser: &mut Parser) -> String {
+    let mut out = String::new();
+    let mut data = String::new();
+    while let Some(event) = parser.next() {
+        let grammar = parser.get_grammar();
+        match event {
+            Event::Text(text) => data.push_str(&escape(&text)),
+            Event::EntityRef { name, text } => match grammar.get_entity(&name) {
+                Some((e, _)) if e.entity_type == EntityType::SData => {
+                    let raw = e.content.chain_text();
+                    data.push_str(&format!("\\|{}\\|", escape(&raw)));
+                }
+                _ => data.push_str(&escape(&text)),
+            },
+            // Markup that isn't part of the ESIS doesn't end the data around it
+            Event::Comment(_)
+            | Event::MarkedSectionStart(_)
+            | Event::MarkedSectionEnd
+            | Event::EntityStart(_)
+            | Event::EntityEnd(_) => {}
+            event => {
+                flush_data(&mut data, &mut out);
+                write_event(event, grammar, &mut out);
+            }
+        }
+    }
+    flush_data(&mut data, &mut out);
+    if parser.get_errors().is_empty() {
+        out.push_str("C\n");
+    }
+    out
+}
+
+fn flush_data(data: &mut String, out: &mut String) {
+    if !data.is_empty() {
+        out.push('-');
+        out.push_str(data);
+        out.push('\n');
+        data.clear();
+    }
+}
+
+fn write_event(event: Event, grammar: &Grammar, out: &mut String) {
+    match event {
+        Event::Start {
+            name, attributes, ..
+        } => {
+            let definitions = grammar
+                .get_element(&name)
+                .map(|t| t.attributes.as_slice())
+                .unwrap_or_default();
+            for d in definitions {
+                let specified = attributes.iter().find(|a| a.name == d.name);
+                let value = match (specified, &d.default_value) {
+                    (Some(a), _) => Some(a.value.clone()),
+                    (None, DefaultValue::Value(v)) | (None, DefaultValue::Fixed(v)) => {
+                        Some(v.clone())
+                    }
+                    _ => None,
+                };
+                let name = grammar.fold(&d.name);
+                match value {
+                    Some(value) => {
+                        let (kind, value) = match &d.declared_value {
+                            DeclaredValue::CData => ("CDATA", value),
+                            DeclaredValue::Entity | DeclaredValue::Entities => ("ENTITY", value),
+                            DeclaredValue::Notation(_) => ("NOTATION", grammar.fold(&value)),
+                            _ => ("TOKEN", grammar.fold(&value)),
+                        };
+                        out.push_str(&format!("A{} {} {}\n", name, kind, escape(&value)));
+                    }
+                    None => out.push_str(&format!("A{} IMPLIED\n", name)),
+                }
+            }
+            // Attributes that aren't declared were errors, but are still part of the start tag
+            for a in &attributes {
+                if !definitions.iter().any(|d| d.name == a.name) {
+                    let name = grammar.fold(&a.name);
+                    out.push_str(&format!("A{} CDATA {}\n", name, escape(&a.value)));
+                }
+            }
+            out.push_str(&format!("({}\n", grammar.fold(&name)));
+        }
+        Event::End { name, .. } => out.push_str(&format!("){}\n", grammar.fold(&name))),
+        Event::ProcessingInstruction(pi) => out.push_str(&format!("?{}\n", escape(&pi))),
+        _ => {}
+    }
+}
+
+/// Escape text as nsgmls does: backslashes doubled, record ends as `\n` and other control
+/// characters in octal
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => out.push_str(&format!("\\{:03o}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::dtd::read_dtd;
+    use crate::esis::to_esis;
+    use crate::instance::Parser;
+
+    const DTD: &str = r#"<!ENTITY eacute SDATA "[eacute]">
+<!ENTITY pi PI "style red">
+<!ELEMENT DOC - O (P+)>
+<!ELEMENT P - O (#PCDATA|BR)*>
+<!ELEMENT BR - O EMPTY>
+<!ATTLIST P align (left|right) left
+            id ID #IMPLIED
+            title CDATA #IMPLIED>
+"#;
+
+    fn esis(input: &str) -> String {
+        let (_, dtd) = read_dtd(DTD).unwrap();
+        to_esis(&mut Parser::new(input, &dtd))
+    }
+
+    #[test]
+    pub fn test_esis() {
+        assert_eq!(
+            esis(
+                "<!DOCTYPE doc><doc>\n<p right title='a\\b\tc'>Caf&eacute;<br>\nx\n&pi;<p id=p2>\n"
+            ),
+            "(DOC\nAALIGN TOKEN RIGHT\nAID IMPLIED\nATITLE CDATA a\\\\b c\n(P\n\
+             -Caf\\|[eacute]\\|\n(BR\n)BR\n-\\nx\n?style red\n)P\n\
+             AALIGN TOKEN LEFT\nAID TOKEN P2\nATITLE IMPLIED\n(P\n)P\n)DOC\nC\n"
+        );
+    }
+
+    #[test]
+    pub fn test_esis_not_conforming() {
+        let out = esis("<!DOCTYPE doc><doc><q>");
+        assert!(!out.ends_with("\nC\n"));
+        assert!(out.contains("(Q\n"));
+    }
+}
@@ -0,0 +1,397 @@
+use crate::att_list::ATTList;
+use crate::catalog::Catalog;
+use crate::dtd::{parse_dtd_element, DTDElement, DocumentTypeDefinition};
+use crate::element::Element;
+use crate::entity::Entity;
+use crate::marked_section::MarkedSectionStatus;
+use crate::template_strings::{parse_string, TemplateString};
+use nom::multi::many0;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct FlattenOptions {
+    /// Keep the declarations of general entities, otherwise only elements and attributes remain
+    pub keep_general_entities: bool,
+    pub keep_comments: bool,
+    /// Keep internal parameter entities and the references to them within declarations,
+    /// so the structure they give a DTD stays visible
+    pub keep_parameter_entities: bool,
+}
+
+impl Default for FlattenOptions {
+    fn default() -> Self {
+        Self {
+            keep_general_entities: false,
+            keep_comments: true,
+            keep_parameter_entities: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum FlattenError {
+    /// A reference to an entity that hasn't been declared
+    UndefinedEntity(String),
+    /// An external entity whose file couldn't be found or read
+    UnresolvedEntity { name: String, reason: String },
+    /// An entity that references itself, directly or through others
+    RecursiveEntity(String),
+    /// Text in an entity that couldn't be parsed as declarations
+    Syntax { entity: String, rest: String },
+    /// A marked section whose status keywords couldn't be evaluated
+    MarkedSectionStatus(String),
+}
+
+impl fmt::Display for FlattenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlattenError::UndefinedEntity(name) => write!(f, "entity %{}; is not declared", name),
+            FlattenError::UnresolvedEntity { name, reason } => {
+                write!(f, "unable to load entity %{};: {}", name, reason)
+            }
+            FlattenError::RecursiveEntity(name) => write!(f, "entity %{}; references itself", name),
+            FlattenError::Syntax { entity, rest } => write!(
+                f,
+                "unable to parse declarations in {} at: {}",
+                entity,
+                rest.chars().take(60).collect::<String>()
+            ),
+            FlattenError::MarkedSectionStatus(status) => {
+                write!(f, "unable to evaluate marked section status '{}'", status)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FlattenError {}
+
+/// Produce a standalone DTD: external parameter entities are read in, marked sections are evaluated
+/// and parameter references in declarations are replaced by their text
+/// System identifiers are resolved relative to `base`, or to the file of the entity that refers to them
+pub fn flatten_dtd(
+    dtd: &DocumentTypeDefinition,
+    base: &Path,
+    catalog: &Catalog,
+    options: &FlattenOptions,
+) -> Result<DocumentTypeDefinition, FlattenError> {
+    flatten_dtd_with_files(dtd, base, catalog, options).map(|(dtd, _)| dtd)
+}
+
+/// Like `flatten_dtd`, also giving the files of the external entities that were read in, such as
+/// to rebuild when one of them changes
+pub fn flatten_dtd_with_files(
+    dtd: &DocumentTypeDefinition,
+    base: &Path,
+    catalog: &Catalog,
+    options: &FlattenOptions,
+) -> Result<(DocumentTypeDefinition, Vec<PathBuf>), FlattenError> {
+    let mut flattener = Flattener {
+        catalog,
+        options,
+        entities: Vec::new(),
+        open: Vec::new(),
+        out: Vec::new(),
+        files: Vec::new(),
+    };
+    flattener.walk(dtd.get_declarations(), base)?;
+    Ok((flattener.out.into(), flattener.files))
+}
+
+struct Flattener<'a> {
+    catalog: &'a Catalog,
+    options: &'a FlattenOptions,
+    /// Entities declared so far, in declaration order
+    entities: Vec<Entity>,
+    /// Entities currently being included, used to detect recursion
+    open: Vec<String>,
+    out: Vec<DTDElement>,
+    /// Files of the external entities read in so far
+    files: Vec<PathBuf>,
+}
+
+impl<'a> Flattener<'a> {
+    fn expand(&self, s: &TemplateString) -> Result<TemplateString, FlattenError> {
+        s.try_expand(&self.entities)
+            .map(|s| s.as_str().into())
+            .map_err(|name| self.unexpandable(name))
+    }
+
+    /// Work out why `try_expand` gave up on a reference
+    fn unexpandable(&self, name: String) -> FlattenError {
+        match self.entities.iter().find(|e| e.parameter && e.name == name) {
+            None => FlattenError::UndefinedEntity(name),
+            Some(e) if e.external || e.public => FlattenError::UnresolvedEntity {
+                name,
+                reason: "external entities can only be referenced between declarations".to_string(),
+            },
+            Some(_) => FlattenError::RecursiveEntity(name),
+        }
+    }
+
+    fn walk(&mut self, declarations: &[DTDElement], base: &Path) -> Result<(), FlattenError> {
+        for declaration in declarations {
+            match declaration {
+                DTDElement::WhiteSpace(_) => self.out.push(declaration.clone()),
+                DTDElement::Comment(_) => {
+                    if self.options.keep_comments {
+                        self.out.push(declaration.clone())
+                    }
+                }
+                DTDElement::Entity(e) => {
+                    if e.parameter
+                        && !e.external
+                        && !e.public
+                        && self.options.keep_parameter_entities
+                    {
+                        self.out.push(declaration.clone());
+                    }
+                    if !e.parameter && self.options.keep_general_entities {
+                        let mut e = e.clone();
+                        if !e.external && !e.public {
+                            e.content = self.expand(&e.content)?;
+                        }
+                        self.out.push(DTDElement::Entity(e));
+                    }
+                    self.entities.push(e.clone());
+                }
+                DTDElement::MarkedSection(ms) => match ms.get_status(&self.entities) {
+                    Some(MarkedSectionStatus::Include) | Some(MarkedSectionStatus::Temp) => {
+                        self.walk(&ms.get_declarations(), base)?
+                    }
+                    // Character data in a declaration subset has no meaning, so it is dropped along with ignored sections
+                    Some(_) => {}
+                    None => return Err(FlattenError::MarkedSectionStatus(ms.status.clone())),
+                },
+                DTDElement::ParameterReference(r) => self.include(&r.name, base)?,
+                DTDElement::Element(e) if self.options.keep_parameter_entities => {
+                    self.expand(&e.name)?;
+                    self.expand(&e.content_model)?;
+                    self.out.push(declaration.clone());
+                }
+                DTDElement::Element(e) => {
+                    let element = Element {
+                        name: self.expand(&e.name)?,
+                        content_model: self.expand(&e.content_model)?,
+                        ..e.clone()
+                    };
+                    self.out.push(DTDElement::Element(element));
+                }
+                DTDElement::ATTList(a) if self.options.keep_parameter_entities => {
+                    self.expand(&a.name)?;
+                    self.expand(&a.value)?;
+                    self.out.push(declaration.clone());
+                }
+                DTDElement::ATTList(a) => {
+                    let att_list = ATTList {
+                        name: self.expand(&a.name)?,
+                        value: self.expand(&a.value)?,
+                    };
+                    self.out.push(DTDElement::ATTList(att_list));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace a parameter reference between declarations with the declarations it stands for
+    fn include(&mut self, name: &str, base: &Path) -> Result<(), FlattenError> {
+        if self.open.iter().any(|n| n == name) {
+            return Err(FlattenError::RecursiveEntity(name.to_string()));
+        }
+
+        let (position, entity) = self
+            .entities
+            .iter()
+            .enumerate()
+            .find(|(_, e)| e.parameter && e.name == name)
+            .map(|(n, e)| (n, e.clone()))
+            .ok_or_else(|| FlattenError::UndefinedEntity(name.to_string()))?;
+
+        let (text, base) = if entity.external || entity.public {
+            let path = self.locate(&entity, base)?;
+            let text =
+                std::fs::read_to_string(&path).map_err(|e| FlattenError::UnresolvedEntity {
+                    name: name.to_string(),
+                    reason: format!("{}: {}", path.display(), e),
+                })?;
+
+            // From here on the entity behaves like an internal one, so references to it inside declarations work too
+            let (_, content) = parse_string(&text, '\0', false).unwrap();
+            self.entities[position] = Entity {
+                external: false,
+                public: false,
+                content,
+                system_id: None,
+                ..entity
+            };
+
+            let base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            if !self.files.contains(&path) {
+                self.files.push(path);
+            }
+            (text, base)
+        } else {
+            (entity.content.chain_text(), base.to_path_buf())
+        };
+
+        let (rest, declarations) =
+            many0(parse_dtd_element)(text.as_str()).unwrap_or((&text, vec![]));
+        if !rest.trim().is_empty() {
+            return Err(FlattenError::Syntax {
+                entity: format!("%{};", name),
+                rest: rest.to_string(),
+            });
+        }
+
+        self.open.push(name.to_string());
+        let result = self.walk(&declarations, &base);
+        self.open.pop();
+        result
+    }
+
+    fn locate(&self, entity: &Entity, base: &Path) -> Result<PathBuf, FlattenError> {
+        self.catalog
+            .locate(entity, base)
+            .ok_or_else(|| FlattenError::UnresolvedEntity {
+                name: entity.name.clone(),
+                reason: format!(
+                    "no catalog entry for public identifier \"{}\"",
+                    entity.get_public_id().unwrap_or_default()
+                ),
+            })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::catalog::Catalog;
+    use crate::dtd::{read_dtd, DTDElement};
+    use crate::flatten::{flatten_dtd, flatten_dtd_with_files, FlattenError, FlattenOptions};
+    use crate::printer::{print_dtd, PrintOptions};
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+
+    fn read(path: &str) -> String {
+        let mut f = File::open(path).unwrap();
+        let mut s = String::new();
+        f.read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    pub fn test_flatten_xhtml() {
+        let s = read("./dtd/xhtml1-strict.dtd");
+        let (_, dtd) = read_dtd(&s).unwrap();
+
+        let options = FlattenOptions {
+            keep_general_entities: true,
+            keep_comments: false,
+            ..FlattenOptions::default()
+        };
+        let flat = flatten_dtd(&dtd, Path::new("./dtd"), &Catalog::default(), &options).unwrap();
+
+        assert!(flat.get_entities().iter().all(|e| !e.parameter));
+        assert!(flat.get_entities().iter().any(|e| e.name == "nbsp"));
+        assert!(flat.get_entities().iter().any(|e| e.name == "alpha"));
+        assert!(flat.get_entities().iter().any(|e| e.name == "euro"));
+        assert!(!flat.get_declarations().iter().any(|d| matches!(
+            d,
+            DTDElement::ParameterReference(_) | DTDElement::MarkedSection(_)
+        )));
+
+        // The result stands on its own
+        let out = print_dtd(&flat, &PrintOptions::default());
+        assert!(!out.contains('%'));
+        let (i, standalone) = read_dtd(&out).unwrap();
+        assert_eq!(i, "");
+        assert_eq!(standalone.get_element_declarations().len(), 77);
+        assert_eq!(standalone.get_elements().count(), 77);
+    }
+
+    #[test]
+    pub fn test_flatten_html() {
+        let s = read("./dtd/html.dtd");
+        let (_, dtd) = read_dtd(&s).unwrap();
+
+        let err = flatten_dtd(
+            &dtd,
+            Path::new("./dtd"),
+            &Catalog::default(),
+            &FlattenOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, FlattenError::UnresolvedEntity { name, .. } if name == "ISOlat1"));
+
+        let mut catalog = Catalog::default();
+        catalog.add_public(
+            "ISO 8879-1986//ENTITIES Added Latin 1//EN//HTML",
+            PathBuf::from("./dtd/xhtml-lat1.ent"),
+        );
+        let (flat, files) = flatten_dtd_with_files(
+            &dtd,
+            Path::new("./dtd"),
+            &catalog,
+            &FlattenOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(files, vec![PathBuf::from("./dtd/xhtml-lat1.ent")]);
+
+        // General entities are dropped by default
+        assert!(flat.get_entities().is_empty());
+        // HTML.Highlighting is included, HTML.Recommended is ignored
+        let names = flat
+            .get_elements()
+            .map(|e| e.get_name())
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"STRONG".to_string()));
+        assert!(names.contains(&"PLAINTEXT".to_string()));
+    }
+
+    #[test]
+    pub fn test_flatten_keeping_parameter_entities() {
+        let s = read("./dtd/html.dtd");
+        let (_, dtd) = read_dtd(&s).unwrap();
+        let mut catalog = Catalog::default();
+        catalog.add_public(
+            "ISO 8879-1986//ENTITIES Added Latin 1//EN//HTML",
+            PathBuf::from("./dtd/xhtml-lat1.ent"),
+        );
+        let options = FlattenOptions {
+            keep_parameter_entities: true,
+            ..FlattenOptions::default()
+        };
+        let flat = flatten_dtd(&dtd, Path::new("./dtd"), &catalog, &options).unwrap();
+
+        assert!(!flat.get_declarations().iter().any(|d| matches!(
+            d,
+            DTDElement::ParameterReference(_) | DTDElement::MarkedSection(_)
+        )));
+        // Only the definitions that were in effect are kept
+        let text = flat
+            .get_entities()
+            .iter()
+            .find(|e| e.parameter && e.name == "text")
+            .unwrap();
+        assert!(text.content.chain_text().contains("%phrase"));
+        let out = print_dtd(&flat, &PrintOptions::default());
+        assert!(out.contains("- O (%text;)*>"));
+        assert!(!out.contains("<!["));
+    }
+
+    #[test]
+    pub fn test_flatten_recursive() {
+        let (_, dtd) = read_dtd("<!ENTITY % a \"%b;\"><!ENTITY % b \"%a;\">%a;").unwrap();
+        let err = flatten_dtd(
+            &dtd,
+            Path::new("."),
+            &Catalog::default(),
+            &FlattenOptions::default(),
+        );
+        assert_eq!(
+            err.unwrap_err(),
+            FlattenError::RecursiveEntity("a".to_string())
+        );
+    }
+}
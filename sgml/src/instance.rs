@@ -0,0 +1,1885 @@
+use crate::att_list::{AttributeDefinition, DeclaredValue, DefaultValue};
+use crate::automaton::{Automaton, Expression, PCDATA};
+use crate::content_model::{is_name_char, DeclaredContent};
+use crate::dtd::{read_dtd, DTDElement, DocumentTypeDefinition};
+use crate::element::ElementName;
+use crate::entity::{Entity, EntityType};
+use crate::marked_section::MarkedSectionStatus;
+use crate::sgml_declaration::{read_sgml_declaration, sgml_declaration_len, SgmlDeclaration};
+use crate::xml_dtd::{character_entities, resolve_character_references};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+/// How deeply entity references may nest before the parser gives up on them
+const ENTITY_DEPTH_LIMIT: usize = 40;
+
+/// How many omitted start tags may be inferred in a row to reach an element, See ISO(7.3.1.1)
+const INFERENCE_DEPTH_LIMIT: usize = 4;
+
+/// The names of the entities XML predefines, which documents converted from XML may still use
+const PREDEFINED_ENTITIES: [(&str, &str); 5] = [
+    ("amp", "&"),
+    ("lt", "<"),
+    ("gt", ">"),
+    ("quot", "\""),
+    ("apos", "'"),
+];
+
+/// See ISO(B.3.4)
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Doctype {
+    /// The document type name, which is the name of the document element
+    pub name: String,
+    pub public_id: Option<String>,
+    pub system_id: Option<String>,
+    /// The declarations between the brackets, as written
+    pub internal_subset: Option<String>,
+}
+
+/// The document type declaration at the start of a document, if it has one
+pub fn read_doctype(input: &str) -> Option<Doctype> {
+    let mut rest = input;
+    loop {
+        rest = rest.trim_start();
+        if rest.starts_with("<?") {
+            rest = &rest[processing_instruction(rest).1..];
+        } else if let Some(len) = comment_declaration(rest).map(|(_, len)| len) {
+            rest = &rest[len..];
+        } else if let Some(len) = sgml_declaration_len(rest) {
+            rest = &rest[len..];
+        } else {
+            return doctype(rest).map(|(doctype, _, _)| doctype);
+        }
+    }
+}
+
+/// Whether `input` goes on past the end of its prolog, so the prolog can be parsed without
+/// waiting for more input
+pub(crate) fn is_prolog_complete(input: &str) -> bool {
+    let mut rest = input;
+    loop {
+        rest = rest.trim_start();
+        let len = if rest.starts_with("<?") {
+            rest.find('>').map(|n| n + 1)
+        } else if let Some((_, len, complete)) = comment_parts(rest) {
+            Some(len).filter(|_| complete)
+        } else if starts_with_keyword(rest, "<!SGML") {
+            sgml_declaration_len(rest)
+        } else if starts_with_keyword(rest, "<!DOCTYPE") {
+            doctype(rest).and_then(|(_, len, complete)| Some(len).filter(|_| complete))
+        } else {
+            // Enough to tell it isn't the start of a declaration or processing instruction
+            return rest.starts_with(|c| c != '<') || rest.len() >= "<!DOCTYPE".len();
+        };
+        match len {
+            Some(len) => rest = &rest[len..],
+            None => return false,
+        }
+    }
+}
+
+/// An element type of a DTD, with its names folded and its content model ready for matching
+#[derive(Debug, Clone)]
+pub struct ElementType {
+    /// The name as declared
+    pub name: String,
+    pub start_optional: bool,
+    pub end_optional: bool,
+    pub content: DeclaredContent,
+    /// The state of the content model before any content
+    pub expression: Expression,
+    /// The content model compiled, which content is matched with
+    pub automaton: Arc<Automaton>,
+    pub inclusions: Vec<String>,
+    pub exclusions: Vec<String>,
+    pub attributes: Vec<AttributeDefinition>,
+}
+
+impl ElementType {
+    /// Declared content is CDATA, RCDATA or EMPTY rather than a model group or ANY
+    fn has_declared_content(&self) -> bool {
+        matches!(
+            self.content,
+            DeclaredContent::CData | DeclaredContent::RCData | DeclaredContent::Empty
+        )
+    }
+}
+
+/// The declarations a document is parsed against, indexed by folded name
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    elements: HashMap<String, ElementType>,
+    entities: HashMap<String, (Entity, String)>,
+    parameter_entities: HashMap<String, String>,
+    roots: Vec<String>,
+    /// False when names are folded to upper case, as NAMECASE GENERAL YES in the
+    /// reference concrete syntax does
+    case_sensitive: bool,
+    /// Entity names are folded to upper case, for NAMECASE ENTITY YES
+    fold_entities: bool,
+}
+
+impl Grammar {
+    /// The grammar of one or more DTDs. Where they declare the same name the first one wins,
+    /// so an internal subset should come before the external DTD. Each DTD should be flattened
+    /// Without an SGML declaration, names keep their case only for XML DTDs, which have no
+    /// tag minimization
+    pub fn new(dtds: &[&DocumentTypeDefinition], declaration: Option<&SgmlDeclaration>) -> Self {
+        let (case_sensitive, fold_entities) = match declaration {
+            Some(d) => (!d.namecase_general, d.namecase_entity),
+            None => (
+                !dtds
+                    .iter()
+                    .flat_map(|dtd| dtd.get_element_declarations().iter())
+                    .any(|e| e.omitted_tag_minimization),
+                false,
+            ),
+        };
+        let mut grammar = Self {
+            elements: HashMap::new(),
+            entities: HashMap::new(),
+            parameter_entities: HashMap::new(),
+            roots: Vec::new(),
+            case_sensitive,
+            fold_entities,
+        };
+        let mut order = Vec::new();
+        for dtd in dtds {
+            grammar.add(dtd, &mut order);
+        }
+
+        // Unlike `DocumentTypeDefinition::get_roots`, this spans every DTD with names folded,
+        // and an element that only contains itself is still a root
+        let referenced = grammar
+            .elements
+            .iter()
+            .flat_map(|(key, t)| {
+                t.expression
+                    .get_names()
+                    .into_iter()
+                    .chain(t.inclusions.iter().cloned())
+                    .filter(move |name| name != key)
+            })
+            .collect::<HashSet<_>>();
+        grammar.roots = order
+            .into_iter()
+            .filter(|key| !referenced.contains(key))
+            .collect();
+        grammar
+    }
+
+    fn add(&mut self, dtd: &DocumentTypeDefinition, order: &mut Vec<String>) {
+        let mut characters = None;
+        for e in dtd.get_entities() {
+            let text = e
+                .content
+                .try_expand_in(dtd)
+                .unwrap_or_else(|_| e.content.chain_text());
+            if e.parameter {
+                self.parameter_entities
+                    .entry(e.name.clone())
+                    .or_insert(text);
+                continue;
+            }
+            let key = self.fold_entity(&e.name);
+            if self.entities.contains_key(&key) {
+                continue;
+            }
+            let text = match e.entity_type {
+                EntityType::CData => resolve_character_references(&text),
+                EntityType::SData => {
+                    let name = text.trim().trim_start_matches('[').trim_end_matches(']');
+                    characters
+                        .get_or_insert_with(character_entities)
+                        .get(name.trim())
+                        .cloned()
+                        .unwrap_or_else(|| resolve_character_references(&text))
+                }
+                _ => text,
+            };
+            self.entities.insert(key, (e.clone(), text));
+        }
+
+        for element in dtd.get_element_declarations() {
+            let names = match element.get_name(dtd.get_entities()) {
+                ElementName::Single(name) => vec![name.trim().to_string()],
+                ElementName::Group(names) => names,
+            };
+            let model = element.get_content(dtd.get_entities());
+            for name in names {
+                let key = self.fold(&name);
+                if self.elements.contains_key(&key) {
+                    continue;
+                }
+                let (content, inclusions, exclusions) = match &model {
+                    Some(m) => (
+                        m.content.clone(),
+                        m.inclusions.iter().map(|n| self.fold(n)).collect(),
+                        m.exclusions.iter().map(|n| self.fold(n)).collect(),
+                    ),
+                    None => (DeclaredContent::Any, Vec::new(), Vec::new()),
+                };
+                let expression = match &content {
+                    DeclaredContent::Empty => Expression::Empty,
+                    DeclaredContent::Any => Expression::Any,
+                    DeclaredContent::CData | DeclaredContent::RCData => {
+                        Expression::ZeroOrMore(Box::new(Expression::Token(PCDATA.to_string())))
+                    }
+                    DeclaredContent::Group(g) => Expression::from_group(g, &|n| self.fold(n)),
+                };
+                order.push(key.clone());
+                self.elements.insert(
+                    key,
+                    ElementType {
+                        name,
+                        start_optional: element.start_optional,
+                        end_optional: element.end_optional,
+                        content,
+                        automaton: Arc::new(Automaton::new(&expression)),
+                        expression,
+                        inclusions,
+                        exclusions,
+                        attributes: Vec::new(),
+                    },
+                );
+            }
+        }
+
+        for declaration in dtd.get_declarations() {
+            let list = match declaration {
+                DTDElement::ATTList(list) => list,
+                _ => continue,
+            };
+            let names = match list.get_name(dtd.get_entities()) {
+                ElementName::Single(name) => vec![name],
+                ElementName::Group(names) => names,
+            };
+            let definitions = list.get_definitions(dtd.get_entities()).unwrap_or_default();
+            for name in names {
+                let key = self.fold(&name);
+                if let Some(t) = self.elements.get_mut(&key) {
+                    for d in &definitions {
+                        if !t
+                            .attributes
+                            .iter()
+                            .any(|a| a.name.eq_ignore_ascii_case(&d.name))
+                        {
+                            t.attributes.push(d.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The name as it is compared, upper case unless names are case sensitive
+    pub fn fold(&self, name: &str) -> String {
+        if self.case_sensitive {
+            name.to_string()
+        } else {
+            name.to_uppercase()
+        }
+    }
+
+    fn fold_entity(&self, name: &str) -> String {
+        if self.fold_entities {
+            name.to_uppercase()
+        } else {
+            name.to_string()
+        }
+    }
+
+    pub fn get_element(&self, name: &str) -> Option<&ElementType> {
+        self.elements.get(&self.fold(name))
+    }
+
+    /// A general entity and its replacement text, with the text of CDATA entities resolved
+    /// and SDATA entities mapped to characters where they are known
+    pub fn get_entity(&self, name: &str) -> Option<(&Entity, &str)> {
+        self.entities
+            .get(&self.fold_entity(name))
+            .map(|(e, text)| (e, text.as_str()))
+    }
+
+    /// The folded names of the elements no other element contains
+    pub fn get_roots(&self) -> &[String] {
+        &self.roots
+    }
+
+    /// Tokenized values have their tokens separated by single spaces, and folded like names
+    pub fn normalize(&self, declared_value: &DeclaredValue, value: String) -> String {
+        match declared_value {
+            DeclaredValue::CData => value,
+            DeclaredValue::NameTokenGroup(g) | DeclaredValue::Notation(g) => {
+                let value = value.trim();
+                g.iter()
+                    .find(|t| t.eq_ignore_ascii_case(value))
+                    .cloned()
+                    .unwrap_or_else(|| self.fold(value))
+            }
+            DeclaredValue::Entity | DeclaredValue::Entities => {
+                value.split_whitespace().collect::<Vec<_>>().join(" ")
+            }
+            _ => self.fold(&value.split_whitespace().collect::<Vec<_>>().join(" ")),
+        }
+    }
+}
+
+impl Expression {
+    /// Every element name in the expression
+    fn get_names(&self) -> Vec<String> {
+        match self {
+            Expression::Token(name) if name != PCDATA => vec![name.clone()],
+            Expression::Sequence(items) | Expression::Or(items) | Expression::And(items) => {
+                items.iter().flat_map(|e| e.get_names()).collect()
+            }
+            Expression::Optional(e) | Expression::ZeroOrMore(e) | Expression::OneOrMore(e) => {
+                e.get_names()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// An attribute of an element, See ISO(B.7.4)
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Attribute {
+    /// The name as declared
+    pub name: String,
+    /// The normalized value: references replaced and tokens separated by single spaces
+    pub value: String,
+    /// CDATA for attributes that aren't declared
+    pub declared_value: DeclaredValue,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Event<'a> {
+    /// The start of an element, `omitted` when the start tag was inferred
+    Start {
+        name: String,
+        attributes: Vec<Attribute>,
+        omitted: bool,
+    },
+    /// The end of an element, `omitted` when the end tag was inferred
+    End {
+        name: String,
+        omitted: bool,
+    },
+    /// Character data, borrowed from the input unless references or record ends made it differ
+    Text(Cow<'a, str>),
+    /// A reference to an entity that is only character data, with its text resolved to Unicode
+    EntityRef {
+        name: String,
+        text: String,
+    },
+    ProcessingInstruction(Cow<'a, str>),
+    Comment(Cow<'a, str>),
+    /// The start of a marked section in content, with the status its keywords give it
+    MarkedSectionStart(MarkedSectionStatus),
+    MarkedSectionEnd,
+    /// The start of the replacement text of an entity that is parsed for markup
+    EntityStart(String),
+    EntityEnd(String),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DocumentErrorKind {
+    UndeclaredElement(String),
+    ElementNotAllowed {
+        element: String,
+        context: String,
+    },
+    DataNotAllowed {
+        context: String,
+    },
+    /// An end tag for an element that isn't open
+    EndTagNotOpen(String),
+    /// An end tag that was left out, for an element whose declaration requires it
+    EndTagOmitted(String),
+    /// An element that ended before its content model was satisfied, with the elements that
+    /// could have come next
+    IncompleteContent {
+        element: String,
+        expected: Vec<String>,
+    },
+    UndefinedEntity(String),
+    /// A reference to an external entity, which can't be read from within a document
+    ExternalEntity(String),
+    RecursiveEntity(String),
+    UndeclaredAttribute {
+        element: String,
+        attribute: String,
+    },
+    /// A value that isn't in the group of the attribute, or isn't the names or numbers its
+    /// declared value calls for
+    InvalidAttributeValue {
+        attribute: String,
+        value: String,
+    },
+    /// A value other than the one a #FIXED attribute is declared with
+    FixedAttributeValue {
+        attribute: String,
+        value: String,
+        fixed: String,
+    },
+    MissingAttribute {
+        element: String,
+        attribute: String,
+    },
+    /// Markup that couldn't be parsed, as written
+    Syntax(String),
+    /// A document type declaration whose internal subset couldn't be parsed
+    InternalSubset(String),
+    NoDocumentElement,
+}
+
+impl DocumentErrorKind {
+    /// A short name for the kind of error, which stays the same whatever names it refers to
+    pub fn get_code(&self) -> &'static str {
+        match self {
+            DocumentErrorKind::UndeclaredElement(_) => "undeclared-element",
+            DocumentErrorKind::ElementNotAllowed { .. } => "element-not-allowed",
+            DocumentErrorKind::DataNotAllowed { .. } => "data-not-allowed",
+            DocumentErrorKind::EndTagNotOpen(_) => "end-tag-not-open",
+            DocumentErrorKind::EndTagOmitted(_) => "end-tag-omitted",
+            DocumentErrorKind::IncompleteContent { .. } => "incomplete-content",
+            DocumentErrorKind::UndefinedEntity(_) => "undefined-entity",
+            DocumentErrorKind::ExternalEntity(_) => "external-entity",
+            DocumentErrorKind::RecursiveEntity(_) => "recursive-entity",
+            DocumentErrorKind::UndeclaredAttribute { .. } => "undeclared-attribute",
+            DocumentErrorKind::InvalidAttributeValue { .. } => "invalid-attribute-value",
+            DocumentErrorKind::FixedAttributeValue { .. } => "fixed-attribute-value",
+            DocumentErrorKind::MissingAttribute { .. } => "missing-attribute",
+            DocumentErrorKind::Syntax(_) => "syntax",
+            DocumentErrorKind::InternalSubset(_) => "internal-subset",
+            DocumentErrorKind::NoDocumentElement => "no-document-element",
+        }
+    }
+}
+
+impl fmt::Display for DocumentErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DocumentErrorKind::UndeclaredElement(name) => {
+                write!(f, "element {} is not declared", name)
+            }
+            DocumentErrorKind::ElementNotAllowed { element, context } => {
+                write!(f, "element {} is not allowed in {}", element, context)
+            }
+            DocumentErrorKind::DataNotAllowed { context } => {
+                write!(f, "character data is not allowed in {}", context)
+            }
+            DocumentErrorKind::EndTagNotOpen(name) => {
+                write!(f, "end tag for {} which is not open", name)
+            }
+            DocumentErrorKind::EndTagOmitted(name) => write!(
+                f,
+                "end tag for {} omitted, but its declaration does not permit this",
+                name
+            ),
+            DocumentErrorKind::IncompleteContent { element, expected } => match expected.len() {
+                0 => write!(f, "{} ended before its required content", element),
+                1 => write!(f, "{} ended before its required {}", element, expected[0]),
+                _ => write!(
+                    f,
+                    "{} ended before its required content, one of {}",
+                    element,
+                    expected.join(", ")
+                ),
+            },
+            DocumentErrorKind::UndefinedEntity(name) => {
+                write!(f, "entity &{}; is not declared", name)
+            }
+            DocumentErrorKind::ExternalEntity(name) => {
+                write!(f, "external entity &{}; is not supported", name)
+            }
+            DocumentErrorKind::RecursiveEntity(name) => {
+                write!(f, "entity &{}; references itself", name)
+            }
+            DocumentErrorKind::UndeclaredAttribute { element, attribute } => {
+                write!(f, "{} has no attribute {}", element, attribute)
+            }
+            DocumentErrorKind::InvalidAttributeValue { attribute, value } => {
+                write!(f, "\"{}\" is not a valid value for {}", value, attribute)
+            }
+            DocumentErrorKind::FixedAttributeValue {
+                attribute,
+                value,
+                fixed,
+            } => write!(
+                f,
+                "\"{}\" is not the value \"{}\" that {} is fixed to",
+                value, fixed, attribute
+            ),
+            DocumentErrorKind::MissingAttribute { element, attribute } => {
+                write!(f, "{} requires attribute {}", element, attribute)
+            }
+            DocumentErrorKind::Syntax(markup) => write!(
+                f,
+                "unable to parse markup: {}",
+                markup.chars().take(60).collect::<String>()
+            ),
+            DocumentErrorKind::InternalSubset(rest) => write!(
+                f,
+                "unable to parse the internal subset at: {}",
+                rest.chars().take(60).collect::<String>()
+            ),
+            DocumentErrorKind::NoDocumentElement => f.write_str("the document has no element"),
+        }
+    }
+}
+
+/// An error in a document, which the parser recovers from
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DocumentError {
+    pub line: usize,
+    /// The column in characters, counting from 1
+    pub column: usize,
+    pub kind: DocumentErrorKind,
+}
+
+impl fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.kind
+        )
+    }
+}
+
+impl std::error::Error for DocumentError {}
+
+/// An attribute specification from a start tag, before it is matched to a definition
+struct Specification {
+    name: Option<String>,
+    value: String,
+    literal: bool,
+}
+
+/// How far the input has been counted in lines, so positions are found without counting from the
+/// start each time
+struct Counted {
+    position: usize,
+    line: usize,
+    /// The characters on the line before `position`
+    column: usize,
+}
+
+impl Counted {
+    fn count_to(&mut self, input: &str, position: usize) {
+        let text = &input[self.position..position];
+        match text.rfind('\n') {
+            Some(n) => {
+                self.line += text.matches('\n').count();
+                self.column = text[n + 1..].chars().count();
+            }
+            None => self.column += text.chars().count(),
+        }
+        self.position = position;
+    }
+}
+
+/// The replacement text of an entity being parsed
+struct EntitySource {
+    name: String,
+    text: String,
+    position: usize,
+}
+
+struct OpenElement {
+    key: String,
+    name: String,
+    automaton: Arc<Automaton>,
+    /// The state of the automaton after the content so far
+    state: usize,
+    end_optional: bool,
+    /// Declared content is CDATA or RCDATA, with RCDATA still recognising references
+    raw: Option<bool>,
+    inclusions: Vec<String>,
+    exclusions: Vec<String>,
+    /// Data or a subelement has been seen, so a record end is no longer the first, See ISO(7.6.1)
+    has_content: bool,
+    /// The last content was data, which the #PCDATA token already accounts for
+    in_data: bool,
+}
+
+/// Parses a document instance into events, inferring omitted tags from the content models
+/// of the DTD. Errors are collected rather than stopping the parse
+pub struct Parser<'a> {
+    /// The input, owned when it is pushed in pieces and only the unparsed part is kept
+    input: Cow<'a, str>,
+    position: usize,
+    entities: Vec<EntitySource>,
+    grammar: Grammar,
+    declaration: Option<SgmlDeclaration>,
+    doctype: Option<Doctype>,
+    /// The open elements, below them a document level whose content is the document element
+    stack: Vec<OpenElement>,
+    events: VecDeque<Event<'a>>,
+    errors: Vec<DocumentError>,
+    /// How many INCLUDE marked sections are open
+    marked_sections: usize,
+    /// The depth of the element a record end was held back in, in case it was the last
+    pending_record_end: Option<usize>,
+    last_element: Option<String>,
+    counted: Counted,
+    finished: bool,
+    /// More input may be pushed after what has been read so far
+    more_input: bool,
+    /// Parsing stopped at markup that may go on in input that hasn't been pushed yet
+    waiting: bool,
+}
+
+impl OpenElement {
+    fn allows(&self, token: &str) -> bool {
+        self.automaton.allows(self.state, token)
+    }
+
+    fn is_complete(&self) -> bool {
+        self.automaton.is_accepting(self.state)
+    }
+
+    /// Move on past `token`, if the model allows it
+    fn advance(&mut self, token: &str) {
+        if let Some(state) = self.automaton.next(self.state, token) {
+            self.state = state;
+        }
+    }
+}
+
+impl<'a> Parser<'a> {
+    /// A parser for `input`, with declarations from its internal subset added to `dtd`
+    pub fn new(input: &'a str, dtd: &DocumentTypeDefinition) -> Self {
+        Self::with_declaration(input, dtd, None)
+    }
+
+    /// A parser following an SGML declaration, which takes the place of any the document has
+    pub fn with_declaration(
+        input: &'a str,
+        dtd: &DocumentTypeDefinition,
+        declaration: Option<&SgmlDeclaration>,
+    ) -> Self {
+        Self::from_input(Cow::Borrowed(input), dtd, declaration, false)
+    }
+
+    fn from_input(
+        input: Cow<'a, str>,
+        dtd: &DocumentTypeDefinition,
+        declaration: Option<&SgmlDeclaration>,
+        more_input: bool,
+    ) -> Self {
+        let mut parser = Parser {
+            input,
+            position: 0,
+            entities: Vec::new(),
+            grammar: Grammar::new(&[], None),
+            declaration: declaration.cloned(),
+            doctype: None,
+            stack: Vec::new(),
+            events: VecDeque::new(),
+            errors: Vec::new(),
+            marked_sections: 0,
+            pending_record_end: None,
+            last_element: None,
+            counted: Counted {
+                position: 0,
+                line: 1,
+                column: 0,
+            },
+            finished: false,
+            more_input,
+            waiting: false,
+        };
+        parser.prolog();
+
+        let subset = parser
+            .doctype
+            .as_ref()
+            .and_then(|d| d.internal_subset.as_ref())
+            .map(|s| match read_dtd(s) {
+                Ok((rest, subset)) => (rest.trim().to_string(), subset),
+                Err(_) => (s.clone(), DocumentTypeDefinition::from(Vec::new())),
+            });
+        parser.grammar = match &subset {
+            Some((rest, subset)) => {
+                if !rest.is_empty() {
+                    parser.error(DocumentErrorKind::InternalSubset(rest.clone()));
+                }
+                Grammar::new(&[subset, dtd], parser.declaration.as_ref())
+            }
+            None => Grammar::new(&[dtd], parser.declaration.as_ref()),
+        };
+
+        let expression = match &parser.doctype {
+            Some(d) => Expression::Token(parser.grammar.fold(&d.name)),
+            None => match parser.grammar.get_roots() {
+                [root] => Expression::Token(root.clone()),
+                roots => Expression::Or(roots.iter().cloned().map(Expression::Token).collect()),
+            },
+        };
+        parser.stack.push(OpenElement {
+            key: String::new(),
+            name: "the document".to_string(),
+            automaton: Arc::new(Automaton::new(&expression)),
+            state: Automaton::START,
+            end_optional: false,
+            raw: None,
+            inclusions: Vec::new(),
+            exclusions: Vec::new(),
+            has_content: false,
+            in_data: false,
+        });
+        parser
+    }
+
+    pub fn get_doctype(&self) -> Option<&Doctype> {
+        self.doctype.as_ref()
+    }
+
+    /// The SGML declaration the document is parsed with, if it or the caller gave one
+    pub fn get_declaration(&self) -> Option<&SgmlDeclaration> {
+        self.declaration.as_ref()
+    }
+
+    pub fn get_grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    /// The errors found so far
+    pub fn get_errors(&self) -> &[DocumentError] {
+        &self.errors
+    }
+
+    /// A parser that reads its input in pieces, starting with the whole prolog, See
+    /// `is_prolog_complete`
+    pub(crate) fn streaming(
+        prolog: String,
+        dtd: &DocumentTypeDefinition,
+        declaration: Option<&SgmlDeclaration>,
+    ) -> Parser<'static> {
+        Parser::from_input(Cow::Owned(prolog), dtd, declaration, true)
+    }
+
+    /// Add the next piece of input, dropping what has been parsed
+    pub(crate) fn push_input(&mut self, text: &str) {
+        self.counted.count_to(&self.input, self.position);
+        let input = self.input.to_mut();
+        input.drain(..self.position);
+        input.push_str(text);
+        self.position = 0;
+        self.counted.position = 0;
+        self.waiting = false;
+    }
+
+    /// There is no more input, so markup at its end is as complete as it will get
+    pub(crate) fn end_input(&mut self) {
+        self.more_input = false;
+        self.waiting = false;
+    }
+
+    /// How much input is held that hasn't been parsed
+    pub(crate) fn get_buffered_len(&self) -> usize {
+        self.input.len() - self.position
+    }
+
+    /// Take the errors found so far, so they aren't kept
+    pub(crate) fn take_errors(&mut self) -> Vec<DocumentError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Comments, processing instructions and declarations before the document element
+    fn prolog(&mut self) {
+        loop {
+            let rest = self.rest();
+            let skip = rest.len() - rest.trim_start().len();
+            self.position += skip;
+
+            let rest = self.rest();
+            if rest.starts_with("<?") {
+                let (_, len) = processing_instruction(rest);
+                let pi = self.take(len);
+                self.push_processing_instruction(pi);
+            } else if let Some((comments, len)) = comment_declaration(rest) {
+                self.position += len;
+                self.events
+                    .extend(comments.into_iter().map(|c| Event::Comment(c.into())));
+            } else if let Some(len) = sgml_declaration_len(rest) {
+                if self.declaration.is_none() {
+                    self.declaration = read_sgml_declaration(rest);
+                }
+                self.position += len;
+            } else if let Some((doctype, len, _)) = doctype(rest) {
+                self.position += len;
+                self.doctype = Some(doctype);
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn rest(&self) -> &str {
+        match self.entities.last() {
+            Some(e) => &e.text[e.position..],
+            None => &self.input[self.position..],
+        }
+    }
+
+    fn advance(&mut self, len: usize) {
+        match self.entities.last_mut() {
+            Some(e) => e.position += len,
+            None => self.position += len,
+        }
+    }
+
+    /// The next `len` bytes, borrowed when they come straight from the input
+    fn take(&mut self, len: usize) -> Cow<'a, str> {
+        match self.entities.last_mut() {
+            Some(e) => {
+                let text = e.text[e.position..e.position + len].to_string();
+                e.position += len;
+                Cow::Owned(text)
+            }
+            None => {
+                let range = self.position..self.position + len;
+                self.position += len;
+                match self.input {
+                    Cow::Borrowed(input) => Cow::Borrowed(&input[range]),
+                    Cow::Owned(ref input) => Cow::Owned(input[range].to_string()),
+                }
+            }
+        }
+    }
+
+    fn error(&mut self, kind: DocumentErrorKind) {
+        self.counted.count_to(&self.input, self.position);
+        self.errors.push(DocumentError {
+            line: self.counted.line,
+            column: self.counted.column + 1,
+            kind,
+        });
+    }
+
+    fn top(&self) -> &OpenElement {
+        self.stack.last().unwrap()
+    }
+
+    fn is_excluded(&self, key: &str) -> bool {
+        self.stack
+            .iter()
+            .any(|e| e.exclusions.iter().any(|n| n == key))
+    }
+
+    fn is_included(&self, key: &str) -> bool {
+        self.stack
+            .iter()
+            .any(|e| e.inclusions.iter().any(|n| n == key))
+    }
+
+    /// Whether the current element allows `token` next, as a model token or an inclusion
+    fn accepts(&self, token: &str) -> bool {
+        let top = self.top();
+        if token == PCDATA {
+            return top.in_data || top.allows(PCDATA);
+        }
+        !self.is_excluded(token) && (top.allows(token) || self.is_included(token))
+    }
+
+    /// The element whose start tag can be omitted before `token`: the content so far needs
+    /// more, and of the elements that can come next it is the only one whose start tag can
+    /// be omitted and whose content can start with `token`, See ISO(7.3.1.1)
+    fn required_start(
+        &self,
+        automaton: &Automaton,
+        state: usize,
+        token: &str,
+        depth: usize,
+    ) -> Option<String> {
+        if automaton.is_accepting(state) || depth == 0 {
+            return None;
+        }
+        let mut candidates = automaton.get_first(state).into_iter().filter(|key| {
+            key != PCDATA && !self.is_excluded(key) && self.can_start(key, token, depth)
+        });
+        match (candidates.next(), candidates.next()) {
+            (Some(key), None) => Some(key),
+            _ => None,
+        }
+    }
+
+    /// Whether the start tag of `key` can be omitted and its content start with `token`
+    fn can_start(&self, key: &str, token: &str, depth: usize) -> bool {
+        let t = match self.grammar.elements.get(key) {
+            Some(t) => t,
+            None => return false,
+        };
+        if !t.start_optional
+            || t.has_declared_content()
+            || t.attributes
+                .iter()
+                .any(|a| a.default_value == DefaultValue::Required)
+        {
+            return false;
+        }
+
+        let start = Automaton::START;
+        let allowed = if token == PCDATA {
+            t.automaton.allows(start, PCDATA)
+        } else {
+            !t.exclusions.iter().any(|n| n == token)
+                && !self.is_excluded(token)
+                && (t.automaton.allows(start, token)
+                    || t.inclusions.iter().any(|n| n == token)
+                    || self.is_included(token))
+        };
+        allowed
+            || self
+                .required_start(&t.automaton, start, token, depth - 1)
+                .is_some()
+    }
+
+    /// Infer omitted start and end tags until the current element accepts `token`
+    fn place(&mut self, token: &str) -> bool {
+        loop {
+            if self.accepts(token) {
+                return true;
+            }
+            let top = self.top();
+            if let Some(key) =
+                self.required_start(&top.automaton, top.state, token, INFERENCE_DEPTH_LIMIT)
+            {
+                self.start_element(&key, Vec::new(), true);
+                continue;
+            }
+            let top = self.top();
+            if self.stack.len() > 1 && top.end_optional && top.is_complete() {
+                self.end_element(true);
+                continue;
+            }
+            return false;
+        }
+    }
+
+    fn start_element(&mut self, key: &str, attributes: Vec<Attribute>, omitted: bool) {
+        let excluded = self.is_excluded(key);
+        self.flush_record_end();
+        let top = self.stack.last_mut().unwrap();
+        if !excluded {
+            top.advance(key);
+        }
+        top.has_content = true;
+        top.in_data = false;
+
+        let open = match self.grammar.elements.get(key) {
+            Some(t) => OpenElement {
+                key: key.to_string(),
+                name: t.name.clone(),
+                automaton: t.automaton.clone(),
+                state: Automaton::START,
+                end_optional: t.end_optional,
+                raw: match t.content {
+                    DeclaredContent::CData => Some(false),
+                    DeclaredContent::RCData => Some(true),
+                    _ => None,
+                },
+                inclusions: t.inclusions.clone(),
+                exclusions: t.exclusions.clone(),
+                has_content: false,
+                in_data: false,
+            },
+            None => OpenElement {
+                key: key.to_string(),
+                name: key.to_string(),
+                automaton: Arc::new(Automaton::new(&Expression::Any)),
+                state: Automaton::START,
+                end_optional: false,
+                raw: None,
+                inclusions: Vec::new(),
+                exclusions: Vec::new(),
+                has_content: false,
+                in_data: false,
+            },
+        };
+        let empty = self
+            .grammar
+            .elements
+            .get(key)
+            .is_some_and(|t| t.content == DeclaredContent::Empty);
+        self.last_element = Some(key.to_string());
+        self.events.push_back(Event::Start {
+            name: open.name.clone(),
+            attributes,
+            omitted,
+        });
+        self.stack.push(open);
+        if empty {
+            let open = self.stack.pop().unwrap();
+            self.events.push_back(Event::End {
+                name: open.name,
+                omitted: true,
+            });
+        }
+    }
+
+    fn end_element(&mut self, omitted: bool) {
+        let depth = self.stack.len() - 1;
+        if self.pending_record_end == Some(depth) {
+            self.pending_record_end = None;
+        }
+        let open = self.stack.pop().unwrap();
+        if omitted && !open.end_optional {
+            self.error(DocumentErrorKind::EndTagOmitted(open.name.clone()));
+        }
+        if !open.is_complete() {
+            let expected = open
+                .automaton
+                .get_first(open.state)
+                .into_iter()
+                .filter(|t| t != PCDATA)
+                .map(|t| self.grammar.get_element(&t).map_or(t, |e| e.name.clone()))
+                .collect();
+            self.error(DocumentErrorKind::IncompleteContent {
+                element: open.name.clone(),
+                expected,
+            });
+        }
+        self.events.push_back(Event::End {
+            name: open.name,
+            omitted,
+        });
+    }
+
+    fn flush_record_end(&mut self) {
+        if self.pending_record_end == Some(self.stack.len() - 1) {
+            self.pending_record_end = None;
+            self.events.push_back(Event::Text("\n".into()));
+        }
+    }
+
+    /// Make the current element one that allows data, then write out any held back record end
+    fn start_data(&mut self) {
+        if !self.place(PCDATA) {
+            let context = self.top().name.clone();
+            self.error(DocumentErrorKind::DataNotAllowed { context });
+        }
+        self.flush_record_end();
+    }
+
+    fn end_data(&mut self) {
+        let top = self.stack.last_mut().unwrap();
+        if !top.in_data {
+            top.advance(PCDATA);
+        }
+        top.has_content = true;
+        top.in_data = true;
+    }
+
+    /// Character data, where record ends are subject to the rules of ISO(7.6.1) unless they
+    /// came from a character reference
+    fn characters(&mut self, text: Cow<'a, str>, records: bool) {
+        let text = if text.contains('\r') {
+            Cow::Owned(text.replace('\r', ""))
+        } else {
+            text
+        };
+        // Whitespace where no data is allowed separates elements
+        if text.trim().is_empty() && !self.accepts(PCDATA) {
+            return;
+        }
+        let mut text = text;
+        self.start_data();
+        if records && !self.top().has_content && text.starts_with('\n') {
+            text = cow_slice(text, 1, 0);
+        }
+        let held = records && text.ends_with('\n');
+        if held {
+            text = cow_slice(text, 0, 1);
+        }
+        if !text.is_empty() {
+            self.events.push_back(Event::Text(text));
+            self.end_data();
+        }
+        if held {
+            self.pending_record_end = Some(self.stack.len() - 1);
+        }
+    }
+
+    fn push_processing_instruction(&mut self, pi: Cow<'a, str>) {
+        let text = match pi {
+            Cow::Borrowed(s) => Cow::Borrowed(&s[2..s.len() - 1]),
+            Cow::Owned(s) => Cow::Owned(s[2..s.len() - 1].to_string()),
+        };
+        self.events.push_back(Event::ProcessingInstruction(text));
+    }
+
+    /// Parse the next piece of markup or data, adding its events to the queue
+    fn step(&mut self) {
+        let rest = self.rest();
+        if self.entities.is_empty() && self.more_input && self.is_truncated(rest) {
+            self.waiting = true;
+            return;
+        }
+        if rest.is_empty() {
+            match self.entities.pop() {
+                Some(entity) => self.events.push_back(Event::EntityEnd(entity.name)),
+                None => self.finish(),
+            }
+            return;
+        }
+
+        if let Some(rcdata) = self.top().raw {
+            return self.raw_content(rcdata);
+        }
+
+        let mut chars = rest.chars();
+        let (first, second) = (chars.next(), chars.next());
+        match (first, second) {
+            (Some('<'), Some('/'))
+                if rest[2..].starts_with(|c: char| c.is_alphabetic() || c == '>') =>
+            {
+                self.end_tag()
+            }
+            (Some('<'), Some('!')) => self.declaration(),
+            (Some('<'), Some('?')) => {
+                let (_, len) = processing_instruction(rest);
+                let pi = self.take(len);
+                self.push_processing_instruction(pi);
+            }
+            (Some('<'), Some(c)) if c.is_alphabetic() || c == '>' => self.start_tag(),
+            (Some(']'), _) if self.marked_sections > 0 && rest.starts_with("]]>") => {
+                self.advance(3);
+                self.marked_sections -= 1;
+                self.events.push_back(Event::MarkedSectionEnd);
+            }
+            (Some('&'), Some(c)) if c.is_alphabetic() || c == '#' => self.reference(),
+            _ => {
+                let len = self.data_len(rest, |c| c == '<' || c == '&' || c == ']');
+                let text = self.take(len);
+                self.characters(text, true);
+            }
+        }
+    }
+
+    /// The length of data before the next delimiter, at least one character
+    fn data_len(&self, rest: &str, delimiter: impl Fn(char) -> bool) -> usize {
+        let first = rest.chars().next().map_or(0, |c| c.len_utf8());
+        rest[first..]
+            .find(delimiter)
+            .map_or(rest.len(), |n| n + first)
+    }
+
+    /// The content of a CDATA or RCDATA element, which ends at the first end tag
+    fn raw_content(&mut self, rcdata: bool) {
+        let rest = self.rest();
+        if rest.starts_with("</") && rest[2..].starts_with(|c: char| c.is_alphabetic() || c == '>')
+        {
+            return self.end_tag();
+        }
+        if rcdata
+            && rest.starts_with('&')
+            && rest[1..].starts_with(|c: char| c.is_alphabetic() || c == '#')
+        {
+            return self.reference();
+        }
+        let len = self.data_len(rest, |c| c == '<' || (rcdata && c == '&'));
+        let text = self.take(len);
+        self.characters(text, true);
+    }
+
+    fn start_tag(&mut self) {
+        let (name, specifications, len) = start_tag(self.rest());
+        let markup = self.rest()[..len].to_string();
+        self.advance(len);
+        let key = match name {
+            Some(name) => self.grammar.fold(&name),
+            // An empty start tag is for the element that last started, See ISO(7.4.1.1)
+            None => match self.last_element.clone() {
+                Some(key) => key,
+                None => return self.error(DocumentErrorKind::Syntax(markup)),
+            },
+        };
+
+        if self.grammar.elements.contains_key(&key) {
+            if !self.place(&key) {
+                let element = self.grammar.elements[&key].name.clone();
+                let context = self.top().name.clone();
+                self.error(DocumentErrorKind::ElementNotAllowed { element, context });
+            }
+        } else {
+            self.error(DocumentErrorKind::UndeclaredElement(key.clone()));
+        }
+        let attributes = self.attributes(&key, specifications);
+        self.start_element(&key, attributes, false);
+    }
+
+    fn end_tag(&mut self) {
+        let rest = self.rest();
+        let name_len = name_len(&rest[2..]);
+        let name = rest[2..2 + name_len].to_string();
+        let close = rest[2 + name_len..]
+            .find('>')
+            .map_or(rest.len(), |n| 2 + name_len + n + 1);
+        self.advance(close);
+
+        let depth = if name.is_empty() {
+            // An empty end tag ends the current element
+            Some(self.stack.len() - 1).filter(|d| *d > 0)
+        } else {
+            let key = self.grammar.fold(&name);
+            self.stack
+                .iter()
+                .rposition(|e| e.key == key)
+                .filter(|d| *d > 0)
+        };
+        match depth {
+            Some(depth) => {
+                while self.stack.len() - 1 > depth {
+                    self.end_element(true);
+                }
+                self.end_element(false);
+            }
+            None => self.error(DocumentErrorKind::EndTagNotOpen(name)),
+        }
+    }
+
+    /// Comment declarations and marked sections, See ISO(10.3) and ISO(10.4)
+    fn declaration(&mut self) {
+        let rest = self.rest();
+        if let Some((comments, len)) = comment_declaration(rest) {
+            self.advance(len);
+            for comment in comments {
+                self.events.push_back(Event::Comment(comment.into()));
+            }
+            return;
+        }
+        if !rest.starts_with("<![") {
+            let len = declaration_len(rest);
+            let markup = rest[..len].to_string();
+            self.advance(len);
+            return self.error(DocumentErrorKind::Syntax(markup));
+        }
+
+        let (status, open) = match self.marked_section_start(rest) {
+            Some(start) => start,
+            None => {
+                let markup = rest.to_string();
+                self.advance(markup.len());
+                return self.error(DocumentErrorKind::Syntax(markup));
+            }
+        };
+        self.advance(open);
+        self.events.push_back(Event::MarkedSectionStart(status));
+
+        match status {
+            MarkedSectionStatus::Ignore => {
+                let len = ignored_section_len(self.rest());
+                self.advance(len);
+                self.events.push_back(Event::MarkedSectionEnd);
+            }
+            MarkedSectionStatus::CData | MarkedSectionStatus::RCData => {
+                let rest = self.rest();
+                let len = rest.find("]]>").unwrap_or(rest.len());
+                let text = self.take(len);
+                self.advance(self.rest().len().min(3));
+                let text = if status == MarkedSectionStatus::CData {
+                    text
+                } else {
+                    Cow::Owned(self.replace_references(&text))
+                };
+                self.characters(text, true);
+                self.events.push_back(Event::MarkedSectionEnd);
+            }
+            MarkedSectionStatus::Temp | MarkedSectionStatus::Include => self.marked_sections += 1,
+        }
+    }
+
+    /// The status of a marked section from its keywords, which may be parameter entity
+    /// references, and the length of its start up to the content. None if it has no `[`
+    /// before the content
+    fn marked_section_start(&self, rest: &str) -> Option<(MarkedSectionStatus, usize)> {
+        let open = rest
+            .find('[')
+            .and_then(|n| rest[n + 1..].find('[').map(|m| n + m + 2))?;
+        let status = rest[3..open - 1]
+            .split_whitespace()
+            .flat_map(|k| match k.strip_prefix('%') {
+                Some(name) => self
+                    .grammar
+                    .parameter_entities
+                    .get(name.trim_end_matches(';'))
+                    .map(|text| text.split_whitespace().map(str::to_string).collect())
+                    .unwrap_or_default(),
+                None => vec![k.to_string()],
+            })
+            .filter_map(|k| MarkedSectionStatus::from_keyword(&k))
+            .max()
+            .map_or(MarkedSectionStatus::Include, |s| {
+                s.max(MarkedSectionStatus::Include)
+            });
+        Some((status, open))
+    }
+
+    /// Whether the markup at the start of `rest` may go on in input that hasn't been pushed yet.
+    /// Markup that ends right at the end of the input counts too, it is parsed with the next piece
+    fn is_truncated(&self, rest: &str) -> bool {
+        let raw = self.top().raw;
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (None, _) | (Some('<'), None) | (Some('&'), None) | (Some(']'), None) => true,
+            (Some('<'), Some('/')) => !rest.contains('>'),
+            // Only end tags are markup in CDATA and RCDATA
+            (Some('<'), _) if raw.is_some() => false,
+            (Some('&'), Some(c)) if raw != Some(false) && (c.is_alphabetic() || c == '#') => {
+                reference(rest).1 == rest.len()
+            }
+            (Some('<'), Some('?')) => !rest.contains('>'),
+            (Some('<'), Some('!')) => {
+                if let Some((_, _, complete)) = comment_parts(rest) {
+                    return !complete;
+                }
+                if rest.len() < 4 {
+                    // Could still be the start of a comment declaration
+                    return true;
+                }
+                if !rest.starts_with("<![") {
+                    return declaration_len(rest) == rest.len();
+                }
+                match self.marked_section_start(rest) {
+                    None => true,
+                    Some((MarkedSectionStatus::Ignore, open)) => {
+                        open + ignored_section_len(&rest[open..]) == rest.len()
+                    }
+                    Some((MarkedSectionStatus::CData, open))
+                    | Some((MarkedSectionStatus::RCData, open)) => !rest[open..].contains("]]>"),
+                    Some(_) => false,
+                }
+            }
+            (Some('<'), Some(c)) if c.is_alphabetic() || c == '>' => {
+                start_tag(rest).2 == rest.len()
+            }
+            (Some(']'), Some(_)) => rest.len() < 3 && "]]>".starts_with(rest),
+            _ => false,
+        }
+    }
+
+    /// Character and entity references in content, See ISO(9.4.4) and ISO(9.5)
+    fn reference(&mut self) {
+        let (name, len) = reference(self.rest());
+        self.advance(len);
+
+        if let Some(number) = name.strip_prefix('#') {
+            match character_reference(number) {
+                Some(c) => self.characters(c.into(), false),
+                None => self.error(DocumentErrorKind::Syntax(format!("&{};", name))),
+            }
+            return;
+        }
+
+        let (entity, text) = match self.grammar.get_entity(&name) {
+            Some((entity, text)) => (entity.clone(), text.to_string()),
+            None => {
+                match PREDEFINED_ENTITIES.iter().find(|(n, _)| *n == name) {
+                    Some((_, c)) => self.characters(Cow::Borrowed(*c), false),
+                    None => self.error(DocumentErrorKind::UndefinedEntity(name)),
+                }
+                return;
+            }
+        };
+
+        if entity.external || entity.public {
+            return self.error(DocumentErrorKind::ExternalEntity(name));
+        }
+        match entity.entity_type {
+            EntityType::CData | EntityType::SData => self.entity_reference(name, text),
+            // Text that is only character references is character data too, as in XML entity sets
+            EntityType::Literal if is_character_text(&text) => {
+                let text = resolve_character_references(&text);
+                self.entity_reference(name, text)
+            }
+            EntityType::ProcessingInstruction => self
+                .events
+                .push_back(Event::ProcessingInstruction(text.into())),
+            _ => {
+                if self.entities.iter().any(|e| e.name == name)
+                    || self.entities.len() >= ENTITY_DEPTH_LIMIT
+                {
+                    return self.error(DocumentErrorKind::RecursiveEntity(name));
+                }
+                self.events.push_back(Event::EntityStart(name.clone()));
+                self.entities.push(EntitySource {
+                    name,
+                    text,
+                    position: 0,
+                });
+            }
+        }
+    }
+
+    fn entity_reference(&mut self, name: String, text: String) {
+        self.start_data();
+        self.events.push_back(Event::EntityRef { name, text });
+        self.end_data();
+    }
+
+    /// Replace character and entity references in text that isn't parsed for markup
+    fn replace_references(&mut self, text: &str) -> String {
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(n) = rest.find('&') {
+            out.push_str(&rest[..n]);
+            rest = &rest[n..];
+            let (name, len) = reference(rest);
+            if name.is_empty() || name == "#" {
+                out.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+            rest = &rest[len..];
+            let replacement = match name.strip_prefix('#') {
+                Some(number) => character_reference(number),
+                None => match self.grammar.get_entity(&name) {
+                    Some((e, _)) if e.external || e.public => None,
+                    Some((e, text)) if e.entity_type == EntityType::Literal => {
+                        Some(resolve_character_references(text))
+                    }
+                    Some((_, text)) => Some(text.to_string()),
+                    None => PREDEFINED_ENTITIES
+                        .iter()
+                        .find(|(n, _)| *n == name)
+                        .map(|(_, c)| c.to_string()),
+                },
+            };
+            match replacement {
+                Some(r) => out.push_str(&r),
+                None => self.error(DocumentErrorKind::UndefinedEntity(name)),
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// Match the specifications in a start tag to the attribute definitions of the element
+    /// and normalize their values, See ISO(7.9)
+    fn attributes(&mut self, key: &str, specifications: Vec<Specification>) -> Vec<Attribute> {
+        let (element, definitions) = match self.grammar.elements.get(key) {
+            Some(t) => (t.name.clone(), t.attributes.clone()),
+            None => (key.to_string(), Vec::new()),
+        };
+        let mut attributes: Vec<Attribute> = Vec::new();
+        for s in specifications {
+            let value = if s.literal {
+                self.replace_references(&s.value)
+                    .replace(['\n', '\t'], " ")
+                    .replace('\r', "")
+            } else {
+                s.value
+            };
+
+            let definition = match &s.name {
+                Some(name) => definitions
+                    .iter()
+                    .find(|d| self.grammar.fold(&d.name) == self.grammar.fold(name)),
+                // A value on its own is a token from the group of one of the attributes
+                None => definitions.iter().find(|d| match &d.declared_value {
+                    DeclaredValue::NameTokenGroup(g) | DeclaredValue::Notation(g) => {
+                        g.iter().any(|t| t.eq_ignore_ascii_case(&value))
+                    }
+                    _ => false,
+                }),
+            };
+            let definition = match definition {
+                Some(d) => d,
+                None => {
+                    self.error(DocumentErrorKind::UndeclaredAttribute {
+                        element: element.clone(),
+                        attribute: s.name.clone().unwrap_or_else(|| value.clone()),
+                    });
+                    // Kept as character data, unless it was a token that matched no group
+                    if let Some(name) = s.name {
+                        attributes.push(Attribute {
+                            name,
+                            value,
+                            declared_value: DeclaredValue::CData,
+                        });
+                    }
+                    continue;
+                }
+            };
+
+            let value = self.grammar.normalize(&definition.declared_value, value);
+            if !is_valid_value(&definition.declared_value, &value) {
+                self.error(DocumentErrorKind::InvalidAttributeValue {
+                    attribute: definition.name.clone(),
+                    value: value.clone(),
+                });
+            } else if let DefaultValue::Fixed(fixed) = &definition.default_value {
+                let fixed = self
+                    .grammar
+                    .normalize(&definition.declared_value, fixed.clone());
+                if fixed != value {
+                    self.error(DocumentErrorKind::FixedAttributeValue {
+                        attribute: definition.name.clone(),
+                        value: value.clone(),
+                        fixed,
+                    });
+                }
+            }
+            attributes.retain(|a| a.name != definition.name);
+            attributes.push(Attribute {
+                name: definition.name.clone(),
+                value,
+                declared_value: definition.declared_value.clone(),
+            });
+        }
+
+        for d in &definitions {
+            if d.default_value == DefaultValue::Required
+                && !attributes.iter().any(|a| a.name == d.name)
+            {
+                self.error(DocumentErrorKind::MissingAttribute {
+                    element: element.clone(),
+                    attribute: d.name.clone(),
+                });
+            }
+        }
+        attributes
+    }
+
+    /// End the elements still open at the end of the document
+    fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        while self.stack.len() > 1 {
+            self.end_element(true);
+        }
+        if !self.stack[0].has_content {
+            self.error(DocumentErrorKind::NoDocumentElement);
+        }
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.events.is_empty() && !self.finished && !self.waiting {
+            self.step();
+        }
+        self.events.pop_front()
+    }
+}
+
+/// Text without `skip_start` bytes at the start and `skip_end` at the end, borrowed if it was
+fn cow_slice(text: Cow<'_, str>, skip_start: usize, skip_end: usize) -> Cow<'_, str> {
+    match text {
+        Cow::Borrowed(s) => Cow::Borrowed(&s[skip_start..s.len() - skip_end]),
+        Cow::Owned(s) => Cow::Owned(s[skip_start..s.len() - skip_end].to_string()),
+    }
+}
+
+/// Whether a normalized value has the tokens its declared value calls for, See ISO(7.9.4)
+fn is_valid_value(declared_value: &DeclaredValue, value: &str) -> bool {
+    let is_name = |t: &str| {
+        t.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == ':')
+            && t.chars().all(is_name_char)
+    };
+    let is_number = |t: &str| t.chars().all(|c| c.is_ascii_digit());
+    let is_name_token = |t: &str| t.chars().all(is_name_char);
+    let is_number_token =
+        |t: &str| t.starts_with(|c: char| c.is_ascii_digit()) && t.chars().all(is_name_char);
+    let (single, valid): (bool, &dyn Fn(&str) -> bool) = match declared_value {
+        DeclaredValue::CData => return true,
+        DeclaredValue::NameTokenGroup(g) | DeclaredValue::Notation(g) => {
+            return g.iter().any(|t| t == value)
+        }
+        DeclaredValue::Entity | DeclaredValue::Id | DeclaredValue::IdRef | DeclaredValue::Name => {
+            (true, &is_name)
+        }
+        DeclaredValue::Entities | DeclaredValue::IdRefs | DeclaredValue::Names => (false, &is_name),
+        DeclaredValue::NmToken => (true, &is_name_token),
+        DeclaredValue::NmTokens => (false, &is_name_token),
+        DeclaredValue::Number => (true, &is_number),
+        DeclaredValue::Numbers => (false, &is_number),
+        DeclaredValue::NuToken => (true, &is_number_token),
+        DeclaredValue::NuTokens => (false, &is_number_token),
+    };
+    let tokens = value.split(' ').collect::<Vec<_>>();
+    !value.is_empty() && (!single || tokens.len() == 1) && tokens.iter().all(|t| valid(t))
+}
+
+fn name_len(s: &str) -> usize {
+    s.find(|c: char| !is_name_char(c)).unwrap_or(s.len())
+}
+
+fn starts_with_keyword(s: &str, keyword: &str) -> bool {
+    s.len() >= keyword.len()
+        && s.is_char_boundary(keyword.len())
+        && s[..keyword.len()].eq_ignore_ascii_case(keyword)
+        && !s[keyword.len()..].starts_with(is_name_char)
+}
+
+/// The length of a markup declaration, skipping literals and comments that may contain `>`
+fn declaration_len(s: &str) -> usize {
+    let mut rest = &s[2..];
+    loop {
+        let n = match rest.find(['>', '"', '\'', '-']) {
+            Some(n) => n,
+            None => return s.len(),
+        };
+        let c = rest[n..].chars().next().unwrap();
+        let close = match c {
+            '>' => return s.len() - rest.len() + n + 1,
+            '-' if rest[n..].starts_with("--") => rest[n + 2..].find("--").map(|m| n + 2 + m + 2),
+            '-' => Some(n + 1),
+            quote => rest[n + 1..].find(quote).map(|m| n + 1 + m + 1),
+        };
+        match close {
+            Some(close) => rest = &rest[close..],
+            None => return s.len(),
+        }
+    }
+}
+
+/// The comments of a comment declaration and its length, See ISO(10.3)
+fn comment_declaration(s: &str) -> Option<(Vec<String>, usize)> {
+    comment_parts(s).map(|(comments, len, _)| (comments, len))
+}
+
+/// The comments of a comment declaration, its length and whether it was closed before the end
+/// of `s`
+fn comment_parts(s: &str) -> Option<(Vec<String>, usize, bool)> {
+    if s.starts_with("<!>") {
+        return Some((Vec::new(), 3, true));
+    }
+    if !s.starts_with("<!--") {
+        return None;
+    }
+    let mut comments = Vec::new();
+    let mut rest = &s[2..];
+    loop {
+        rest = rest.trim_start();
+        if let Some(r) = rest.strip_prefix('>') {
+            return Some((comments, s.len() - r.len(), true));
+        }
+        let r = rest.strip_prefix("--")?;
+        let end = r.find("--").unwrap_or(r.len());
+        comments.push(r[..end].to_string());
+        rest = &r[(end + 2).min(r.len())..];
+        if rest.is_empty() {
+            return Some((comments, s.len(), false));
+        }
+    }
+}
+
+/// The text of a processing instruction and its length, See ISO(8)
+fn processing_instruction(s: &str) -> (&str, usize) {
+    match s.find('>') {
+        Some(n) => (&s[2..n], n + 1),
+        None => (&s[2..], s.len()),
+    }
+}
+
+/// The length of an ignored marked section, including nested sections and its end
+fn ignored_section_len(s: &str) -> usize {
+    let mut depth = 1;
+    let mut position = 0;
+    while depth > 0 {
+        let rest = &s[position..];
+        let open = rest.find("<![");
+        let close = match rest.find("]]>") {
+            Some(close) => close,
+            None => return s.len(),
+        };
+        match open {
+            Some(open) if open < close => {
+                depth += 1;
+                position += open + 3;
+            }
+            _ => {
+                depth -= 1;
+                position += close + 3;
+            }
+        }
+    }
+    position
+}
+
+/// The name of a reference after `&`, with `#` for character references, and its length
+fn reference(s: &str) -> (String, usize) {
+    let start = if s[1..].starts_with('#') { 2 } else { 1 };
+    let len = start + name_len(&s[start..]);
+    let name = s[1..len].to_string();
+    // The reference close may be left out, or be a record end, See ISO(9.4.5)
+    let len = if s[len..].starts_with(';') || s[len..].starts_with('\n') {
+        len + 1
+    } else {
+        len
+    };
+    (name, len)
+}
+
+/// The character for a numeric or function character reference, without its `#`
+fn character_reference(reference: &str) -> Option<String> {
+    let code = match reference.strip_prefix(|c| c == 'x' || c == 'X') {
+        Some(hex) if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+            u32::from_str_radix(hex, 16).ok()
+        }
+        _ => reference.parse::<u32>().ok(),
+    };
+    match code {
+        Some(code) => std::char::from_u32(code).map(|c| c.to_string()),
+        None => match reference.to_uppercase().as_str() {
+            "RE" => Some("\n".to_string()),
+            "RS" => Some(String::new()),
+            "SPACE" => Some(" ".to_string()),
+            "TAB" => Some("\t".to_string()),
+            _ => None,
+        },
+    }
+}
+
+/// Whether text is only character references, and so has no markup to parse
+fn is_character_text(text: &str) -> bool {
+    !text.contains('<')
+        && text
+            .match_indices('&')
+            .all(|(n, _)| text[n + 1..].starts_with('#'))
+}
+
+/// A start tag's name, None for an empty start tag, its attribute specifications and its length,
+/// which ends early for an unclosed start tag, See ISO(7.4)
+fn start_tag(s: &str) -> (Option<String>, Vec<Specification>, usize) {
+    let len = name_len(&s[1..]);
+    let name = Some(s[1..1 + len].to_string()).filter(|n| !n.is_empty());
+    let mut rest = &s[1 + len..];
+    let mut specifications = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if let Some(r) = rest.strip_prefix('>').or_else(|| rest.strip_prefix("/>")) {
+            return (name, specifications, s.len() - r.len());
+        }
+        if rest.is_empty() || rest.starts_with('<') {
+            return (name, specifications, s.len() - rest.len());
+        }
+
+        let len = name_len(rest);
+        if len == 0 {
+            // Skip a character that can't start a specification
+            rest = &rest[rest.chars().next().unwrap().len_utf8()..];
+            continue;
+        }
+        let token = rest[..len].to_string();
+        rest = &rest[len..];
+        let after = rest.trim_start();
+        if let Some(value) = after.strip_prefix('=') {
+            let value = value.trim_start();
+            let (text, literal, len) = match value.chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => {
+                    let end = value[1..].find(quote).map_or(value.len(), |n| n + 1);
+                    (&value[1..end], true, (end + 1).min(value.len()))
+                }
+                _ => {
+                    let end = value
+                        .find(|c: char| c.is_whitespace() || c == '>' || c == '<')
+                        .unwrap_or(value.len());
+                    (&value[..end], false, end)
+                }
+            };
+            specifications.push(Specification {
+                name: Some(token),
+                value: text.to_string(),
+                literal,
+            });
+            rest = &value[len..];
+        } else {
+            specifications.push(Specification {
+                name: None,
+                value: token,
+                literal: false,
+            });
+        }
+    }
+}
+
+/// A document type declaration, its length and whether it was closed before the end of `s`,
+/// See ISO(11.1)
+fn doctype(s: &str) -> Option<(Doctype, usize, bool)> {
+    if !starts_with_keyword(s, "<!DOCTYPE") {
+        return None;
+    }
+    let mut rest = s[9..].trim_start();
+    let len = name_len(rest);
+    let name = rest[..len].to_string();
+    rest = rest[len..].trim_start();
+
+    let literal = |rest: &mut &str| -> Option<String> {
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let end = rest[1..].find(quote)? + 1;
+        let value = rest[1..end].to_string();
+        *rest = rest[end + 1..].trim_start();
+        Some(value)
+    };
+    let (mut public_id, mut system_id) = (None, None);
+    if starts_with_keyword(rest, "PUBLIC") {
+        rest = rest[6..].trim_start();
+        public_id = literal(&mut rest);
+        system_id = literal(&mut rest);
+    } else if starts_with_keyword(rest, "SYSTEM") {
+        rest = rest[6..].trim_start();
+        system_id = literal(&mut rest);
+    }
+
+    let mut internal_subset = None;
+    let mut complete = true;
+    if let Some(subset) = rest.strip_prefix('[') {
+        let end = internal_subset_len(subset);
+        complete = end < subset.len();
+        internal_subset = Some(subset[..end].to_string());
+        rest = subset[(end + 1).min(subset.len())..].trim_start();
+    }
+    let (rest, complete) = match rest.strip_prefix('>') {
+        Some(rest) => (rest, complete),
+        None => (rest, false),
+    };
+    Some((
+        Doctype {
+            name,
+            public_id,
+            system_id,
+            internal_subset,
+        },
+        s.len() - rest.len(),
+        complete,
+    ))
+}
+
+/// The length of an internal subset up to its closing bracket
+fn internal_subset_len(s: &str) -> usize {
+    let mut position = 0;
+    let mut marked_sections = 0;
+    while position < s.len() {
+        let rest = &s[position..];
+        if rest.starts_with("<![") {
+            marked_sections += 1;
+            position += 3;
+        } else if rest.starts_with("]]>") && marked_sections > 0 {
+            marked_sections -= 1;
+            position += 3;
+        } else if rest.starts_with("<!") {
+            position += declaration_len(rest);
+        } else if rest.starts_with(']') {
+            return position;
+        } else {
+            position += rest.chars().next().unwrap().len_utf8();
+        }
+    }
+    s.len()
+}
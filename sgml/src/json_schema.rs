@@ -0,0 +1,982 @@
+//! JSON Schemas for the JSON encoding of documents, the one shape a document has as JSON:
+//!
+//! - An element is an object with its `name`, an `attributes` object when it has attributes, and
+//!   a `content` array when it has content, in which character data is a string
+//! - Attributes are keyed by name. NUMBER values are integers and NUMBERS values arrays of them,
+//!   ENTITIES, IDREFS, NAMES, NMTOKENS and NUTOKENS values are arrays of strings, a value from a
+//!   name token group or notation is the token as declared, and other values are strings
+//!
+//! With the `serde` feature, `Document` is written and read in this encoding. The types generated
+//! by `dtd_gen` and those `de` reads into follow the content models instead, with child elements
+//! keyed by name, but their attribute values are the same
+use crate::att_list::{AttributeDefinition, DeclaredValue, DefaultValue};
+use crate::content_model::{Connector, Occurrence};
+use crate::dtd::DocumentTypeDefinition;
+use crate::schema::{
+    AttributeItem, Particle, Schema, SchemaContent, SchemaElement, SchemaError, SchemaOptions,
+};
+use std::collections::HashMap;
+use std::fmt;
+
+const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Patterns for name and name token values, allowing the characters XML adds to SGML's reference syntax
+const NAME_PATTERN: &str = "^[A-Za-z_:][A-Za-z0-9._:-]*$";
+const NAME_TOKEN_PATTERN: &str = "^[A-Za-z0-9._:-]+$";
+const NUMBER_TOKEN_PATTERN: &str = "^[0-9][A-Za-z0-9._:-]*$";
+
+/// A JSON value, written with two space indentation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    /// Members in the order they are written
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    fn string(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+
+    fn definition(name: &str) -> Json {
+        Json::object(vec![("$ref", Json::String(format!("#/$defs/{}", name)))])
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Json::Array(_) | Json::Object(_))
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let indent = "  ".repeat(depth + 1);
+        match self {
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(v) if v.is_empty() => f.write_str("[]"),
+            // Lists of values stay on one line
+            Json::Array(v) if v.iter().all(|j| j.is_scalar()) => {
+                f.write_str("[")?;
+                for (n, j) in v.iter().enumerate() {
+                    if n > 0 {
+                        f.write_str(", ")?;
+                    }
+                    j.write(f, depth)?;
+                }
+                f.write_str("]")
+            }
+            Json::Array(v) => {
+                f.write_str("[\n")?;
+                for (n, j) in v.iter().enumerate() {
+                    f.write_str(&indent)?;
+                    j.write(f, depth + 1)?;
+                    f.write_str(if n + 1 < v.len() { ",\n" } else { "\n" })?;
+                }
+                write!(f, "{}]", "  ".repeat(depth))
+            }
+            Json::Object(v) if v.is_empty() => f.write_str("{}"),
+            Json::Object(v) => {
+                f.write_str("{\n")?;
+                for (n, (k, j)) in v.iter().enumerate() {
+                    f.write_str(&indent)?;
+                    write_string(f, k)?;
+                    f.write_str(": ")?;
+                    j.write(f, depth + 1)?;
+                    f.write_str(if n + 1 < v.len() { ",\n" } else { "\n" })?;
+                }
+                write!(f, "{}}}", "  ".repeat(depth))
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+/// Generate a JSON Schema from a DTD read as by `Schema::from_dtd`, for documents in the JSON
+/// encoding described above
+pub fn to_json_schema(
+    dtd: &DocumentTypeDefinition,
+    options: &SchemaOptions,
+) -> Result<Json, SchemaError> {
+    let schema = Schema::from_dtd(dtd, options)?;
+    let converter = Converter {
+        schema: &schema,
+        names: schema.get_definition_names(),
+        included: schema.get_included(),
+    };
+    Ok(converter.convert())
+}
+
+/// How often something may occur, where None is unbounded
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct Bounds(usize, Option<usize>);
+
+impl From<Occurrence> for Bounds {
+    fn from(o: Occurrence) -> Self {
+        match o {
+            Occurrence::Once => Bounds(1, Some(1)),
+            Occurrence::Optional => Bounds(0, Some(1)),
+            Occurrence::OneOrMore => Bounds(1, None),
+            Occurrence::ZeroOrMore => Bounds(0, None),
+        }
+    }
+}
+
+/// One alternative for an item of a content array
+struct Alternative {
+    schema: Json,
+    names: Vec<String>,
+    text: bool,
+}
+
+struct Converter<'a> {
+    schema: &'a Schema,
+    /// Names in $defs of groups and attribute groups
+    names: HashMap<String, String>,
+    /// Inclusions that apply to each element, from its own declaration or an ancestor's
+    included: HashMap<String, Vec<String>>,
+}
+
+impl<'a> Converter<'a> {
+    fn convert(&self) -> Json {
+        let mut definitions = Vec::new();
+        for g in &self.schema.groups {
+            let mut alternatives = Vec::new();
+            for p in &g.particles {
+                self.alternatives(p, &[], &mut alternatives);
+            }
+            definitions.push((self.names[&g.name].clone(), union(alternatives)));
+        }
+        for g in &self.schema.attribute_groups {
+            definitions.push((
+                self.names[&g.name].clone(),
+                self.attributes(&g.attributes, false),
+            ));
+        }
+        for e in &self.schema.elements {
+            definitions.push((e.name.clone(), self.element(e)));
+        }
+
+        let roots = self.schema.get_roots();
+        let root = match roots.as_slice() {
+            [root] => ("$ref", Json::String(format!("#/$defs/{}", root.name))),
+            _ => (
+                "oneOf",
+                Json::Array(roots.iter().map(|e| Json::definition(&e.name)).collect()),
+            ),
+        };
+        Json::object(vec![
+            ("$schema", Json::string(DIALECT)),
+            root,
+            ("$defs", Json::Object(definitions)),
+        ])
+    }
+
+    fn group_particles(&self, r: &str) -> Option<(Connector, &'a [Particle])> {
+        self.schema
+            .get_group(r)
+            .map(|g| (g.connector, g.particles.as_slice()))
+    }
+
+    fn excludes(&self, particle: &Particle, exclusions: &[String]) -> bool {
+        !exclusions.is_empty()
+            && self
+                .schema
+                .get_particle_names(particle)
+                .iter()
+                .any(|n| exclusions.contains(n))
+    }
+
+    /// The schemas an item of a content array may match for a particle
+    fn alternatives(&self, particle: &Particle, exclusions: &[String], out: &mut Vec<Alternative>) {
+        let alternative = match particle {
+            Particle::PcData => Alternative {
+                schema: Json::object(vec![("type", Json::string("string"))]),
+                names: Vec::new(),
+                text: true,
+            },
+            Particle::Element(name, _) if exclusions.contains(name) => return,
+            Particle::Element(name, _) => Alternative {
+                schema: Json::definition(name),
+                names: vec![name.clone()],
+                text: false,
+            },
+            Particle::Group(g) => {
+                g.particles
+                    .iter()
+                    .for_each(|p| self.alternatives(p, exclusions, out));
+                return;
+            }
+            // The group mentions an excluded element, so this use of it is written out
+            Particle::Reference(r) if self.excludes(particle, exclusions) => {
+                if let Some((_, particles)) = self.group_particles(r) {
+                    particles
+                        .iter()
+                        .for_each(|p| self.alternatives(p, exclusions, out));
+                }
+                return;
+            }
+            Particle::Reference(r) => Alternative {
+                schema: Json::definition(&self.names[r]),
+                names: self.schema.get_particle_names(particle),
+                text: self.schema.is_mixed(particle),
+            },
+        };
+        if !out.iter().any(|a| a.schema == alternative.schema) {
+            out.push(alternative);
+        }
+    }
+
+    /// How often each element may occur within a particle
+    fn counts(&self, particle: &Particle, exclusions: &[String]) -> Vec<(String, Bounds)> {
+        let (connector, particles, bounds) = match particle {
+            Particle::PcData => return Vec::new(),
+            Particle::Element(name, _) if exclusions.contains(name) => return Vec::new(),
+            Particle::Element(name, o) => return vec![(name.clone(), (*o).into())],
+            Particle::Group(g) => (g.connector, g.particles.as_slice(), g.occurrence.into()),
+            Particle::Reference(r) => match self.group_particles(r) {
+                Some((connector, particles)) => (connector, particles, Bounds(1, Some(1))),
+                None => return Vec::new(),
+            },
+        };
+
+        let members = particles
+            .iter()
+            .map(|p| self.counts(p, exclusions))
+            .collect::<Vec<_>>();
+        let mut names: Vec<String> = Vec::new();
+        for (name, _) in members.iter().flatten() {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+
+        names
+            .into_iter()
+            .map(|name| {
+                let each = members.iter().map(|m| {
+                    m.iter()
+                        .find(|(n, _)| *n == name)
+                        .map(|(_, b)| *b)
+                        .unwrap_or(Bounds(0, Some(0)))
+                });
+                let Bounds(min, max) = match connector {
+                    Connector::Or => each
+                        .reduce(|a, b| {
+                            Bounds(a.0.min(b.0), a.1.and_then(|a| b.1.map(|b| a.max(b))))
+                        })
+                        .unwrap(),
+                    _ => each
+                        .reduce(|a, b| Bounds(a.0 + b.0, a.1.and_then(|a| b.1.map(|b| a + b))))
+                        .unwrap(),
+                };
+                let max = match (max, bounds.1) {
+                    (Some(0), _) => Some(0),
+                    (Some(a), Some(b)) => Some(a * b),
+                    _ => None,
+                };
+                (name, Bounds(min * bounds.0, max))
+            })
+            .collect()
+    }
+
+    /// The fewest items a particle needs in a content array
+    fn min_length(&self, particle: &Particle, exclusions: &[String]) -> usize {
+        let (connector, particles, occurrence) = match particle {
+            Particle::PcData => return 0,
+            Particle::Element(name, _) if exclusions.contains(name) => return 0,
+            Particle::Element(_, o) => return Bounds::from(*o).0,
+            Particle::Group(g) => (g.connector, g.particles.as_slice(), g.occurrence),
+            Particle::Reference(r) => match self.group_particles(r) {
+                Some((connector, particles)) => (connector, particles, Occurrence::Once),
+                None => return 0,
+            },
+        };
+        let lengths = particles.iter().map(|p| self.min_length(p, exclusions));
+        let length = match connector {
+            Connector::Or => lengths.min().unwrap_or(0),
+            _ => lengths.sum(),
+        };
+        length * Bounds::from(occurrence).0
+    }
+
+    /// A sequence that can be checked by position: required items, then either optional items or
+    /// one repeated item
+    fn positional(&self, particle: &Particle, exclusions: &[String]) -> Option<Json> {
+        let g = match particle {
+            Particle::Group(g)
+                if g.connector == Connector::Sequence && g.occurrence == Occurrence::Once =>
+            {
+                g
+            }
+            _ => return None,
+        };
+
+        let mut prefix = Vec::new();
+        let mut required = 0;
+        let mut rest = None;
+        let mut optional = false;
+        let members = g
+            .particles
+            .iter()
+            .filter(|p| !matches!(p, Particle::Element(n, _) if exclusions.contains(n)))
+            .collect::<Vec<_>>();
+        if members.len() < 2 {
+            return None;
+        }
+        for (n, p) in members.iter().enumerate() {
+            let (schema, occurrence) = match p {
+                Particle::Element(name, o) => (Json::definition(name), *o),
+                // A choice of single elements is still one item
+                Particle::Group(g)
+                    if g.connector == Connector::Or
+                        && g.particles.iter().all(|p| {
+                            matches!(p, Particle::Element(n, Occurrence::Once) if !exclusions.contains(n))
+                        }) =>
+                {
+                    let mut alternatives = Vec::new();
+                    self.alternatives(p, exclusions, &mut alternatives);
+                    (union(alternatives), g.occurrence)
+                }
+                _ => return None,
+            };
+            match occurrence {
+                Occurrence::Once if !optional => {
+                    prefix.push(schema);
+                    required += 1;
+                }
+                Occurrence::Optional => {
+                    optional = true;
+                    prefix.push(schema);
+                }
+                Occurrence::OneOrMore | Occurrence::ZeroOrMore
+                    if !optional && n + 1 == members.len() =>
+                {
+                    if occurrence == Occurrence::OneOrMore {
+                        required += 1;
+                    }
+                    rest = Some(schema);
+                }
+                _ => return None,
+            }
+        }
+
+        let mut members = vec![
+            ("type", Json::string("array")),
+            ("prefixItems", Json::Array(prefix)),
+            ("items", rest.unwrap_or(Json::Bool(false))),
+        ];
+        if required > 0 {
+            members.push(("minItems", Json::Number(required as i64)));
+        }
+        Some(Json::object(members))
+    }
+
+    /// The schema of the content array, and whether it must be present
+    fn content(&self, e: &SchemaElement) -> Option<(Json, bool)> {
+        let text = || Json::object(vec![("type", Json::string("string"))]);
+        let array =
+            |items: Json| Json::object(vec![("type", Json::string("array")), ("items", items)]);
+        let p = match &e.content {
+            SchemaContent::Empty => return None,
+            SchemaContent::CData | SchemaContent::RCData => return Some((array(text()), false)),
+            SchemaContent::Any => {
+                let mut alternatives = vec![text()];
+                alternatives.extend(
+                    self.schema
+                        .elements
+                        .iter()
+                        .map(|e| Json::definition(&e.name)),
+                );
+                return Some((
+                    array(Json::object(vec![("anyOf", Json::Array(alternatives))])),
+                    false,
+                ));
+            }
+            SchemaContent::Model(p) => p,
+        };
+
+        // Inclusions don't apply to declared content, See ISO(B.4.2.6)
+        let present = self.schema.get_children(&SchemaElement {
+            inclusions: Vec::new(),
+            ..e.clone()
+        });
+        let extra = self
+            .included
+            .get(&e.name)
+            .map(|included| {
+                included
+                    .iter()
+                    .filter(|n| !present.contains(n) && !e.exclusions.contains(n))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let min_length = self.min_length(p, &e.exclusions);
+        if extra.is_empty() && !self.schema.is_mixed(p) {
+            if let Some(positional) = self.positional(p, &e.exclusions) {
+                return Some((positional, min_length > 0));
+            }
+        }
+
+        let mut alternatives = Vec::new();
+        self.alternatives(p, &e.exclusions, &mut alternatives);
+        alternatives.extend(extra.iter().map(|n| Alternative {
+            schema: Json::definition(n),
+            names: vec![n.clone()],
+            text: false,
+        }));
+
+        let mut members = vec![("type", Json::string("array"))];
+        if alternatives.is_empty() {
+            members.push(("maxItems", Json::Number(0)));
+        } else {
+            members.push(("items", union(alternatives)));
+        }
+        if min_length > 0 {
+            members.push(("minItems", Json::Number(min_length as i64)));
+        }
+
+        // Without order, the number of each element can still be checked
+        let constraints = self
+            .counts(p, &e.exclusions)
+            .into_iter()
+            .filter(|(_, b)| b.0 > 0 || (b.1.is_some() && b.1 != Some(0)))
+            .map(|(name, Bounds(min, max))| {
+                let mut members = vec![("contains", Json::definition(&name))];
+                if min != 1 {
+                    members.push(("minContains", Json::Number(min as i64)));
+                }
+                if let Some(max) = max {
+                    members.push(("maxContains", Json::Number(max as i64)));
+                }
+                Json::object(members)
+            })
+            .collect::<Vec<_>>();
+        if !constraints.is_empty() {
+            members.push(("allOf", Json::Array(constraints)));
+        }
+        Some((Json::object(members), min_length > 0))
+    }
+
+    fn element(&self, e: &SchemaElement) -> Json {
+        let mut comments = Vec::new();
+        if !e.exclusions.is_empty() {
+            comments.push(format!(
+                "Exclusions -({}) are only removed from the content of {}, not its descendants.",
+                e.exclusions.join("|"),
+                e.name
+            ));
+        }
+        if !e.inclusions.is_empty() {
+            comments.push(format!(
+                "Inclusions +({}) are allowed in the content of {} and every element it can contain.",
+                e.inclusions.join("|"),
+                e.name
+            ));
+        }
+
+        let mut properties = vec![("name", Json::object(vec![("const", Json::string(&e.name))]))];
+        let mut required = vec![Json::string("name")];
+        if !e.attributes.is_empty() {
+            properties.push(("attributes", self.attributes(&e.attributes, true)));
+            if self.has_required(&e.attributes) {
+                required.push(Json::string("attributes"));
+            }
+        }
+        if let Some((content, needed)) = self.content(e) {
+            properties.push(("content", content));
+            if needed {
+                required.push(Json::string("content"));
+            }
+        }
+
+        let mut members = Vec::new();
+        if !comments.is_empty() {
+            members.push(("$comment", Json::String(comments.join(" "))));
+        }
+        members.push(("type", Json::string("object")));
+        members.push(("properties", Json::object(properties)));
+        members.push(("required", Json::Array(required)));
+        members.push(("additionalProperties", Json::Bool(false)));
+        Json::object(members)
+    }
+
+    fn has_required(&self, items: &[AttributeItem]) -> bool {
+        items.iter().any(|item| match item {
+            AttributeItem::Definition(d) => d.default_value == DefaultValue::Required,
+            AttributeItem::Reference(r) => self
+                .schema
+                .get_attribute_group(r)
+                .map(|g| self.has_required(&g.attributes))
+                .unwrap_or(false),
+        })
+    }
+
+    /// The schema of an attributes object, or of an attribute group that it refers to
+    fn attributes(&self, items: &[AttributeItem], object: bool) -> Json {
+        let mut groups = Vec::new();
+        let mut properties: Vec<(String, Json)> = Vec::new();
+        let mut required = Vec::new();
+        for item in items {
+            match item {
+                AttributeItem::Definition(d) => {
+                    // The first definition of an attribute is the one that applies
+                    if properties.iter().any(|(n, _)| *n == d.name) {
+                        continue;
+                    }
+                    if d.default_value == DefaultValue::Required {
+                        required.push(Json::string(&d.name));
+                    }
+                    properties.push((d.name.clone(), attribute(d)));
+                }
+                AttributeItem::Reference(r) => groups.push(Json::definition(&self.names[r])),
+            }
+        }
+
+        let mut members = Vec::new();
+        if object {
+            members.push(("type", Json::string("object")));
+        }
+        if !groups.is_empty() {
+            members.push(("allOf", Json::Array(groups)));
+        }
+        if !properties.is_empty() {
+            members.push(("properties", Json::Object(properties)));
+        }
+        if !required.is_empty() {
+            members.push(("required", Json::Array(required)));
+        }
+        // Attributes from groups are evaluated through allOf
+        if object {
+            members.push(("unevaluatedProperties", Json::Bool(false)));
+        }
+        Json::object(members)
+    }
+}
+
+/// A schema matching any one of the alternatives, using oneOf unless they overlap
+fn union(mut alternatives: Vec<Alternative>) -> Json {
+    if alternatives.len() == 1 {
+        return alternatives.remove(0).schema;
+    }
+    let overlap = alternatives.iter().enumerate().any(|(n, a)| {
+        alternatives[n + 1..]
+            .iter()
+            .any(|b| (a.text && b.text) || a.names.iter().any(|name| b.names.contains(name)))
+    });
+    let keyword = if overlap { "anyOf" } else { "oneOf" };
+    Json::object(vec![(
+        keyword,
+        Json::Array(alternatives.into_iter().map(|a| a.schema).collect()),
+    )])
+}
+
+/// The JSON form of an attribute value
+fn attribute_value(declared_value: &DeclaredValue, v: &str) -> Json {
+    match declared_value {
+        DeclaredValue::Number => v.parse().map(Json::Number).unwrap_or(Json::string(v)),
+        DeclaredValue::Numbers => Json::Array(
+            v.split_whitespace()
+                .map(|n| n.parse().map(Json::Number).unwrap_or(Json::string(n)))
+                .collect(),
+        ),
+        DeclaredValue::Entities
+        | DeclaredValue::IdRefs
+        | DeclaredValue::Names
+        | DeclaredValue::NmTokens
+        | DeclaredValue::NuTokens => Json::Array(v.split_whitespace().map(Json::string).collect()),
+        _ => Json::string(v),
+    }
+}
+
+fn attribute(d: &AttributeDefinition) -> Json {
+    let pattern = |p: &str| {
+        Json::object(vec![
+            ("type", Json::string("string")),
+            ("pattern", Json::string(p)),
+        ])
+    };
+    let list = |item: Json| {
+        Json::object(vec![
+            ("type", Json::string("array")),
+            ("items", item),
+            ("minItems", Json::Number(1)),
+        ])
+    };
+    let number = || {
+        Json::object(vec![
+            ("type", Json::string("integer")),
+            ("minimum", Json::Number(0)),
+        ])
+    };
+
+    let schema = match &d.declared_value {
+        DeclaredValue::CData => Json::object(vec![("type", Json::string("string"))]),
+        DeclaredValue::Entity | DeclaredValue::Id | DeclaredValue::IdRef | DeclaredValue::Name => {
+            pattern(NAME_PATTERN)
+        }
+        DeclaredValue::Entities | DeclaredValue::IdRefs | DeclaredValue::Names => {
+            list(pattern(NAME_PATTERN))
+        }
+        DeclaredValue::NmToken => pattern(NAME_TOKEN_PATTERN),
+        DeclaredValue::NmTokens => list(pattern(NAME_TOKEN_PATTERN)),
+        DeclaredValue::NuToken => pattern(NUMBER_TOKEN_PATTERN),
+        DeclaredValue::NuTokens => list(pattern(NUMBER_TOKEN_PATTERN)),
+        DeclaredValue::Number => number(),
+        DeclaredValue::Numbers => list(number()),
+        DeclaredValue::Notation(v) | DeclaredValue::NameTokenGroup(v) => Json::object(vec![(
+            "enum",
+            Json::Array(v.iter().map(|v| Json::string(v)).collect()),
+        )]),
+    };
+
+    match &d.default_value {
+        DefaultValue::Fixed(v) => {
+            Json::object(vec![("const", attribute_value(&d.declared_value, v))])
+        }
+        DefaultValue::Value(v) => match schema {
+            Json::Object(mut members) => {
+                members.push(("default".to_string(), attribute_value(&d.declared_value, v)));
+                Json::Object(members)
+            }
+            schema => schema,
+        },
+        _ => schema,
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::dtd::read_dtd;
+    use crate::json_schema::{
+        to_json_schema, Json, NAME_PATTERN, NAME_TOKEN_PATTERN, NUMBER_TOKEN_PATTERN,
+    };
+    use crate::schema::test::{html_schema_dtd, xhtml_schema_dtd};
+    use crate::schema::SchemaOptions;
+    use std::collections::HashSet;
+
+    /// The errors of a JSON value against a schema written by `to_json_schema`, checking the
+    /// keywords it uses. Panics on any other keyword, so that new ones get checked too
+    pub fn validate(schema: &serde_json::Value, value: &serde_json::Value) -> Vec<String> {
+        match evaluate(schema, schema, value, "") {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors,
+        }
+    }
+
+    /// The names of the properties evaluated in a valid object, otherwise the errors
+    fn evaluate(
+        root: &serde_json::Value,
+        schema: &serde_json::Value,
+        value: &serde_json::Value,
+        path: &str,
+    ) -> Result<HashSet<String>, Vec<String>> {
+        use serde_json::Value;
+        let members = match schema {
+            Value::Bool(true) => return Ok(HashSet::new()),
+            Value::Bool(false) => return Err(vec![format!("{}: not allowed", path)]),
+            Value::Object(members) => members,
+            _ => panic!("not a schema: {}", schema),
+        };
+        let fail = |message: String| Err(vec![format!("{}: {}", path, message)]);
+        // The properties evaluated by schemas applied to the value itself, rather than to a part
+        let mut evaluated = HashSet::new();
+        let mut errors = Vec::new();
+        let mut apply = |schema: &Value, value: &Value, path: &str| {
+            evaluate(root, schema, value, path).unwrap_or_else(|e| {
+                errors.extend(e);
+                HashSet::new()
+            })
+        };
+        let array = value.as_array();
+        let object = value.as_object();
+        for (keyword, v) in members {
+            match keyword.as_str() {
+                "$schema" | "$defs" | "$comment" | "default" => {}
+                "$ref" => {
+                    let name = v.as_str().unwrap().trim_start_matches("#/$defs/");
+                    evaluated.extend(apply(&root["$defs"][name], value, path));
+                }
+                "type" => {
+                    let matches = match v.as_str().unwrap() {
+                        "object" => value.is_object(),
+                        "array" => value.is_array(),
+                        "string" => value.is_string(),
+                        "integer" => value.is_u64() || value.is_i64(),
+                        ty => panic!("type {}", ty),
+                    };
+                    if !matches {
+                        return fail(format!("{} is not of type {}", value, v));
+                    }
+                }
+                "const" if value != v => return fail(format!("{} is not {}", value, v)),
+                "enum" if !v.as_array().unwrap().contains(value) => {
+                    return fail(format!("{} is not one of {}", value, v))
+                }
+                "const" | "enum" => {}
+                "pattern" => {
+                    let pattern = v.as_str().unwrap();
+                    let name = |c: char| c.is_ascii_alphanumeric() || "._:-".contains(c);
+                    let first = |c: char| match pattern {
+                        NAME_PATTERN => c.is_ascii_alphabetic() || c == '_' || c == ':',
+                        NAME_TOKEN_PATTERN => name(c),
+                        NUMBER_TOKEN_PATTERN => c.is_ascii_digit(),
+                        _ => panic!("pattern {}", pattern),
+                    };
+                    if let Some(s) = value.as_str() {
+                        let mut chars = s.chars();
+                        if !chars.next().is_some_and(first) || !chars.all(name) {
+                            return fail(format!("{} does not match {}", value, pattern));
+                        }
+                    }
+                }
+                "minimum" => {
+                    if value.as_i64().is_some_and(|n| n < v.as_i64().unwrap()) {
+                        return fail(format!("{} is less than {}", value, v));
+                    }
+                }
+                "properties" => {
+                    for (name, schema) in v.as_object().unwrap() {
+                        if let Some(property) = object.and_then(|o| o.get(name)) {
+                            apply(schema, property, &format!("{}/{}", path, name));
+                            evaluated.insert(name.clone());
+                        }
+                    }
+                }
+                "required" => {
+                    for name in v.as_array().unwrap() {
+                        if object.is_some_and(|o| !o.contains_key(name.as_str().unwrap())) {
+                            return fail(format!("{} is required", name));
+                        }
+                    }
+                }
+                "additionalProperties" => {
+                    let properties = members.get("properties").and_then(Value::as_object);
+                    for (name, property) in object.into_iter().flatten() {
+                        if !properties.is_some_and(|p| p.contains_key(name)) {
+                            apply(v, property, &format!("{}/{}", path, name));
+                        }
+                    }
+                }
+                "allOf" => {
+                    for schema in v.as_array().unwrap() {
+                        evaluated.extend(apply(schema, value, path));
+                    }
+                }
+                "anyOf" | "oneOf" => {
+                    let (passed, failed): (Vec<_>, Vec<_>) = v
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|schema| evaluate(root, schema, value, path))
+                        .partition(Result::is_ok);
+                    if passed.is_empty() {
+                        return Err(failed.into_iter().flat_map(Result::unwrap_err).collect());
+                    }
+                    if keyword == "oneOf" && passed.len() > 1 {
+                        return fail(format!("{} matches more than one of {}", value, v));
+                    }
+                    evaluated.extend(passed.into_iter().flat_map(Result::unwrap));
+                }
+                "prefixItems" => {
+                    for (n, (schema, item)) in v
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .zip(array.into_iter().flatten())
+                        .enumerate()
+                    {
+                        apply(schema, item, &format!("{}/{}", path, n));
+                    }
+                }
+                "items" => {
+                    let skip = members
+                        .get("prefixItems")
+                        .and_then(Value::as_array)
+                        .map_or(0, Vec::len);
+                    for (n, item) in array.into_iter().flatten().enumerate().skip(skip) {
+                        apply(v, item, &format!("{}/{}", path, n));
+                    }
+                }
+                "minItems" | "maxItems" => {
+                    let bound = v.as_u64().unwrap() as usize;
+                    if let Some(len) = array.map(Vec::len) {
+                        if (keyword == "minItems" && len < bound)
+                            || (keyword == "maxItems" && len > bound)
+                        {
+                            return fail(format!("{} items against {} {}", len, keyword, bound));
+                        }
+                    }
+                }
+                "contains" => {
+                    let count = array
+                        .into_iter()
+                        .flatten()
+                        .filter(|item| evaluate(root, v, item, path).is_ok())
+                        .count();
+                    let bound = |keyword: &str| members.get(keyword).and_then(Value::as_u64);
+                    let min = bound("minContains").unwrap_or(1) as usize;
+                    let max = bound("maxContains").map(|n| n as usize);
+                    if array.is_some() && (count < min || max.is_some_and(|max| count > max)) {
+                        return fail(format!("{} items match {}", count, v));
+                    }
+                }
+                "minContains" | "maxContains" => {}
+                "unevaluatedProperties" => {}
+                keyword => panic!("keyword {}", keyword),
+            }
+        }
+        // Applied last, once every other keyword has evaluated what it does
+        if let Some(unevaluated) = members.get("unevaluatedProperties") {
+            for (name, property) in object.into_iter().flatten() {
+                if !evaluated.contains(name) {
+                    if let Err(e) = evaluate(root, unevaluated, property, path) {
+                        errors.extend(e.into_iter().map(|e| format!("{} {}", name, e)));
+                    }
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(evaluated)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn definition<'a>(schema: &'a Json, name: &str) -> &'a Json {
+        schema.get("$defs").unwrap().get(name).unwrap()
+    }
+
+    fn content<'a>(schema: &'a Json, name: &str) -> &'a Json {
+        definition(schema, name)
+            .get("properties")
+            .unwrap()
+            .get("content")
+            .unwrap()
+    }
+
+    #[test]
+    pub fn test_json_schema_models() {
+        let (_, dtd) = read_dtd(
+            "<!ENTITY % inline \"B | I\">
+<!ENTITY % common \"id ID #IMPLIED\">
+<!ELEMENT DOC - - (HEAD, BODY?)>
+<!ELEMENT HEAD - O EMPTY>
+<!ELEMENT BODY - - (#PCDATA | %inline)* -(I)>
+<!ELEMENT (B|I) - - (NOTE & (%inline)+)>
+<!ELEMENT NOTE - - (#PCDATA)>
+<!ATTLIST DOC
+    %common;
+    version NUMBER #REQUIRED
+    status (draft|final) final
+    sizes NUMBERS \"1 2\">",
+        )
+        .unwrap();
+        let schema = to_json_schema(&dtd, &SchemaOptions::default()).unwrap();
+        assert_eq!(
+            schema.get("$schema"),
+            Some(&Json::string(
+                "https://json-schema.org/draft/2020-12/schema"
+            ))
+        );
+        assert_eq!(schema.get("$ref"), Some(&Json::string("#/$defs/DOC")));
+        assert_eq!(
+            definition(&schema, "inline").to_string(),
+            "{\n  \"oneOf\": [\n    {\n      \"$ref\": \"#/$defs/B\"\n    },\n    {\n      \"$ref\": \"#/$defs/I\"\n    }\n  ]\n}"
+        );
+
+        // Sequences are checked by position
+        let doc = content(&schema, "DOC");
+        assert_eq!(doc.get("items"), Some(&Json::Bool(false)));
+        assert_eq!(doc.get("minItems"), Some(&Json::Number(1)));
+        assert!(matches!(doc.get("prefixItems"), Some(Json::Array(v)) if v.len() == 2));
+
+        // The exclusion is applied by writing out the group
+        let body = content(&schema, "BODY").to_string();
+        assert!(body.contains("\"oneOf\": [\n      {\n        \"type\": \"string\"\n      },\n      {\n        \"$ref\": \"#/$defs/B\"\n      }\n    ]"));
+        assert!(definition(&schema, "BODY").get("$comment").is_some());
+
+        // Other groups are checked by the number of each element
+        let b = content(&schema, "B").to_string();
+        assert!(b.contains("\"$ref\": \"#/$defs/inline\""));
+        assert!(b.contains("\"contains\": {\n        \"$ref\": \"#/$defs/NOTE\"\n      },\n      \"maxContains\": 1"));
+        assert!(b.contains("\"minItems\": 2"));
+
+        let attributes = definition(&schema, "DOC")
+            .get("properties")
+            .unwrap()
+            .get("attributes")
+            .unwrap()
+            .to_string();
+        assert!(attributes
+            .contains("\"allOf\": [\n    {\n      \"$ref\": \"#/$defs/common\"\n    }\n  ]"));
+        assert!(
+            attributes.contains("\"enum\": [\"draft\", \"final\"],\n      \"default\": \"final\"")
+        );
+        assert!(attributes.contains("\"default\": [1, 2]"));
+        assert!(attributes.contains("\"required\": [\"version\"]"));
+        assert!(attributes.contains("\"unevaluatedProperties\": false"));
+        assert_eq!(
+            definition(&schema, "HEAD")
+                .get("properties")
+                .unwrap()
+                .get("content"),
+            None
+        );
+    }
+
+    #[test]
+    pub fn test_json_schema_html() {
+        let schema = to_json_schema(&html_schema_dtd(), &SchemaOptions::default()).unwrap();
+        assert_eq!(schema.get("$ref"), Some(&Json::string("#/$defs/HTML")));
+        // Inclusions are allowed as items
+        assert!(content(&schema, "HEAD")
+            .to_string()
+            .contains("\"$ref\": \"#/$defs/META\""));
+        assert!(definition(&schema, "text").get("oneOf").is_some());
+
+        let schema = to_json_schema(&xhtml_schema_dtd(), &SchemaOptions::default()).unwrap();
+        assert_eq!(schema.get("$ref"), Some(&Json::string("#/$defs/html")));
+        assert!(content(&schema, "html").get("prefixItems").is_some());
+    }
+}
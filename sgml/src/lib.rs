@@ -1,7 +1,27 @@
 pub mod att_list;
+pub mod automaton;
+pub mod borrowed;
+pub mod catalog;
 pub mod comment;
+pub mod content_model;
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod document;
 pub mod dtd;
 pub mod element;
 pub mod entity;
+pub mod esis;
+pub mod flatten;
+pub mod instance;
+pub mod json_schema;
 pub mod marked_section;
+pub mod printer;
+pub mod reader;
+pub mod relax_ng;
+pub mod schema;
+pub mod sgml_declaration;
+pub mod stream;
 pub mod template_strings;
+pub mod typed;
+pub mod xml_dtd;
+pub mod xsd;
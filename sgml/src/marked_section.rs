@@ -1,17 +1,86 @@
+use crate::borrowed;
 use crate::dtd::{
-    take_whitespace, take_whitespace_opt, DECLARATION_SUBSET_OPEN, MARKED_SECTION_CLOSE,
-    MARKUP_DECLARATION_OPEN, MDC,
+    parse_dtd_element, take_whitespace, take_whitespace_opt, DTDElement, DECLARATION_SUBSET_OPEN,
+    MARKED_SECTION_CLOSE, MARKUP_DECLARATION_OPEN, MDC,
 };
+use crate::entity::Entity;
+use crate::template_strings::parse_string;
 use nom::bytes::complete::{tag, take_until};
+use nom::multi::many0;
 use nom::IResult;
 
+/// See ISO(B.10.1)
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum MarkedSectionStatus {
+    // Ordered by priority, when several keywords are given the highest one applies
+    Temp,
+    Include,
+    RCData,
+    CData,
+    Ignore,
+}
+
+impl MarkedSectionStatus {
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword.to_ascii_uppercase().as_str() {
+            "TEMP" => Some(MarkedSectionStatus::Temp),
+            "INCLUDE" => Some(MarkedSectionStatus::Include),
+            "RCDATA" => Some(MarkedSectionStatus::RCData),
+            "CDATA" => Some(MarkedSectionStatus::CData),
+            "IGNORE" => Some(MarkedSectionStatus::Ignore),
+            _ => None,
+        }
+    }
+
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            MarkedSectionStatus::Temp => "TEMP",
+            MarkedSectionStatus::Include => "INCLUDE",
+            MarkedSectionStatus::RCData => "RCDATA",
+            MarkedSectionStatus::CData => "CDATA",
+            MarkedSectionStatus::Ignore => "IGNORE",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MarkedSection {
     pub status: String,
     pub content: String,
 }
 
+impl MarkedSection {
+    /// Resolve the status keywords of this section, expanding any parameter references
+    /// Returns None if a reference can't be resolved or a keyword is not recognised
+    pub fn get_status(&self, entities: &[Entity]) -> Option<MarkedSectionStatus> {
+        let (_, status) = parse_string(&self.status, '\0', false).ok()?;
+        let status = status.try_expand(entities).ok()?;
+
+        status
+            .split_whitespace()
+            .map(MarkedSectionStatus::from_keyword)
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .max()
+            // An empty status list means INCLUDE (B.10.1.1)
+            .or(Some(MarkedSectionStatus::Include))
+    }
+
+    /// Parse the content of this section as declarations
+    pub fn get_declarations(&self) -> Vec<DTDElement> {
+        many0(parse_dtd_element)(self.content.as_str())
+            .map(|(_, elements)| elements)
+            .unwrap_or_default()
+    }
+}
+
 pub fn parse_marked_section(i: &str) -> IResult<&str, MarkedSection> {
+    let (i, ms) = parse_borrowed_marked_section(i)?;
+    Ok((i, ms.into_owned()))
+}
+
+pub fn parse_borrowed_marked_section(i: &str) -> IResult<&str, borrowed::MarkedSection<'_>> {
     let (i, _) = tag(MARKUP_DECLARATION_OPEN)(i)?;
     let (i, _) = tag(DECLARATION_SUBSET_OPEN)(i)?;
     let (i, _) = take_whitespace_opt(i)?;
@@ -22,18 +91,13 @@ pub fn parse_marked_section(i: &str) -> IResult<&str, MarkedSection> {
     let (i, _) = tag(MARKED_SECTION_CLOSE)(i)?;
     let (i, _) = tag(MDC)(i)?;
 
-    Ok((
-        i,
-        MarkedSection {
-            status: status.to_string(),
-            content: content.to_string(),
-        },
-    ))
+    Ok((i, borrowed::MarkedSection { status, content }))
 }
 
 #[cfg(test)]
 pub mod test {
-    use crate::marked_section::parse_marked_section;
+    use crate::entity::parse_entity;
+    use crate::marked_section::{parse_marked_section, MarkedSectionStatus};
 
     #[test]
     pub fn test_marked_section() {
@@ -48,4 +112,15 @@ pub mod test {
 
         assert_eq!(i, "");
     }
+
+    #[test]
+    pub fn test_marked_section_status() {
+        let (_, ms) =
+            parse_marked_section("<![ %HTML.Recommended [ <!ENTITY % x \"y\"> ]]>").unwrap();
+        let (_, e) = parse_entity("<!ENTITY % HTML.Recommended \"IGNORE\">").unwrap();
+
+        assert_eq!(ms.get_status(&[]), None);
+        assert_eq!(ms.get_status(&[e]), Some(MarkedSectionStatus::Ignore));
+        assert_eq!(ms.get_declarations().len(), 3);
+    }
 }
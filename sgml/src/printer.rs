@@ -0,0 +1,899 @@
+use crate::att_list::ATTList;
+use crate::dtd::{parse_dtd_element, DTDElement, DocumentTypeDefinition};
+use crate::element::{Element, RESERVED_NAME_INDICATOR};
+use crate::entity::{Entity, ParameterReference};
+use crate::marked_section::{MarkedSection, MarkedSectionStatus};
+use crate::template_strings::{parse_string, ChainElement, TemplateString};
+use nom::multi::many0;
+
+/// Keywords that can appear as the declared content of an element, See ISO(B.4.2.6)
+const DECLARED_CONTENT_KEYWORDS: &[&str] = &["CDATA", "RCDATA", "EMPTY", "ANY"];
+
+/// Keywords that can appear as the declared value of an attribute, See ISO(B.5.1.1)
+const DECLARED_VALUE_KEYWORDS: &[&str] = &[
+    "CDATA", "ENTITY", "ENTITIES", "ID", "IDREF", "IDREFS", "NAME", "NAMES", "NMTOKEN", "NMTOKENS",
+    "NOTATION", "NUMBER", "NUMBERS", "NUTOKEN", "NUTOKENS",
+];
+
+const ATTRIBUTE_INDENT: &str = "  ";
+
+/// Continuation indent for content models that start too far right to be aligned with their group
+const CONTINUATION_INDENT: usize = 4;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum KeywordCase {
+    Upper,
+    Lower,
+}
+
+impl KeywordCase {
+    pub fn apply(&self, keyword: &str) -> String {
+        match self {
+            KeywordCase::Upper => keyword.to_ascii_uppercase(),
+            KeywordCase::Lower => keyword.to_ascii_lowercase(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ReferenceMode {
+    /// Parameter entity references are written as `%name;`
+    Keep,
+    /// Parameter entity references are replaced by their replacement text where it is known
+    Expand,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrintOptions {
+    pub keyword_case: KeywordCase,
+    /// Content models are wrapped to fit within this many columns where possible
+    pub width: usize,
+    /// Element names and attribute definition parameters are padded so their columns line up,
+    /// anything wider than this is left out of the alignment
+    pub max_column_width: usize,
+    pub parameter_references: ReferenceMode,
+    /// Replace INCLUDE marked sections with their content and drop IGNORE sections
+    pub flatten_marked_sections: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            keyword_case: KeywordCase::Upper,
+            width: 80,
+            max_column_width: 24,
+            parameter_references: ReferenceMode::Keep,
+            flatten_marked_sections: false,
+        }
+    }
+}
+
+/// Write a DTD back out as canonical declaration text
+pub fn print_dtd(dtd: &DocumentTypeDefinition, options: &PrintOptions) -> String {
+    let mut printer = Printer {
+        options,
+        entities: Vec::new(),
+        items: Vec::new(),
+    };
+    printer.walk(dtd.get_declarations());
+    printer.finish()
+}
+
+/// A lexical token of a declaration parameter, see ISO(B.4.2) and ISO(B.5.1)
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Reference(String),
+    Literal(Vec<ChainElement>),
+    Comment(String),
+    Delimiter(char),
+    Inclusion,
+    Exclusion,
+}
+
+enum Piece<'a> {
+    Char(char),
+    Reference(&'a str),
+}
+
+fn is_delimiter(c: char) -> bool {
+    "()|,&?*+\"'".contains(c)
+}
+
+fn tokenize(chain: &[ChainElement]) -> Vec<Token> {
+    let pieces = chain
+        .iter()
+        .flat_map(|c| match c {
+            ChainElement::String(s) => s.chars().map(Piece::Char).collect::<Vec<_>>(),
+            ChainElement::Reference(r) => vec![Piece::Reference(r)],
+        })
+        .collect::<Vec<_>>();
+    let char_at = |n: usize| match pieces.get(n) {
+        Some(Piece::Char(c)) => Some(*c),
+        _ => None,
+    };
+
+    let mut tokens = Vec::new();
+    let mut spaced = true;
+    let mut n = 0;
+    while n < pieces.len() {
+        let c = match pieces[n] {
+            Piece::Reference(r) => {
+                tokens.push(Token::Reference(r.to_string()));
+                spaced = false;
+                n += 1;
+                continue;
+            }
+            Piece::Char(c) => c,
+        };
+
+        if c.is_whitespace() {
+            spaced = true;
+            n += 1;
+            continue;
+        }
+
+        if c == '"' || c == '\'' {
+            let mut literal = Vec::new();
+            let mut text = String::new();
+            n += 1;
+            while n < pieces.len() && char_at(n) != Some(c) {
+                match pieces[n] {
+                    Piece::Char(c) => text.push(c),
+                    Piece::Reference(r) => {
+                        if !text.is_empty() {
+                            literal.push(ChainElement::String(std::mem::take(&mut text)));
+                        }
+                        literal.push(ChainElement::Reference(r.to_string()));
+                    }
+                }
+                n += 1;
+            }
+            if !text.is_empty() {
+                literal.push(ChainElement::String(text));
+            }
+            tokens.push(Token::Literal(literal));
+        } else if c == '-' && char_at(n + 1) == Some('-') {
+            let mut text = String::new();
+            n += 2;
+            while n < pieces.len() && !(char_at(n) == Some('-') && char_at(n + 1) == Some('-')) {
+                match pieces[n] {
+                    Piece::Char(c) => text.push(c),
+                    Piece::Reference(r) => text.push_str(&format!("%{};", r)),
+                }
+                n += 1;
+            }
+            n += 1;
+            tokens.push(Token::Comment(text));
+        } else if (c == '-' || (c == '+' && (spaced || tokens.is_empty())))
+            && matches!(
+                pieces[n + 1..]
+                    .iter()
+                    .find(|p| !matches!(p, Piece::Char(c) if c.is_whitespace())),
+                Some(Piece::Char('('))
+            )
+        {
+            // Exceptions, See ISO(B.4.2.7)
+            tokens.push(if c == '-' {
+                Token::Exclusion
+            } else {
+                Token::Inclusion
+            });
+        } else if is_delimiter(c) {
+            tokens.push(Token::Delimiter(c));
+        } else {
+            let mut name = String::new();
+            while let Some(c) = char_at(n) {
+                if c.is_whitespace() || is_delimiter(c) {
+                    break;
+                }
+                name.push(c);
+                n += 1;
+            }
+            tokens.push(Token::Name(name));
+            spaced = false;
+            continue;
+        }
+
+        spaced = false;
+        n += 1;
+    }
+
+    tokens
+}
+
+fn render_reference(name: &str) -> String {
+    format!("%{};", name)
+}
+
+fn render_literal(chain: &[ChainElement]) -> String {
+    let text = chain
+        .iter()
+        .map(|c| match c {
+            ChainElement::String(s) => s.clone(),
+            ChainElement::Reference(r) => render_reference(r),
+        })
+        .collect::<String>();
+    let quote = if text.contains('"') { '\'' } else { '"' };
+    format!("{}{}{}", quote, text, quote)
+}
+
+/// Render a content model or name group as unbreakable segments, lines may be broken between segments
+fn model_segments(tokens: &[Token], declared_content: bool, case: KeywordCase) -> Vec<String> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut previous: Option<&Token> = None;
+
+    for (n, token) in tokens.iter().enumerate() {
+        let text = match token {
+            Token::Name(name) if name.starts_with(RESERVED_NAME_INDICATOR) => case.apply(name),
+            Token::Name(name)
+                if declared_content
+                    && n == 0
+                    && DECLARED_CONTENT_KEYWORDS.contains(&name.to_ascii_uppercase().as_str()) =>
+            {
+                case.apply(name)
+            }
+            Token::Name(name) => name.clone(),
+            Token::Reference(r) => render_reference(r),
+            Token::Literal(l) => render_literal(l),
+            Token::Comment(c) => format!("--{}--", c),
+            Token::Delimiter(d) => d.to_string(),
+            Token::Inclusion => "+".to_string(),
+            Token::Exclusion => "-".to_string(),
+        };
+
+        if let Some(previous) = previous {
+            let attached = matches!(
+                previous,
+                Token::Delimiter('(') | Token::Inclusion | Token::Exclusion
+            ) || matches!(token, Token::Delimiter(')' | ',' | '?' | '*' | '+'));
+            let breakable = matches!(previous, Token::Delimiter(',' | '|' | '&'))
+                || matches!(token, Token::Inclusion | Token::Exclusion);
+
+            if !attached {
+                if breakable {
+                    segments.push(std::mem::take(&mut current));
+                } else {
+                    current.push(' ');
+                }
+            }
+        }
+
+        current.push_str(&text);
+        previous = Some(token);
+    }
+
+    if !current.is_empty() {
+        segments.push(current);
+    }
+    segments
+}
+
+/// Join segments with spaces, breaking lines so they fit within `width` where possible
+fn wrap(segments: &[String], start: usize, indent: usize, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = start;
+
+    for (n, segment) in segments.iter().enumerate() {
+        let len = segment.chars().count();
+        if n > 0 {
+            if column + 1 + len > width && column > indent {
+                out.push('\n');
+                out.push_str(&" ".repeat(indent));
+                column = indent;
+            } else {
+                out.push(' ');
+                column += 1;
+            }
+        }
+        out.push_str(segment);
+        column += len;
+    }
+
+    out
+}
+
+/// A single parameter of an attribute definition, groups count as one unit
+#[derive(Debug, Clone)]
+enum Unit {
+    Name(String),
+    Group(Vec<Token>),
+    Literal(Vec<ChainElement>),
+    Reference(String),
+    Comment(String),
+}
+
+fn units(tokens: &[Token]) -> Vec<Unit> {
+    let mut out = Vec::new();
+    let mut n = 0;
+    while n < tokens.len() {
+        match &tokens[n] {
+            Token::Delimiter('(') => {
+                let mut depth = 0;
+                let start = n;
+                while n < tokens.len() {
+                    match tokens[n] {
+                        Token::Delimiter('(') => depth += 1,
+                        Token::Delimiter(')') => depth -= 1,
+                        _ => {}
+                    }
+                    n += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                out.push(Unit::Group(tokens[start..n].to_vec()));
+                continue;
+            }
+            Token::Name(name) => out.push(Unit::Name(name.clone())),
+            Token::Literal(l) => out.push(Unit::Literal(l.clone())),
+            Token::Reference(r) => out.push(Unit::Reference(r.clone())),
+            Token::Comment(c) => out.push(Unit::Comment(c.clone())),
+            // Stray delimiters are kept as names so nothing is lost
+            Token::Delimiter(d) => out.push(Unit::Name(d.to_string())),
+            Token::Inclusion => out.push(Unit::Name("+".to_string())),
+            Token::Exclusion => out.push(Unit::Name("-".to_string())),
+        }
+        n += 1;
+    }
+    out
+}
+
+/// Tracks which parameter of an attribute definition comes next, See ISO(B.5.1)
+#[derive(Debug, Clone, Copy, Default)]
+struct DefinitionState {
+    /// 0 = attribute name, 1 = declared value, 2 = default value
+    position: usize,
+    /// The previous keyword (NOTATION or #FIXED) needs another parameter at this position
+    pending: bool,
+}
+
+impl DefinitionState {
+    fn at_start(&self) -> bool {
+        self.position == 0 && !self.pending
+    }
+
+    fn advance(&mut self, unit: &Unit) {
+        let keyword = match unit {
+            Unit::Name(name) => name.to_ascii_uppercase(),
+            _ => String::new(),
+        };
+        match self.position {
+            0 => self.position = 1,
+            1 if keyword == "NOTATION" && !self.pending => self.pending = true,
+            1 => {
+                self.pending = false;
+                self.position = 2;
+            }
+            _ if keyword == "#FIXED" && !self.pending => self.pending = true,
+            _ => {
+                self.pending = false;
+                self.position = 0;
+            }
+        }
+    }
+}
+
+struct AttributeEntry {
+    position: usize,
+    /// The entry covers exactly one parameter, so it takes part in column alignment
+    single: bool,
+    text: String,
+}
+
+enum Item {
+    Blank,
+    Text(String),
+    Element {
+        name: String,
+        minimization: String,
+        content: Vec<Token>,
+    },
+    Open(String),
+    Close,
+}
+
+struct Printer<'a> {
+    options: &'a PrintOptions,
+    /// Entities declared so far, in declaration order
+    entities: Vec<Entity>,
+    items: Vec<Item>,
+}
+
+impl<'a> Printer<'a> {
+    fn keyword(&self, keyword: &str) -> String {
+        self.options.keyword_case.apply(keyword)
+    }
+
+    fn expanding(&self) -> bool {
+        self.options.parameter_references == ReferenceMode::Expand
+    }
+
+    /// Apply the reference mode to a template string, references that can't be resolved are kept
+    fn resolve(&self, s: &TemplateString) -> Vec<ChainElement> {
+        if self.expanding() {
+            if let Ok(expanded) = s.try_expand(&self.entities) {
+                return vec![ChainElement::String(expanded)];
+            }
+        }
+        s.chain.clone()
+    }
+
+    fn walk(&mut self, declarations: &[DTDElement]) {
+        for declaration in declarations {
+            match declaration {
+                DTDElement::WhiteSpace(w) => {
+                    if w.matches('\n').count() > 1 {
+                        self.items.push(Item::Blank);
+                    }
+                }
+                DTDElement::Comment(c) => self.items.push(Item::Text(format!("<!--{}-->", c))),
+                DTDElement::Entity(e) => {
+                    self.entity(e);
+                    self.entities.push(e.clone());
+                }
+                DTDElement::MarkedSection(ms) => self.marked_section(ms),
+                DTDElement::ParameterReference(r) => self.parameter_reference(r),
+                DTDElement::Element(e) => self.element(e),
+                DTDElement::ATTList(a) => self.att_list(a),
+            }
+        }
+    }
+
+    fn entity(&mut self, e: &Entity) {
+        // Once references are expanded internal parameter entities are no longer needed
+        if self.expanding() && e.parameter && !e.external && !e.public {
+            return;
+        }
+
+        let mut out = format!("<!{} ", self.keyword("ENTITY"));
+        if e.parameter {
+            out.push_str("% ");
+        }
+        out.push_str(&e.name);
+        if e.external {
+            out.push_str(&format!(" {}", self.keyword("SYSTEM")));
+        }
+        if e.public {
+            out.push_str(&format!(" {}", self.keyword("PUBLIC")));
+        }
+        if let Some(keyword) = e.entity_type.keyword() {
+            out.push_str(&format!(" {}", self.keyword(keyword)));
+        }
+        out.push_str(&format!(" {}", render_literal(&self.resolve(&e.content))));
+        if let Some(system_id) = &e.system_id {
+            out.push_str(&format!(
+                " {}",
+                render_literal(&[ChainElement::String(system_id.clone())])
+            ));
+        }
+        if let Some(comment) = &e.comment {
+            out.push_str(&format!(" --{}--", comment));
+        }
+        out.push('>');
+
+        self.items.push(Item::Text(out));
+    }
+
+    fn marked_section(&mut self, ms: &MarkedSection) {
+        let status = ms.get_status(&self.entities);
+        let flatten = self.options.flatten_marked_sections;
+
+        match status {
+            Some(MarkedSectionStatus::Include) | Some(MarkedSectionStatus::Temp) if flatten => {
+                self.walk(&ms.get_declarations())
+            }
+            Some(MarkedSectionStatus::Ignore) if flatten => {}
+            Some(MarkedSectionStatus::Include) | Some(MarkedSectionStatus::Temp) => {
+                self.items
+                    .push(Item::Open(format!("<![ {} [", self.render_status(ms))));
+                self.walk(&ms.get_declarations());
+                self.items.push(Item::Close);
+            }
+            // Content that isn't parsed as declarations is kept as written
+            _ => self.items.push(Item::Text(format!(
+                "<![ {} [{}]]>",
+                self.render_status(ms),
+                ms.content
+            ))),
+        }
+    }
+
+    fn render_status(&self, ms: &MarkedSection) -> String {
+        let status = parse_string(&ms.status, '\0', false)
+            .map(|(_, s)| s)
+            .unwrap_or_else(|_| ms.status.as_str().into());
+
+        tokenize(&self.resolve(&status))
+            .iter()
+            .map(|t| match t {
+                Token::Name(name) => self.keyword(name),
+                Token::Reference(r) => render_reference(r),
+                _ => String::new(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn parameter_reference(&mut self, r: &ParameterReference) {
+        if self.expanding() {
+            let replacement = self
+                .entities
+                .iter()
+                .find(|e| e.parameter && e.name == r.name && !e.external && !e.public)
+                .and_then(|e| e.content.try_expand(&self.entities).ok());
+
+            if let Some(replacement) = replacement {
+                if let Ok((_, declarations)) = many0(parse_dtd_element)(replacement.as_str()) {
+                    self.walk(&declarations);
+                    return;
+                }
+            }
+        }
+
+        self.items.push(Item::Text(render_reference(&r.name)));
+    }
+
+    /// Render the name of an element or attribute list declaration, which may be a group
+    fn render_name(&self, name: &TemplateString) -> String {
+        let tokens = tokenize(&self.resolve(name));
+        let is_group = name
+            .try_expand(&self.entities)
+            .map(|n| n.contains('|'))
+            .unwrap_or_else(|_| tokens.contains(&Token::Delimiter('|')));
+
+        let rendered = model_segments(&tokens, false, self.options.keyword_case).join(" ");
+        if is_group {
+            format!("({})", rendered)
+        } else {
+            rendered
+        }
+    }
+
+    fn element(&mut self, e: &Element) {
+        let omission = |optional: bool| {
+            if optional {
+                self.keyword("O")
+            } else {
+                "-".to_string()
+            }
+        };
+
+        let item = Item::Element {
+            name: self.render_name(&e.name),
+            minimization: if e.omitted_tag_minimization {
+                format!(
+                    "{} {} ",
+                    omission(e.start_optional),
+                    omission(e.end_optional)
+                )
+            } else {
+                String::new()
+            },
+            content: tokenize(&self.resolve(&e.content_model)),
+        };
+        self.items.push(item);
+    }
+
+    /// Expand a reference into the parameters it stands for, if it can be resolved
+    fn reference_units(&self, name: &str) -> Option<Vec<Unit>> {
+        let entity = self
+            .entities
+            .iter()
+            .find(|e| e.parameter && e.name == name && !e.external && !e.public)?;
+        let expanded = entity.content.try_expand(&self.entities).ok()?;
+        Some(units(&tokenize(&[ChainElement::String(expanded)])))
+    }
+
+    fn attribute_lines(&self, tokens: &[Token]) -> Vec<String> {
+        let mut lines: Vec<Vec<AttributeEntry>> = Vec::new();
+        let mut state = DefinitionState::default();
+
+        for unit in units(tokens) {
+            let position = state.position;
+            let (single, text) = match &unit {
+                Unit::Comment(c) => {
+                    let entry = AttributeEntry {
+                        position: usize::MAX,
+                        single: false,
+                        text: format!("--{}--", c),
+                    };
+                    match lines.last_mut() {
+                        Some(line) => line.push(entry),
+                        None => lines.push(vec![entry]),
+                    }
+                    continue;
+                }
+                Unit::Reference(r) => match self.reference_units(r) {
+                    Some(expansion) => (expansion.len() == 1, render_reference(r)),
+                    // Can't tell which parameters this covers, so give it a line of its own
+                    None => (false, render_reference(r)),
+                },
+                Unit::Name(name) => {
+                    let upper = name.to_ascii_uppercase();
+                    let keyword = (position == 1
+                        && DECLARED_VALUE_KEYWORDS.contains(&upper.as_str()))
+                        || (position == 2 && name.starts_with(RESERVED_NAME_INDICATOR));
+                    let text = if keyword {
+                        self.keyword(name)
+                    } else {
+                        name.clone()
+                    };
+                    (true, text)
+                }
+                Unit::Group(g) => (
+                    true,
+                    model_segments(g, false, self.options.keyword_case).join(" "),
+                ),
+                Unit::Literal(l) => (true, render_literal(l)),
+            };
+
+            if state.at_start() || lines.is_empty() {
+                lines.push(Vec::new());
+            }
+            lines.last_mut().unwrap().push(AttributeEntry {
+                position,
+                single,
+                text,
+            });
+
+            match &unit {
+                Unit::Reference(r) => match self.reference_units(r) {
+                    Some(expansion) => expansion.iter().for_each(|u| state.advance(u)),
+                    None => state = DefinitionState::default(),
+                },
+                unit => state.advance(unit),
+            }
+        }
+
+        let column_width = |position: usize| {
+            lines
+                .iter()
+                .filter(|line| line.len() > 1)
+                .flat_map(|line| line.iter())
+                .filter(|e| e.single && e.position == position)
+                .map(|e| e.text.chars().count())
+                .filter(|width| *width <= self.options.max_column_width)
+                .max()
+                .unwrap_or(0)
+        };
+        let widths = [column_width(0), column_width(1)];
+
+        lines
+            .iter()
+            .map(|line| {
+                let mut out = String::new();
+                for (n, entry) in line.iter().enumerate() {
+                    if n > 0 {
+                        let target = match entry.position {
+                            1 => widths[0] + 1,
+                            2 => widths[0] + widths[1] + 2,
+                            _ => 0,
+                        };
+                        let column = out.chars().count();
+                        out.push_str(&" ".repeat(target.saturating_sub(column).max(1)));
+                    }
+                    out.push_str(&entry.text);
+                }
+                out
+            })
+            .collect()
+    }
+
+    fn att_list(&mut self, a: &ATTList) {
+        let head = format!(
+            "<!{} {}",
+            self.keyword("ATTLIST"),
+            self.render_name(&a.name)
+        );
+        let lines = self.attribute_lines(&tokenize(&self.resolve(&a.value)));
+
+        let out = match lines.as_slice() {
+            [] => format!("{}>", head),
+            [line] if head.len() + line.len() + 2 <= self.options.width && !line.contains('\n') => {
+                format!("{} {}>", head, line)
+            }
+            lines => format!(
+                "{}\n{}\n{}>",
+                head,
+                lines
+                    .iter()
+                    .map(|l| format!("{}{}", ATTRIBUTE_INDENT, l))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                ATTRIBUTE_INDENT
+            ),
+        };
+
+        self.items.push(Item::Text(out));
+    }
+
+    fn finish(self) -> String {
+        let name_width = self
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Element { name, .. } => name.chars().count(),
+                _ => 0,
+            })
+            .filter(|width| *width <= self.options.max_column_width)
+            .max()
+            .unwrap_or(0);
+
+        let mut lines = Vec::new();
+        let mut pending_blank = false;
+        let mut after_open = true;
+
+        for item in &self.items {
+            let text = match item {
+                Item::Blank => {
+                    pending_blank = !after_open;
+                    continue;
+                }
+                Item::Close => {
+                    pending_blank = false;
+                    "]]>".to_string()
+                }
+                Item::Text(text) | Item::Open(text) => text.clone(),
+                Item::Element {
+                    name,
+                    minimization,
+                    content,
+                } => {
+                    let head = format!(
+                        "<!{} {:<width$} {}",
+                        self.keyword("ELEMENT"),
+                        name,
+                        minimization,
+                        width = name_width
+                    );
+                    let start = head.chars().count();
+                    let segments = model_segments(content, true, self.options.keyword_case);
+                    let indent = match segments.first() {
+                        _ if start * 2 > self.options.width => CONTINUATION_INDENT,
+                        Some(s) if s.starts_with('(') => start + 1,
+                        _ => start,
+                    };
+                    format!(
+                        "{}{}>",
+                        head,
+                        wrap(
+                            &segments,
+                            start,
+                            indent,
+                            self.options.width.saturating_sub(1)
+                        )
+                    )
+                }
+            };
+
+            if pending_blank {
+                lines.push(String::new());
+            }
+            pending_blank = false;
+            after_open = matches!(item, Item::Open(_));
+            lines.push(text);
+        }
+
+        let mut out = lines.join("\n");
+        out.push('\n');
+        out
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::dtd::read_dtd;
+    use crate::printer::{print_dtd, KeywordCase, PrintOptions, ReferenceMode};
+    use std::fs::File;
+    use std::io::Read;
+
+    fn print(s: &str, options: &PrintOptions) -> String {
+        let (i, dtd) = read_dtd(s).unwrap();
+        assert_eq!(i, "");
+        print_dtd(&dtd, options)
+    }
+
+    #[test]
+    pub fn test_print_element() {
+        let out = print(
+            "<!ELEMENT memo - o (sender,receivers ,  contents)>\n<!element P - O (#pcdata)*>",
+            &PrintOptions::default(),
+        );
+        assert_eq!(
+            out,
+            "<!ELEMENT memo - O (sender, receivers, contents)>\n<!ELEMENT P    - O (#PCDATA)*>\n"
+        );
+    }
+
+    #[test]
+    pub fn test_print_wrapping() {
+        let options = PrintOptions {
+            width: 40,
+            ..PrintOptions::default()
+        };
+        let out = print("<!ELEMENT BODY O O (H1|H2|H3|P|UL|OL) -(A)>", &options);
+        assert_eq!(
+            out,
+            "<!ELEMENT BODY O O (H1 | H2 | H3 | P |\n                    UL | OL) -(A)>\n"
+        );
+    }
+
+    #[test]
+    pub fn test_print_att_list() {
+        let out = print(
+            "<!ENTITY % SDAPREF  \"SDAPREF  CDATA  #FIXED\">
+<!ATTLIST IMG
+        SRC CDATA  #required
+        ALT cdata #IMPLIED
+        ALIGN (top|middle|bottom) #IMPLIED
+        %SDAPREF; \"<Fig>\"
+        >",
+            &PrintOptions::default(),
+        );
+        assert_eq!(
+            out,
+            "<!ENTITY % SDAPREF \"SDAPREF  CDATA  #FIXED\">
+<!ATTLIST IMG
+  SRC   CDATA                   #REQUIRED
+  ALT   CDATA                   #IMPLIED
+  ALIGN (top | middle | bottom) #IMPLIED
+  %SDAPREF;                     \"<Fig>\"
+  >
+"
+        );
+    }
+
+    #[test]
+    pub fn test_print_expand_and_flatten() {
+        let dtd = "<!ENTITY % HTML.Recommended \"IGNORE\">
+<![ %HTML.Recommended [
+<!ENTITY % text \"#PCDATA\">
+]]>
+<!ENTITY % text \"#PCDATA | A\">
+
+<!ELEMENT P - O (%text)*>";
+
+        let kept = print(dtd, &PrintOptions::default());
+        assert_eq!(
+            kept,
+            "<!ENTITY % HTML.Recommended \"IGNORE\">
+<![ %HTML.Recommended; [
+<!ENTITY % text \"#PCDATA\">
+]]>
+<!ENTITY % text \"#PCDATA | A\">
+
+<!ELEMENT P - O (%text;)*>
+"
+        );
+
+        let options = PrintOptions {
+            keyword_case: KeywordCase::Lower,
+            parameter_references: ReferenceMode::Expand,
+            flatten_marked_sections: true,
+            ..PrintOptions::default()
+        };
+        assert_eq!(print(dtd, &options), "<!element P - o (#pcdata | A)*>\n");
+    }
+
+    #[test]
+    pub fn test_print_html_dtd_round_trip() {
+        let mut f = File::open("./dtd/html.dtd").unwrap();
+        let mut s = String::new();
+        f.read_to_string(&mut s).unwrap();
+
+        for options in &[
+            PrintOptions::default(),
+            PrintOptions {
+                parameter_references: ReferenceMode::Expand,
+                flatten_marked_sections: true,
+                ..PrintOptions::default()
+            },
+        ] {
+            let out = print(&s, options);
+            // Formatting is idempotent, and the output is still a DTD we can read
+            assert_eq!(print(&out, options), out);
+        }
+    }
+}
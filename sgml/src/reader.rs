@@ -0,0 +1,166 @@
+use crate::dtd::DocumentTypeDefinition;
+pub use crate::instance::Event;
+use crate::instance::{Doctype, DocumentError, Grammar, Parser};
+use crate::sgml_declaration::SgmlDeclaration;
+
+/// Reads the events of a document one at a time: `for event in SgmlReader::new(input, &dtd)`.
+/// Tags are inferred by the same parser `parse_document` builds trees with, so both give the
+/// same structure
+pub struct SgmlReader<'a> {
+    parser: Parser<'a>,
+    depth: usize,
+}
+
+impl<'a> SgmlReader<'a> {
+    /// A reader for `input`, with declarations from its internal subset added to `dtd`
+    pub fn new(input: &'a str, dtd: &DocumentTypeDefinition) -> Self {
+        Self::with_declaration(input, dtd, None)
+    }
+
+    /// A reader following an SGML declaration, which takes the place of any the document has
+    pub fn with_declaration(
+        input: &'a str,
+        dtd: &DocumentTypeDefinition,
+        declaration: Option<&SgmlDeclaration>,
+    ) -> Self {
+        SgmlReader {
+            parser: Parser::with_declaration(input, dtd, declaration),
+            depth: 0,
+        }
+    }
+
+    pub fn get_doctype(&self) -> Option<&Doctype> {
+        self.parser.get_doctype()
+    }
+
+    pub fn get_grammar(&self) -> &Grammar {
+        self.parser.get_grammar()
+    }
+
+    /// The errors found so far
+    pub fn get_errors(&self) -> &[DocumentError] {
+        self.parser.get_errors()
+    }
+
+    /// How many elements are open
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Skip the rest of the current element, up to and including its end
+    pub fn read_to_end(&mut self) {
+        let depth = self.depth;
+        while self.depth >= depth && depth > 0 {
+            if self.next().is_none() {
+                return;
+            }
+        }
+    }
+
+    /// The text in the rest of the current element and the elements in it, reading up to and
+    /// including its end
+    pub fn read_text(&mut self) -> String {
+        let mut text = String::new();
+        let depth = self.depth;
+        while self.depth >= depth && depth > 0 {
+            match self.next() {
+                Some(Event::Text(t)) => text.push_str(&t),
+                Some(Event::EntityRef { text: t, .. }) => text.push_str(&t),
+                Some(_) => {}
+                None => break,
+            }
+        }
+        text
+    }
+}
+
+impl<'a> Iterator for SgmlReader<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.parser.next()?;
+        match event {
+            Event::Start { .. } => self.depth += 1,
+            Event::End { .. } => self.depth -= 1,
+            _ => {}
+        }
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::document::{parse_document, Node};
+    use crate::dtd::read_dtd;
+    use crate::reader::{Event, SgmlReader};
+    use std::borrow::Cow;
+
+    const DTD: &str = r#"<!ENTITY co CDATA "&#169;">
+<!ELEMENT MEMO - - (TO, BODY)>
+<!ELEMENT TO - O (#PCDATA)>
+<!ELEMENT BODY O O (P+)>
+<!ELEMENT P - O (#PCDATA|EM)*>
+<!ELEMENT EM - - (#PCDATA)>
+"#;
+
+    const DOCUMENT: &str = "<!DOCTYPE memo><memo><to>All<p>One &co; <em>two</em><p>Three</memo>";
+
+    #[test]
+    pub fn test_events() {
+        let (_, dtd) = read_dtd(DTD).unwrap();
+        let mut reader = SgmlReader::new(DOCUMENT, &dtd);
+        assert!(
+            matches!(reader.next(), Some(Event::Start { name, omitted: false, .. }) if name == "MEMO")
+        );
+        assert!(matches!(reader.next(), Some(Event::Start { name, .. }) if name == "TO"));
+        match reader.next() {
+            Some(Event::Text(Cow::Borrowed(text))) => assert_eq!(text, "All"),
+            event => panic!("expected borrowed text, found {:?}", event),
+        }
+        assert_eq!(
+            reader.next(),
+            Some(Event::End {
+                name: "TO".to_string(),
+                omitted: true
+            })
+        );
+        assert!(
+            matches!(reader.next(), Some(Event::Start { name, omitted: true, .. }) if name == "BODY")
+        );
+        assert!(matches!(reader.next(), Some(Event::Start { name, .. }) if name == "P"));
+        assert_eq!(reader.get_depth(), 3);
+        assert_eq!(reader.read_text(), "One © two");
+        assert_eq!(reader.get_depth(), 2);
+        reader.read_to_end();
+        assert_eq!(reader.get_depth(), 1);
+        assert!(matches!(reader.next(), Some(Event::End { name, .. }) if name == "MEMO"));
+        assert_eq!(reader.next(), None);
+        assert_eq!(reader.get_errors(), &[]);
+    }
+
+    #[test]
+    pub fn test_same_structure_as_tree() {
+        let (_, dtd) = read_dtd(DTD).unwrap();
+        let mut from_reader = Vec::new();
+        for event in SgmlReader::new(DOCUMENT, &dtd) {
+            match event {
+                Event::Start { name, .. } => from_reader.push(format!("({}", name)),
+                Event::End { name, .. } => from_reader.push(format!("){}", name)),
+                _ => {}
+            }
+        }
+
+        fn walk(nodes: &[Node], out: &mut Vec<String>) {
+            for node in nodes {
+                if let Node::Element(e) = node {
+                    out.push(format!("({}", e.name));
+                    walk(&e.children, out);
+                    out.push(format!("){}", e.name));
+                }
+            }
+        }
+        let mut from_tree = Vec::new();
+        walk(&parse_document(DOCUMENT, &dtd).children, &mut from_tree);
+        assert_eq!(from_reader, from_tree);
+    }
+}
@@ -0,0 +1,652 @@
+use crate::att_list::{AttributeDefinition, DeclaredValue, DefaultValue};
+use crate::content_model::{Connector, Occurrence};
+use crate::dtd::DocumentTypeDefinition;
+use crate::schema::{
+    AttributeItem, Particle, Schema, SchemaContent, SchemaElement, SchemaError, SchemaOptions,
+};
+use std::collections::HashMap;
+
+const STRUCTURE_NAMESPACE: &str = "http://relaxng.org/ns/structure/1.0";
+/// Namespace of `a:defaultValue`, from the RELAX NG DTD Compatibility specification
+const ANNOTATIONS_NAMESPACE: &str = "http://relaxng.org/ns/compatibility/annotations/1.0";
+const DATATYPE_LIBRARY: &str = "http://www.w3.org/2001/XMLSchema-datatypes";
+
+/// Lines longer than this put each member of a named pattern on its own line
+const COMPACT_WIDTH: usize = 100;
+
+/// Identifiers that have to be escaped with a backslash in the compact syntax
+const COMPACT_KEYWORDS: [&str; 19] = [
+    "attribute",
+    "default",
+    "datatypes",
+    "div",
+    "element",
+    "empty",
+    "external",
+    "grammar",
+    "include",
+    "inherit",
+    "list",
+    "mixed",
+    "namespace",
+    "notAllowed",
+    "parent",
+    "start",
+    "string",
+    "text",
+    "token",
+];
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Pattern {
+    Empty,
+    Text,
+    NotAllowed,
+    Ref(String),
+    Group(Vec<Pattern>),
+    Choice(Vec<Pattern>),
+    Interleave(Vec<Pattern>),
+    Optional(Box<Pattern>),
+    ZeroOrMore(Box<Pattern>),
+    OneOrMore(Box<Pattern>),
+    Element {
+        name: String,
+        pattern: Box<Pattern>,
+    },
+    Attribute {
+        name: String,
+        pattern: Box<Pattern>,
+        /// Written as an `a:defaultValue` annotation
+        default: Option<String>,
+    },
+    /// A type from the XML Schema datatype library
+    Data(&'static str),
+    Value(String),
+    List(Box<Pattern>),
+}
+
+impl Pattern {
+    fn connect(connector: Connector, mut patterns: Vec<Pattern>) -> Pattern {
+        match patterns.len() {
+            0 => Pattern::Empty,
+            1 => patterns.remove(0),
+            _ => match connector {
+                Connector::Sequence => Pattern::Group(patterns),
+                Connector::Or => Pattern::Choice(patterns),
+                Connector::And => Pattern::Interleave(patterns),
+            },
+        }
+    }
+
+    fn occur(self, occurrence: Occurrence) -> Pattern {
+        match (self, occurrence) {
+            (Pattern::Empty, _) => Pattern::Empty,
+            (p, Occurrence::Once) => p,
+            (p, Occurrence::Optional) => Pattern::Optional(Box::new(p)),
+            (p, Occurrence::OneOrMore) => Pattern::OneOrMore(Box::new(p)),
+            (p, Occurrence::ZeroOrMore) => Pattern::ZeroOrMore(Box::new(p)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Define {
+    pub name: String,
+    pub pattern: Pattern,
+    /// Notes on what couldn't be expressed exactly
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    /// The namespace of the elements, from the default of an `xmlns` attribute
+    pub namespace: Option<String>,
+    pub start: Pattern,
+    pub defines: Vec<Define>,
+}
+
+/// Generate a RELAX NG grammar from a DTD read as by `Schema::from_dtd`, with the parameter
+/// entities it keeps as named patterns
+pub fn to_relax_ng(
+    dtd: &DocumentTypeDefinition,
+    options: &SchemaOptions,
+) -> Result<Grammar, SchemaError> {
+    let schema = Schema::from_dtd(dtd, options)?;
+    Ok(Converter::new(&schema).convert())
+}
+
+struct Converter<'a> {
+    schema: &'a Schema,
+    /// Define names of parameter entities, which may clash with element names
+    group_names: HashMap<String, String>,
+    /// Inclusions that apply to each element, from its own declaration or an ancestor's
+    included: HashMap<String, Vec<String>>,
+}
+
+impl<'a> Converter<'a> {
+    fn new(schema: &'a Schema) -> Self {
+        let group_names = schema.get_definition_names();
+        let included = schema.get_included();
+
+        Self {
+            schema,
+            group_names,
+            included,
+        }
+    }
+
+    fn convert(&self) -> Grammar {
+        let mut defines = Vec::new();
+        for g in &self.schema.groups {
+            let patterns = g
+                .particles
+                .iter()
+                .filter_map(|p| self.particle(p, &[]))
+                .collect();
+            defines.push(Define {
+                name: self.group_names[&g.name].clone(),
+                pattern: Pattern::connect(g.connector, patterns),
+                comment: None,
+            });
+        }
+        for g in &self.schema.attribute_groups {
+            defines.push(Define {
+                name: self.group_names[&g.name].clone(),
+                pattern: Pattern::connect(
+                    Connector::Sequence,
+                    self.attributes(&g.attributes, &mut Vec::new()),
+                ),
+                comment: None,
+            });
+        }
+        for e in &self.schema.elements {
+            defines.push(self.element(e));
+        }
+
+        let roots = self
+            .schema
+            .get_roots()
+            .iter()
+            .map(|e| Pattern::Ref(e.name.clone()))
+            .collect();
+        Grammar {
+            namespace: self.schema.get_namespace(),
+            start: Pattern::connect(Connector::Or, roots),
+            defines,
+        }
+    }
+
+    /// The pattern for a content token, or None if all of it is excluded
+    fn particle(&self, particle: &Particle, exclusions: &[String]) -> Option<Pattern> {
+        match particle {
+            Particle::PcData => Some(Pattern::Text),
+            Particle::Element(name, _) if exclusions.contains(name) => None,
+            Particle::Element(name, occurrence) => {
+                Some(Pattern::Ref(name.clone()).occur(*occurrence))
+            }
+            Particle::Group(g) => {
+                let patterns = g
+                    .particles
+                    .iter()
+                    .filter_map(|p| self.particle(p, exclusions))
+                    .collect::<Vec<_>>();
+                if patterns.is_empty() {
+                    return None;
+                }
+                Some(Pattern::connect(g.connector, patterns).occur(g.occurrence))
+            }
+            Particle::Reference(r) => {
+                let excluded = self
+                    .schema
+                    .get_particle_names(particle)
+                    .iter()
+                    .any(|n| exclusions.contains(n));
+                if !excluded {
+                    return Some(Pattern::Ref(self.group_names[r].clone()));
+                }
+
+                // The named pattern mentions an excluded element, so this use of it is written out
+                let g = self.schema.get_group(r)?;
+                let patterns = g
+                    .particles
+                    .iter()
+                    .filter_map(|p| self.particle(p, exclusions))
+                    .collect::<Vec<_>>();
+                if patterns.is_empty() {
+                    return None;
+                }
+                Some(Pattern::connect(g.connector, patterns))
+            }
+        }
+    }
+
+    fn element(&self, e: &SchemaElement) -> Define {
+        let mut comments = Vec::new();
+        let mut content = match &e.content {
+            SchemaContent::CData | SchemaContent::RCData => Pattern::Text,
+            SchemaContent::Empty => Pattern::Empty,
+            SchemaContent::Any => {
+                let mut patterns = vec![Pattern::Text];
+                patterns.extend(
+                    self.schema
+                        .elements
+                        .iter()
+                        .map(|e| Pattern::Ref(e.name.clone())),
+                );
+                Pattern::ZeroOrMore(Box::new(Pattern::Choice(patterns)))
+            }
+            SchemaContent::Model(p) => self.particle(p, &e.exclusions).unwrap_or(Pattern::Empty),
+        };
+
+        if !e.exclusions.is_empty() {
+            comments.push(format!(
+                "Exclusions -({}) are only removed from the content of {}, not its descendants",
+                e.exclusions.join("|"),
+                e.name
+            ));
+        }
+        if !e.inclusions.is_empty() {
+            comments.push(format!(
+                "Inclusions +({}) are interleaved with the content of {} and every element it can contain",
+                e.inclusions.join("|"),
+                e.name
+            ));
+        }
+
+        // Inclusions don't apply to declared content, See ISO(B.4.2.6)
+        if let (SchemaContent::Model(_), Some(included)) = (&e.content, self.included.get(&e.name))
+        {
+            let present = self.schema.get_children(&SchemaElement {
+                inclusions: Vec::new(),
+                ..e.clone()
+            });
+            let extra = included
+                .iter()
+                .filter(|n| !present.contains(n) && !e.exclusions.contains(n))
+                .map(|n| Pattern::Ref(n.clone()))
+                .collect::<Vec<_>>();
+            if !extra.is_empty() {
+                content = Pattern::Interleave(vec![
+                    content,
+                    Pattern::ZeroOrMore(Box::new(Pattern::connect(Connector::Or, extra))),
+                ]);
+            }
+        }
+
+        let mut patterns = self.attributes(&e.attributes, &mut Vec::new());
+        if content != Pattern::Empty || patterns.is_empty() {
+            patterns.push(content);
+        }
+
+        Define {
+            name: e.name.clone(),
+            pattern: Pattern::Element {
+                name: e.name.clone(),
+                pattern: Box::new(Pattern::connect(Connector::Sequence, patterns)),
+            },
+            comment: match comments.is_empty() {
+                true => None,
+                false => Some(comments.join("\n")),
+            },
+        }
+    }
+
+    /// Attribute patterns, skipping any attribute defined earlier in the list as SGML does
+    fn attributes(&self, items: &[AttributeItem], seen: &mut Vec<String>) -> Vec<Pattern> {
+        let mut patterns = Vec::new();
+        for item in items {
+            match item {
+                AttributeItem::Definition(d) => {
+                    // Namespace declarations aren't attributes in RELAX NG
+                    if seen.contains(&d.name) || d.name == "xmlns" || d.name.starts_with("xmlns:") {
+                        continue;
+                    }
+                    seen.push(d.name.clone());
+                    patterns.push(attribute(d));
+                }
+                AttributeItem::Reference(r) => {
+                    if let Some(g) = self.schema.get_attribute_group(r) {
+                        self.attributes(&g.attributes, seen);
+                    }
+                    patterns.push(Pattern::Ref(self.group_names[r].clone()));
+                }
+            }
+        }
+        patterns
+    }
+}
+
+fn attribute(d: &AttributeDefinition) -> Pattern {
+    let list =
+        |t: &'static str| Pattern::List(Box::new(Pattern::OneOrMore(Box::new(Pattern::Data(t)))));
+    let values = |v: &Vec<String>| {
+        Pattern::connect(
+            Connector::Or,
+            v.iter().map(|v| Pattern::Value(v.clone())).collect(),
+        )
+    };
+    let pattern = match &d.declared_value {
+        DeclaredValue::CData => Pattern::Text,
+        DeclaredValue::Entity => Pattern::Data("ENTITY"),
+        DeclaredValue::Entities => Pattern::Data("ENTITIES"),
+        DeclaredValue::Id => Pattern::Data("ID"),
+        DeclaredValue::IdRef => Pattern::Data("IDREF"),
+        DeclaredValue::IdRefs => Pattern::Data("IDREFS"),
+        DeclaredValue::Name => Pattern::Data("Name"),
+        DeclaredValue::Names => list("Name"),
+        DeclaredValue::NmToken | DeclaredValue::NuToken => Pattern::Data("NMTOKEN"),
+        DeclaredValue::NmTokens | DeclaredValue::NuTokens => Pattern::Data("NMTOKENS"),
+        DeclaredValue::Number => Pattern::Data("nonNegativeInteger"),
+        DeclaredValue::Numbers => list("nonNegativeInteger"),
+        DeclaredValue::Notation(v) | DeclaredValue::NameTokenGroup(v) => values(v),
+    };
+
+    let (pattern, default) = match &d.default_value {
+        DefaultValue::Fixed(v) => (Pattern::Value(v.clone()), Some(v.clone())),
+        DefaultValue::Value(v) => (pattern, Some(v.clone())),
+        _ => (pattern, None),
+    };
+    let attribute = Pattern::Attribute {
+        name: d.name.clone(),
+        pattern: Box::new(pattern),
+        default,
+    };
+    match d.default_value {
+        DefaultValue::Required => attribute,
+        _ => Pattern::Optional(Box::new(attribute)),
+    }
+}
+
+impl Grammar {
+    /// Write the grammar in the compact syntax
+    pub fn to_rnc(&self) -> String {
+        let mut out = String::new();
+        if let Some(namespace) = &self.namespace {
+            out.push_str(&format!(
+                "default namespace = {}\n",
+                compact_literal(namespace)
+            ));
+        }
+        out.push_str(&format!("namespace a = \"{}\"\n\n", ANNOTATIONS_NAMESPACE));
+        out.push_str(&format!("start = {}\n", compact(&self.start, false, 0)));
+        for define in &self.defines {
+            out.push('\n');
+            if let Some(comment) = &define.comment {
+                for line in comment.lines() {
+                    out.push_str(&format!("# {}\n", line));
+                }
+            }
+            // Elements with attributes and long lists start on a line of their own
+            let mut pattern = compact(&define.pattern, false, 0);
+            if pattern.len() > COMPACT_WIDTH {
+                let members = match &define.pattern {
+                    Pattern::Group(v) => Some((v, ",\n  ")),
+                    Pattern::Choice(v) => Some((v, "\n  | ")),
+                    Pattern::Interleave(v) => Some((v, "\n  & ")),
+                    _ => None,
+                };
+                if let Some((v, separator)) = members {
+                    pattern = format!(
+                        "\n  {}",
+                        v.iter()
+                            .map(|p| compact(p, true, 0))
+                            .collect::<Vec<_>>()
+                            .join(separator)
+                    );
+                }
+            }
+            let separator = if pattern.starts_with('\n') { "" } else { " " };
+            out.push_str(&format!(
+                "{} ={}{}\n",
+                compact_identifier(&define.name),
+                separator,
+                pattern
+            ));
+        }
+        out
+    }
+
+    /// Write the grammar in the XML syntax
+    pub fn to_rng(&self) -> String {
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let ns = match &self.namespace {
+            Some(namespace) => format!(" ns=\"{}\"", xml_escape(namespace)),
+            None => String::new(),
+        };
+        out.push_str(&format!(
+            "<grammar xmlns=\"{}\" xmlns:a=\"{}\"{} datatypeLibrary=\"{}\">\n",
+            STRUCTURE_NAMESPACE, ANNOTATIONS_NAMESPACE, ns, DATATYPE_LIBRARY
+        ));
+        out.push_str("  <start>\n");
+        xml(&self.start, 2, &mut out);
+        out.push_str("  </start>\n");
+        for define in &self.defines {
+            if let Some(comment) = &define.comment {
+                out.push_str(&format!("  <!-- {} -->\n", xml_comment(comment)));
+            }
+            out.push_str(&format!(
+                "  <define name=\"{}\">\n",
+                xml_escape(&define.name)
+            ));
+            xml_children(&define.pattern, 1, &mut out);
+            out.push_str("  </define>\n");
+        }
+        out.push_str("</grammar>\n");
+        out
+    }
+}
+
+fn compact_identifier(name: &str) -> String {
+    if COMPACT_KEYWORDS.contains(&name) {
+        format!("\\{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn compact_literal(v: &str) -> String {
+    format!("\"{}\"", v.replace('"', "\\x{22}").replace('\n', "\\x{A}"))
+}
+
+/// Write a pattern in the compact syntax, in parentheses if it has to be a single particle
+fn compact(pattern: &Pattern, particle: bool, indent: usize) -> String {
+    let join = |patterns: &Vec<Pattern>, separator: &str| {
+        let s = patterns
+            .iter()
+            .map(|p| compact(p, true, indent))
+            .collect::<Vec<_>>()
+            .join(separator);
+        if particle {
+            format!("({})", s)
+        } else {
+            s
+        }
+    };
+    match pattern {
+        Pattern::Empty => "empty".to_string(),
+        Pattern::Text => "text".to_string(),
+        Pattern::NotAllowed => "notAllowed".to_string(),
+        Pattern::Ref(name) => compact_identifier(name),
+        Pattern::Group(v) => join(v, ", "),
+        Pattern::Choice(v) => join(v, " | "),
+        Pattern::Interleave(v) => join(v, " & "),
+        Pattern::Optional(p) => format!("{}?", compact(p, true, indent)),
+        Pattern::ZeroOrMore(p) => format!("{}*", compact(p, true, indent)),
+        Pattern::OneOrMore(p) => format!("{}+", compact(p, true, indent)),
+        Pattern::Element { name, pattern } => match pattern.as_ref() {
+            // Attributes and content go on a line each
+            Pattern::Group(v) => {
+                let inner = " ".repeat(indent + 4);
+                format!(
+                    "\n{}element {} {{\n{}{}\n{}}}",
+                    " ".repeat(indent + 2),
+                    name,
+                    inner,
+                    v.iter()
+                        .map(|p| compact(p, false, indent + 4))
+                        .collect::<Vec<_>>()
+                        .join(&format!(",\n{}", inner)),
+                    " ".repeat(indent + 2)
+                )
+            }
+            p => format!("element {} {{ {} }}", name, compact(p, false, indent)),
+        },
+        Pattern::Attribute {
+            name,
+            pattern,
+            default,
+        } => {
+            let annotation = match default {
+                Some(v) => format!("[ a:defaultValue = {} ] ", compact_literal(v)),
+                None => String::new(),
+            };
+            format!(
+                "{}attribute {} {{ {} }}",
+                annotation,
+                name,
+                compact(pattern, false, indent)
+            )
+        }
+        Pattern::Data(t) => format!("xsd:{}", t),
+        Pattern::Value(v) => compact_literal(v),
+        Pattern::List(p) => format!("list {{ {} }}", compact(p, false, indent)),
+    }
+}
+
+pub(crate) fn xml_escape(v: &str) -> String {
+    v.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn xml_comment(v: &str) -> String {
+    v.replace("--", "- -").replace('\n', " ")
+}
+
+/// Write the patterns within an implicit group, as `element`, `define` and `attribute` allow
+fn xml_children(pattern: &Pattern, depth: usize, out: &mut String) {
+    match pattern {
+        Pattern::Group(v) => v.iter().for_each(|p| xml(p, depth + 1, out)),
+        p => xml(p, depth + 1, out),
+    }
+}
+
+/// Write a pattern in the XML syntax
+fn xml(pattern: &Pattern, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    let container = |tag: &str, patterns: &[Pattern], out: &mut String| {
+        out.push_str(&format!("{}<{}>\n", indent, tag));
+        patterns.iter().for_each(|p| xml(p, depth + 1, out));
+        out.push_str(&format!("{}</{}>\n", indent, tag));
+    };
+    match pattern {
+        Pattern::Empty => out.push_str(&format!("{}<empty/>\n", indent)),
+        Pattern::Text => out.push_str(&format!("{}<text/>\n", indent)),
+        Pattern::NotAllowed => out.push_str(&format!("{}<notAllowed/>\n", indent)),
+        Pattern::Ref(name) => {
+            out.push_str(&format!("{}<ref name=\"{}\"/>\n", indent, xml_escape(name)))
+        }
+        Pattern::Group(v) => container("group", v, out),
+        Pattern::Choice(v) => container("choice", v, out),
+        Pattern::Interleave(v) => container("interleave", v, out),
+        Pattern::Optional(p) => container("optional", std::slice::from_ref(p), out),
+        Pattern::ZeroOrMore(p) => container("zeroOrMore", std::slice::from_ref(p), out),
+        Pattern::OneOrMore(p) => container("oneOrMore", std::slice::from_ref(p), out),
+        Pattern::Element { name, pattern } => {
+            out.push_str(&format!(
+                "{}<element name=\"{}\">\n",
+                indent,
+                xml_escape(name)
+            ));
+            xml_children(pattern, depth, out);
+            out.push_str(&format!("{}</element>\n", indent));
+        }
+        Pattern::Attribute {
+            name,
+            pattern,
+            default,
+        } => {
+            let annotation = match default {
+                Some(v) => format!(" a:defaultValue=\"{}\"", xml_escape(v)),
+                None => String::new(),
+            };
+            let start = format!(
+                "{}<attribute name=\"{}\"{}",
+                indent,
+                xml_escape(name),
+                annotation
+            );
+            // Text is the default pattern of an attribute
+            if **pattern == Pattern::Text {
+                out.push_str(&format!("{}/>\n", start));
+            } else {
+                out.push_str(&format!("{}>\n", start));
+                xml(pattern, depth + 1, out);
+                out.push_str(&format!("{}</attribute>\n", indent));
+            }
+        }
+        Pattern::Data(t) => out.push_str(&format!("{}<data type=\"{}\"/>\n", indent, t)),
+        Pattern::Value(v) => out.push_str(&format!("{}<value>{}</value>\n", indent, xml_escape(v))),
+        Pattern::List(p) => container("list", std::slice::from_ref(p), out),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::dtd::read_dtd;
+    use crate::relax_ng::{to_relax_ng, Pattern};
+    use crate::schema::test::html_schema_dtd;
+    use crate::schema::SchemaOptions;
+
+    #[test]
+    pub fn test_relax_ng_patterns() {
+        let (_, dtd) = read_dtd(
+            "<!ENTITY % inline \"B | I\">
+<!ELEMENT DOC - - (HEAD & BODY) +(NOTE)>
+<!ELEMENT HEAD - O EMPTY>
+<!ELEMENT BODY - - (#PCDATA | %inline)* -(I)>
+<!ELEMENT (B|I|NOTE) - - (%inline)*>
+<!ATTLIST DOC
+    version NUMBER #REQUIRED
+    status (draft|final) final
+    owner CDATA #FIXED \"me\">",
+        )
+        .unwrap();
+        let grammar = to_relax_ng(&dtd, &SchemaOptions::default()).unwrap();
+        assert_eq!(grammar.start, Pattern::Ref("DOC".to_string()));
+
+        let rnc = grammar.to_rnc();
+        println!("{}", rnc);
+        assert!(rnc.contains("inline = B | I\n"));
+        assert!(rnc.contains("(HEAD & BODY) & NOTE*"));
+        // The exclusion is applied by writing out the named pattern
+        assert!(rnc.contains("BODY = element BODY { (text | B)* & NOTE* }"));
+        assert!(rnc.contains("B = element B { inline* & NOTE* }"));
+        assert!(rnc.contains("attribute version { xsd:nonNegativeInteger },"));
+        assert!(rnc.contains(
+            "[ a:defaultValue = \"final\" ] attribute status { \"draft\" | \"final\" }?,"
+        ));
+        assert!(rnc.contains("attribute owner { \"me\" }?"));
+        assert!(rnc.contains("# Exclusions -(I) are only removed from the content of BODY"));
+
+        let rng = grammar.to_rng();
+        println!("{}", rng);
+        assert!(rng.contains("<define name=\"inline\">\n    <choice>"));
+        assert!(rng.contains("<attribute name=\"status\" a:defaultValue=\"final\">"));
+    }
+
+    #[test]
+    pub fn test_relax_ng_html() {
+        let dtd = html_schema_dtd();
+        let grammar = to_relax_ng(&dtd, &SchemaOptions::default()).unwrap();
+        let rnc = grammar.to_rnc();
+        println!("{}", rnc);
+        assert!(rnc.starts_with("namespace a = "));
+        // Keywords used as entity names are escaped
+        assert!(rnc.contains("\n\\text = text | "));
+        assert!(rnc.contains("    \\text* & (INPUT | SELECT | TEXTAREA)*\n"));
+        assert!(rnc.contains("TITLE & ISINDEX? & BASE? & NEXTID?"));
+        assert!(rnc.contains("start = HTML\n"));
+    }
+}
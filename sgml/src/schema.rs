@@ -0,0 +1,1038 @@
+use crate::att_list::{
+    parse_attribute_definitions, AttributeDefinition, DeclaredValue, DefaultValue,
+};
+use crate::content_model::{is_name_char, Connector, Occurrence};
+use crate::dtd::{DTDElement, DocumentTypeDefinition};
+use crate::element::ElementName;
+use crate::entity::Entity;
+use crate::template_strings::ChainElement;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Splices allowed while reading one declaration before the entities are assumed to be recursive
+const SPLICE_LIMIT: usize = 10_000;
+
+/// A content token that may stand for a group of tokens declared by a parameter entity
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Particle {
+    PcData,
+    Element(String, Occurrence),
+    Group(ParticleGroup),
+    /// A reference to a `NamedGroup`
+    Reference(String),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParticleGroup {
+    pub connector: Connector,
+    pub particles: Vec<Particle>,
+    pub occurrence: Occurrence,
+}
+
+/// See ISO(B.4.2.6)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SchemaContent {
+    CData,
+    RCData,
+    Empty,
+    Any,
+    Model(Particle),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AttributeItem {
+    Definition(AttributeDefinition),
+    /// A reference to a `NamedAttributes`
+    Reference(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct SchemaElement {
+    pub name: String,
+    pub content: SchemaContent,
+    pub attributes: Vec<AttributeItem>,
+    pub inclusions: Vec<String>,
+    pub exclusions: Vec<String>,
+}
+
+/// Content tokens declared by a parameter entity, such as `%text` or `%block`
+#[derive(Debug, Clone)]
+pub struct NamedGroup {
+    pub name: String,
+    /// The connector between the particles, Sequence when there is only one
+    pub connector: Connector,
+    pub particles: Vec<Particle>,
+}
+
+/// Attribute definitions declared by a parameter entity, such as `%attrs`
+#[derive(Debug, Clone)]
+pub struct NamedAttributes {
+    pub name: String,
+    pub attributes: Vec<AttributeItem>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SchemaError {
+    /// Marked sections and parameter references between declarations should be flattened first
+    NotStandalone(String),
+    UndefinedEntity(String),
+    RecursiveEntity(String),
+    Syntax {
+        declaration: String,
+        rest: String,
+    },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::NotStandalone(declaration) => write!(
+                f,
+                "{}: marked sections and parameter references must be flattened first",
+                declaration
+            ),
+            SchemaError::UndefinedEntity(name) => write!(f, "entity %{}; is not declared", name),
+            SchemaError::RecursiveEntity(name) => write!(f, "entity %{}; references itself", name),
+            SchemaError::Syntax { declaration, rest } => {
+                write!(f, "{}: unable to parse at '{}'", declaration, rest)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+/// Options for reading a DTD into a `Schema`, shared by the RELAX NG, XSD and JSON Schema converters
+#[derive(Debug, Clone, Default)]
+pub struct SchemaOptions {
+    /// Lowercase element names, attribute names and name token groups, as XHTML did for HTML
+    pub lowercase_names: bool,
+}
+
+/// The elements and attributes of a DTD, keeping the parameter entities that group content tokens and
+/// attribute definitions, as a starting point for generating other kinds of schema
+#[derive(Debug, Clone)]
+pub struct Schema {
+    pub elements: Vec<SchemaElement>,
+    pub groups: Vec<NamedGroup>,
+    pub attribute_groups: Vec<NamedAttributes>,
+}
+
+impl Schema {
+    /// Read a standalone DTD, such as the output of `flatten_dtd` with `keep_parameter_entities`
+    /// Entities that stand for whole content tokens or attribute definitions become named groups,
+    /// any others are expanded in place
+    pub fn from_dtd(
+        dtd: &DocumentTypeDefinition,
+        options: &SchemaOptions,
+    ) -> Result<Self, SchemaError> {
+        let mut builder = Builder {
+            entities: dtd.get_entities(),
+            lowercase_names: options.lowercase_names,
+            groups: Vec::new(),
+            attribute_groups: Vec::new(),
+            not_groups: Vec::new(),
+            open: Vec::new(),
+            splices: 0,
+        };
+
+        let mut elements: Vec<SchemaElement> = Vec::new();
+        let mut attributes: Vec<(String, Vec<AttributeItem>)> = Vec::new();
+        for declaration in dtd.get_declarations() {
+            match declaration {
+                DTDElement::Element(e) => {
+                    let names = element_names(e.get_name(dtd.get_entities()));
+                    let context = format!("<!ELEMENT {}", names.join("|"));
+                    let (content, inclusions, exclusions) = builder
+                        .content(&e.content_model.chain)
+                        .map_err(|e| e.within(&context))?;
+                    for name in names {
+                        elements.push(SchemaElement {
+                            name: builder.name(&name),
+                            content: content.clone(),
+                            attributes: Vec::new(),
+                            inclusions: inclusions.clone(),
+                            exclusions: exclusions.clone(),
+                        });
+                    }
+                }
+                DTDElement::ATTList(a) => {
+                    let names = element_names(a.get_name(dtd.get_entities()));
+                    let context = format!("<!ATTLIST {}", names.join("|"));
+                    let items = builder
+                        .attributes(&a.value.chain)
+                        .map_err(|e| e.within(&context))?;
+                    for name in names {
+                        attributes.push((builder.name(&name), items.clone()));
+                    }
+                }
+                DTDElement::MarkedSection(ms) => {
+                    return Err(SchemaError::NotStandalone(format!("<![{}[", ms.status)))
+                }
+                DTDElement::ParameterReference(r) => {
+                    return Err(SchemaError::NotStandalone(format!("%{};", r.name)))
+                }
+                _ => {}
+            }
+        }
+
+        // Attribute lists may come before or after the element they apply to
+        for (name, items) in attributes {
+            if let Some(element) = elements.iter_mut().find(|e| e.name == name) {
+                element.attributes.extend(items);
+            }
+        }
+
+        let mut schema = Schema {
+            elements,
+            groups: builder.groups,
+            attribute_groups: builder.attribute_groups,
+        };
+        schema.remove_unused_groups();
+        Ok(schema)
+    }
+
+    pub fn get_element(&self, name: &str) -> Option<&SchemaElement> {
+        self.elements.iter().find(|e| e.name == name)
+    }
+
+    pub fn get_group(&self, name: &str) -> Option<&NamedGroup> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    pub fn get_attribute_group(&self, name: &str) -> Option<&NamedAttributes> {
+        self.attribute_groups.iter().find(|g| g.name == name)
+    }
+
+    /// Every element a particle allows, looking through named groups
+    pub fn get_particle_names(&self, particle: &Particle) -> Vec<String> {
+        let mut names = Vec::new();
+        self.collect_names(particle, &mut names, &mut Vec::new());
+        names
+    }
+
+    fn collect_names(&self, particle: &Particle, names: &mut Vec<String>, seen: &mut Vec<String>) {
+        match particle {
+            Particle::PcData => {}
+            Particle::Element(name, _) => {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+            Particle::Group(g) => g
+                .particles
+                .iter()
+                .for_each(|p| self.collect_names(p, names, seen)),
+            Particle::Reference(r) => {
+                if seen.contains(r) {
+                    return;
+                }
+                seen.push(r.clone());
+                if let Some(g) = self.get_group(r) {
+                    g.particles
+                        .iter()
+                        .for_each(|p| self.collect_names(p, names, seen));
+                }
+            }
+        }
+    }
+
+    /// Whether character data may appear anywhere in a particle, looking through named groups
+    pub fn is_mixed(&self, particle: &Particle) -> bool {
+        match particle {
+            Particle::PcData => true,
+            Particle::Element(..) => false,
+            Particle::Group(g) => g.particles.iter().any(|p| self.is_mixed(p)),
+            Particle::Reference(r) => self
+                .get_group(r)
+                .map(|g| g.particles.iter().any(|p| self.is_mixed(p)))
+                .unwrap_or(false),
+        }
+    }
+
+    /// The elements that may contain an element, and the elements it may contain
+    pub fn get_children(&self, element: &SchemaElement) -> Vec<String> {
+        let mut names = match &element.content {
+            SchemaContent::Model(p) => self.get_particle_names(p),
+            SchemaContent::Any => self.elements.iter().map(|e| e.name.clone()).collect(),
+            _ => Vec::new(),
+        };
+        for inclusion in &element.inclusions {
+            if !names.contains(inclusion) {
+                names.push(inclusion.clone());
+            }
+        }
+        names
+    }
+
+    /// Names for groups and attribute groups in a schema where they share a namespace with elements
+    pub fn get_definition_names(&self) -> HashMap<String, String> {
+        let define_name = |name: &str, suffix: &str| {
+            if self.get_element(name).is_some() {
+                format!("{}.{}", name, suffix)
+            } else {
+                name.to_string()
+            }
+        };
+        let mut names = HashMap::new();
+        for g in &self.groups {
+            names.insert(g.name.clone(), define_name(&g.name, "group"));
+        }
+        for g in &self.attribute_groups {
+            names.insert(g.name.clone(), define_name(&g.name, "attributes"));
+        }
+        names
+    }
+
+    /// The inclusions that apply within each element, from its own declaration or an ancestor's
+    pub fn get_included(&self) -> HashMap<String, Vec<String>> {
+        let mut included: HashMap<String, Vec<String>> = HashMap::new();
+        for element in self.elements.iter().filter(|e| !e.inclusions.is_empty()) {
+            let mut reachable = vec![element.name.clone()];
+            let mut n = 0;
+            while n < reachable.len() {
+                if let Some(e) = self.get_element(&reachable[n]) {
+                    for child in self.get_children(e) {
+                        if !reachable.contains(&child) {
+                            reachable.push(child);
+                        }
+                    }
+                }
+                n += 1;
+            }
+            for name in reachable {
+                let names = included.entry(name).or_default();
+                for inclusion in &element.inclusions {
+                    if !names.contains(inclusion) {
+                        names.push(inclusion.clone());
+                    }
+                }
+            }
+        }
+        included
+    }
+
+    /// The namespace of the elements, which XHTML declares as the default of an `xmlns` attribute
+    pub fn get_namespace(&self) -> Option<String> {
+        let groups = self.attribute_groups.iter().map(|g| &g.attributes);
+        let elements = self.elements.iter().map(|e| &e.attributes);
+        groups
+            .chain(elements)
+            .flatten()
+            .find_map(|item| match item {
+                AttributeItem::Definition(d) if d.name == "xmlns" => match &d.default_value {
+                    DefaultValue::Fixed(v) | DefaultValue::Value(v) => Some(v.clone()),
+                    _ => None,
+                },
+                _ => None,
+            })
+    }
+
+    /// Elements that no other element can contain, the likely document elements
+    pub fn get_roots(&self) -> Vec<&SchemaElement> {
+        let mut contained = Vec::new();
+        for element in &self.elements {
+            for child in self.get_children(element) {
+                if child != element.name {
+                    contained.push(child);
+                }
+            }
+        }
+
+        let roots = self
+            .elements
+            .iter()
+            .filter(|e| !contained.contains(&e.name))
+            .collect::<Vec<_>>();
+        if roots.is_empty() {
+            self.elements.iter().take(1).collect()
+        } else {
+            roots
+        }
+    }
+
+    /// Drop groups that were read while trying entities that turned out not to be usable on their own
+    fn remove_unused_groups(&mut self) {
+        let mut used = Vec::new();
+        let mut used_attributes = Vec::new();
+
+        fn visit(schema: &Schema, particle: &Particle, used: &mut Vec<String>) {
+            match particle {
+                Particle::Group(g) => g.particles.iter().for_each(|p| visit(schema, p, used)),
+                Particle::Reference(r) if !used.contains(r) => {
+                    used.push(r.clone());
+                    if let Some(g) = schema.get_group(r) {
+                        g.particles.iter().for_each(|p| visit(schema, p, used));
+                    }
+                }
+                _ => {}
+            }
+        }
+        fn visit_attributes(schema: &Schema, items: &[AttributeItem], used: &mut Vec<String>) {
+            for item in items {
+                if let AttributeItem::Reference(r) = item {
+                    if !used.contains(r) {
+                        used.push(r.clone());
+                        if let Some(g) = schema.get_attribute_group(r) {
+                            visit_attributes(schema, &g.attributes, used);
+                        }
+                    }
+                }
+            }
+        }
+
+        for element in &self.elements {
+            if let SchemaContent::Model(p) = &element.content {
+                visit(self, p, &mut used);
+            }
+            visit_attributes(self, &element.attributes, &mut used_attributes);
+        }
+
+        self.groups.retain(|g| used.contains(&g.name));
+        self.attribute_groups
+            .retain(|g| used_attributes.contains(&g.name));
+    }
+}
+
+fn element_names(name: ElementName) -> Vec<String> {
+    match name {
+        ElementName::Single(s) => vec![s.trim().to_string()],
+        ElementName::Group(g) => g,
+    }
+}
+
+impl SchemaError {
+    /// Name the declaration a syntax error was found in
+    fn within(self, declaration: &str) -> Self {
+        match self {
+            SchemaError::Syntax { rest, .. } => SchemaError::Syntax {
+                declaration: declaration.to_string(),
+                rest,
+            },
+            e => e,
+        }
+    }
+}
+
+fn syntax(rest: &[Lex]) -> SchemaError {
+    SchemaError::Syntax {
+        declaration: String::new(),
+        rest: rest
+            .iter()
+            .take(8)
+            .map(|l| l.to_string())
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// The pieces of a content model, with references left in place
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Lex {
+    Open,
+    Close,
+    Connector(Connector),
+    Occurrence(Occurrence),
+    PcData,
+    Name(String),
+    Reference(String),
+    Exclusion,
+    Inclusion,
+    Other(char),
+}
+
+impl fmt::Display for Lex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lex::Open => f.write_str("("),
+            Lex::Close => f.write_str(")"),
+            Lex::Connector(c) => f.write_str(c.symbol()),
+            Lex::Occurrence(o) => write!(f, "{}", o),
+            Lex::PcData => f.write_str("#PCDATA"),
+            Lex::Name(n) => f.write_str(n),
+            Lex::Reference(r) => write!(f, "%{};", r),
+            Lex::Exclusion => f.write_str("-"),
+            Lex::Inclusion => f.write_str("+"),
+            Lex::Other(c) => write!(f, "{}", c),
+        }
+    }
+}
+
+fn lex(chain: &[ChainElement]) -> Vec<Lex> {
+    let mut out = Vec::new();
+    for element in chain {
+        let s = match element {
+            ChainElement::Reference(r) => {
+                out.push(Lex::Reference(r.clone()));
+                continue;
+            }
+            ChainElement::String(s) => s,
+        };
+
+        let chars = s.chars().collect::<Vec<_>>();
+        let mut n = 0;
+        while n < chars.len() {
+            let c = chars[n];
+            let next = chars.get(n + 1).copied();
+            n += 1;
+            match c {
+                c if c.is_whitespace() => {}
+                '-' if next == Some('-') => {
+                    // Comment, See ISO(B.3.1)
+                    n += 1;
+                    while n < chars.len() && !(chars[n] == '-' && chars.get(n + 1) == Some(&'-')) {
+                        n += 1;
+                    }
+                    n += 2;
+                }
+                '(' => out.push(Lex::Open),
+                ')' => out.push(Lex::Close),
+                ',' => out.push(Lex::Connector(Connector::Sequence)),
+                '|' => out.push(Lex::Connector(Connector::Or)),
+                '&' => out.push(Lex::Connector(Connector::And)),
+                '?' => out.push(Lex::Occurrence(Occurrence::Optional)),
+                '*' => out.push(Lex::Occurrence(Occurrence::ZeroOrMore)),
+                '+' => {
+                    // A plus sign followed by a group is an inclusion rather than an occurrence indicator
+                    let group = chars[n..].iter().find(|c| !c.is_whitespace()) == Some(&'(');
+                    out.push(if group {
+                        Lex::Inclusion
+                    } else {
+                        Lex::Occurrence(Occurrence::OneOrMore)
+                    });
+                }
+                '-' => out.push(Lex::Exclusion),
+                c if c == '#' || is_name_char(c) => {
+                    let start = n - 1;
+                    while n < chars.len() && is_name_char(chars[n]) {
+                        n += 1;
+                    }
+                    let name = chars[start..n].iter().collect::<String>();
+                    out.push(if name.eq_ignore_ascii_case("#PCDATA") {
+                        Lex::PcData
+                    } else {
+                        Lex::Name(name)
+                    });
+                }
+                c => out.push(Lex::Other(c)),
+            }
+        }
+    }
+    out
+}
+
+const DECLARED_CONTENT: [&str; 4] = ["CDATA", "RCDATA", "EMPTY", "ANY"];
+
+struct Builder<'a> {
+    entities: &'a [Entity],
+    lowercase_names: bool,
+    groups: Vec<NamedGroup>,
+    attribute_groups: Vec<NamedAttributes>,
+    /// Entities already found not to stand for content tokens or attribute definitions on their own
+    not_groups: Vec<String>,
+    /// Entities being read, used to detect recursion
+    open: Vec<String>,
+    splices: usize,
+}
+
+impl<'a> Builder<'a> {
+    fn name(&self, name: &str) -> String {
+        if self.lowercase_names {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn entity(&self, name: &str) -> Result<&'a Entity, SchemaError> {
+        self.entities
+            .iter()
+            .find(|e| e.parameter && e.name == name)
+            .ok_or_else(|| SchemaError::UndefinedEntity(name.to_string()))
+    }
+
+    /// Replace the reference at `position` with the pieces of its replacement text
+    fn splice(&mut self, lexes: &mut Vec<Lex>, position: usize) -> Result<(), SchemaError> {
+        let name = match &lexes[position] {
+            Lex::Reference(r) => r.clone(),
+            _ => return Ok(()),
+        };
+        self.splices += 1;
+        if self.splices > SPLICE_LIMIT {
+            return Err(SchemaError::RecursiveEntity(name));
+        }
+        let replacement = lex(&self.entity(&name)?.content.chain);
+        lexes.splice(position..position + 1, replacement);
+        Ok(())
+    }
+
+    /// The piece at `position`, after expanding any reference there
+    fn peek(&mut self, lexes: &mut Vec<Lex>, position: usize) -> Result<Option<Lex>, SchemaError> {
+        while let Some(Lex::Reference(_)) = lexes.get(position) {
+            self.splice(lexes, position)?;
+        }
+        Ok(lexes.get(position).cloned())
+    }
+
+    fn occurrence(&mut self, lexes: &mut [Lex], position: &mut usize) -> Occurrence {
+        match lexes.get(*position) {
+            Some(Lex::Occurrence(o)) => {
+                *position += 1;
+                *o
+            }
+            _ => Occurrence::Once,
+        }
+    }
+
+    fn token(
+        &mut self,
+        lexes: &mut Vec<Lex>,
+        position: &mut usize,
+    ) -> Result<Particle, SchemaError> {
+        loop {
+            match lexes.get(*position).cloned() {
+                Some(Lex::Reference(r)) => {
+                    if self.group(&r)? {
+                        *position += 1;
+                        return Ok(Particle::Reference(r));
+                    }
+                    self.splice(lexes, *position)?;
+                }
+                Some(Lex::PcData) => {
+                    *position += 1;
+                    return Ok(Particle::PcData);
+                }
+                Some(Lex::Name(name)) if !name.starts_with('#') => {
+                    *position += 1;
+                    let occurrence = self.occurrence(lexes, position);
+                    return Ok(Particle::Element(self.name(&name), occurrence));
+                }
+                Some(Lex::Open) => return Ok(Particle::Group(self.model_group(lexes, position)?)),
+                _ => return Err(syntax(&lexes[*position..])),
+            }
+        }
+    }
+
+    /// Tokens joined by a connector, until the end or a closing parenthesis
+    fn tokens(
+        &mut self,
+        lexes: &mut Vec<Lex>,
+        position: &mut usize,
+    ) -> Result<(Connector, Vec<Particle>), SchemaError> {
+        let mut particles = vec![self.token(lexes, position)?];
+        let mut connector = None;
+        while let Some(Lex::Connector(c)) = self.peek(lexes, *position)? {
+            // All connectors in a group must be the same
+            if connector.is_some() && connector != Some(c) {
+                return Err(syntax(&lexes[*position..]));
+            }
+            connector = Some(c);
+            *position += 1;
+            particles.push(self.token(lexes, position)?);
+        }
+        Ok((connector.unwrap_or(Connector::Sequence), particles))
+    }
+
+    fn model_group(
+        &mut self,
+        lexes: &mut Vec<Lex>,
+        position: &mut usize,
+    ) -> Result<ParticleGroup, SchemaError> {
+        if self.peek(lexes, *position)? != Some(Lex::Open) {
+            return Err(syntax(&lexes[*position..]));
+        }
+        *position += 1;
+        let (connector, particles) = self.tokens(lexes, position)?;
+        if self.peek(lexes, *position)? != Some(Lex::Close) {
+            return Err(syntax(&lexes[*position..]));
+        }
+        *position += 1;
+        let occurrence = self.occurrence(lexes, position);
+        Ok(ParticleGroup {
+            connector,
+            particles,
+            occurrence,
+        })
+    }
+
+    /// Whether an entity stands for whole content tokens, reading it into a named group if it does
+    fn group(&mut self, name: &str) -> Result<bool, SchemaError> {
+        if self.groups.iter().any(|g| g.name == name) {
+            return Ok(true);
+        }
+        if self.not_groups.iter().any(|n| n == name) {
+            return Ok(false);
+        }
+        if self.open.iter().any(|n| n == name) {
+            return Err(SchemaError::RecursiveEntity(name.to_string()));
+        }
+
+        let mut lexes = lex(&self.entity(name)?.content.chain);
+        let keyword = matches!(lexes.as_slice(), [Lex::Name(n)]
+            if DECLARED_CONTENT.iter().any(|k| k.eq_ignore_ascii_case(n)));
+
+        self.open.push(name.to_string());
+        let mut position = 0;
+        let result = match keyword {
+            true => None,
+            false => match self.tokens(&mut lexes, &mut position) {
+                Ok(tokens) if position == lexes.len() => Some(tokens),
+                Ok(_) | Err(SchemaError::Syntax { .. }) => None,
+                Err(e) => {
+                    self.open.pop();
+                    return Err(e);
+                }
+            },
+        };
+        self.open.pop();
+
+        match result {
+            Some((connector, particles)) => {
+                self.groups.push(NamedGroup {
+                    name: name.to_string(),
+                    connector,
+                    particles,
+                });
+                Ok(true)
+            }
+            None => {
+                self.not_groups.push(name.to_string());
+                Ok(false)
+            }
+        }
+    }
+
+    fn exception(
+        &mut self,
+        lexes: &mut Vec<Lex>,
+        position: &mut usize,
+    ) -> Result<Vec<String>, SchemaError> {
+        *position += 1;
+        let group = self.model_group(lexes, position)?;
+        let mut names = Vec::new();
+        for particle in &group.particles {
+            match particle {
+                Particle::Element(n, _) => names.push(n.clone()),
+                Particle::Reference(r) => {
+                    let group = self.groups.iter().find(|g| &g.name == r).unwrap();
+                    let schema = Schema {
+                        elements: Vec::new(),
+                        groups: self.groups.clone(),
+                        attribute_groups: Vec::new(),
+                    };
+                    names.extend(schema.get_particle_names(&Particle::Group(ParticleGroup {
+                        connector: group.connector,
+                        particles: group.particles.clone(),
+                        occurrence: Occurrence::Once,
+                    })));
+                }
+                _ => return Err(syntax(&lexes[*position..])),
+            }
+        }
+        Ok(names)
+    }
+
+    /// Read the content of an element declaration, with its inclusions and exclusions
+    fn content(
+        &mut self,
+        chain: &[ChainElement],
+    ) -> Result<(SchemaContent, Vec<String>, Vec<String>), SchemaError> {
+        self.splices = 0;
+        let mut lexes = lex(chain);
+        let mut position = 0;
+
+        let content = loop {
+            match lexes.first().cloned() {
+                Some(Lex::Reference(r)) => {
+                    if self.group(&r)? {
+                        position = 1;
+                        break SchemaContent::Model(Particle::Reference(r));
+                    }
+                    self.splice(&mut lexes, 0)?;
+                }
+                Some(Lex::Name(n)) if n.eq_ignore_ascii_case("CDATA") => {
+                    position = 1;
+                    break SchemaContent::CData;
+                }
+                Some(Lex::Name(n)) if n.eq_ignore_ascii_case("RCDATA") => {
+                    position = 1;
+                    break SchemaContent::RCData;
+                }
+                Some(Lex::Name(n)) if n.eq_ignore_ascii_case("EMPTY") => {
+                    position = 1;
+                    break SchemaContent::Empty;
+                }
+                Some(Lex::Name(n)) if n.eq_ignore_ascii_case("ANY") => {
+                    position = 1;
+                    break SchemaContent::Any;
+                }
+                _ => {
+                    break SchemaContent::Model(Particle::Group(
+                        self.model_group(&mut lexes, &mut position)?,
+                    ))
+                }
+            }
+        };
+
+        let mut inclusions = Vec::new();
+        let mut exclusions = Vec::new();
+        loop {
+            match self.peek(&mut lexes, position)? {
+                Some(Lex::Exclusion) => exclusions = self.exception(&mut lexes, &mut position)?,
+                Some(Lex::Inclusion) => inclusions = self.exception(&mut lexes, &mut position)?,
+                Some(_) => return Err(syntax(&lexes[position..])),
+                None => break,
+            }
+        }
+
+        Ok((content, inclusions, exclusions))
+    }
+
+    fn definition(&self, d: AttributeDefinition) -> AttributeDefinition {
+        let lowercase_default = matches!(d.declared_value, DeclaredValue::NameTokenGroup(_));
+        let default_value = match d.default_value {
+            DefaultValue::Value(v) if lowercase_default => DefaultValue::Value(self.name(&v)),
+            DefaultValue::Fixed(v) if lowercase_default => DefaultValue::Fixed(self.name(&v)),
+            v => v,
+        };
+        let declared_value = match d.declared_value {
+            DeclaredValue::NameTokenGroup(g) => {
+                DeclaredValue::NameTokenGroup(g.iter().map(|n| self.name(n)).collect())
+            }
+            v => v,
+        };
+        AttributeDefinition {
+            name: self.name(&d.name),
+            declared_value,
+            default_value,
+        }
+    }
+
+    /// Complete attribute definitions, or None if the text stops part way through one
+    fn definitions(&self, text: &str) -> Option<Vec<AttributeItem>> {
+        match parse_attribute_definitions(text) {
+            Ok(("", definitions)) => Some(
+                definitions
+                    .into_iter()
+                    .map(|d| AttributeItem::Definition(self.definition(d)))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Read the definitions of an attribute list declaration
+    fn attributes(&mut self, chain: &[ChainElement]) -> Result<Vec<AttributeItem>, SchemaError> {
+        let mut items = Vec::new();
+        let mut pending = String::new();
+
+        for element in chain {
+            match element {
+                ChainElement::String(s) => pending.push_str(s),
+                ChainElement::Reference(r) => match self.definitions(&pending) {
+                    Some(definitions) if self.attribute_group(r)? => {
+                        items.extend(definitions);
+                        items.push(AttributeItem::Reference(r.clone()));
+                        pending.clear();
+                    }
+                    _ => {
+                        let entity = self.entity(r)?;
+                        let text = entity
+                            .content
+                            .try_expand(self.entities)
+                            .map_err(SchemaError::UndefinedEntity)?;
+                        pending.push_str(&text);
+                    }
+                },
+            }
+        }
+
+        match self.definitions(&pending) {
+            Some(definitions) => {
+                items.extend(definitions);
+                Ok(items)
+            }
+            None => Err(SchemaError::Syntax {
+                declaration: String::new(),
+                rest: pending.trim().chars().take(60).collect(),
+            }),
+        }
+    }
+
+    /// Whether an entity stands for whole attribute definitions, reading it into a named group if it does
+    fn attribute_group(&mut self, name: &str) -> Result<bool, SchemaError> {
+        if self.attribute_groups.iter().any(|g| g.name == name) {
+            return Ok(true);
+        }
+        if self.not_groups.iter().any(|n| n == name) {
+            return Ok(false);
+        }
+        if self.open.iter().any(|n| n == name) {
+            return Err(SchemaError::RecursiveEntity(name.to_string()));
+        }
+
+        let chain = self.entity(name)?.content.chain.clone();
+        self.open.push(name.to_string());
+        let result = self.attributes(&chain);
+        self.open.pop();
+
+        match result {
+            Ok(attributes) if !attributes.is_empty() => {
+                self.attribute_groups.push(NamedAttributes {
+                    name: name.to_string(),
+                    attributes,
+                });
+                Ok(true)
+            }
+            Ok(_) | Err(SchemaError::Syntax { .. }) => {
+                self.not_groups.push(name.to_string());
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::catalog::Catalog;
+    use crate::content_model::{Connector, Occurrence};
+    use crate::dtd::{read_dtd, DocumentTypeDefinition};
+    use crate::flatten::{flatten_dtd, FlattenOptions};
+    use crate::schema::{
+        AttributeItem, Particle, Schema, SchemaContent, SchemaError, SchemaOptions,
+    };
+    use std::path::{Path, PathBuf};
+
+    /// html.dtd, flattened with its parameter entities kept
+    pub fn html_schema_dtd() -> DocumentTypeDefinition {
+        let s = std::fs::read_to_string("./dtd/html.dtd").unwrap();
+        let (_, dtd) = read_dtd(&s).unwrap();
+        let mut catalog = Catalog::default();
+        catalog.add_public(
+            "ISO 8879-1986//ENTITIES Added Latin 1//EN//HTML",
+            PathBuf::from("./dtd/xhtml-lat1.ent"),
+        );
+        let options = FlattenOptions {
+            keep_parameter_entities: true,
+            ..FlattenOptions::default()
+        };
+        flatten_dtd(&dtd, Path::new("./dtd"), &catalog, &options).unwrap()
+    }
+
+    pub fn html_schema(lowercase_names: bool) -> Schema {
+        Schema::from_dtd(&html_schema_dtd(), &SchemaOptions { lowercase_names }).unwrap()
+    }
+
+    /// xhtml1-strict.dtd, flattened with its parameter entities kept
+    pub fn xhtml_schema_dtd() -> DocumentTypeDefinition {
+        let s = std::fs::read_to_string("./dtd/xhtml1-strict.dtd").unwrap();
+        let (_, dtd) = read_dtd(&s).unwrap();
+        let options = FlattenOptions {
+            keep_parameter_entities: true,
+            ..FlattenOptions::default()
+        };
+        flatten_dtd(&dtd, Path::new("./dtd"), &Catalog::default(), &options).unwrap()
+    }
+
+    pub fn xhtml_schema() -> Schema {
+        Schema::from_dtd(&xhtml_schema_dtd(), &SchemaOptions::default()).unwrap()
+    }
+
+    #[test]
+    pub fn test_html_schema() {
+        let schema = html_schema(false);
+
+        // (%text)* keeps the reference to the group
+        let p = schema.get_element("P").unwrap();
+        assert!(
+            matches!(&p.content, SchemaContent::Model(Particle::Group(g))
+            if g.occurrence == Occurrence::ZeroOrMore && g.particles == vec![Particle::Reference("text".to_string())])
+        );
+        let text = schema.get_group("text").unwrap();
+        assert_eq!(text.connector, Connector::Or);
+        assert!(text
+            .particles
+            .contains(&Particle::Reference("phrase".to_string())));
+
+        // %literal is a keyword, so it is expanded in place
+        assert_eq!(
+            schema.get_element("XMP").unwrap().content,
+            SchemaContent::CData
+        );
+        assert!(schema.get_group("literal").is_none());
+
+        // %head.extra starts with a connector, so it is expanded within %head.content
+        let head = schema.get_element("HEAD").unwrap();
+        assert!(schema.get_group("head.extra").is_none());
+        assert_eq!(
+            schema.get_group("head.content").unwrap().connector,
+            Connector::And
+        );
+        assert_eq!(
+            schema.get_particle_names(match &head.content {
+                SchemaContent::Model(p) => p,
+                _ => panic!(),
+            }),
+            vec!["TITLE", "ISINDEX", "BASE", "NEXTID"]
+        );
+        assert_eq!(head.inclusions, vec!["META", "LINK"]);
+
+        // Exclusions through a reference are listed by element
+        let dir = schema.get_element("DIR").unwrap();
+        assert!(dir.exclusions.contains(&"BLOCKQUOTE".to_string()));
+
+        // Partial definitions are expanded, whole ones are kept as groups
+        let a = schema.get_element("A").unwrap();
+        assert!(a
+            .attributes
+            .contains(&AttributeItem::Reference("linkExtraAttributes".to_string())));
+        assert!(a
+            .attributes
+            .iter()
+            .any(|i| matches!(i, AttributeItem::Definition(d) if d.name == "SDAPREF")));
+        assert!(schema.get_attribute_group("SDAPREF").is_none());
+
+        assert_eq!(
+            schema
+                .get_roots()
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["HTML"]
+        );
+    }
+
+    #[test]
+    pub fn test_xhtml_schema() {
+        let schema = xhtml_schema();
+        assert_eq!(schema.elements.len(), 77);
+        assert!(schema.get_attribute_group("attrs").is_some());
+        assert_eq!(
+            schema.get_attribute_group("attrs").unwrap().attributes,
+            vec![
+                AttributeItem::Reference("coreattrs".to_string()),
+                AttributeItem::Reference("i18n".to_string()),
+                AttributeItem::Reference("events".to_string()),
+            ]
+        );
+        assert!(schema.get_group("Inline").is_some());
+        assert_eq!(
+            schema
+                .get_roots()
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["html"]
+        );
+    }
+
+    #[test]
+    pub fn test_not_standalone() {
+        let (_, dtd) = read_dtd("<!ENTITY % x SYSTEM \"x.dtd\">%x;").unwrap();
+        assert_eq!(
+            Schema::from_dtd(&dtd, &SchemaOptions::default()).unwrap_err(),
+            SchemaError::NotStandalone("%x;".to_string())
+        );
+    }
+}
@@ -0,0 +1,105 @@
+/// The parts of an SGML declaration that change how documents are parsed, See ISO(13)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SgmlDeclaration {
+    /// Fold element, attribute and token names to upper case, See ISO(13.4.5)
+    pub namecase_general: bool,
+    /// Fold entity names to upper case
+    pub namecase_entity: bool,
+}
+
+/// The reference concrete syntax
+impl Default for SgmlDeclaration {
+    fn default() -> Self {
+        Self {
+            namecase_general: true,
+            namecase_entity: false,
+        }
+    }
+}
+
+/// The length of an SGML declaration at the start of `i`, if there is one
+pub fn sgml_declaration_len(i: &str) -> Option<usize> {
+    let prefix = i.get(..6)?;
+    if !prefix.eq_ignore_ascii_case("<!SGML") {
+        return None;
+    }
+    let mut rest = &i[6..];
+    loop {
+        let n = rest.find(['>', '"', '\'', '-'])?;
+        let c = rest[n..].chars().next().unwrap();
+        let skip = match c {
+            '>' => return Some(i.len() - rest.len() + n + 1),
+            '-' if rest[n..].starts_with("--") => n + 2 + rest[n + 2..].find("--")? + 2,
+            '-' => n + 1,
+            quote => n + 1 + rest[n + 1..].find(quote)? + 1,
+        };
+        rest = &rest[skip..];
+    }
+}
+
+/// Read the SGML declaration at the start of `i`, ignoring parameters this parser doesn't use
+pub fn read_sgml_declaration(i: &str) -> Option<SgmlDeclaration> {
+    let i = i.trim_start();
+    let len = sgml_declaration_len(i)?;
+
+    // Literals and comments can't hold the keywords that matter here
+    let mut words = Vec::new();
+    let mut rest = &i[6..len - 1];
+    while let Some(n) = rest.find(|c: char| !c.is_whitespace()) {
+        rest = &rest[n..];
+        let skip = if let Some(comment) = rest.strip_prefix("--") {
+            comment.find("--").map_or(rest.len(), |n| n + 4)
+        } else if rest.starts_with(['"', '\'']) {
+            let quote = rest.chars().next().unwrap();
+            rest[1..].find(quote).map_or(rest.len(), |n| n + 2)
+        } else {
+            let n = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            words.push(rest[..n].to_uppercase());
+            n
+        };
+        rest = &rest[skip..];
+    }
+
+    let mut declaration = SgmlDeclaration::default();
+    if let Some(n) = words.iter().position(|w| w == "NAMECASE") {
+        let value = |keyword: &str| {
+            words[n + 1..]
+                .iter()
+                .take(4)
+                .skip_while(|w| *w != keyword)
+                .nth(1)
+                .map(|w| w == "YES")
+        };
+        declaration.namecase_general = value("GENERAL").unwrap_or(true);
+        declaration.namecase_entity = value("ENTITY").unwrap_or(false);
+    }
+    Some(declaration)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::sgml_declaration::{read_sgml_declaration, SgmlDeclaration};
+
+    #[test]
+    pub fn test_read_namecase() {
+        let xml = r#"<!SGML "ISO 8879:1986 (WWW)"
+     -- SGML declaration for XML --
+     SYNTAX
+         NAMING
+             LCNMSTRT ""
+             NAMECASE GENERAL NO ENTITY NO
+     FEATURES MINIMIZE OMITTAG NO>"#;
+        assert_eq!(
+            read_sgml_declaration(xml),
+            Some(SgmlDeclaration {
+                namecase_general: false,
+                namecase_entity: false
+            })
+        );
+        assert_eq!(
+            read_sgml_declaration("<!SGML 'x' -- NAMECASE GENERAL NO -- SYNTAX PUBLIC 'y'>"),
+            Some(SgmlDeclaration::default())
+        );
+        assert_eq!(read_sgml_declaration("<!DOCTYPE x>"), None);
+    }
+}
@@ -0,0 +1,394 @@
+use crate::dtd::DocumentTypeDefinition;
+use crate::entity::EntityType;
+use crate::instance::{is_prolog_complete, Attribute, DocumentError, Event, Parser};
+use crate::marked_section::MarkedSectionStatus;
+use crate::sgml_declaration::SgmlDeclaration;
+use std::io::{self, Read};
+
+/// How much input `parse_reader` reads at a time
+const CHUNK_SIZE: usize = 8192;
+
+/// Receives the content of a document as it is parsed, See `PushParser`.
+/// Every method does nothing unless it is implemented
+#[allow(unused_variables)]
+pub trait SgmlHandler {
+    /// The start of an element, `omitted` when its start tag was inferred. Only the attributes
+    /// that were specified are given
+    fn start_element(&mut self, name: &str, attributes: &[Attribute], omitted: bool) {}
+
+    /// The end of an element, `omitted` when its end tag was inferred
+    fn end_element(&mut self, name: &str, omitted: bool) {}
+
+    /// Character data, which may come in several pieces
+    fn characters(&mut self, text: &str) {}
+
+    /// A reference to a specific character data entity, with its text resolved to Unicode when
+    /// it names a known character, See ISO(10.5.3)
+    fn sdata(&mut self, name: &str, text: &str) {}
+
+    fn processing_instruction(&mut self, text: &str) {}
+
+    fn comment(&mut self, text: &str) {}
+
+    fn start_marked_section(&mut self, status: MarkedSectionStatus) {}
+
+    fn end_marked_section(&mut self) {}
+
+    /// The start of the replacement text of an entity, which is then parsed as content
+    fn start_entity(&mut self, name: &str) {}
+
+    fn end_entity(&mut self, name: &str) {}
+
+    /// An error the parser recovered from
+    fn error(&mut self, error: &DocumentError) {}
+}
+
+/// Parses a document pushed to it in pieces, calling a handler as it goes. Only the input
+/// that hasn't been parsed and the open elements are kept, so memory use doesn't grow with
+/// the document. The prolog is the exception, it is kept until it has been read whole so the
+/// internal subset can be added to the DTD
+pub struct PushParser<'d, H: SgmlHandler> {
+    dtd: &'d DocumentTypeDefinition,
+    declaration: Option<SgmlDeclaration>,
+    prolog: String,
+    parser: Option<Parser<'static>>,
+    handler: H,
+}
+
+impl<'d, H: SgmlHandler> PushParser<'d, H> {
+    pub fn new(dtd: &'d DocumentTypeDefinition, handler: H) -> Self {
+        Self::with_declaration(dtd, None, handler)
+    }
+
+    /// A parser following an SGML declaration, which takes the place of any the document has
+    pub fn with_declaration(
+        dtd: &'d DocumentTypeDefinition,
+        declaration: Option<&SgmlDeclaration>,
+        handler: H,
+    ) -> Self {
+        PushParser {
+            dtd,
+            declaration: declaration.cloned(),
+            prolog: String::new(),
+            parser: None,
+            handler,
+        }
+    }
+
+    pub fn get_handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn get_handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// How much of the input pushed so far is held because it hasn't been parsed yet
+    pub fn get_buffered_len(&self) -> usize {
+        match &self.parser {
+            Some(parser) => parser.get_buffered_len(),
+            None => self.prolog.len(),
+        }
+    }
+
+    /// Parse the next piece of the document, as far as it can be without knowing what follows
+    pub fn push(&mut self, text: &str) {
+        match &mut self.parser {
+            Some(parser) => parser.push_input(text),
+            None => {
+                self.prolog.push_str(text);
+                if !is_prolog_complete(&self.prolog) {
+                    return;
+                }
+                self.start();
+            }
+        }
+        self.dispatch();
+    }
+
+    /// Parse the rest of the document, ending any elements left open, and give back the handler
+    pub fn finish(mut self) -> H {
+        if self.parser.is_none() {
+            self.start();
+        }
+        if let Some(parser) = &mut self.parser {
+            parser.end_input();
+        }
+        self.dispatch();
+        self.handler
+    }
+
+    fn start(&mut self) {
+        let prolog = std::mem::take(&mut self.prolog);
+        self.parser = Some(Parser::streaming(
+            prolog,
+            self.dtd,
+            self.declaration.as_ref(),
+        ));
+    }
+
+    fn dispatch(&mut self) {
+        let parser = match &mut self.parser {
+            Some(parser) => parser,
+            None => return,
+        };
+        let handler = &mut self.handler;
+        loop {
+            let event = parser.next();
+            for error in parser.take_errors() {
+                handler.error(&error);
+            }
+            let event = match event {
+                Some(event) => event,
+                None => return,
+            };
+            match event {
+                Event::Start {
+                    name,
+                    attributes,
+                    omitted,
+                } => handler.start_element(&name, &attributes, omitted),
+                Event::End { name, omitted } => handler.end_element(&name, omitted),
+                Event::Text(text) => handler.characters(&text),
+                Event::EntityRef { name, text } => {
+                    let sdata = parser
+                        .get_grammar()
+                        .get_entity(&name)
+                        .is_some_and(|(e, _)| e.entity_type == EntityType::SData);
+                    if sdata {
+                        handler.sdata(&name, &text);
+                    } else {
+                        handler.start_entity(&name);
+                        handler.characters(&text);
+                        handler.end_entity(&name);
+                    }
+                }
+                Event::ProcessingInstruction(pi) => handler.processing_instruction(&pi),
+                Event::Comment(c) => handler.comment(&c),
+                Event::MarkedSectionStart(status) => handler.start_marked_section(status),
+                Event::MarkedSectionEnd => handler.end_marked_section(),
+                Event::EntityStart(name) => handler.start_entity(&name),
+                Event::EntityEnd(name) => handler.end_entity(&name),
+            }
+        }
+    }
+}
+
+/// Parse a document from `reader` a piece at a time, giving back the handler at the end
+pub fn parse_reader<R: Read, H: SgmlHandler>(
+    mut reader: R,
+    dtd: &DocumentTypeDefinition,
+    handler: H,
+) -> io::Result<H> {
+    let mut parser = PushParser::new(dtd, handler);
+    let mut buffer = vec![0; CHUNK_SIZE];
+    // The bytes of a character split between reads
+    let mut partial = 0;
+    loop {
+        let read = reader.read(&mut buffer[partial..])?;
+        if read == 0 {
+            if partial > 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the input ends part way through a UTF-8 character",
+                ));
+            }
+            return Ok(parser.finish());
+        }
+        let end = partial + read;
+        let valid = match std::str::from_utf8(&buffer[..end]) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        parser.push(std::str::from_utf8(&buffer[..valid]).unwrap());
+        buffer.copy_within(valid..end, 0);
+        partial = end - valid;
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::dtd::read_dtd;
+    use crate::instance::{Attribute, DocumentError};
+    use crate::marked_section::MarkedSectionStatus;
+    use crate::stream::{parse_reader, PushParser, SgmlHandler};
+
+    const DTD: &str = r#"<!ENTITY eacute SDATA "[eacute]">
+<!ENTITY co CDATA "&#169;">
+<!ENTITY sig "<SIG>The Management</SIG>">
+<!ELEMENT MEMO - - (BODY, SIG?)>
+<!ELEMENT BODY O O (P+)>
+<!ELEMENT P - O (#PCDATA)>
+<!ELEMENT SIG - - (#PCDATA)>
+<!ATTLIST P id ID #IMPLIED>
+"#;
+
+    const DOCUMENT: &str = "<!DOCTYPE memo [ <!ENTITY % draft \"INCLUDE\"> ]>\n\
+        <!-- A memo --><memo>\n<p id=p1>Caf&eacute; &co;\n\
+        <![ %draft; [<p>Draft]]><![ CDATA [<p>]]><![ IGNORE [<p>Final]]>\n\
+        <?page>&sig;&undefined;</memo>\n";
+
+    /// Writes each call on a line of its own
+    #[derive(Default)]
+    pub struct Recorder {
+        pub lines: Vec<String>,
+    }
+
+    impl SgmlHandler for Recorder {
+        fn start_element(&mut self, name: &str, attributes: &[Attribute], omitted: bool) {
+            let attributes = attributes
+                .iter()
+                .map(|a| format!(" {}={}", a.name, a.value))
+                .collect::<String>();
+            let omitted = if omitted { " omitted" } else { "" };
+            self.lines
+                .push(format!("start {}{}{}", name, attributes, omitted));
+        }
+
+        fn end_element(&mut self, name: &str, omitted: bool) {
+            let omitted = if omitted { " omitted" } else { "" };
+            self.lines.push(format!("end {}{}", name, omitted));
+        }
+
+        fn characters(&mut self, text: &str) {
+            // Data may come in any number of pieces
+            match self.lines.last_mut() {
+                Some(last) if last.starts_with("data ") => last.push_str(text),
+                _ => self.lines.push(format!("data {}", text)),
+            }
+        }
+
+        fn sdata(&mut self, name: &str, text: &str) {
+            self.lines.push(format!("sdata {} {}", name, text));
+        }
+
+        fn processing_instruction(&mut self, text: &str) {
+            self.lines.push(format!("pi {}", text));
+        }
+
+        fn comment(&mut self, text: &str) {
+            self.lines.push(format!("comment {}", text));
+        }
+
+        fn start_marked_section(&mut self, status: MarkedSectionStatus) {
+            self.lines.push(format!("section {}", status.keyword()));
+        }
+
+        fn end_marked_section(&mut self) {
+            self.lines.push("end section".to_string());
+        }
+
+        fn start_entity(&mut self, name: &str) {
+            self.lines.push(format!("entity {}", name));
+        }
+
+        fn end_entity(&mut self, name: &str) {
+            self.lines.push(format!("end entity {}", name));
+        }
+
+        fn error(&mut self, error: &DocumentError) {
+            self.lines.push(format!("error {}", error));
+        }
+    }
+
+    fn push_in_pieces(document: &str, size: usize) -> Vec<String> {
+        let (_, dtd) = read_dtd(DTD).unwrap();
+        let mut parser = PushParser::new(&dtd, Recorder::default());
+        let chars = document.chars().collect::<Vec<_>>();
+        for piece in chars.chunks(size) {
+            parser.push(&piece.iter().collect::<String>());
+        }
+        parser.finish().lines
+    }
+
+    #[test]
+    pub fn test_push_parser() {
+        let lines = push_in_pieces(DOCUMENT, DOCUMENT.len());
+        assert_eq!(
+            lines,
+            vec![
+                "comment  A memo ",
+                "start MEMO",
+                "start BODY omitted",
+                "start P id=P1",
+                "data Caf",
+                "sdata eacute é",
+                "data  ",
+                "entity co",
+                "data ©",
+                "end entity co",
+                "section INCLUDE",
+                "end P omitted",
+                "start P",
+                "data Draft",
+                "end section",
+                "section CDATA",
+                "data <p>",
+                "end section",
+                "section IGNORE",
+                "end section",
+                "pi page",
+                "entity sig",
+                "end P omitted",
+                "end BODY omitted",
+                "start SIG",
+                "data The Management",
+                "end SIG",
+                "end entity sig",
+                "error line 5, column 24: entity &undefined; is not declared",
+                "end MEMO",
+            ]
+        );
+        // However the input is split, the same calls are made
+        for size in 1..12 {
+            assert_eq!(push_in_pieces(DOCUMENT, size), lines, "pieces of {}", size);
+        }
+    }
+
+    #[test]
+    pub fn test_constant_memory() {
+        let (_, dtd) = read_dtd(DTD).unwrap();
+        let mut parser = PushParser::new(&dtd, Recorder::default());
+        parser.push("<!DOCTYPE memo><memo>");
+        let mut most = 0;
+        for n in 0..10000 {
+            parser.push(&format!("<p>Paragraph {}\n", n));
+            parser.get_handler_mut().lines.clear();
+            most = most.max(parser.get_buffered_len());
+        }
+        assert!(most < 32, "{} bytes held", most);
+        parser.push("</memo>");
+        assert_eq!(parser.finish().lines.last().unwrap(), "end MEMO");
+    }
+
+    #[test]
+    pub fn test_parse_reader() {
+        let (_, dtd) = read_dtd(DTD).unwrap();
+        // Split part way through the characters of a multibyte character by a reader that
+        // gives a byte at a time
+        struct Bytes<'a>(&'a [u8]);
+        impl std::io::Read for Bytes<'_> {
+            fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+                match self.0.split_first() {
+                    Some((b, rest)) if !buffer.is_empty() => {
+                        buffer[0] = *b;
+                        self.0 = rest;
+                        Ok(1)
+                    }
+                    _ => Ok(0),
+                }
+            }
+        }
+        let handler = parse_reader(
+            Bytes("<!DOCTYPE memo><memo><p>Été".as_bytes()),
+            &dtd,
+            Recorder::default(),
+        )
+        .unwrap();
+        assert_eq!(handler.lines[3], "data Été");
+
+        let error = parse_reader(Bytes(b"<memo>\xff"), &dtd, Recorder::default());
+        assert!(error.is_err());
+    }
+}
@@ -1,15 +1,19 @@
-use crate::entity::{parse_parameter_reference, Entity};
+use crate::borrowed::{ChainStr, TemplateStr};
+use crate::dtd::DocumentTypeDefinition;
+use crate::entity::{parse_borrowed_parameter_reference, Entity};
 use nom::bytes::complete::take;
 use nom::error::ErrorKind;
 use nom::IResult;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ChainElement {
     String(String),
     Reference(String),
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TemplateString {
     pub chain: Vec<ChainElement>,
 }
@@ -21,75 +25,124 @@ fn take_char(i: &str) -> IResult<&str, char> {
 
 //TODO: could this be impled as a state machine
 pub fn parse_string(i: &str, terminal: char, quoting: bool) -> IResult<&str, TemplateString> {
+    let (i, s) = parse_borrowed_string(i, terminal, quoting)?;
+    Ok((i, s.into_owned()))
+}
+
+/// Like `parse_string`, but the text and references are borrowed from the input
+pub fn parse_borrowed_string(
+    i: &str,
+    terminal: char,
+    quoting: bool,
+) -> IResult<&str, TemplateStr<'_>> {
     if quoting {
-        assert_ne!(terminal, '\"', "terminal must differ from quotation");
+        assert_ne!(terminal, '"', "terminal must differ from quotation");
     }
 
     let mut i = i;
-    let mut out = String::new();
+    // The text since the last reference, as a suffix of the input
+    let mut start = i;
     let mut quoted = false;
 
     let mut chain = Vec::new();
 
     loop {
         // Try to take a parameter reference first
-        if let Ok((j, param)) = parse_parameter_reference(i) {
-            if out.len() > 0 {
-                chain.push(ChainElement::String(out));
-                out = String::new();
+        if let Ok((j, param)) = parse_borrowed_parameter_reference(i) {
+            let out = &start[..start.len() - i.len()];
+            if !out.is_empty() {
+                chain.push(ChainStr::String(out));
             }
             //TODO: keep obj
-            chain.push(ChainElement::Reference(param.name));
+            chain.push(ChainStr::Reference(param.name));
             i = j;
+            start = i;
             continue;
         }
 
         if let Ok((j, c)) = take_char(i) {
-            i = j;
-
             if c == '"' && quoting {
                 quoted = !quoted;
             }
 
             if c == terminal && !quoted {
-                break;
+                let out = &start[..start.len() - i.len()];
+                if !out.is_empty() {
+                    chain.push(ChainStr::String(out));
+                }
+                return Ok((j, TemplateStr { chain }));
             }
 
-            out.push(c);
+            i = j;
         } else {
             break;
         }
     }
 
-    if out.len() > 0 {
-        chain.push(ChainElement::String(out));
+    if !start.is_empty() {
+        chain.push(ChainStr::String(start));
     }
 
-    Ok((i, TemplateString { chain }))
+    Ok((i, TemplateStr { chain }))
 }
 
 impl TemplateString {
     /// Expand a template string to a real string, this will recursively resolve all entity references until a concrete value is found
     /// NOTE: this should be avoided at all costs and delayed as late as possible when needed, this will trigger entity expansion exploits: e.g. "Billion laughs"
     pub fn expand(&self, entities: &[Entity]) -> String {
+        self.try_expand(entities)
+            .unwrap_or_else(|r| panic!("Unable to find {}", r))
+    }
+
+    /// The text of this string with any references written back out as `%name;`
+    pub fn chain_text(&self) -> String {
         self.chain
             .iter()
-            .cloned()
             .map(|c| match c {
-                ChainElement::String(s) => s,
+                ChainElement::String(s) => s.clone(),
+                ChainElement::Reference(r) => format!("%{};", r),
+            })
+            .collect()
+    }
+
+    /// Like `expand`, but returns the name of the first reference that could not be resolved instead of panicking
+    /// References to external entities and references that recurse into themselves cannot be resolved
+    pub fn try_expand(&self, entities: &[Entity]) -> Result<String, String> {
+        let lookup = |name: &str| entities.iter().find(|e| e.parameter && e.name == name);
+        self.expand_with(&lookup, &mut Vec::new())
+    }
+
+    /// Like `expand`, looking references up in the index of a DTD rather than searching its entities
+    pub fn expand_in(&self, dtd: &DocumentTypeDefinition) -> String {
+        self.try_expand_in(dtd)
+            .unwrap_or_else(|r| panic!("Unable to find {}", r))
+    }
+
+    /// Like `try_expand`, looking references up in the index of a DTD rather than searching its entities
+    pub fn try_expand_in(&self, dtd: &DocumentTypeDefinition) -> Result<String, String> {
+        self.expand_with(&|name| dtd.get_parameter_entity(name), &mut Vec::new())
+    }
+
+    fn expand_with<'e>(
+        &self,
+        lookup: &dyn Fn(&str) -> Option<&'e Entity>,
+        open: &mut Vec<String>,
+    ) -> Result<String, String> {
+        let mut out = String::new();
+        for c in &self.chain {
+            match c {
+                ChainElement::String(s) => out.push_str(s),
                 ChainElement::Reference(r) => {
-                    //TODO: this is the recursion
-                    entities
-                        .iter()
-                        .find(|e| e.name == r)
-                        .expect(&format!("Unable to find {}", r))
-                        .content
-                        .expand(entities)
-                    // &"".to_string()
+                    let entity = lookup(r)
+                        .filter(|e| !e.external && !e.public && !open.contains(r))
+                        .ok_or_else(|| r.clone())?;
+                    open.push(r.clone());
+                    out.push_str(&entity.content.expand_with(lookup, open)?);
+                    open.pop();
                 }
-            })
-            .collect::<Vec<_>>()
-            .join("")
+            }
+        }
+        Ok(out)
     }
 }
 
@@ -103,7 +156,7 @@ impl From<&str> for TemplateString {
 
 #[cfg(test)]
 pub mod test {
-    use crate::entity::Entity;
+    use crate::entity::{Entity, EntityType};
     use crate::template_strings::{parse_string, ChainElement, TemplateString};
 
     #[test]
@@ -158,6 +211,9 @@ pub mod test {
             parameter: true,
             external: false,
             public: false,
+            entity_type: EntityType::Literal,
+            system_id: None,
+            comment: None,
         };
 
         let expanded_string = x.expand(&vec![e]);
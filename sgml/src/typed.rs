@@ -0,0 +1,639 @@
+//! Reading and writing the element types `dtd_gen` generates. The generated code implements
+//! `TypedElement` for each element and calls into `Events` and `Writer`, which hold everything that
+//! doesn't depend on the DTD
+use crate::dtd::DocumentTypeDefinition;
+use crate::instance::{read_doctype, Attribute, Event, Parser};
+use std::fmt;
+
+/// What the writer needs to know about an element to leave out the tags its declaration allows to
+/// be omitted
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ElementInfo {
+    /// The name as declared
+    pub name: &'static str,
+    pub start_optional: bool,
+    pub end_optional: bool,
+    /// Declared EMPTY, so it has no end tag
+    pub empty: bool,
+    /// Character data is allowed in the content, as in mixed content, CDATA and RCDATA
+    pub text: bool,
+    /// Declared CDATA, so the content is written without references
+    pub cdata: bool,
+    /// The elements that can begin the content
+    pub first: &'static [&'static str],
+    /// The elements the content model names, with its inclusions
+    pub contains: &'static [&'static str],
+    pub inclusions: &'static [&'static str],
+}
+
+/// An element type that can be read from the events of a parsed document and written back out
+pub trait TypedElement: Sized {
+    const INFO: ElementInfo;
+
+    /// Read the content of the element, whose start tag had `attributes`, up to its end
+    fn read(attributes: &[Attribute], events: &mut Events) -> Result<Self, BindError>;
+
+    /// Write the element, `required` when the content model requires it where it is written so
+    /// its start tag may be omitted
+    fn write(&self, writer: &mut Writer, required: bool);
+}
+
+/// A group in a content model that is given a type of its own
+pub trait ContentGroup: Sized {
+    /// The elements that can begin the group
+    const FIRST: &'static [&'static str];
+
+    fn read(parent: &str, events: &mut Events) -> Result<Self, BindError>;
+
+    fn write(&self, writer: &mut Writer);
+}
+
+/// An attribute value type, which is parsed from the normalized value the parser gives
+pub trait AttributeValue: Sized {
+    fn parse(value: &str) -> Option<Self>;
+
+    fn write(&self) -> String;
+}
+
+impl AttributeValue for String {
+    fn parse(value: &str) -> Option<Self> {
+        Some(value.to_string())
+    }
+
+    fn write(&self) -> String {
+        self.clone()
+    }
+}
+
+impl AttributeValue for u32 {
+    fn parse(value: &str) -> Option<Self> {
+        value.trim().parse().ok()
+    }
+
+    fn write(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Lists of tokens are separated by spaces
+impl<T: AttributeValue> AttributeValue for Vec<T> {
+    fn parse(value: &str) -> Option<Self> {
+        value.split_whitespace().map(T::parse).collect()
+    }
+
+    fn write(&self) -> String {
+        self.iter().map(|v| v.write()).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Read an attribute value, failing if it isn't valid for its type
+pub fn parse_attribute<T: AttributeValue>(
+    element: &str,
+    attribute: &Attribute,
+) -> Result<T, BindError> {
+    T::parse(&attribute.value).ok_or_else(|| BindError::InvalidAttribute {
+        element: element.to_string(),
+        attribute: attribute.name.clone(),
+        value: attribute.value.clone(),
+    })
+}
+
+/// A document that doesn't fit the types it is read into
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BindError {
+    /// An element that isn't allowed where it was found
+    UnexpectedElement { parent: String, found: String },
+    /// Character data in element content
+    UnexpectedText { parent: String, text: String },
+    /// The content of an element ended without an element it requires
+    MissingElement { parent: String, expected: String },
+    InvalidAttribute {
+        element: String,
+        attribute: String,
+        value: String,
+    },
+    /// The events ended inside an element
+    UnexpectedEnd,
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindError::UnexpectedElement { parent, found } => {
+                write!(f, "element {} is not expected in {}", found, parent)
+            }
+            BindError::UnexpectedText { parent, text } => {
+                write!(f, "character data {:?} is not expected in {}", text, parent)
+            }
+            BindError::MissingElement { parent, expected } => {
+                write!(f, "{} is missing {}", parent, expected)
+            }
+            BindError::InvalidAttribute {
+                element,
+                attribute,
+                value,
+            } => write!(
+                f,
+                "{:?} is not a valid value of attribute {} of {}",
+                value, attribute, element
+            ),
+            BindError::UnexpectedEnd => write!(f, "the document ends inside an element"),
+        }
+    }
+}
+
+impl std::error::Error for BindError {}
+
+/// The next part of the content of an element
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Content {
+    /// The start of an element, which is read next
+    Start(String),
+    /// Character data, with entity references and adjacent text joined
+    Text(String),
+    /// The end of the element
+    End,
+}
+
+/// The events of a document with markup that isn't content, such as comments and processing
+/// instructions, left out
+pub struct Events<'a> {
+    events: Box<dyn Iterator<Item = Event<'a>> + 'a>,
+    peeked: Option<Event<'a>>,
+}
+
+impl<'a> Events<'a> {
+    pub fn new(events: impl Iterator<Item = Event<'a>> + 'a) -> Self {
+        Events {
+            events: Box::new(events),
+            peeked: None,
+        }
+    }
+
+    fn next_event(&mut self) -> Option<Event<'a>> {
+        loop {
+            match self.peeked.take().or_else(|| self.events.next())? {
+                Event::Comment(_)
+                | Event::ProcessingInstruction(_)
+                | Event::MarkedSectionStart(_)
+                | Event::MarkedSectionEnd
+                | Event::EntityStart(_)
+                | Event::EntityEnd(_) => {}
+                event => return Some(event),
+            }
+        }
+    }
+
+    /// The next start tag, text or end tag. A start or end is left to be read
+    pub fn next_content(&mut self) -> Result<Content, BindError> {
+        let mut text = String::new();
+        loop {
+            let event = match self.next_event() {
+                Some(Event::Text(t)) => {
+                    text.push_str(&t);
+                    continue;
+                }
+                Some(Event::EntityRef { text: t, .. }) => {
+                    text.push_str(&t);
+                    continue;
+                }
+                Some(event) => event,
+                None if !text.is_empty() => return Ok(Content::Text(text)),
+                None => return Err(BindError::UnexpectedEnd),
+            };
+            let content = match &event {
+                _ if !text.is_empty() => Content::Text(text),
+                Event::Start { name, .. } => Content::Start(name.clone()),
+                _ => Content::End,
+            };
+            self.peeked = Some(event);
+            return Ok(content);
+        }
+    }
+
+    /// The name of the next element in the element content of `parent`, None at its end.
+    /// White space between elements is skipped
+    pub fn peek_start(&mut self, parent: &str) -> Result<Option<String>, BindError> {
+        loop {
+            match self.next_content()? {
+                Content::Start(name) => return Ok(Some(name)),
+                Content::End => return Ok(None),
+                Content::Text(text) => check_text(parent, &text)?,
+            }
+        }
+    }
+
+    /// Whether the next element in `parent` is one of `names`
+    pub fn at(&mut self, parent: &str, names: &[&str]) -> Result<bool, BindError> {
+        Ok(self
+            .peek_start(parent)?
+            .is_some_and(|n| names.contains(&n.as_str())))
+    }
+
+    /// Read the next element in `parent`, which must be a `T`
+    pub fn read<T: TypedElement>(&mut self, parent: &str) -> Result<T, BindError> {
+        match self.peek_start(parent)? {
+            Some(name) if name == T::INFO.name => {}
+            Some(found) => {
+                return Err(BindError::UnexpectedElement {
+                    parent: parent.to_string(),
+                    found,
+                })
+            }
+            None => return Err(self.missing(parent, &[T::INFO.name])),
+        }
+        let attributes = match self.next_event() {
+            Some(Event::Start { attributes, .. }) => attributes,
+            _ => return Err(BindError::UnexpectedEnd),
+        };
+        let element = T::read(&attributes, self)?;
+        self.end(T::INFO.name)?;
+        Ok(element)
+    }
+
+    /// Read the text in the rest of a CDATA or RCDATA element
+    pub fn read_text(&mut self, parent: &str) -> Result<String, BindError> {
+        let mut text = String::new();
+        loop {
+            match self.next_content()? {
+                Content::Text(t) => text.push_str(&t),
+                Content::End => return Ok(text),
+                Content::Start(found) => {
+                    return Err(BindError::UnexpectedElement {
+                        parent: parent.to_string(),
+                        found,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Read the end of `element`, which must come next
+    pub fn end(&mut self, element: &str) -> Result<(), BindError> {
+        match self.peek_start(element)? {
+            None => {
+                self.next_event();
+                Ok(())
+            }
+            Some(found) => Err(BindError::UnexpectedElement {
+                parent: element.to_string(),
+                found,
+            }),
+        }
+    }
+
+    /// The error for content of `parent` that isn't one of the `expected` elements
+    pub fn missing(&mut self, parent: &str, expected: &[&str]) -> BindError {
+        match self.peek_start(parent) {
+            Ok(Some(found)) => BindError::UnexpectedElement {
+                parent: parent.to_string(),
+                found,
+            },
+            Ok(None) => BindError::MissingElement {
+                parent: parent.to_string(),
+                expected: expected.join(" or "),
+            },
+            Err(e) => e,
+        }
+    }
+}
+
+/// Only white space may come between the elements of element content
+pub fn check_text(parent: &str, text: &str) -> Result<(), BindError> {
+    if text.trim().is_empty() {
+        Ok(())
+    } else {
+        Err(BindError::UnexpectedText {
+            parent: parent.to_string(),
+            text: text.to_string(),
+        })
+    }
+}
+
+/// Whether the start tag of an open element has been written
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum StartTag {
+    Written,
+    Omitted,
+    /// Left out if what comes next allows it, the element has no attributes to write
+    Pending,
+}
+
+struct OpenElement {
+    info: &'static ElementInfo,
+    start: StartTag,
+    /// The content has been written, but the end tag is held back until what follows shows
+    /// whether it can be omitted
+    ended: bool,
+}
+
+/// Writes elements as SGML, leaving out the tags the parser would infer, or as XML
+///
+/// A tag is only omitted when it is certain to be inferred, a start tag when its element is
+/// required and the first content is an element that can begin it, and an end tag when what
+/// follows is not allowed in the element
+pub struct Writer {
+    out: String,
+    xml: bool,
+    open: Vec<OpenElement>,
+}
+
+impl Writer {
+    pub fn new(xml: bool) -> Self {
+        Writer {
+            out: String::new(),
+            xml,
+            open: Vec::new(),
+        }
+    }
+
+    /// Whether the output is XML, which has every tag and attribute
+    pub fn is_xml(&self) -> bool {
+        self.xml
+    }
+
+    /// Write the start of an element with the attributes that have to be specified
+    pub fn start(
+        &mut self,
+        info: &'static ElementInfo,
+        attributes: &[(&str, String)],
+        required: bool,
+    ) {
+        self.before(Some(info.name));
+        let start = if !self.xml && info.start_optional && attributes.is_empty() && required {
+            StartTag::Pending
+        } else {
+            self.start_tag(info.name, attributes, false);
+            StartTag::Written
+        };
+        self.open.push(OpenElement {
+            info,
+            start,
+            ended: false,
+        });
+    }
+
+    /// Write an EMPTY element, which has no content or end tag
+    pub fn empty(&mut self, info: &'static ElementInfo, attributes: &[(&str, String)]) {
+        self.before(Some(info.name));
+        self.start_tag(info.name, attributes, self.xml);
+    }
+
+    pub fn text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.before(None);
+        let cdata = self.open.last().is_some_and(|o| o.info.cdata);
+        if self.xml {
+            self.out.push_str(
+                &text
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;"),
+            );
+        } else if cdata {
+            self.out.push_str(text);
+        } else {
+            self.out
+                .push_str(&text.replace('&', "&#38;").replace('<', "&#60;"));
+        }
+    }
+
+    /// Write the end of the element started last
+    pub fn end(&mut self) {
+        let index = match self.open.iter().rposition(|o| !o.ended) {
+            Some(index) => index,
+            None => return,
+        };
+        // An element with an omitted start tag and no content would vanish
+        if self.open[index].start == StartTag::Pending {
+            self.start_tag(self.open[index].info.name, &[], false);
+            self.open[index].start = StartTag::Written;
+        }
+        let info = self.open[index].info;
+        if !self.xml && info.end_optional {
+            self.open[index].ended = true;
+        } else {
+            self.out.push_str(&format!("</{}>", info.name));
+            self.open.truncate(index);
+        }
+    }
+
+    /// The document, with any end tags still held back omitted
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    /// Settle the tags held back now that the next element, or text when `next` is None, is known
+    fn before(&mut self, next: Option<&str>) {
+        if let Some(first) = self.open.iter().position(|o| o.ended) {
+            // The outermost ended element that would take what comes next needs its end tag
+            let allows = |i: usize| match next {
+                Some(name) => {
+                    self.open[i].info.contains.contains(&name)
+                        || self.open[..=i]
+                            .iter()
+                            .any(|o| o.info.inclusions.contains(&name))
+                }
+                None => self.open[i].info.text,
+            };
+            if let Some(i) = (first..self.open.len()).find(|&i| allows(i)) {
+                self.out.push_str(&format!("</{}>", self.open[i].info.name));
+            }
+            self.open.truncate(first);
+        }
+
+        if let Some(parent) = self.open.last_mut() {
+            if parent.start == StartTag::Pending {
+                if next.is_some_and(|n| parent.info.first.contains(&n)) {
+                    parent.start = StartTag::Omitted;
+                } else {
+                    parent.start = StartTag::Written;
+                    let name = parent.info.name;
+                    self.start_tag(name, &[], false);
+                }
+            }
+        }
+    }
+
+    fn start_tag(&mut self, name: &str, attributes: &[(&str, String)], close: bool) {
+        self.out.push('<');
+        self.out.push_str(name);
+        for (attribute, value) in attributes {
+            self.out.push(' ');
+            self.out.push_str(attribute);
+            self.out.push('=');
+            self.out.push_str(&self.quote(value));
+        }
+        self.out.push_str(if close { "/>" } else { ">" });
+    }
+
+    fn quote(&self, value: &str) -> String {
+        let value = if self.xml {
+            value.replace('&', "&amp;").replace('<', "&lt;")
+        } else {
+            value.replace('&', "&#38;")
+        };
+        if value.contains('"') && !value.contains('\'') && !self.xml {
+            format!("'{}'", value)
+        } else if self.xml {
+            format!("\"{}\"", value.replace('"', "&quot;"))
+        } else {
+            format!("\"{}\"", value.replace('"', "&#34;"))
+        }
+    }
+}
+
+/// Read a `T` from a document, as the document element when the document has no document type
+/// declaration. Errors the parser reports are recovered from as it recovers, only a document whose
+/// structure doesn't fit `T` fails
+pub fn from_sgml<T: TypedElement>(
+    input: &str,
+    dtd: &DocumentTypeDefinition,
+) -> Result<T, BindError> {
+    let input = if read_doctype(input).is_none() && !input.trim_start().starts_with("<!SGML") {
+        format!("<!DOCTYPE {}>{}", T::INFO.name, input)
+    } else {
+        input.to_string()
+    };
+    from_events(Parser::new(&input, dtd))
+}
+
+/// Read a `T` from the events of a document, such as from an `SgmlReader`
+pub fn from_events<'a, T: TypedElement>(
+    events: impl Iterator<Item = Event<'a>> + 'a,
+) -> Result<T, BindError> {
+    Events::new(events).read("the document")
+}
+
+/// Write an element as SGML, with the tags its declaration allows omitted where the parser infers
+/// them again
+pub fn to_sgml<T: TypedElement>(element: &T) -> String {
+    let mut writer = Writer::new(false);
+    element.write(&mut writer, true);
+    writer.finish()
+}
+
+/// Write an element as XML, with every tag and every attribute that has a value
+pub fn to_xml<T: TypedElement>(element: &T) -> String {
+    let mut writer = Writer::new(true);
+    element.write(&mut writer, true);
+    writer.finish()
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::dtd::read_dtd;
+    use crate::reader::{Event, SgmlReader};
+    use crate::typed::{ElementInfo, Writer};
+
+    const DTD: &str = r#"<!ELEMENT MEMO - - (TO, BODY)>
+<!ELEMENT TO - O (#PCDATA)>
+<!ELEMENT BODY O O (P+)>
+<!ELEMENT P - O (#PCDATA|EM)*>
+<!ELEMENT EM - - (#PCDATA)>
+"#;
+
+    const MEMO: ElementInfo = ElementInfo {
+        name: "MEMO",
+        start_optional: false,
+        end_optional: false,
+        empty: false,
+        text: false,
+        cdata: false,
+        first: &["TO"],
+        contains: &["TO", "BODY"],
+        inclusions: &[],
+    };
+    const TO: ElementInfo = ElementInfo {
+        name: "TO",
+        start_optional: false,
+        end_optional: true,
+        empty: false,
+        text: true,
+        cdata: false,
+        first: &[],
+        contains: &[],
+        inclusions: &[],
+    };
+    const BODY: ElementInfo = ElementInfo {
+        name: "BODY",
+        start_optional: true,
+        end_optional: true,
+        empty: false,
+        text: false,
+        cdata: false,
+        first: &["P"],
+        contains: &["P"],
+        inclusions: &[],
+    };
+    const P: ElementInfo = ElementInfo {
+        name: "P",
+        start_optional: false,
+        end_optional: true,
+        empty: false,
+        text: true,
+        cdata: false,
+        first: &["EM"],
+        contains: &["EM"],
+        inclusions: &[],
+    };
+    const EM: ElementInfo = ElementInfo {
+        name: "EM",
+        start_optional: false,
+        end_optional: false,
+        empty: false,
+        text: true,
+        cdata: false,
+        first: &[],
+        contains: &[],
+        inclusions: &[],
+    };
+
+    fn write(writer: &mut Writer) {
+        writer.start(&MEMO, &[], true);
+        writer.start(&TO, &[], true);
+        writer.text("All & <some>");
+        writer.end();
+        writer.start(&BODY, &[], true);
+        writer.start(&P, &[], false);
+        writer.text("One ");
+        writer.start(&EM, &[], false);
+        writer.text("two");
+        writer.end();
+        writer.end();
+        writer.start(&P, &[], false);
+        writer.end();
+        writer.end();
+        writer.end();
+    }
+
+    #[test]
+    pub fn test_minimized_tags() {
+        let mut writer = Writer::new(false);
+        write(&mut writer);
+        let sgml = writer.finish();
+        assert_eq!(
+            sgml,
+            "<MEMO><TO>All &#38; &#60;some><P>One <EM>two</EM><P></MEMO>"
+        );
+
+        let mut writer = Writer::new(true);
+        write(&mut writer);
+        assert_eq!(
+            writer.finish(),
+            "<MEMO><TO>All &amp; &lt;some&gt;</TO><BODY><P>One <EM>two</EM></P><P></P></BODY></MEMO>"
+        );
+
+        // The parser infers the same elements again
+        let (_, dtd) = read_dtd(DTD).unwrap();
+        let names = SgmlReader::new(&sgml, &dtd)
+            .filter_map(|e| match e {
+                Event::Start { name, .. } => Some(name),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["MEMO", "TO", "BODY", "P", "EM", "P"]);
+    }
+}
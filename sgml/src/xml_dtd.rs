@@ -0,0 +1,981 @@
+use crate::att_list::{ATTList, AttributeDefinition, DeclaredValue, DefaultValue};
+use crate::content_model::{
+    Connector, ContentModel, ContentToken, DeclaredContent, ModelGroup, Occurrence,
+};
+use crate::dtd::{read_dtd, DTDElement, DocumentTypeDefinition};
+use crate::element::{Element, ElementName};
+use crate::entity::{Entity, EntityType};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Entity sets used to map SDATA entities to characters
+const CHARACTER_ENTITY_SETS: [&str; 3] = [
+    include_str!("../dtd/xhtml-lat1.ent"),
+    include_str!("../dtd/xhtml-symbol.ent"),
+    include_str!("../dtd/xhtml-special.ent"),
+];
+
+/// Function character references and the characters XML uses for them, See ISO(B.7.3)
+const FUNCTION_CHARACTERS: [(&str, &str); 4] = [
+    ("RE", "&#10;"),
+    ("RS", ""),
+    ("SPACE", "&#32;"),
+    ("TAB", "&#9;"),
+];
+
+#[derive(Debug, Clone)]
+pub struct XmlDtdOptions {
+    /// Lowercase element names, attribute names and name token groups, as XHTML did for HTML
+    pub lowercase_names: bool,
+    pub keep_comments: bool,
+}
+
+impl Default for XmlDtdOptions {
+    fn default() -> Self {
+        Self {
+            lowercase_names: false,
+            keep_comments: true,
+        }
+    }
+}
+
+/// A construct that XML can't express, and what was done with it
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum XmlIssue {
+    /// `&` groups are approximated by a repeatable choice of their members
+    AndConnector { element: String },
+    /// Mixed content is loosened to `(#PCDATA | a | b)*`
+    MixedContent { element: String },
+    /// CDATA and RCDATA declared content is treated as #PCDATA
+    DeclaredContent {
+        element: String,
+        keyword: &'static str,
+    },
+    /// Included elements are allowed in the repeatable choices of the element and its descendants
+    Inclusions { element: String, names: Vec<String> },
+    /// Included elements that were left out, as there is no repeatable choice to allow them in
+    DroppedInclusions { element: String, names: Vec<String> },
+    /// Excluded elements are only removed from the element's own repeatable choices
+    Exclusions { element: String, names: Vec<String> },
+    /// Declared values without an XML equivalent are replaced by the closest name token type
+    DeclaredValue {
+        element: String,
+        attribute: String,
+        keyword: &'static str,
+    },
+    /// #CURRENT and #CONREF defaults become #IMPLIED
+    DefaultValue {
+        element: String,
+        attribute: String,
+        keyword: &'static str,
+    },
+    /// An entity that was left out of the output
+    Entity { name: String, reason: String },
+    /// A declaration whose parameters couldn't be resolved or parsed, it was left out of the output
+    Unparsed { declaration: String },
+    /// Marked sections and parameter references should be flattened before conversion
+    NotStandalone { declaration: String },
+}
+
+impl fmt::Display for XmlIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XmlIssue::AndConnector { element } => write!(
+                f,
+                "{}: `&` groups approximated by a repeatable choice",
+                element
+            ),
+            XmlIssue::MixedContent { element } => write!(
+                f,
+                "{}: mixed content loosened to a repeatable choice with #PCDATA",
+                element
+            ),
+            XmlIssue::DeclaredContent { element, keyword } => {
+                write!(f, "{}: {} content treated as #PCDATA", element, keyword)
+            }
+            XmlIssue::Inclusions { element, names } => write!(
+                f,
+                "{}: inclusions +({}) only allowed where choices repeat",
+                element,
+                names.join("|")
+            ),
+            XmlIssue::DroppedInclusions { element, names } => write!(
+                f,
+                "{}: inclusions +({}) dropped, no choice repeats",
+                element,
+                names.join("|")
+            ),
+            XmlIssue::Exclusions { element, names } => write!(
+                f,
+                "{}: exclusions -({}) not enforced in descendants",
+                element,
+                names.join("|")
+            ),
+            XmlIssue::DeclaredValue {
+                element,
+                attribute,
+                keyword,
+            } => write!(
+                f,
+                "{}: attribute {} declared as {} is a name token in XML",
+                element, attribute, keyword
+            ),
+            XmlIssue::DefaultValue {
+                element,
+                attribute,
+                keyword,
+            } => write!(
+                f,
+                "{}: attribute {} default {} replaced by #IMPLIED",
+                element, attribute, keyword
+            ),
+            XmlIssue::Entity { name, reason } => write!(f, "entity {}: {}", name, reason),
+            XmlIssue::Unparsed { declaration } => {
+                write!(f, "{}: unable to parse declaration", declaration)
+            }
+            XmlIssue::NotStandalone { declaration } => write!(
+                f,
+                "{}: marked sections and parameter references must be flattened first",
+                declaration
+            ),
+        }
+    }
+}
+
+pub struct XmlDtd {
+    pub dtd: DocumentTypeDefinition,
+    pub issues: Vec<XmlIssue>,
+}
+
+/// Convert a standalone SGML DTD, such as the output of `flatten_dtd`, into an XML 1.0 DTD
+/// Print the result with `print_dtd`
+pub fn to_xml_dtd(dtd: &DocumentTypeDefinition, options: &XmlDtdOptions) -> XmlDtd {
+    let mut converter = Converter {
+        options,
+        entities: dtd.get_entities(),
+        issues: Vec::new(),
+        models: HashMap::new(),
+        notes: HashMap::new(),
+    };
+    converter.convert_models(dtd.get_declarations());
+
+    let mut out = Vec::new();
+    for declaration in dtd.get_declarations() {
+        converter.declaration(declaration, &mut out);
+    }
+
+    XmlDtd {
+        dtd: out.into(),
+        issues: converter.issues,
+    }
+}
+
+struct Converter<'a> {
+    options: &'a XmlDtdOptions,
+    entities: &'a [Entity],
+    issues: Vec<XmlIssue>,
+    /// Converted content models, keyed by converted element name
+    models: HashMap<String, ContentModel>,
+    /// Comments documenting approximations, keyed by converted element name
+    notes: HashMap<String, Vec<String>>,
+}
+
+/// A repeatable choice, which accepts its members in any order, so more members can be added safely
+fn is_open_choice(model: &ContentModel) -> bool {
+    matches!(&model.content, DeclaredContent::Group(g)
+        if g.connector == Connector::Or
+            && matches!(g.occurrence, Occurrence::ZeroOrMore | Occurrence::OneOrMore))
+}
+
+fn element_names(name: ElementName) -> Vec<String> {
+    match name {
+        ElementName::Single(s) => vec![s.trim().to_string()],
+        ElementName::Group(g) => g,
+    }
+}
+
+impl<'a> Converter<'a> {
+    fn name(&self, name: &str) -> String {
+        if self.options.lowercase_names {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn note(&mut self, element: &str, note: String) {
+        self.notes
+            .entry(element.to_string())
+            .or_default()
+            .push(note);
+    }
+
+    /// Convert every content model up front, since inclusions reach into other elements
+    fn convert_models(&mut self, declarations: &[DTDElement]) {
+        let mut order = Vec::new();
+        let mut exceptions = Vec::new();
+
+        for declaration in declarations {
+            let element = match declaration {
+                DTDElement::Element(e) => e,
+                _ => continue,
+            };
+            let model = match element.get_content(self.entities) {
+                Some(model) => model,
+                None => continue,
+            };
+
+            for name in element_names(element.get_name(self.entities)) {
+                let name = self.name(&name);
+                let converted = self.convert_model(&name, &model);
+                if !model.inclusions.is_empty() || !model.exclusions.is_empty() {
+                    exceptions.push((
+                        name.clone(),
+                        model
+                            .inclusions
+                            .iter()
+                            .map(|n| self.name(n))
+                            .collect::<Vec<_>>(),
+                        model
+                            .exclusions
+                            .iter()
+                            .map(|n| self.name(n))
+                            .collect::<Vec<_>>(),
+                    ));
+                }
+                order.push(name.clone());
+                self.models.insert(name, converted);
+            }
+        }
+
+        for (element, _, exclusions) in &exceptions {
+            if exclusions.is_empty() {
+                continue;
+            }
+            if let Some(DeclaredContent::Group(g)) =
+                self.models.get_mut(element).map(|m| &mut m.content)
+            {
+                if g.connector == Connector::Or && g.occurrence == Occurrence::ZeroOrMore {
+                    g.tokens.retain(
+                        |t| !matches!(t, ContentToken::Element(n, _) if exclusions.contains(n)),
+                    );
+                }
+            }
+            self.issues.push(XmlIssue::Exclusions {
+                element: element.clone(),
+                names: exclusions.clone(),
+            });
+            self.note(
+                element,
+                format!(
+                    "SGML exclusions -({}) are not enforced in the content of descendants",
+                    exclusions.join(" | ")
+                ),
+            );
+        }
+
+        for (element, inclusions, _) in &exceptions {
+            if inclusions.is_empty() {
+                continue;
+            }
+            // Everything that can appear within the element, including itself
+            let mut reachable = vec![element.clone()];
+            let mut n = 0;
+            while n < reachable.len() {
+                if let Some(model) = self.models.get(&reachable[n]) {
+                    for child in model.get_element_names() {
+                        if !reachable.contains(&child) {
+                            reachable.push(child);
+                        }
+                    }
+                }
+                n += 1;
+            }
+
+            let mut placed = false;
+            for name in order.iter().filter(|n| reachable.contains(n)) {
+                let model = self.models.get_mut(name).unwrap();
+                if !is_open_choice(model) {
+                    continue;
+                }
+                placed = true;
+                if let DeclaredContent::Group(g) = &mut model.content {
+                    for inclusion in inclusions {
+                        if !g.get_element_names().contains(inclusion) {
+                            g.tokens
+                                .push(ContentToken::Element(inclusion.clone(), Occurrence::Once));
+                        }
+                    }
+                }
+            }
+
+            if !placed {
+                self.issues.push(XmlIssue::DroppedInclusions {
+                    element: element.clone(),
+                    names: inclusions.clone(),
+                });
+                self.note(
+                    element,
+                    format!(
+                        "SGML inclusions +({}) are dropped, as neither {} nor its descendants have a repeatable choice",
+                        inclusions.join(" | "),
+                        element
+                    ),
+                );
+                continue;
+            }
+            self.issues.push(XmlIssue::Inclusions {
+                element: element.clone(),
+                names: inclusions.clone(),
+            });
+            self.note(
+                element,
+                format!(
+                    "SGML inclusions +({}) are allowed in the repeatable choices of {} and its descendants",
+                    inclusions.join(" | "),
+                    element
+                ),
+            );
+        }
+    }
+
+    fn convert_model(&mut self, element: &str, model: &ContentModel) -> ContentModel {
+        let content = match &model.content {
+            DeclaredContent::CData | DeclaredContent::RCData => {
+                let keyword = if model.content == DeclaredContent::CData {
+                    "CDATA"
+                } else {
+                    "RCDATA"
+                };
+                self.issues.push(XmlIssue::DeclaredContent {
+                    element: element.to_string(),
+                    keyword,
+                });
+                self.note(
+                    element,
+                    format!("SGML {} content is parsed as #PCDATA", keyword),
+                );
+                DeclaredContent::Group(ModelGroup {
+                    connector: Connector::Sequence,
+                    tokens: vec![ContentToken::PcData],
+                    occurrence: Occurrence::Once,
+                })
+            }
+            DeclaredContent::Empty => DeclaredContent::Empty,
+            DeclaredContent::Any => DeclaredContent::Any,
+            DeclaredContent::Group(g) if g.contains_pcdata() => {
+                DeclaredContent::Group(self.convert_mixed(element, g))
+            }
+            DeclaredContent::Group(g) => DeclaredContent::Group(self.convert_group(element, g)),
+        };
+
+        ContentModel {
+            content,
+            inclusions: Vec::new(),
+            exclusions: Vec::new(),
+        }
+    }
+
+    /// XML only allows mixed content as `(#PCDATA)` or `(#PCDATA | a | b)*`, See XML(3.2.2)
+    fn convert_mixed(&mut self, element: &str, g: &ModelGroup) -> ModelGroup {
+        let names = g.get_element_names();
+        if names.is_empty() {
+            return ModelGroup {
+                connector: Connector::Sequence,
+                tokens: vec![ContentToken::PcData],
+                occurrence: if g.occurrence == Occurrence::ZeroOrMore {
+                    Occurrence::ZeroOrMore
+                } else {
+                    Occurrence::Once
+                },
+            };
+        }
+
+        let expressible = g.connector == Connector::Or
+            && g.occurrence == Occurrence::ZeroOrMore
+            && g.tokens[0] == ContentToken::PcData
+            && g.tokens[1..]
+                .iter()
+                .all(|t| matches!(t, ContentToken::Element(_, Occurrence::Once)))
+            && names.len() == g.tokens.len() - 1;
+        if !expressible {
+            self.issues.push(XmlIssue::MixedContent {
+                element: element.to_string(),
+            });
+        }
+
+        let mut tokens = vec![ContentToken::PcData];
+        tokens.extend(
+            names
+                .iter()
+                .map(|n| ContentToken::Element(self.name(n), Occurrence::Once)),
+        );
+        ModelGroup {
+            connector: Connector::Or,
+            tokens,
+            occurrence: Occurrence::ZeroOrMore,
+        }
+    }
+
+    fn convert_group(&mut self, element: &str, g: &ModelGroup) -> ModelGroup {
+        if g.connector == Connector::And {
+            if !self.issues.contains(&XmlIssue::AndConnector {
+                element: element.to_string(),
+            }) {
+                self.issues.push(XmlIssue::AndConnector {
+                    element: element.to_string(),
+                });
+                self.note(
+                    element,
+                    "SGML `&` groups are written as a repeatable choice".to_string(),
+                );
+            }
+
+            // Every member may now appear any number of times, so their own occurrence is redundant
+            let tokens = g
+                .tokens
+                .iter()
+                .map(|t| match t {
+                    ContentToken::Element(n, _) => {
+                        ContentToken::Element(self.name(n), Occurrence::Once)
+                    }
+                    ContentToken::Group(g) => ContentToken::Group(self.convert_group(element, g)),
+                    ContentToken::PcData => ContentToken::PcData,
+                })
+                .collect();
+            return ModelGroup {
+                connector: Connector::Or,
+                tokens,
+                occurrence: Occurrence::ZeroOrMore,
+            };
+        }
+
+        ModelGroup {
+            connector: g.connector,
+            tokens: g
+                .tokens
+                .iter()
+                .map(|t| match t {
+                    ContentToken::Element(n, o) => ContentToken::Element(self.name(n), *o),
+                    ContentToken::Group(g) => ContentToken::Group(self.convert_group(element, g)),
+                    ContentToken::PcData => ContentToken::PcData,
+                })
+                .collect(),
+            occurrence: g.occurrence,
+        }
+    }
+
+    fn declaration(&mut self, declaration: &DTDElement, out: &mut Vec<DTDElement>) {
+        match declaration {
+            DTDElement::WhiteSpace(_) => out.push(declaration.clone()),
+            DTDElement::Comment(c) => {
+                // XML doesn't allow -- within a comment
+                if self.options.keep_comments && !c.contains("--") {
+                    out.push(declaration.clone())
+                }
+            }
+            DTDElement::Entity(e) => {
+                if let Some(e) = self.entity(e) {
+                    out.push(DTDElement::Entity(e));
+                }
+            }
+            DTDElement::MarkedSection(ms) => self.issues.push(XmlIssue::NotStandalone {
+                declaration: format!("<![{}[", ms.status),
+            }),
+            DTDElement::ParameterReference(r) => self.issues.push(XmlIssue::NotStandalone {
+                declaration: format!("%{};", r.name),
+            }),
+            DTDElement::Element(e) => self.element(e, out),
+            DTDElement::ATTList(a) => self.att_list(a, out),
+        }
+    }
+
+    fn element(&mut self, e: &Element, out: &mut Vec<DTDElement>) {
+        for name in element_names(e.get_name(self.entities)) {
+            let name = self.name(&name);
+            let model = match self.models.get(&name) {
+                Some(model) => model.to_string(),
+                None => {
+                    self.issues.push(XmlIssue::Unparsed {
+                        declaration: format!("<!ELEMENT {}", name),
+                    });
+                    continue;
+                }
+            };
+
+            for note in self.notes.get(&name).into_iter().flatten() {
+                out.push(DTDElement::Comment(format!(" {} ", note)));
+                out.push(DTDElement::WhiteSpace("\n".to_string()));
+            }
+            out.push(DTDElement::Element(Element {
+                name: name.as_str().into(),
+                omitted_tag_minimization: false,
+                start_optional: false,
+                end_optional: false,
+                content_model: model.as_str().into(),
+            }));
+            out.push(DTDElement::WhiteSpace("\n".to_string()));
+        }
+    }
+
+    fn att_list(&mut self, a: &ATTList, out: &mut Vec<DTDElement>) {
+        let names = element_names(a.get_name(self.entities));
+        let definitions = match a.get_definitions(self.entities) {
+            Some(definitions) => definitions,
+            None => {
+                self.issues.push(XmlIssue::Unparsed {
+                    declaration: format!("<!ATTLIST {}", names.join("|")),
+                });
+                return;
+            }
+        };
+
+        for name in names {
+            let name = self.name(&name);
+            let value = definitions
+                .iter()
+                .map(|d| self.attribute(&name, d).to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            out.push(DTDElement::ATTList(ATTList {
+                name: name.as_str().into(),
+                value: value.as_str().into(),
+            }));
+            out.push(DTDElement::WhiteSpace("\n".to_string()));
+        }
+    }
+
+    fn attribute(&mut self, element: &str, d: &AttributeDefinition) -> AttributeDefinition {
+        let attribute = self.name(&d.name);
+
+        let declared_value = match &d.declared_value {
+            DeclaredValue::NameTokenGroup(g) => {
+                DeclaredValue::NameTokenGroup(g.iter().map(|n| self.name(n)).collect())
+            }
+            v @ (DeclaredValue::Name | DeclaredValue::Number | DeclaredValue::NuToken) => {
+                self.issues.push(XmlIssue::DeclaredValue {
+                    element: element.to_string(),
+                    attribute: attribute.clone(),
+                    keyword: v.keyword().unwrap(),
+                });
+                DeclaredValue::NmToken
+            }
+            v @ (DeclaredValue::Names | DeclaredValue::Numbers | DeclaredValue::NuTokens) => {
+                self.issues.push(XmlIssue::DeclaredValue {
+                    element: element.to_string(),
+                    attribute: attribute.clone(),
+                    keyword: v.keyword().unwrap(),
+                });
+                DeclaredValue::NmTokens
+            }
+            v => v.clone(),
+        };
+
+        // Name token values are case folded along with the names that list them
+        let lowercase = self.options.lowercase_names
+            && matches!(declared_value, DeclaredValue::NameTokenGroup(_));
+        let value = |v: &str| {
+            let v = xml_attribute_value(v);
+            if lowercase {
+                v.to_lowercase()
+            } else {
+                v
+            }
+        };
+        let default_value = match &d.default_value {
+            DefaultValue::Fixed(v) => DefaultValue::Fixed(value(v)),
+            DefaultValue::Value(v) => DefaultValue::Value(value(v)),
+            DefaultValue::Required => DefaultValue::Required,
+            DefaultValue::Implied => DefaultValue::Implied,
+            DefaultValue::Current | DefaultValue::ConRef => {
+                let keyword = if d.default_value == DefaultValue::Current {
+                    "#CURRENT"
+                } else {
+                    "#CONREF"
+                };
+                self.issues.push(XmlIssue::DefaultValue {
+                    element: element.to_string(),
+                    attribute: attribute.clone(),
+                    keyword,
+                });
+                DefaultValue::Implied
+            }
+        };
+
+        AttributeDefinition {
+            name: attribute,
+            declared_value,
+            default_value,
+        }
+    }
+
+    fn skip_entity(&mut self, e: &Entity, reason: &str) -> Option<Entity> {
+        self.issues.push(XmlIssue::Entity {
+            name: e.name.clone(),
+            reason: reason.to_string(),
+        });
+        None
+    }
+
+    fn entity(&mut self, e: &Entity) -> Option<Entity> {
+        // Declarations are written out in full, so parameter entities are no longer needed
+        if e.parameter {
+            return None;
+        }
+
+        if e.external || e.public {
+            if e.entity_type != EntityType::Literal {
+                return self.skip_entity(e, "external data entities need a notation in XML");
+            }
+            if e.get_system_id().is_none() {
+                return self.skip_entity(e, "XML requires a system identifier");
+            }
+            return Some(Entity {
+                comment: None,
+                ..e.clone()
+            });
+        }
+
+        let text = match e.content.try_expand(self.entities) {
+            Ok(text) => text,
+            Err(_) => return self.skip_entity(e, "unable to resolve its replacement text"),
+        };
+        let content = match e.entity_type {
+            EntityType::Literal => xml_entity_value(&text),
+            EntityType::CData => escape_data(&resolve_character_references(&text)),
+            EntityType::SData => {
+                let name = text
+                    .trim()
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .trim();
+                let resolved = resolve_character_references(&text);
+                match sdata_character(name) {
+                    Some(c) => c,
+                    // The literal is made of character references
+                    None if resolved != text => escape_data(&resolved),
+                    None => {
+                        self.issues.push(XmlIssue::Entity {
+                            name: e.name.clone(),
+                            reason: format!(
+                                "no character known for SDATA \"{}\", kept as text",
+                                text
+                            ),
+                        });
+                        escape_data(&text)
+                    }
+                }
+            }
+            EntityType::ProcessingInstruction => format!("<?{}?>", text),
+            EntityType::StartTag
+            | EntityType::EndTag
+            | EntityType::MarkedSection
+            | EntityType::MarkupDeclaration => {
+                return self.skip_entity(
+                    e,
+                    &format!(
+                        "{} entities have no XML equivalent",
+                        e.entity_type.keyword().unwrap()
+                    ),
+                )
+            }
+        };
+
+        Some(Entity {
+            name: e.name.clone(),
+            external: false,
+            parameter: false,
+            public: false,
+            entity_type: EntityType::Literal,
+            content: content.as_str().into(),
+            system_id: None,
+            comment: None,
+        })
+    }
+}
+
+/// The character reference for an entity in the ISO character entity sets
+fn sdata_character(name: &str) -> Option<String> {
+    CHARACTER_ENTITY_SETS.iter().find_map(|set| {
+        let (_, dtd) = read_dtd(set).ok()?;
+        dtd.get_entities()
+            .iter()
+            .find(|e| e.name == name)
+            .map(|e| e.content.expand(&[]))
+    })
+}
+
+/// The characters of every entity in the ISO character entity sets, by entity name
+pub(crate) fn character_entities() -> HashMap<String, String> {
+    let mut characters = HashMap::new();
+    for set in CHARACTER_ENTITY_SETS.iter() {
+        if let Ok((_, dtd)) = read_dtd(set) {
+            for e in dtd.get_entities() {
+                let text = resolve_character_references(&e.content.expand(&[]));
+                characters.entry(e.name.clone()).or_insert(text);
+            }
+        }
+    }
+    characters
+}
+
+/// Split text into plain text and references of the form `&name;` or `&#name;`
+fn replace_references(text: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let len = after
+            .char_indices()
+            .find(|(n, c)| {
+                !(c.is_alphanumeric() || *c == '.' || *c == '-' || (*n == 0 && *c == '#'))
+            })
+            .map(|(n, _)| n)
+            .unwrap_or(after.len());
+        let name = &after[..len];
+
+        match f(name) {
+            Some(replacement) if !name.is_empty() && name != "#" => {
+                out.push_str(&replacement);
+                rest = &after[len..];
+                rest = rest.strip_prefix(';').unwrap_or(rest);
+            }
+            // Not a reference, so the ampersand itself has to be escaped
+            _ => {
+                out.push_str("&#38;");
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Replace SGML function character references with XML ones, See ISO(B.7.3)
+fn function_character(name: &str) -> Option<String> {
+    let function = name.strip_prefix('#')?;
+    FUNCTION_CHARACTERS
+        .iter()
+        .find(|(f, _)| f.eq_ignore_ascii_case(function))
+        .map(|(_, c)| c.to_string())
+}
+
+fn keep_reference(name: &str) -> Option<String> {
+    function_character(name).or_else(|| Some(format!("&{};", name)))
+}
+
+/// An attribute value literal, with markup characters escaped
+fn xml_attribute_value(v: &str) -> String {
+    replace_references(v, keep_reference)
+        .replace('<', "&#60;")
+        .replace('"', "&#34;")
+}
+
+/// An entity value literal, which is still parsed as markup when the entity is referenced
+fn xml_entity_value(v: &str) -> String {
+    replace_references(v, keep_reference)
+        .replace('%', "&#37;")
+        .replace('"', "&#34;")
+}
+
+/// Resolve numeric and function character references, as the literal of a CDATA entity is parsed
+pub(crate) fn resolve_character_references(v: &str) -> String {
+    replace_references(v, |name| {
+        let reference = name.strip_prefix('#')?;
+        match reference.parse::<u32>() {
+            Ok(code) => std::char::from_u32(code).map(|c| c.to_string()),
+            Err(_) => FUNCTION_CHARACTERS
+                .iter()
+                .find(|(f, _)| f.eq_ignore_ascii_case(reference))
+                .map(|(f, _)| match *f {
+                    "RE" => "\n".to_string(),
+                    "SPACE" => " ".to_string(),
+                    "TAB" => "\t".to_string(),
+                    _ => String::new(),
+                }),
+        }
+    })
+    .replace("&#38;", "&")
+}
+
+/// An entity value whose replacement text is character data, with nothing left to be parsed as markup
+fn escape_data(v: &str) -> String {
+    v.replace('&', "&#38;#38;")
+        .replace('<', "&#38;#60;")
+        .replace('%', "&#37;")
+        .replace('"', "&#34;")
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::catalog::Catalog;
+    use crate::dtd::read_dtd;
+    use crate::flatten::{flatten_dtd, FlattenOptions};
+    use crate::printer::{print_dtd, PrintOptions};
+    use crate::xml_dtd::{to_xml_dtd, XmlDtdOptions, XmlIssue};
+    use std::path::{Path, PathBuf};
+
+    fn convert(s: &str, options: &XmlDtdOptions) -> (String, Vec<XmlIssue>) {
+        let (_, dtd) = read_dtd(s).unwrap();
+        let xml = to_xml_dtd(&dtd, options);
+        (print_dtd(&xml.dtd, &PrintOptions::default()), xml.issues)
+    }
+
+    #[test]
+    pub fn test_convert_entities() {
+        let (out, issues) = convert(
+            "<!ENTITY amp CDATA \"&#38;\" -- ampersand -->
+<!ENTITY nbsp SDATA \"[nbsp  ]\">
+<!ENTITY odd SDATA \"[odd]\">
+<!ENTITY dash SDATA \"&#8212;\">
+<!ENTITY br STARTTAG \"BR\">
+<!ENTITY sig \"Yours,&#RE;Me\">
+<!ENTITY lat1 PUBLIC \"ISO 8879-1986//ENTITIES Added Latin 1//EN\">",
+            &XmlDtdOptions::default(),
+        );
+
+        assert!(out.contains("<!ENTITY amp \"&#38;#38;\">"));
+        assert!(out.contains("<!ENTITY nbsp \"&#160;\">"));
+        assert!(out.contains("<!ENTITY odd \"[odd]\">"));
+        assert!(out.contains("<!ENTITY dash \"—\">"));
+        assert!(out.contains("<!ENTITY sig \"Yours,&#10;Me\">"));
+        assert!(!out.contains("br"));
+        assert!(!out.contains("lat1"));
+        assert_eq!(issues.len(), 3);
+    }
+
+    #[test]
+    pub fn test_convert_inclusions() {
+        let (out, issues) = convert(
+            "<!ELEMENT body - - (p|list)+ +(note)>
+<!ELEMENT (p|note) - O (#PCDATA)>
+<!ELEMENT list - - (p, p)>
+<!ELEMENT title - - (p, p) +(note)>",
+            &XmlDtdOptions::default(),
+        );
+        let out = out.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert!(out.contains("<!ELEMENT body (p | list | note)+>"));
+        assert!(out.contains("<!ELEMENT list (p, p)>"));
+        // Nothing within the title repeats
+        assert!(out.contains("<!ELEMENT title (p, p)>"));
+        assert_eq!(
+            issues,
+            vec![
+                XmlIssue::Inclusions {
+                    element: "body".to_string(),
+                    names: vec!["note".to_string()]
+                },
+                XmlIssue::DroppedInclusions {
+                    element: "title".to_string(),
+                    names: vec!["note".to_string()]
+                }
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_convert_attributes() {
+        let (out, issues) = convert(
+            "<!ELEMENT P - O (#PCDATA)*>
+<!ATTLIST P
+        ALIGN (LEFT|RIGHT) LEFT
+        WIDTH NUMBER #IMPLIED
+        LANG CDATA #CURRENT
+        SDAPREF CDATA #FIXED \"<Para>&#RE;\">",
+            &XmlDtdOptions {
+                lowercase_names: true,
+                ..XmlDtdOptions::default()
+            },
+        );
+
+        assert!(out.contains("<!ELEMENT p (#PCDATA)*>"));
+        assert!(out.contains("align   (left | right) \"left\""));
+        assert!(out.contains("width   NMTOKEN        #IMPLIED"));
+        assert!(out.contains("lang    CDATA          #IMPLIED"));
+        assert!(out.contains("sdapref CDATA          #FIXED \"&#60;Para>&#10;\""));
+        assert_eq!(
+            issues,
+            vec![
+                XmlIssue::DeclaredValue {
+                    element: "p".to_string(),
+                    attribute: "width".to_string(),
+                    keyword: "NUMBER"
+                },
+                XmlIssue::DefaultValue {
+                    element: "p".to_string(),
+                    attribute: "lang".to_string(),
+                    keyword: "#CURRENT"
+                },
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_convert_html() {
+        let s = std::fs::read_to_string("./dtd/html.dtd").unwrap();
+        let (_, dtd) = read_dtd(&s).unwrap();
+        let mut catalog = Catalog::default();
+        catalog.add_public(
+            "ISO 8879-1986//ENTITIES Added Latin 1//EN//HTML",
+            PathBuf::from("./dtd/xhtml-lat1.ent"),
+        );
+        let options = FlattenOptions {
+            keep_general_entities: true,
+            keep_comments: false,
+            ..FlattenOptions::default()
+        };
+        let flat = flatten_dtd(&dtd, Path::new("./dtd"), &catalog, &options).unwrap();
+
+        let xml = to_xml_dtd(
+            &flat,
+            &XmlDtdOptions {
+                lowercase_names: true,
+                keep_comments: false,
+            },
+        );
+        let out = print_dtd(&xml.dtd, &PrintOptions::default());
+        let (rest, converted) = read_dtd(&out).unwrap();
+        assert_eq!(rest, "");
+
+        let model = |name: &str| {
+            let element = converted
+                .get_element_declarations()
+                .iter()
+                .find(|e| e.name.expand(&[]) == name)
+                .unwrap();
+            let text = element.content_model.expand(&[]);
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        };
+        assert_eq!(model("br"), "EMPTY");
+        assert_eq!(
+            model("head"),
+            "(title | isindex | base | nextid | meta | link)*"
+        );
+        assert_eq!(model("xmp"), "(#PCDATA)");
+        // The exclusion is applied to the element itself
+        assert!(!model("a").contains("| a |"));
+        // Inclusions are allowed in descendants with mixed content
+        assert!(model("p").contains("input"));
+        assert!(!converted
+            .get_element_declarations()
+            .iter()
+            .any(|e| e.omitted_tag_minimization));
+        assert!(!converted
+            .get_element_declarations()
+            .iter()
+            .any(|e| e.content_model.expand(&[]).contains('&')));
+
+        assert!(xml.issues.contains(&XmlIssue::AndConnector {
+            element: "head".to_string()
+        }));
+        assert!(xml.issues.contains(&XmlIssue::DeclaredContent {
+            element: "plaintext".to_string(),
+            keyword: "CDATA"
+        }));
+        assert!(xml.issues.contains(&XmlIssue::Exclusions {
+            element: "a".to_string(),
+            names: vec!["a".to_string()]
+        }));
+    }
+}
@@ -0,0 +1,705 @@
+use crate::att_list::{AttributeDefinition, DeclaredValue, DefaultValue};
+use crate::content_model::{Connector, Occurrence};
+use crate::dtd::DocumentTypeDefinition;
+use crate::relax_ng::xml_escape;
+use crate::schema::{
+    AttributeItem, Particle, Schema, SchemaContent, SchemaElement, SchemaError, SchemaOptions,
+};
+use crate::xml_dtd::XmlIssue;
+use std::collections::HashMap;
+
+const XSD_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema";
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+pub struct Xsd {
+    pub document: String,
+    pub issues: Vec<XmlIssue>,
+}
+
+/// Generate a W3C XML Schema from a DTD read as by `Schema::from_dtd`, with the parameter
+/// entities it keeps as named groups and attribute groups
+pub fn to_xsd(dtd: &DocumentTypeDefinition, options: &SchemaOptions) -> Result<Xsd, SchemaError> {
+    let schema = Schema::from_dtd(dtd, options)?;
+    let mut converter = Converter {
+        schema: &schema,
+        included: schema.get_included(),
+        groups: HashMap::new(),
+        issues: Vec::new(),
+        notes: Vec::new(),
+    };
+    let document = converter.convert();
+    Ok(Xsd {
+        document,
+        issues: converter.issues,
+    })
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Compositor {
+    Sequence,
+    Choice,
+    All,
+}
+
+impl Compositor {
+    fn tag(&self) -> &'static str {
+        match self {
+            Compositor::Sequence => "xs:sequence",
+            Compositor::Choice => "xs:choice",
+            Compositor::All => "xs:all",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Term {
+    Element(String),
+    Group(String),
+    Model(Compositor, Vec<Item>),
+}
+
+/// minOccurs and maxOccurs, where None is unbounded
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct Occurs(usize, Option<usize>);
+
+const ONCE: Occurs = Occurs(1, Some(1));
+
+impl From<Occurrence> for Occurs {
+    fn from(o: Occurrence) -> Self {
+        match o {
+            Occurrence::Once => ONCE,
+            Occurrence::Optional => Occurs(0, Some(1)),
+            Occurrence::OneOrMore => Occurs(1, None),
+            Occurrence::ZeroOrMore => Occurs(0, None),
+        }
+    }
+}
+
+impl Occurs {
+    /// The occurrence of the only member of a group, with the group's own occurrence applied
+    fn combine(self, other: Occurs) -> Occurs {
+        let max = match (self.1, other.1) {
+            (Some(a), Some(b)) => Some(a * b),
+            _ => None,
+        };
+        Occurs(self.0 * other.0, max)
+    }
+
+    fn attributes(&self) -> String {
+        let mut s = String::new();
+        if self.0 != 1 {
+            s.push_str(&format!(" minOccurs=\"{}\"", self.0));
+        }
+        match self.1 {
+            Some(1) => {}
+            Some(n) => s.push_str(&format!(" maxOccurs=\"{}\"", n)),
+            None => s.push_str(" maxOccurs=\"unbounded\""),
+        }
+        s
+    }
+}
+
+/// A term and how often it occurs
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Item(Term, Occurs);
+
+impl Item {
+    fn is_repeatable_choice(&self) -> bool {
+        self.1 .1.is_none() && !matches!(self.0, Term::Model(Compositor::Sequence, _))
+    }
+}
+
+struct Converter<'a> {
+    schema: &'a Schema,
+    /// Inclusions that apply to each element, from its own declaration or an ancestor's
+    included: HashMap<String, Vec<String>>,
+    /// The model of each named group
+    groups: HashMap<String, Item>,
+    issues: Vec<XmlIssue>,
+    /// Notes for the element being converted
+    notes: Vec<String>,
+}
+
+impl<'a> Converter<'a> {
+    fn convert(&mut self) -> String {
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        match self.schema.get_namespace() {
+            Some(namespace) => out.push_str(&format!(
+                "<xs:schema xmlns:xs=\"{}\" xmlns=\"{ns}\" targetNamespace=\"{ns}\" elementFormDefault=\"qualified\">\n",
+                XSD_NAMESPACE,
+                ns = xml_escape(&namespace)
+            )),
+            None => out.push_str(&format!("<xs:schema xmlns:xs=\"{}\">\n", XSD_NAMESPACE)),
+        }
+
+        for g in &self.schema.groups {
+            let (compositor, items) =
+                self.members(&format!("%{};", g.name), g.connector, &g.particles, &[]);
+            out.push_str(&format!(
+                "\n  <xs:group name=\"{}\">\n",
+                xml_escape(&g.name)
+            ));
+            // The compositor of a named group can't repeat, so an approximated `&` group is wrapped
+            let item = match (g.connector, compositor) {
+                (Connector::And, Compositor::Choice) => model(
+                    Compositor::Sequence,
+                    vec![model(compositor, items, Occurs(1, None))],
+                    ONCE,
+                ),
+                _ => model(compositor, items, ONCE),
+            };
+            write_item(&item, 2, &mut out);
+            self.groups.insert(g.name.clone(), item);
+            out.push_str("  </xs:group>\n");
+        }
+
+        for g in &self.schema.attribute_groups {
+            out.push_str(&format!(
+                "\n  <xs:attributeGroup name=\"{}\">\n",
+                xml_escape(&g.name)
+            ));
+            out.push_str(&self.attributes(&format!("%{};", g.name), &g.attributes, 2));
+            out.push_str("  </xs:attributeGroup>\n");
+        }
+
+        for e in &self.schema.elements {
+            out.push('\n');
+            out.push_str(&self.element(e));
+        }
+
+        out.push_str("</xs:schema>\n");
+        out
+    }
+
+    /// Look through references to named groups and sequences of one
+    fn resolve<'b>(&'b self, item: &'b Item) -> &'b Item {
+        match item {
+            Item(Term::Group(r), ONCE) if self.groups.contains_key(r) => {
+                self.resolve(&self.groups[r])
+            }
+            Item(Term::Model(Compositor::Sequence, items), ONCE) if items.len() == 1 => {
+                self.resolve(&items[0])
+            }
+            i => i,
+        }
+    }
+
+    fn and_connector(&mut self, context: &str) {
+        let issue = XmlIssue::AndConnector {
+            element: context.to_string(),
+        };
+        if !self.issues.contains(&issue) {
+            self.notes
+                .push("SGML `&` groups are approximated by a repeatable choice".to_string());
+            self.issues.push(issue);
+        }
+    }
+
+    /// The compositor and items for the members of a group
+    fn members(
+        &mut self,
+        context: &str,
+        connector: Connector,
+        particles: &[Particle],
+        exclusions: &[String],
+    ) -> (Compositor, Vec<Item>) {
+        let items = particles
+            .iter()
+            .filter_map(|p| self.particle(context, p, exclusions, false))
+            .collect::<Vec<_>>();
+        match connector {
+            Connector::Sequence => (Compositor::Sequence, items),
+            Connector::Or => (Compositor::Choice, items),
+            // xs:all may only hold elements that occur at most once
+            Connector::And
+                if items
+                    .iter()
+                    .all(|Item(t, o)| matches!(t, Term::Element(_)) && o.1 == Some(1)) =>
+            {
+                (Compositor::All, items)
+            }
+            Connector::And => {
+                self.and_connector(context);
+                let items = items.into_iter().map(|Item(t, _)| Item(t, ONCE)).collect();
+                (Compositor::Choice, items)
+            }
+        }
+    }
+
+    /// The item for a content token, or None if it is only character data or is excluded
+    fn particle(
+        &mut self,
+        context: &str,
+        particle: &Particle,
+        exclusions: &[String],
+        top: bool,
+    ) -> Option<Item> {
+        match particle {
+            Particle::PcData => None,
+            Particle::Element(name, _) if exclusions.contains(name) => None,
+            Particle::Element(name, occurrence) => {
+                Some(Item(Term::Element(name.clone()), (*occurrence).into()))
+            }
+            // A group of one, such as (%head.content), passes on whether xs:all is allowed
+            Particle::Group(g) if g.particles.len() == 1 => {
+                let occurs = Occurs::from(g.occurrence);
+                let top = top && occurs.1 == Some(1);
+                let Item(term, inner) = self.particle(context, &g.particles[0], exclusions, top)?;
+                Some(Item(term, occurs.combine(inner)))
+            }
+            Particle::Group(g) => {
+                let (compositor, mut items) =
+                    self.members(context, g.connector, &g.particles, exclusions);
+                let occurs = match (g.connector, compositor) {
+                    (Connector::And, Compositor::Choice) => {
+                        Occurs(Occurs::from(g.occurrence).0, None)
+                    }
+                    _ => Occurs::from(g.occurrence),
+                };
+                if items.is_empty() {
+                    return None;
+                }
+                if items.len() == 1 && compositor != Compositor::All {
+                    let Item(term, inner) = items.remove(0);
+                    return Some(Item(term, occurs.combine(inner)));
+                }
+                // xs:all can only be the whole content, at most once
+                if compositor == Compositor::All && (!top || occurs.1 != Some(1)) {
+                    self.and_connector(context);
+                    let items = items.into_iter().map(|Item(t, _)| Item(t, ONCE)).collect();
+                    return Some(model(Compositor::Choice, items, Occurs(occurs.0, None)));
+                }
+                Some(model(compositor, items, occurs))
+            }
+            Particle::Reference(r) => {
+                let excluded = self
+                    .schema
+                    .get_particle_names(particle)
+                    .iter()
+                    .any(|n| exclusions.contains(n));
+                let all = matches!(
+                    self.resolve(&Item(Term::Group(r.clone()), ONCE)).0,
+                    Term::Model(Compositor::All, _)
+                );
+                if !excluded && (top || !all) {
+                    return Some(Item(Term::Group(r.clone()), ONCE));
+                }
+
+                // Write out the group, without the excluded elements or where xs:all isn't allowed
+                let g = self.schema.get_group(r)?;
+                let (compositor, items) =
+                    self.members(context, g.connector, &g.particles, exclusions);
+                match items.len() {
+                    0 => None,
+                    1 if compositor != Compositor::All => items.into_iter().next(),
+                    _ if g.connector == Connector::And && compositor == Compositor::Choice => {
+                        Some(model(compositor, items, Occurs(1, None)))
+                    }
+                    _ if compositor == Compositor::All && !top => {
+                        self.and_connector(context);
+                        let items = items.into_iter().map(|Item(t, _)| Item(t, ONCE)).collect();
+                        Some(model(Compositor::Choice, items, Occurs(0, None)))
+                    }
+                    _ => Some(model(compositor, items, ONCE)),
+                }
+            }
+        }
+    }
+
+    fn element(&mut self, e: &SchemaElement) -> String {
+        self.notes.clear();
+        let context = e.name.clone();
+
+        let (mixed, mut item) = match &e.content {
+            SchemaContent::CData | SchemaContent::RCData => {
+                let keyword = if e.content == SchemaContent::CData {
+                    "CDATA"
+                } else {
+                    "RCDATA"
+                };
+                self.issues.push(XmlIssue::DeclaredContent {
+                    element: e.name.clone(),
+                    keyword,
+                });
+                self.notes
+                    .push(format!("SGML {} content is parsed as #PCDATA", keyword));
+                (true, None)
+            }
+            SchemaContent::Empty => (false, None),
+            SchemaContent::Any => (true, None),
+            SchemaContent::Model(p) => {
+                let item = self.particle(&context, p, &e.exclusions, true);
+                let mixed = self.schema.is_mixed(p);
+                // XSD allows character data anywhere in mixed content
+                let loosened = item
+                    .as_ref()
+                    .map(|i| !self.resolve(i).is_repeatable_choice());
+                if mixed && loosened == Some(true) {
+                    self.issues.push(XmlIssue::MixedContent {
+                        element: e.name.clone(),
+                    });
+                    self.notes.push(
+                        "SGML mixed content is loosened to allow character data anywhere"
+                            .to_string(),
+                    );
+                }
+                (mixed, item)
+            }
+        };
+
+        if !e.exclusions.is_empty() {
+            self.issues.push(XmlIssue::Exclusions {
+                element: e.name.clone(),
+                names: e.exclusions.clone(),
+            });
+            self.notes.push(format!(
+                "SGML exclusions -({}) are not enforced in the content of descendants",
+                e.exclusions.join(" | ")
+            ));
+        }
+        if !e.inclusions.is_empty() {
+            self.issues.push(XmlIssue::Inclusions {
+                element: e.name.clone(),
+                names: e.inclusions.clone(),
+            });
+            self.notes.push(format!(
+                "SGML inclusions +({}) are allowed in the repeatable choices of {} and its descendants",
+                e.inclusions.join(" | "),
+                e.name
+            ));
+        }
+
+        // Inclusions don't apply to declared content, See ISO(B.4.2.6)
+        if let (SchemaContent::Model(_), Some(included)) = (&e.content, self.included.get(&e.name))
+        {
+            let present = self.schema.get_children(&SchemaElement {
+                inclusions: Vec::new(),
+                ..e.clone()
+            });
+            let extra = included
+                .iter()
+                .filter(|n| !present.contains(n) && !e.exclusions.contains(n))
+                .map(|n| Item(Term::Element(n.clone()), ONCE))
+                .collect::<Vec<_>>();
+            if !extra.is_empty() {
+                let resolved = item.as_ref().map(|i| self.resolve(i).clone());
+                item = match (item, resolved) {
+                    (None, _) if mixed => Some(model(Compositor::Choice, extra, Occurs(0, None))),
+                    // xs:all can't hold repeatable elements, so the `&` group is approximated
+                    (Some(_), Some(Item(Term::Model(Compositor::All, items), _))) => {
+                        self.and_connector(&context);
+                        let mut items = items
+                            .into_iter()
+                            .map(|Item(t, _)| Item(t, ONCE))
+                            .collect::<Vec<_>>();
+                        items.extend(extra);
+                        Some(model(Compositor::Choice, items, Occurs(0, None)))
+                    }
+                    (
+                        Some(Item(Term::Model(Compositor::Choice, mut items), Occurs(min, None))),
+                        _,
+                    ) => {
+                        items.extend(extra);
+                        Some(model(Compositor::Choice, items, Occurs(min, None)))
+                    }
+                    (Some(i), Some(r)) if r.is_repeatable_choice() => {
+                        let min = i.1 .0 * r.1 .0;
+                        let mut items = vec![Item(i.0, ONCE)];
+                        items.extend(extra);
+                        Some(model(Compositor::Choice, items, Occurs(min, None)))
+                    }
+                    (i, _) => i,
+                };
+            }
+        }
+
+        let mut out = format!("  <xs:element name=\"{}\">\n", xml_escape(&e.name));
+        if !self.notes.is_empty() {
+            out.push_str("    <xs:annotation>\n");
+            for note in &self.notes {
+                out.push_str(&format!(
+                    "      <xs:documentation>{}</xs:documentation>\n",
+                    xml_escape(note)
+                ));
+            }
+            out.push_str("    </xs:annotation>\n");
+        }
+        match mixed {
+            true => out.push_str("    <xs:complexType mixed=\"true\">\n"),
+            false => out.push_str("    <xs:complexType>\n"),
+        }
+        match (&e.content, item) {
+            (SchemaContent::Any, _) => out.push_str(
+                "      <xs:sequence>\n        <xs:any minOccurs=\"0\" maxOccurs=\"unbounded\"/>\n      </xs:sequence>\n",
+            ),
+            (_, None) => {}
+            // A complex type holds a single model group
+            (_, Some(item @ Item(Term::Element(_), _))) => write_item(
+                &model(Compositor::Sequence, vec![item], ONCE),
+                3,
+                &mut out,
+            ),
+            (_, Some(item)) => write_item(&item, 3, &mut out),
+        }
+        out.push_str(&self.attributes(&e.name, &e.attributes, 3));
+        out.push_str("    </xs:complexType>\n  </xs:element>\n");
+        out
+    }
+
+    /// Attribute declarations, skipping any attribute defined earlier in the list as SGML does
+    fn attributes(&mut self, element: &str, items: &[AttributeItem], depth: usize) -> String {
+        let mut seen = Vec::new();
+        for item in items {
+            if let AttributeItem::Reference(r) = item {
+                self.attribute_names(r, &mut seen);
+            }
+        }
+
+        let indent = "  ".repeat(depth);
+        let mut out = String::new();
+        let mut wildcard = None;
+        for item in items {
+            match item {
+                AttributeItem::Definition(d) => {
+                    // Namespace declarations aren't attributes in XSD
+                    if seen.contains(&d.name) || d.name == "xmlns" || d.name.starts_with("xmlns:") {
+                        continue;
+                    }
+                    // Attributes such as xml:lang belong to another namespace, which would need a schema of its own
+                    if d.name.contains(':') {
+                        wildcard = match (wildcard, d.name.starts_with("xml:")) {
+                            (None, true) => Some(XML_NAMESPACE),
+                            _ => Some("##other"),
+                        };
+                        continue;
+                    }
+                    seen.push(d.name.clone());
+                    out.push_str(&self.attribute(element, d, depth));
+                }
+                AttributeItem::Reference(r) => out.push_str(&format!(
+                    "{}<xs:attributeGroup ref=\"{}\"/>\n",
+                    indent,
+                    xml_escape(r)
+                )),
+            }
+        }
+        if let Some(namespace) = wildcard {
+            out.push_str(&format!(
+                "{}<xs:anyAttribute namespace=\"{}\" processContents=\"lax\"/>\n",
+                indent, namespace
+            ));
+        }
+        out
+    }
+
+    fn attribute_names(&self, group: &str, names: &mut Vec<String>) {
+        if let Some(g) = self.schema.get_attribute_group(group) {
+            for item in &g.attributes {
+                match item {
+                    AttributeItem::Definition(d) => names.push(d.name.clone()),
+                    AttributeItem::Reference(r) => self.attribute_names(r, names),
+                }
+            }
+        }
+    }
+
+    fn attribute(&mut self, element: &str, d: &AttributeDefinition, depth: usize) -> String {
+        let indent = "  ".repeat(depth);
+        let list = |t: &str| {
+            format!(
+                "{}  <xs:simpleType>\n{}    <xs:list itemType=\"{}\"/>\n{}  </xs:simpleType>\n",
+                indent, indent, t, indent
+            )
+        };
+        let enumeration = |v: &Vec<String>| {
+            let mut s = format!(
+                "{}  <xs:simpleType>\n{}    <xs:restriction base=\"xs:token\">\n",
+                indent, indent
+            );
+            for value in v {
+                s.push_str(&format!(
+                    "{}      <xs:enumeration value=\"{}\"/>\n",
+                    indent,
+                    xml_escape(value)
+                ));
+            }
+            s.push_str(&format!(
+                "{}    </xs:restriction>\n{}  </xs:simpleType>\n",
+                indent, indent
+            ));
+            s
+        };
+
+        let (simple_type, content) = match &d.declared_value {
+            DeclaredValue::CData => (Some("xs:string"), None),
+            DeclaredValue::Entity => (Some("xs:ENTITY"), None),
+            DeclaredValue::Entities => (Some("xs:ENTITIES"), None),
+            DeclaredValue::Id => (Some("xs:ID"), None),
+            DeclaredValue::IdRef => (Some("xs:IDREF"), None),
+            DeclaredValue::IdRefs => (Some("xs:IDREFS"), None),
+            DeclaredValue::Name => (Some("xs:Name"), None),
+            DeclaredValue::Names => (None, Some(list("xs:Name"))),
+            DeclaredValue::NmToken => (Some("xs:NMTOKEN"), None),
+            DeclaredValue::NmTokens => (Some("xs:NMTOKENS"), None),
+            DeclaredValue::Number => (Some("xs:nonNegativeInteger"), None),
+            DeclaredValue::Numbers => (None, Some(list("xs:nonNegativeInteger"))),
+            DeclaredValue::NuToken | DeclaredValue::NuTokens => {
+                self.issues.push(XmlIssue::DeclaredValue {
+                    element: element.to_string(),
+                    attribute: d.name.clone(),
+                    keyword: match d.declared_value {
+                        DeclaredValue::NuToken => "NUTOKEN",
+                        _ => "NUTOKENS",
+                    },
+                });
+                match d.declared_value {
+                    DeclaredValue::NuToken => (Some("xs:NMTOKEN"), None),
+                    _ => (Some("xs:NMTOKENS"), None),
+                }
+            }
+            DeclaredValue::Notation(v) | DeclaredValue::NameTokenGroup(v) => {
+                (None, Some(enumeration(v)))
+            }
+        };
+
+        let mut out = format!("{}<xs:attribute name=\"{}\"", indent, xml_escape(&d.name));
+        if let Some(t) = simple_type {
+            out.push_str(&format!(" type=\"{}\"", t));
+        }
+        match &d.default_value {
+            DefaultValue::Required => out.push_str(" use=\"required\""),
+            DefaultValue::Fixed(v) => out.push_str(&format!(" fixed=\"{}\"", xml_escape(v))),
+            DefaultValue::Value(v) => out.push_str(&format!(" default=\"{}\"", xml_escape(v))),
+            DefaultValue::Current | DefaultValue::ConRef => {
+                self.issues.push(XmlIssue::DefaultValue {
+                    element: element.to_string(),
+                    attribute: d.name.clone(),
+                    keyword: match d.default_value {
+                        DefaultValue::Current => "#CURRENT",
+                        _ => "#CONREF",
+                    },
+                })
+            }
+            DefaultValue::Implied => {}
+        }
+        match content {
+            Some(content) => {
+                out.push_str(">\n");
+                out.push_str(&content);
+                out.push_str(&format!("{}</xs:attribute>\n", indent));
+            }
+            None => out.push_str("/>\n"),
+        }
+        out
+    }
+}
+
+fn model(compositor: Compositor, items: Vec<Item>, occurs: Occurs) -> Item {
+    Item(Term::Model(compositor, items), occurs)
+}
+
+fn write_item(item: &Item, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    let Item(term, occurs) = item;
+    match term {
+        Term::Element(name) => out.push_str(&format!(
+            "{}<xs:element ref=\"{}\"{}/>\n",
+            indent,
+            xml_escape(name),
+            occurs.attributes()
+        )),
+        Term::Group(name) => out.push_str(&format!(
+            "{}<xs:group ref=\"{}\"{}/>\n",
+            indent,
+            xml_escape(name),
+            occurs.attributes()
+        )),
+        Term::Model(compositor, items) => {
+            out.push_str(&format!(
+                "{}<{}{}>\n",
+                indent,
+                compositor.tag(),
+                occurs.attributes()
+            ));
+            items.iter().for_each(|i| write_item(i, depth + 1, out));
+            out.push_str(&format!("{}</{}>\n", indent, compositor.tag()));
+        }
+    }
+}
+#[cfg(test)]
+pub mod test {
+    use crate::dtd::read_dtd;
+    use crate::schema::test::{html_schema_dtd, xhtml_schema_dtd};
+    use crate::schema::SchemaOptions;
+    use crate::xml_dtd::XmlIssue;
+    use crate::xsd::to_xsd;
+
+    #[test]
+    pub fn test_xsd_models() {
+        let (_, dtd) = read_dtd(
+            "<!ENTITY % inline \"B | I\">
+<!ENTITY % common \"id ID #IMPLIED\">
+<!ELEMENT DOC - - (HEAD & BODY?)>
+<!ELEMENT HEAD - O EMPTY>
+<!ELEMENT BODY - - (#PCDATA | %inline)* -(I)>
+<!ELEMENT (B|I) - - ((%inline)+, HEAD?)>
+<!ATTLIST DOC
+    %common;
+    version NUMBER #REQUIRED
+    status (draft|final) final
+    owner CDATA #FIXED \"me\">",
+        )
+        .unwrap();
+        let xsd = to_xsd(&dtd, &SchemaOptions::default()).unwrap();
+        let document = xsd.document;
+        println!("{}", document);
+
+        assert!(document.contains(
+            "  <xs:group name=\"inline\">\n    <xs:choice>\n      <xs:element ref=\"B\"/>\n      <xs:element ref=\"I\"/>\n    </xs:choice>\n  </xs:group>\n"
+        ));
+        assert!(document.contains("  <xs:attributeGroup name=\"common\">\n    <xs:attribute name=\"id\" type=\"xs:ID\"/>\n"));
+        assert!(document.contains(
+            "      <xs:all>\n        <xs:element ref=\"HEAD\"/>\n        <xs:element ref=\"BODY\" minOccurs=\"0\"/>\n      </xs:all>\n"
+        ));
+        // The exclusion is applied by writing out the group
+        assert!(document.contains(
+            "    <xs:complexType mixed=\"true\">\n      <xs:sequence>\n        <xs:element ref=\"B\" minOccurs=\"0\" maxOccurs=\"unbounded\"/>"
+        ));
+        assert!(document.contains("      <xs:sequence>\n        <xs:group ref=\"inline\" maxOccurs=\"unbounded\"/>\n        <xs:element ref=\"HEAD\" minOccurs=\"0\"/>\n      </xs:sequence>\n"));
+        assert!(document.contains("<xs:attributeGroup ref=\"common\"/>"));
+        assert!(document.contains(
+            "<xs:attribute name=\"version\" type=\"xs:nonNegativeInteger\" use=\"required\"/>"
+        ));
+        assert!(document.contains("<xs:attribute name=\"status\" default=\"final\">"));
+        assert!(document.contains("<xs:enumeration value=\"draft\"/>"));
+        assert!(document.contains("<xs:attribute name=\"owner\" type=\"xs:string\" fixed=\"me\"/>"));
+        assert_eq!(
+            xsd.issues,
+            vec![XmlIssue::Exclusions {
+                element: "BODY".to_string(),
+                names: vec!["I".to_string()]
+            }]
+        );
+    }
+
+    #[test]
+    pub fn test_xsd_html() {
+        let xsd = to_xsd(&html_schema_dtd(), &SchemaOptions::default()).unwrap();
+        // Inclusions can't be added to xs:all, so the `&` group is approximated
+        assert!(xsd.issues.contains(&XmlIssue::AndConnector {
+            element: "HEAD".to_string()
+        }));
+        assert!(xsd.document.contains(
+            "<xs:element ref=\"META\"/>\n        <xs:element ref=\"LINK\"/>\n      </xs:choice>"
+        ));
+        assert!(xsd.document.contains("  <xs:group name=\"text\">\n"));
+
+        let xsd = to_xsd(&xhtml_schema_dtd(), &SchemaOptions::default()).unwrap();
+        assert!(xsd.issues.is_empty());
+        assert!(xsd
+            .document
+            .contains("targetNamespace=\"http://www.w3.org/1999/xhtml\""));
+        assert!(xsd.document.contains("<xs:anyAttribute namespace=\"http://www.w3.org/XML/1998/namespace\" processContents=\"lax\"/>"));
+        assert!(!xsd.document.contains("name=\"xmlns\""));
+    }
+}
@@ -0,0 +1,145 @@
+//! The conformance suite in `./conformance`, see its README for the layout of a case
+
+use sgml::catalog::Catalog;
+use sgml::dtd::{read_dtd, DocumentTypeDefinition};
+use sgml::esis::to_esis;
+use sgml::flatten::{flatten_dtd, FlattenOptions};
+use sgml::instance::Parser;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Run every case under `./conformance`, in order of their paths, reporting the ones that fail
+#[test]
+fn test_conformance_suite() {
+    let dir = Path::new("./conformance");
+    let mut documents = Vec::new();
+    find_documents(dir, &mut documents).unwrap();
+    documents.sort();
+    assert!(!documents.is_empty());
+
+    let failures = documents
+        .iter()
+        .filter_map(|document| {
+            let failure = run_case(document).unwrap().err()?;
+            let name = document
+                .strip_prefix(dir)
+                .unwrap_or(document)
+                .with_extension("")
+                .to_string_lossy()
+                .replace('\\', "/");
+            Some(format!("{}: {}", name, failure))
+        })
+        .collect::<Vec<_>>();
+    assert!(
+        failures.is_empty(),
+        "{} of {} cases failed\n{}",
+        failures.len(),
+        documents.len(),
+        failures.join("\n")
+    );
+}
+
+fn find_documents(dir: &Path, documents: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_documents(&path, documents)?;
+        } else if path.extension().is_some_and(|e| e == "sgml") {
+            documents.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Run the case for one document, giving the reason it failed. Only missing or unreadable files
+/// are io errors
+fn run_case(document: &Path) -> io::Result<Result<(), String>> {
+    let source = std::fs::read_to_string(document)?;
+    let dtd = match read_optional(&document.with_extension("dtd"))? {
+        Some(text) => match load_dtd(&text, document.parent().unwrap_or(Path::new(""))) {
+            Ok(dtd) => dtd,
+            Err(e) => return Ok(Err(e)),
+        },
+        None => DocumentTypeDefinition::from(Vec::new()),
+    };
+    let expected_esis = read_optional(&document.with_extension("esis"))?;
+    let expected_errors = read_optional(&document.with_extension("errors"))?.unwrap_or_default();
+
+    let mut parser = Parser::new(&source, &dtd);
+    let esis = to_esis(&mut parser);
+    let errors = parser
+        .get_errors()
+        .iter()
+        .map(|e| format!("{} {}", e.line, e.kind.get_code()))
+        .collect::<Vec<_>>();
+
+    if let Some(expected) = expected_esis {
+        if let Err(difference) = compare("ESIS", &expected, &esis) {
+            return Ok(Err(difference));
+        }
+    }
+    let expected_errors = expected_errors
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>();
+    if expected_errors != errors {
+        let found = parser
+            .get_errors()
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        return Ok(Err(format!(
+            "expected errors [{}], found [{}]",
+            expected_errors.join(", "),
+            found.join(", ")
+        )));
+    }
+    Ok(Ok(()))
+}
+
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn load_dtd(text: &str, base: &Path) -> Result<DocumentTypeDefinition, String> {
+    let dtd = match read_dtd(text) {
+        Ok(("", dtd)) => dtd,
+        Ok((rest, _)) => {
+            let rest = rest.chars().take(60).collect::<String>();
+            return Err(format!("unable to parse the DTD at: {}", rest));
+        }
+        Err(e) => return Err(format!("unable to parse the DTD: {:?}", e)),
+    };
+    let options = FlattenOptions {
+        keep_general_entities: true,
+        ..FlattenOptions::default()
+    };
+    flatten_dtd(&dtd, base, &Catalog::default(), &options).map_err(|e| e.to_string())
+}
+
+/// Compare output line by line, describing the first difference
+fn compare(what: &str, expected: &str, found: &str) -> Result<(), String> {
+    let mut expected_lines = expected.lines();
+    let mut found_lines = found.lines();
+    for line in 1.. {
+        match (expected_lines.next(), found_lines.next()) {
+            (None, None) => break,
+            (e, f) if e == f => {}
+            (e, f) => {
+                return Err(format!(
+                    "{} line {}: expected {}, found {}",
+                    what,
+                    line,
+                    e.map_or("the end".to_string(), |e| format!("`{}`", e)),
+                    f.map_or("the end".to_string(), |f| format!("`{}`", f))
+                ))
+            }
+        }
+    }
+    Ok(())
+}
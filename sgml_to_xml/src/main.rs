@@ -1,17 +1,191 @@
-use crate::dtd::read_dtd;
-use crate::element::{parse_content_model, ContentModelTokenValue};
-use std::fs::File;
+use sgml::catalog::Catalog;
+use sgml::document::{to_xml, Document, XmlOptions};
+use sgml::dtd::{read_dtd, DocumentTypeDefinition};
+use sgml::esis::to_esis;
+use sgml::flatten::{flatten_dtd, FlattenOptions};
+use sgml::instance::{read_doctype, Parser};
+use sgml::sgml_declaration::{read_sgml_declaration, sgml_declaration_len};
 use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+const USAGE: &str = "Usage: sgml_to_xml [options] [FILE]
+
+Converts an SGML document to XML, reading stdin when no file or - is given.
+Exits with status 1 if the document is not valid, and 2 if it couldn't be converted.
+
+Options:
+    --dtd FILE          The DTD to parse against, otherwise found from the document type
+                        declaration through the catalogs
+    --catalog FILE      A catalog of public identifiers, may be repeated
+                        SGML_CATALOG_FILES is read after these, separated by colons
+    --decl FILE         The SGML declaration, otherwise the one in the document or catalog
+    --output FILE       Write to FILE instead of stdout
+    --lowercase-names   Lowercase element and attribute names
+    --expand-entities   Write character entities as their characters (default)
+    --keep-entities     Write character entities as references, for use with --doctype
+    --xml-decl          Start with an XML declaration
+    --doctype SYSTEM_ID Write a document type declaration with this system identifier
+    --esis              Write the ESIS of the document as nsgmls does instead of XML";
+
+fn fail(message: &str) -> ! {
+    eprintln!("sgml_to_xml: {}", message);
+    exit(2)
+}
+
+fn read(path: &Path) -> String {
+    std::fs::read_to_string(path)
+        .unwrap_or_else(|e| fail(&format!("unable to read {}: {}", path.display(), e)))
+}
+
+/// Read and flatten a DTD, with the general entities documents refer to
+fn load_dtd(path: &Path, catalog: &Catalog) -> DocumentTypeDefinition {
+    let source = read(path);
+    let dtd = match read_dtd(&source) {
+        Ok((rest, dtd)) if rest.trim().is_empty() => dtd,
+        // Validating against the declarations before it would report errors that aren't there
+        Ok((rest, _)) => {
+            let line = source[..source.len() - rest.len()].lines().count() + 1;
+            fail(&format!(
+                "unable to parse {}: declaration at line {}",
+                path.display(),
+                line
+            ))
+        }
+        Err(e) => fail(&format!("unable to parse {}: {:?}", path.display(), e)),
+    };
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let options = FlattenOptions {
+        keep_general_entities: true,
+        ..FlattenOptions::default()
+    };
+    flatten_dtd(&dtd, base, catalog, &options)
+        .unwrap_or_else(|e| fail(&format!("{}: {}", path.display(), e)))
+}
 
 fn main() {
-    let mut f = File::open("../sgml/dtd/html.dtd").unwrap();
-    let mut s = String::new();
-    f.read_to_string(&mut s).unwrap();
+    let mut options = XmlOptions::default();
+    let mut input = None;
+    let mut dtd_path = None;
+    let mut catalog = Catalog::default();
+    let mut declaration_path = None;
+    let mut output = None;
+    let mut esis = false;
+
+    let mut args = std::env::args().skip(1);
+    let value = |args: &mut dyn Iterator<Item = String>, option: &str| {
+        args.next()
+            .unwrap_or_else(|| fail(&format!("{} needs a value\n{}", option, USAGE)))
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dtd" => dtd_path = Some(PathBuf::from(value(&mut args, &arg))),
+            "--catalog" => {
+                let path = PathBuf::from(value(&mut args, &arg));
+                catalog
+                    .load(&path)
+                    .unwrap_or_else(|e| fail(&format!("unable to read {}: {}", path.display(), e)));
+            }
+            "--decl" => declaration_path = Some(PathBuf::from(value(&mut args, &arg))),
+            "--output" | "-o" => output = Some(PathBuf::from(value(&mut args, &arg))),
+            "--lowercase-names" => options.lowercase_names = true,
+            "--expand-entities" => options.expand_entities = true,
+            "--keep-entities" => options.expand_entities = false,
+            "--xml-decl" => options.xml_declaration = true,
+            "--doctype" => options.doctype = Some(value(&mut args, &arg)),
+            "--esis" => esis = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return;
+            }
+            "-" => input = None,
+            a if a.starts_with('-') => fail(&format!("unknown option {}\n{}", a, USAGE)),
+            _ if input.is_some() => fail(USAGE),
+            _ => input = Some(PathBuf::from(arg)),
+        }
+    }
+    // Missing catalogs in the environment are not worth stopping for
+    for (file, e) in catalog.load_env() {
+        eprintln!("sgml_to_xml: unable to read catalog {}: {}", file, e);
+    }
+
+    let (name, source) = match &input {
+        Some(path) => (path.display().to_string(), read(path)),
+        None => {
+            let mut source = String::new();
+            std::io::stdin()
+                .read_to_string(&mut source)
+                .unwrap_or_else(|e| fail(&format!("unable to read stdin: {}", e)));
+            ("stdin".to_string(), source)
+        }
+    };
+    let base = input
+        .as_deref()
+        .and_then(Path::parent)
+        .unwrap_or_else(|| Path::new(""));
+
+    let dtd = match dtd_path {
+        Some(path) => load_dtd(&path, &catalog),
+        None => match read_doctype(&source) {
+            Some(doctype) => {
+                let path = catalog
+                    .locate_external_id(
+                        doctype.public_id.as_deref(),
+                        doctype.system_id.as_deref(),
+                        base,
+                    )
+                    .or_else(|| catalog.doctype.get(&doctype.name).cloned());
+                match path {
+                    Some(path) => load_dtd(&path, &catalog),
+                    // Everything may be declared in the internal subset
+                    None if doctype.public_id.is_none() && doctype.internal_subset.is_some() => {
+                        DocumentTypeDefinition::from(Vec::new())
+                    }
+                    None => fail(&format!(
+                        "no DTD found for {}, use --dtd or --catalog",
+                        doctype.public_id.unwrap_or(doctype.name)
+                    )),
+                }
+            }
+            None => fail(&format!(
+                "{} has no document type declaration, use --dtd",
+                name
+            )),
+        },
+    };
+
+    // A declaration in the document comes before the catalog's
+    let own_declaration = sgml_declaration_len(source.trim_start()).is_some();
+    let declaration = declaration_path
+        .or_else(|| {
+            catalog
+                .sgml_declaration
+                .clone()
+                .filter(|_| !own_declaration)
+        })
+        .map(|path| {
+            read_sgml_declaration(&read(&path))
+                .unwrap_or_else(|| fail(&format!("{} is not an SGML declaration", path.display())))
+        });
 
-    let x = sgml::read_dtd(&s);
-    println!("{:?}", x);
-    let (i, e) = x.unwrap();
+    let mut parser = Parser::with_declaration(&source, &dtd, declaration.as_ref());
+    let (text, errors) = if esis {
+        let text = to_esis(&mut parser);
+        (text, parser.get_errors().to_vec())
+    } else {
+        let document = Document::from_parser(parser);
+        (to_xml(&document, &options), document.errors)
+    };
+    for error in &errors {
+        eprintln!("{}:{}:{}: {}", name, error.line, error.column, error.kind);
+    }
 
-    // find the root, will be the only element which is not a child of another
-    println!("Root = {:#?}", e.get_roots());
+    match output {
+        Some(path) => std::fs::write(&path, text)
+            .unwrap_or_else(|e| fail(&format!("unable to write {}: {}", path.display(), e))),
+        None => print!("{}", text),
+    }
+    if !errors.is_empty() {
+        exit(1);
+    }
 }